@@ -0,0 +1,257 @@
+//! Security advisories in the format of the RustSec advisory database
+use crate::errors::*;
+use crate::fetch::{fetch_with_cli, is_offline};
+use crate::registry::cargo_home;
+use semver::{Version, VersionReq};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Where the RustSec advisory database is fetched from
+pub const ADVISORY_DB_URL: &str = "https://github.com/rustsec/advisory-db.git";
+
+/// A security advisory for a crate, as published in the RustSec advisory database
+#[derive(Debug, Clone, PartialEq)]
+pub struct Advisory {
+    /// The identifier, like `RUSTSEC-2020-0001`
+    pub id: String,
+    /// The name of the affected crate
+    pub package: String,
+    /// The versions with a fix
+    pub patched: Vec<VersionReq>,
+    /// The versions that were never affected
+    pub unaffected: Vec<VersionReq>,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryFile {
+    advisory: AdvisoryMetadata,
+    #[serde(default)]
+    versions: AdvisoryVersions,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryMetadata {
+    id: String,
+    package: String,
+    informational: Option<String>,
+    withdrawn: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct AdvisoryVersions {
+    #[serde(default)]
+    patched: Vec<VersionReq>,
+    #[serde(default)]
+    unaffected: Vec<VersionReq>,
+}
+
+impl Advisory {
+    /// Parse an advisory, either a markdown file whose TOML front matter is in a ```` ```toml ````
+    /// block, or the older plain TOML format.
+    ///
+    /// Withdrawn and informational advisories, e.g. about unmaintained crates, are no
+    /// vulnerabilities and give `None`.
+    pub fn parse(content: &str) -> Result<Option<Advisory>> {
+        let content = content.trim_start();
+        let front_matter = match content.strip_prefix("```toml") {
+            Some(rest) => rest.split("\n```").next().unwrap_or(rest),
+            None => content,
+        };
+        let file: AdvisoryFile =
+            toml::from_str(front_matter).chain_err(|| ErrorKind::InvalidAdvisory)?;
+        if file.advisory.withdrawn.is_some() || file.advisory.informational.is_some() {
+            return Ok(None);
+        }
+        Ok(Some(Advisory {
+            id: file.advisory.id,
+            package: file.advisory.package,
+            patched: file.versions.patched,
+            unaffected: file.versions.unaffected,
+        }))
+    }
+
+    /// Whether `version` is vulnerable, i.e. neither patched nor unaffected
+    pub fn affects(&self, version: &Version) -> bool {
+        !self
+            .patched
+            .iter()
+            .chain(&self.unaffected)
+            .any(|req| req.matches(version))
+    }
+}
+
+/// The advisories of a copy of the RustSec advisory database, by crate
+#[derive(Debug, Clone, Default)]
+pub struct AdvisoryDatabase {
+    advisories: HashMap<String, Vec<Advisory>>,
+}
+
+impl AdvisoryDatabase {
+    /// Read the advisories in `crates/<name>/` of a checkout of the database at `path`.
+    pub fn open(path: &Path) -> Result<AdvisoryDatabase> {
+        let crates = path.join("crates");
+        if !crates.is_dir() {
+            return Err(ErrorKind::AdvisoryDbMissing(path.display().to_string()).into());
+        }
+        let mut db = AdvisoryDatabase::default();
+        for dir in fs::read_dir(&crates)? {
+            let dir = dir?.path();
+            if !dir.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&dir)? {
+                let file = file?.path();
+                match file.extension().and_then(|ext| ext.to_str()) {
+                    Some("md") | Some("toml") => {}
+                    _ => continue,
+                }
+                let advisory = fs::read_to_string(&file)
+                    .map_err(Error::from)
+                    .and_then(|content| Advisory::parse(&content))
+                    .chain_err(|| format!("Failed to read the advisory `{}`", file.display()))?;
+                if let Some(advisory) = advisory {
+                    db.insert(advisory);
+                }
+            }
+        }
+        Ok(db)
+    }
+
+    /// Where `cargo audit` keeps its copy of the database, `$CARGO_HOME/advisory-db`
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(cargo_home()?.join("advisory-db"))
+    }
+
+    /// Clone or update the database at `path` from `ADVISORY_DB_URL`, and read it.
+    ///
+    /// Running offline, an existing copy is read as it is.
+    pub fn fetch(path: &Path, timeout: Duration) -> Result<AdvisoryDatabase> {
+        if is_offline() {
+            if !path.join("crates").is_dir() {
+                return Err(ErrorKind::Offline("fetch the advisory database".into()).into());
+            }
+            return AdvisoryDatabase::open(path);
+        }
+        let repo = match git2::Repository::open(path) {
+            Ok(repo) => repo,
+            Err(_) => git2::Repository::init(path)?,
+        };
+        fetch_with_cli(
+            &repo,
+            ADVISORY_DB_URL,
+            "+HEAD:refs/remotes/origin/HEAD",
+            "advisory database",
+            timeout,
+        )?;
+        let head = repo
+            .find_reference("refs/remotes/origin/HEAD")
+            .and_then(|head| head.peel_to_commit())
+            .chain_err(|| ErrorKind::AdvisoryDbMissing(path.display().to_string()))?;
+        repo.reset(head.as_object(), git2::ResetType::Hard, None)?;
+        AdvisoryDatabase::open(path)
+    }
+
+    /// Add `advisory` to the database.
+    pub fn insert(&mut self, advisory: Advisory) {
+        self.advisories
+            .entry(advisory.package.clone())
+            .or_default()
+            .push(advisory);
+    }
+
+    /// The advisories that affect `version` of `crate_name`
+    pub fn affecting(&self, crate_name: &str, version: &Version) -> Vec<&Advisory> {
+        self.advisories
+            .get(crate_name)
+            .map(|advisories| {
+                advisories
+                    .iter()
+                    .filter(|advisory| advisory.affects(version))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether any advisory affects `version` of `crate_name`
+    pub fn is_affected(&self, crate_name: &str, version: &Version) -> bool {
+        !self.affecting(crate_name, version).is_empty()
+    }
+}
+
+#[cfg(test)]
+fn version(version: &str) -> Version {
+    Version::parse(version).unwrap()
+}
+
+#[test]
+fn parse_advisory_front_matter() {
+    let advisory = Advisory::parse(
+        r#"```toml
+[advisory]
+id = "RUSTSEC-2021-0001"
+package = "foo"
+date = "2021-01-01"
+
+[versions]
+patched = [">= 1.2.3, < 1.3.0", ">= 1.3.1"]
+unaffected = ["< 1.0.0"]
+```
+
+# Memory corruption in `foo`
+"#,
+    )
+    .unwrap()
+    .unwrap();
+
+    assert_eq!(advisory.id, "RUSTSEC-2021-0001");
+    assert_eq!(advisory.package, "foo");
+    assert!(!advisory.affects(&version("0.9.0")));
+    assert!(advisory.affects(&version("1.2.2")));
+    assert!(!advisory.affects(&version("1.2.3")));
+    assert!(advisory.affects(&version("1.3.0")));
+    assert!(!advisory.affects(&version("1.4.0")));
+}
+
+#[test]
+fn skip_withdrawn_and_informational_advisories() {
+    let parse = |extra: &str| {
+        Advisory::parse(&format!(
+            "[advisory]\nid = \"RUSTSEC-2021-0002\"\npackage = \"foo\"\n{}\n",
+            extra
+        ))
+        .unwrap()
+    };
+
+    assert!(parse("").is_some());
+    assert!(parse("withdrawn = \"2021-02-01\"").is_none());
+    assert!(parse("informational = \"unmaintained\"").is_none());
+}
+
+#[test]
+fn read_advisories_of_database_checkout() {
+    let db_dir = tempdir::TempDir::new("advisory-db").unwrap();
+    let foo = db_dir.path().join("crates").join("foo");
+    fs::create_dir_all(&foo).unwrap();
+    fs::write(
+        foo.join("RUSTSEC-2021-0001.md"),
+        "```toml\n[advisory]\nid = \"RUSTSEC-2021-0001\"\npackage = \"foo\"\n\n\
+         [versions]\npatched = [\">= 1.2.3\"]\n```\n",
+    )
+    .unwrap();
+    fs::write(foo.join("README"), "not an advisory").unwrap();
+
+    let db = AdvisoryDatabase::open(db_dir.path()).unwrap();
+    let ids = |v| {
+        db.affecting("foo", &version(v))
+            .into_iter()
+            .map(|advisory| advisory.id.as_str())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids("1.2.2"), vec!["RUSTSEC-2021-0001"]);
+    assert!(ids("1.2.3").is_empty());
+    assert!(!db.is_affected("bar", &version("0.1.0")));
+
+    assert!(AdvisoryDatabase::open(&db_dir.path().join("missing")).is_err());
+}
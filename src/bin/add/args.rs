@@ -1,10 +1,23 @@
 //! Handle `cargo add` arguments
 
-use cargo_edit::{find, registry_url, Dependency};
-use cargo_edit::{get_latest_dependency, CrateName};
-use semver;
-use std::path::PathBuf;
+use cargo_edit::version::style::RequirementStyle;
+use cargo_edit::{
+    default_registry_name, dependency_table_path, find, find_named, find_workspace_member,
+    original_registry_url, rebase_path, relative_path, upstream_registry_url, vendor_directory,
+    warn, workspace_dependency_versions, Dependency, DependencyKind, GitReference, Manifest,
+};
+use cargo_edit::{
+    get_crate_name_from_path, get_crate_names_from_git_repo, get_crate_version_from_path,
+    get_default_timeout, get_latest_compatible_dependency_from_source,
+    get_latest_dependency_from_source, get_latest_dependency_from_sources, get_latest_git_tag,
+    is_offline, verify_checksum, verify_git_reference, verify_version_exists, CrateName, CrateSpec,
+    RegistryIndex, VersionSource,
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use structopt::StructOpt;
+use url::Url;
 
 use crate::errors::*;
 
@@ -28,10 +41,35 @@ dependencies (version set to '*')."
 
 #[derive(Debug, StructOpt)]
 pub struct Args {
-    /// Crates to be added.
-    #[structopt(name = "crate", required = true)]
+    /// Crates to be added, optionally with a version requirement like `serde@1`. Arguments like
+    /// `+derive` enable features of the crate before them. May be left out with `--path`, which
+    /// then adds the package at that path.
+    #[structopt(
+        name = "crate",
+        required_unless_one = &["dev-crates", "build-crates", "path"]
+    )]
     pub crates: Vec<String>,
 
+    /// Add this crate to `[dev-dependencies]` in the same edit as the other crates, e.g.
+    /// `cargo add serde rand --dev-crate tempfile`. Can be given several times.
+    #[structopt(
+        long = "dev-crate",
+        value_name = "crate",
+        number_of_values = 1,
+        conflicts_with_all = &["section", "move_from", "workspace", "optional", "print"]
+    )]
+    pub dev_crates: Vec<String>,
+
+    /// Add this crate to `[build-dependencies]` in the same edit as the other crates. Can be
+    /// given several times.
+    #[structopt(
+        long = "build-crate",
+        value_name = "crate",
+        number_of_values = 1,
+        conflicts_with_all = &["section", "move_from", "workspace", "optional", "print"]
+    )]
+    pub build_crates: Vec<String>,
+
     /// Rename a dependency in Cargo.toml,
     /// https://doc.rust-lang.org/cargo/reference/specifying-dependencies.html#renaming-dependencies-in-cargotoml
     /// Only works when specifying a single dependency.
@@ -46,13 +84,59 @@ pub struct Args {
     #[structopt(long = "build", short = "B", conflicts_with = "dev")]
     pub build: bool,
 
+    /// Add crate as normal dependency. This is the default unless `--dev` or `--build` is given,
+    /// combine it with one of them to add the crate to both tables with the same version.
+    #[structopt(long = "normal")]
+    pub normal: bool,
+
+    /// Add crate to the table of this kind of dependency: `normal`, `dev` or `build`. The table
+    /// names, like `dev-dependencies`, are accepted as well.
+    #[structopt(
+        long = "section",
+        value_name = "kind",
+        conflicts_with_all = &["dev", "build", "normal", "workspace"]
+    )]
+    pub section: Option<DependencyKind>,
+
+    /// Move the crate from the table of this kind of dependency, e.g. `dev`, keeping its
+    /// features and options. With `--target`, the table for that target is used.
+    #[structopt(long = "move-from", value_name = "kind", conflicts_with = "workspace")]
+    pub move_from: Option<DependencyKind>,
+
+    /// Add crate to `[workspace.dependencies]` of the workspace root, so that members can use it
+    /// with `workspace = true`. This also works for virtual manifests.
+    #[structopt(
+        long = "workspace",
+        conflicts_with_all = &["dev", "build", "normal", "target", "optional"]
+    )]
+    pub workspace: bool,
+
+    /// Add crate to `[workspace.dependencies]` of the workspace root and reference it from this
+    /// member with `workspace = true`. Features and `--optional` are set in the member.
+    #[structopt(long = "workspace-dep", conflicts_with_all = &["workspace", "print"])]
+    pub workspace_dep: bool,
+
+    /// Override the crate for the whole dependency graph in `[patch.<source>]`, where `source` is
+    /// `crates-io`, the name of a registry, or the URL of a registry or git repository. The crate
+    /// comes from `--git` or `--path`, and the manifest must be the workspace root.
+    #[structopt(
+        long = "patch",
+        value_name = "source",
+        conflicts_with_all = &["dev", "build", "normal", "section", "target", "optional"],
+        conflicts_with_all = &["workspace", "workspace_dep", "move_from", "print", "registry"],
+        conflicts_with_all = &["dev_crates", "build_crates"]
+    )]
+    pub patch: Option<String>,
+
     /// Specify the version to grab from the registry(crates.io).
     /// You can also specify version as part of name, e.g
     /// `cargo add bitflags@0.3.2`.
     #[structopt(long = "vers", value_name = "uri", conflicts_with = "git")]
     pub vers: Option<String>,
 
-    /// Specify a git repository to download the crate from.
+    /// Specify a git repository to download the crate from. The repository may contain several
+    /// crates, Cargo picks the one with the given package name. For local repositories, its
+    /// existence is checked.
     #[structopt(
         long = "git",
         value_name = "uri",
@@ -61,10 +145,52 @@ pub struct Args {
     )]
     pub git: Option<String>,
 
+    /// Branch of the git repository to use, with `--git`.
+    #[structopt(
+        long = "branch",
+        value_name = "branch",
+        requires = "git",
+        conflicts_with_all = &["tag", "rev"]
+    )]
+    pub branch: Option<String>,
+
+    /// Tag of the git repository to use, with `--git`.
+    #[structopt(
+        long = "tag",
+        value_name = "tag",
+        requires = "git",
+        conflicts_with = "rev"
+    )]
+    pub tag: Option<String>,
+
+    /// Revision of the git repository to use, e.g. a commit hash, with `--git`.
+    #[structopt(long = "rev", value_name = "rev", requires = "git")]
+    pub rev: Option<String>,
+
+    /// Pin the newest tag of the git repository that is a semver version, like `v1.2.0`, with
+    /// `--git`.
+    #[structopt(
+        long = "at-latest-tag",
+        requires = "git",
+        conflicts_with_all = &["branch", "tag", "rev"]
+    )]
+    pub at_latest_tag: bool,
+
+    /// Fail if the branch, tag or revision given with `--git` doesn't exist in the repository.
+    /// Revisions that no branch or tag points to are only checked in local repositories. Skipped
+    /// with `--offline`.
+    #[structopt(long = "verify-git", requires = "git")]
+    pub verify_git: bool,
+
     /// Specify the path the crate should be loaded from.
     #[structopt(long = "path", conflicts_with = "git")]
     pub path: Option<PathBuf>,
 
+    /// With `--path` or a path as crate, also require the version of the package at the path, so
+    /// that the dependency can be published.
+    #[structopt(long = "path-version")]
+    pub path_version: bool,
+
     /// Add as dependency to the given target platform.
     #[structopt(long = "target", conflicts_with = "dev", conflicts_with = "build")]
     pub target: Option<String>,
@@ -73,10 +199,35 @@ pub struct Args {
     #[structopt(long = "optional", conflicts_with = "dev", conflicts_with = "build")]
     pub optional: bool,
 
-    /// Path to the manifest to add a dependency to.
+    /// Enable the optional dependency in this feature, by adding `dep:<crate>` to it in the
+    /// `[features]` table, or just `<crate>` for packages before edition 2021.
+    #[structopt(
+        long = "enable-feature",
+        visible_alias = "feature-name",
+        value_name = "feature",
+        requires = "optional"
+    )]
+    pub enable_feature: Option<String>,
+
+    /// Path to the manifest to add a dependency to, or the directory containing it.
     #[structopt(long = "manifest-path", value_name = "path")]
     pub manifest_path: Option<PathBuf>,
 
+    /// Package of the workspace to add the dependency to, instead of the one at `--manifest-path`
+    /// or in the current directory, which may also be the workspace root.
+    #[structopt(
+        short = "p",
+        long = "package",
+        value_name = "member",
+        conflicts_with = "workspace"
+    )]
+    pub package: Option<String>,
+
+    /// Look for manifests with this file name instead of `Cargo.toml`, e.g. for templates like
+    /// `Cargo.toml.in` that follow the structure of a Cargo manifest.
+    #[structopt(long = "manifest-file-name", value_name = "name")]
+    pub manifest_file_name: Option<String>,
+
     /// Choose method of semantic version upgrade.
     #[structopt(
         long = "upgrade",
@@ -90,30 +241,275 @@ pub struct Args {
     )]
     pub upgrade: String,
 
+    /// Write the resolved version as a caret requirement, e.g. `^1.2.3`.
+    #[structopt(long = "caret", conflicts_with_all = &["tilde", "exact", "wildcard"])]
+    pub caret: bool,
+
+    /// Write the resolved version as a tilde requirement, e.g. `~1.2.3`, which only allows
+    /// patch-level updates.
+    #[structopt(long = "tilde", conflicts_with_all = &["exact", "wildcard"])]
+    pub tilde: bool,
+
+    /// Write the resolved version as an exact requirement, e.g. `=1.2.3`.
+    #[structopt(long = "exact", conflicts_with = "wildcard")]
+    pub exact: bool,
+
+    /// Write the resolved version as a wildcard requirement, e.g. `1.2.*`.
+    #[structopt(long = "wildcard")]
+    pub wildcard: bool,
+
+    /// Write the resolved version in this form: `caret` (`^1.2.3`), `tilde` (`~1.2.3`), `exact`
+    /// (`=1.2.3`) or `minor` (`~1.2`). Defaults to `req-style` of
+    /// `[package.metadata.cargo-edit]`.
+    #[structopt(
+        long = "req-style",
+        value_name = "style",
+        possible_values = &["caret", "tilde", "exact", "minor"],
+        conflicts_with_all = &["caret", "tilde", "exact", "wildcard"]
+    )]
+    pub req_style: Option<RequirementStyle>,
+
+    /// Explain why the dependency is pinned, with a `# pinned: <reason>` comment on its line. The
+    /// requirement has to be exact, e.g. `foo@=1.2.3` or `--exact`.
+    #[structopt(long = "reason", value_name = "reason")]
+    pub reason: Option<String>,
+
+    /// Remove the `# pinned: <reason>` comment of the dependency.
+    #[structopt(long = "clear-reason", conflicts_with = "reason")]
+    pub clear_reason: bool,
+
     /// Include prerelease versions when fetching from crates.io (e.g.
     /// '0.6.0-alpha').
     #[structopt(long = "allow-prerelease")]
     pub allow_prerelease: bool,
 
+    /// Only pick versions that support this Rust version. Defaults to the `package.rust-version`
+    /// of the manifest.
+    #[structopt(
+        long = "rust-version",
+        value_name = "version",
+        conflicts_with = "ignore_rust_version"
+    )]
+    pub rust_version: Option<String>,
+
+    /// Pick the latest version, even if it requires a newer Rust version than
+    /// `package.rust-version`.
+    #[structopt(long = "ignore-rust-version")]
+    pub ignore_rust_version: bool,
+
+    /// When the crate is already a dependency, only pick a version that is semver compatible
+    /// with its current requirement, i.e. never across a major version.
+    #[structopt(
+        long = "compatible-only",
+        conflicts_with = "vers",
+        conflicts_with = "default_registry_fallback"
+    )]
+    pub compatible_only: bool,
+
+    /// Allow picking a version across a semver-major boundary from the current requirement.
+    /// This is the default unless `--compatible-only` is given.
+    #[structopt(long = "incompatible", conflicts_with = "compatible_only")]
+    pub incompatible: bool,
+
     /// Set `default-features = false` for the added dependency.
     #[structopt(long = "no-default-features")]
     pub no_default_features: bool,
 
+    /// Write `default-features = true` for the added dependency instead of leaving it implicit.
+    /// Shorthand for `--default-features-style explicit`.
+    #[structopt(long = "default-features", conflicts_with = "no-default-features")]
+    pub default_features: bool,
+
+    /// How to write enabled default features: `explicit` writes `default-features = true`,
+    /// `auto` omits the key. Defaults to `[package.metadata.cargo-edit] default-features-style`,
+    /// or `auto`.
+    #[structopt(
+        long = "default-features-style",
+        value_name = "style",
+        possible_value = "auto",
+        possible_value = "explicit",
+        conflicts_with = "default_features"
+    )]
+    pub default_features_style: Option<String>,
+
+    /// Comma- or space-separated list of features to enable. `default` is written as an explicit
+    /// member of the list, and therefore can't be combined with `--no-default-features`.
+    #[structopt(long = "features", number_of_values = 1)]
+    pub features: Option<Vec<String>>,
+
+    /// Write the dependency as an inline table even if only a version is set, e.g.
+    /// `foo = { version = "1.0" }`. Defaults to `[package.metadata.cargo-edit] table-always`.
+    #[structopt(long = "table-always")]
+    pub table_always: bool,
+
+    /// Sort the features given with `--features` alphabetically and remove duplicates, instead of
+    /// keeping them in the given order. Defaults to `[package.metadata.cargo-edit] sort-features`.
+    #[structopt(long = "sort-features")]
+    pub sort_features: bool,
+
+    /// Fail instead of warning when a feature given with `--features` doesn't exist in the
+    /// version being added, according to the registry index.
+    #[structopt(long = "strict-features")]
+    pub strict_features: bool,
+
+    /// Turn warnings into errors, e.g. for CI.
+    #[structopt(long = "strict")]
+    pub strict: bool,
+
+    /// Use the version requirement that other members of the workspace already declare for the
+    /// crate, if any. If they disagree, the one used by most members is picked.
+    #[structopt(
+        long = "match-workspace",
+        conflicts_with_all = &["vers", "git", "path"]
+    )]
+    pub match_workspace: bool,
+
+    /// Fail instead of picking the most common requirement when the members of the workspace
+    /// disagree on it with `--match-workspace`.
+    #[structopt(long = "strict-match-workspace", requires = "match-workspace")]
+    pub strict_match_workspace: bool,
+
+    /// Fail if a required dependency of the version being added can only be resolved to yanked
+    /// versions, according to the registry index.
+    #[structopt(long = "reject-yanked")]
+    pub reject_yanked: bool,
+
+    /// Print the existing entries of the given crates in a normalized form (source, version,
+    /// features, optional, target) instead of adding them. The manifest is not modified.
+    #[structopt(
+        long = "print",
+        conflicts_with_all = &["vers", "git", "path", "rename", "optional", "features"]
+    )]
+    pub print: bool,
+
+    /// Keep only the last entry of dependencies that are listed more than once in the same
+    /// table, instead of refusing to edit the manifest.
+    #[structopt(long = "merge-duplicates")]
+    pub merge_duplicates: bool,
+
+    /// Print a unified diff of the changes to the manifest.
+    #[structopt(long = "show-diff")]
+    pub show_diff: bool,
+
+    /// Skip re-parsing the edited manifests before writing them, which is otherwise done to make
+    /// sure the edit produced valid manifests. With `--workspace-dep`, this includes the workspace
+    /// root.
+    #[structopt(long = "no-verify")]
+    pub no_verify: bool,
+
     /// Do not print any output in case of success.
     #[structopt(long = "quiet", short = "q")]
     pub quiet: bool,
 
-    /// Run without accessing the network
+    /// Print a unified diff of what would be added without changing the manifest, or only the
+    /// messages with `--quiet` or `--message-format json`.
+    #[structopt(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// How to report each added dependency. `json` prints one JSON record per crate and table,
+    /// whose `schema` field is bumped when the format changes.
+    #[structopt(
+        long = "message-format",
+        visible_alias = "output-format",
+        value_name = "fmt",
+        possible_value = "human",
+        possible_value = "json",
+        default_value = "human"
+    )]
+    pub message_format: String,
+
+    /// Also write the JSON records of `--message-format json` to this file, wrapped in an object
+    /// with a `format_version`, e.g. to collect them as a CI artifact.
+    #[structopt(long = "report-file", value_name = "path")]
+    pub report_file: Option<PathBuf>,
+
+    /// Run without accessing the network, like with `CARGO_NET_OFFLINE=true`. Versions are only
+    /// looked up in the registry indices that were downloaded before.
     #[structopt(long = "offline")]
     pub offline: bool,
 
-    /// Keep dependencies sorted
+    /// Timeout in seconds for network operations. Defaults to the value of
+    /// `CARGO_HTTP_TIMEOUT`, or 30 seconds.
+    #[structopt(long = "timeout", value_name = "seconds")]
+    pub timeout: Option<u64>,
+
+    /// Only refresh the registry index if the local copy is older than this many seconds, even
+    /// with `--offline`. Cached entries of sparse indices are reused until they are this old.
+    #[structopt(long = "refresh-index", value_name = "seconds")]
+    pub refresh_index: Option<u64>,
+
+    /// Keep dependencies sorted. Groups listed in `[package.metadata.cargo-edit] group-order`
+    /// are kept together, in that order. Defaults to `[package.metadata.cargo-edit] sort`.
     #[structopt(long = "sort", short = "s")]
     pub sort: bool,
 
     /// Registry to use
     #[structopt(long = "registry", conflicts_with = "git", conflicts_with = "path")]
     pub registry: Option<String>,
+
+    /// Add crates that are not in `--registry` from crates.io instead, without a `registry` key.
+    #[structopt(long = "default-registry-fallback", requires = "registry")]
+    pub default_registry_fallback: bool,
+
+    /// Ask the web API of the registry for features and yanked versions when the index entry of
+    /// a crate doesn't list any features. This needs an additional request per crate.
+    #[structopt(long = "registry-api")]
+    pub registry_api: bool,
+
+    /// Read the versions and features of crates from the web API of the registry instead of its
+    /// index, which then isn't updated, e.g. where cloning a git index takes too long. Versions
+    /// from the API don't list their dependencies.
+    #[structopt(
+        long = "registry-api-only",
+        conflicts_with_all = &["registry_api", "index_rev", "refresh_index"]
+    )]
+    pub registry_api_only: bool,
+
+    /// Resolve versions against the git index at this commit, e.g. for reproducible audits.
+    /// Defaults to `index-rev` in `[package.metadata.cargo-edit]` or
+    /// `[workspace.metadata.cargo-edit]`.
+    #[structopt(long = "index-rev", value_name = "rev")]
+    pub index_rev: Option<String>,
+
+    /// Read every entry from the git index instead of the entries cached from it the last time
+    /// the index was at the same commit.
+    #[structopt(long = "no-cache")]
+    pub no_cache: bool,
+
+    /// Abort unless the SHA256 checksum of the resolved version, or the one given with `--vers`,
+    /// in the registry index matches.
+    /// Only works when specifying a single dependency from a registry.
+    #[structopt(
+        long = "require-checksum",
+        value_name = "sha256",
+        conflicts_with = "git",
+        conflicts_with = "path"
+    )]
+    pub require_checksum: Option<String>,
+
+    /// Skip cargo config files that cannot be parsed instead of failing.
+    #[structopt(long = "lenient-config")]
+    pub lenient_config: bool,
+
+    /// Resolve the registry to its original index, ignoring any `replace-with` source replacement
+    /// in the cargo config, e.g. to add from upstream while a mirror is broken.
+    #[structopt(long = "no-source-replacement")]
+    pub no_source_replacement: bool,
+
+    /// Refresh the vendored crates with `cargo vendor` after adding, if the registry is replaced
+    /// with a vendor directory in the cargo config.
+    #[structopt(long = "revendor")]
+    pub revendor: bool,
+
+    /// Add the crates that can be resolved even if others fail, and report the failures at the
+    /// end.
+    #[structopt(long = "keep-going")]
+    pub keep_going: bool,
+
+    /// If the crate can't be found, offer to add the crate called most like it instead, e.g.
+    /// `serde` for `serd`. Without it, the similarly called crates are only listed.
+    #[structopt(long = "suggest")]
+    pub suggest: bool,
 }
 
 fn parse_version_req(s: &str) -> Result<&str> {
@@ -122,27 +518,95 @@ fn parse_version_req(s: &str) -> Result<&str> {
 }
 
 impl Args {
-    /// Get dependency section
-    pub fn get_section(&self) -> Vec<String> {
-        if self.dev {
-            vec!["dev-dependencies".to_owned()]
-        } else if self.build {
-            vec!["build-dependencies".to_owned()]
-        } else if let Some(ref target) = self.target {
+    /// Get the dependency sections to add the crates to
+    pub fn get_sections(&self) -> Vec<Vec<String>> {
+        if self.workspace {
+            return vec![vec!["workspace".to_owned(), "dependencies".to_owned()]];
+        }
+        if let Some(source) = &self.patch {
+            return vec![vec!["patch".to_owned(), source.clone()]];
+        }
+        if let Some(ref target) = self.target {
             if target.is_empty() {
                 panic!("Target specification may not be empty");
             }
-            vec![
-                "target".to_owned(),
-                target.clone(),
-                "dependencies".to_owned(),
-            ]
+        }
+        let kinds = match self.section {
+            Some(kind) => vec![kind],
+            None => {
+                let mut kinds = Vec::new();
+                if self.normal || !(self.dev || self.build) {
+                    kinds.push(DependencyKind::Normal);
+                }
+                if self.dev {
+                    kinds.push(DependencyKind::Development);
+                }
+                if self.build {
+                    kinds.push(DependencyKind::Build);
+                }
+                kinds
+            }
+        };
+        kinds
+            .into_iter()
+            .map(|kind| dependency_table_path(kind, self.target.as_deref()))
+            .collect()
+    }
+
+    /// The git reference given with `--branch`, `--tag` or `--rev`, if any.
+    fn git_reference(&self) -> Option<GitReference> {
+        if let Some(branch) = &self.branch {
+            Some(GitReference::Branch(branch.clone()))
+        } else if let Some(tag) = &self.tag {
+            Some(GitReference::Tag(tag.clone()))
         } else {
-            vec!["dependencies".to_owned()]
+            self.rev.clone().map(GitReference::Rev)
         }
     }
 
-    fn parse_single_dependency(&self, crate_name: &str) -> Result<Dependency> {
+    /// Express a path given on the command line relative to the directory of the manifest.
+    fn manifest_relative_path(&self, path: &Path) -> Result<String> {
+        let manifest_path = find(&self.manifest_path)?;
+        let manifest_dir = manifest_path
+            .parent()
+            .expect("there must be a parent directory");
+        Ok(rebase_path(manifest_dir, path)?)
+    }
+
+    /// Make `dependency` a dependency on the package at `path`, which is given relative to the
+    /// current directory. The package must have the name of the dependency, if it can be read. With
+    /// `--path-version`, its version is required as well, unless one is given already.
+    fn set_path(&self, dependency: Dependency, path: &Path) -> Result<Dependency> {
+        let local = path.to_string_lossy();
+        if let Ok(package) = get_crate_name_from_path(&local) {
+            if package != dependency.name {
+                return Err(ErrorKind::PathPackageMismatch(
+                    local.into_owned(),
+                    package,
+                    dependency.name,
+                )
+                .into());
+            }
+        }
+        let mut dependency = dependency.set_path(&self.manifest_relative_path(path)?);
+        if self.path_version && dependency.version().is_none() {
+            let version = get_crate_version_from_path(&local)?;
+            dependency = dependency.set_version(&self.format_requirement(&version));
+        }
+        Ok(dependency)
+    }
+
+    /// The name of the package at `--path`, if it can be read.
+    fn path_package_name(&self) -> Option<String> {
+        let path = self.path.as_ref()?;
+        get_crate_name_from_path(&path.to_string_lossy()).ok()
+    }
+
+    fn parse_single_dependency(
+        &self,
+        crate_name: &str,
+        sections: &[Vec<String>],
+    ) -> Result<Dependency> {
         let crate_name = CrateName::new(crate_name);
 
         if let Some(mut dependency) = crate_name.parse_as_version()? {
@@ -154,88 +618,609 @@ impl Args {
             }
 
             if let Some(ref path) = self.path {
-                dependency = dependency.set_path(path.to_str().unwrap());
+                dependency = self.set_path(dependency, path)?;
+            } else {
+                if let Some(registry) = &self.registry {
+                    dependency = dependency.set_registry(registry);
+                }
+                self.verify_requested_version(&dependency)?;
             }
 
             Ok(dependency)
         } else if crate_name.is_url_or_path() {
-            Ok(crate_name.parse_crate_name_from_uri()?)
+            let dependency = crate_name.parse_crate_name_from_uri()?;
+            match dependency.path().map(PathBuf::from) {
+                Some(path) => self.set_path(dependency, &path),
+                None => Ok(dependency),
+            }
         } else {
-            assert_eq!(self.git.is_some() && self.vers.is_some(), false);
-            assert_eq!(self.git.is_some() && self.path.is_some(), false);
-            assert_eq!(self.git.is_some() && self.registry.is_some(), false);
-            assert_eq!(self.path.is_some() && self.registry.is_some(), false);
+            assert!(!(self.git.is_some() && self.vers.is_some()));
+            assert!(!(self.git.is_some() && self.path.is_some()));
+            assert!(!(self.git.is_some() && self.registry.is_some()));
+            assert!(!(self.path.is_some() && self.registry.is_some()));
 
-            let mut dependency = Dependency::new(crate_name.name());
+            let mut dependency = Dependency::new(&CrateSpec::parse(crate_name.name())?.name);
 
             if let Some(repo) = &self.git {
+                // A repository may contain several crates, so make sure the requested one is
+                // among them if the repository can be read.
+                if let Some(names) = get_crate_names_from_git_repo(repo) {
+                    if !names.is_empty() && !names.iter().any(|name| name == crate_name.name()) {
+                        let kind = cargo_edit::ErrorKind::NoCrateInGitRepository(
+                            crate_name.name().to_owned(),
+                            repo.clone(),
+                            names.join("`, `"),
+                        );
+                        return Err(cargo_edit::Error::from(kind).into());
+                    }
+                }
                 dependency = dependency.set_git(repo);
-            }
-            if let Some(path) = &self.path {
-                dependency = dependency.set_path(path.to_str().unwrap());
+                let timeout = self
+                    .timeout
+                    .map(Duration::from_secs)
+                    .unwrap_or_else(get_default_timeout);
+                if self.at_latest_tag {
+                    let tag = get_latest_git_tag(repo, timeout)?;
+                    if !self.quiet {
+                        println!("    Using the latest tag `{}` of `{}`", tag, repo);
+                    }
+                    dependency = dependency.set_git_reference(GitReference::Tag(tag));
+                } else if let Some(reference) = self.git_reference() {
+                    if self.verify_git && !is_offline() {
+                        verify_git_reference(repo, &reference, timeout)?;
+                    }
+                    dependency = dependency.set_git_reference(reference);
+                }
             }
             if let Some(version) = &self.vers {
                 dependency = dependency.set_version(parse_version_req(version)?);
             }
-            let registry_url = if let Some(registry) = &self.registry {
-                Some(registry_url(&find(&self.manifest_path)?, Some(registry))?)
+            if let Some(path) = &self.path {
+                dependency = self.set_path(dependency, path)?;
+            }
+            let registry_url = if self.registry.is_some()
+                || self.lenient_config
+                || self.no_source_replacement
+                || self.vendor_directory()?.is_some()
+            {
+                Some(self.get_registry_url()?)
             } else {
                 None
             };
 
-            if self.git.is_none() && self.path.is_none() && self.vers.is_none() {
-                let dep = get_latest_dependency(
-                    crate_name.name(),
-                    self.allow_prerelease,
-                    &find(&self.manifest_path)?,
-                    &registry_url,
-                )?;
-                let v = format!(
-                    "{prefix}{version}",
-                    prefix = self.get_upgrade_prefix(),
-                    // If version is unavailable `get_latest_dependency` must have
-                    // returned `Err(FetchVersionError::GetVersion)`
-                    version = dep.version().unwrap_or_else(|| unreachable!())
-                );
+            if self.git.is_none()
+                && self.path.is_none()
+                && self.vers.is_none()
+                && self.registry.is_none()
+            {
+                // A crate of the same name in the registry is most likely not the one meant, so
+                // don't look it up there, but don't guess either.
+                let manifest_path = find(&self.manifest_path)?;
+                if let Some(member_dir) = find_workspace_member(&manifest_path, crate_name.name())?
+                {
+                    // `--path` is relative to the current directory, unlike the manifest entry.
+                    let current_dir = std::env::current_dir()
+                        .chain_err(|| "Failed to determine the current directory")?;
+                    let path = relative_path(&current_dir, &member_dir)?;
+                    return Err(ErrorKind::WorkspaceMemberByName(
+                        crate_name.name().to_owned(),
+                        path,
+                    )
+                    .into());
+                }
+            }
+
+            if self.match_workspace {
+                if let Some(version) = self.workspace_version(crate_name.name())? {
+                    dependency = dependency.set_version(&version);
+                }
+            }
+
+            let mut from_crates_io = false;
+            if self.git.is_none()
+                && self.path.is_none()
+                && self.vers.is_none()
+                && dependency.version().is_none()
+            {
+                let manifest_path = find(&self.manifest_path)?;
+                let rust_version = self.rust_version(&manifest_path);
+                let rust_version = rust_version.as_deref();
+                let dep = if self.default_registry_fallback {
+                    let registry = self.registry_index(&manifest_path, &registry_url);
+                    let crates_io =
+                        self.registry_index(&manifest_path, &Some(self.get_crates_io_url()?));
+                    let sources: [&dyn VersionSource; 2] = [&registry, &crates_io];
+                    let (dep, index) = get_latest_dependency_from_sources(
+                        crate_name.name(),
+                        self.allow_prerelease,
+                        rust_version,
+                        &sources,
+                    )?;
+                    from_crates_io = index > 0;
+                    if !self.quiet {
+                        println!(
+                            "    Found `{}` in registry `{}`",
+                            crate_name.name(),
+                            if from_crates_io {
+                                "crates-io"
+                            } else {
+                                self.registry.as_deref().unwrap_or_default()
+                            }
+                        );
+                    }
+                    dep
+                } else {
+                    let registry = self.registry_index(&manifest_path, &registry_url);
+                    let existing = self
+                        .existing_requirement(crate_name.name(), sections)
+                        .filter(|_| self.compatible_only && !self.incompatible);
+                    match existing {
+                        Some(existing) => get_latest_compatible_dependency_from_source(
+                            crate_name.name(),
+                            &existing,
+                            self.allow_prerelease,
+                            rust_version,
+                            &registry,
+                        )?,
+                        None => {
+                            self.latest_or_suggested(crate_name.name(), rust_version, &registry)?
+                        }
+                    }
+                };
+                // If version is unavailable `get_latest_dependency` must have
+                // returned `Err(FetchVersionError::GetVersion)`
+                let version = dep.version().unwrap_or_else(|| unreachable!());
+                let v = self
+                    .existing_requirement(crate_name.name(), sections)
+                    .filter(|_| !self.has_requirement_style())
+                    .and_then(|existing| keep_operator(&existing, version))
+                    .unwrap_or_else(|| self.format_requirement(version));
                 dependency = dep.set_version(&v);
             }
 
             // Set the registry after getting the latest version as
             // get_latest_dependency returns a registry-less Dependency
-            if let Some(registry) = &self.registry {
+            if let Some(registry) = self.registry.as_ref().filter(|_| !from_crates_io) {
                 dependency = dependency.set_registry(registry);
             }
+            if self.vers.is_some() && self.path.is_none() {
+                self.verify_requested_version(&dependency)?;
+            }
 
             Ok(dependency)
         }
     }
 
+    /// Get the latest version of `name` from `registry`. If there is no such crate, the crates
+    /// called similarly are listed in the error, and with `--suggest` the closest one may be
+    /// added instead.
+    fn latest_or_suggested(
+        &self,
+        name: &str,
+        rust_version: Option<&str>,
+        registry: &RegistryIndex,
+    ) -> Result<Dependency> {
+        let latest = |name: &str| {
+            get_latest_dependency_from_source(name, self.allow_prerelease, rust_version, registry)
+        };
+        match latest(name) {
+            Err(cargo_edit::Error(cargo_edit::ErrorKind::NoCrate(_), _)) => {}
+            result => return Ok(result?),
+        }
+
+        // Failing to search only loses the suggestions, not the reason for the failure.
+        let similar = registry.similar_names(name).unwrap_or_default();
+        let closest = match similar.first() {
+            Some(closest) => closest,
+            None => {
+                return Err(cargo_edit::Error::from(cargo_edit::ErrorKind::NoCrate(
+                    name.to_owned(),
+                ))
+                .into())
+            }
+        };
+        if self.suggest && atty::is(atty::Stream::Stdin) && confirm_suggestion(name, closest)? {
+            return Ok(latest(closest)?);
+        }
+        let kind = cargo_edit::ErrorKind::NoCrateButSimilar(name.to_owned(), similar.join("`, `"));
+        Err(cargo_edit::Error::from(kind).into())
+    }
+
+    /// Check that the registry has a version matching the requirement given with `--vers` or
+    /// `crate@req`, so that a typo doesn't end up in the manifest.
+    ///
+    /// This is skipped with `--offline`, as the local copy of the index may not know about the
+    /// requested version yet.
+    fn verify_requested_version(&self, dependency: &Dependency) -> Result<()> {
+        if self.offline {
+            return Ok(());
+        }
+        if let Some(version) = dependency.version() {
+            let manifest_path = find(&self.manifest_path)?;
+            let registry_url = Some(self.get_registry_url_for(dependency)?);
+            verify_version_exists(
+                &dependency.name,
+                version,
+                &self.registry_index(&manifest_path, &registry_url),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Get the requirement other members of the workspace use for `name`, for
+    /// `--match-workspace`
+    fn workspace_version(&self, name: &str) -> Result<Option<String>> {
+        let versions = workspace_dependency_versions(&find(&self.manifest_path)?, name)?;
+        if versions.len() > 1 {
+            let listed = versions
+                .iter()
+                .map(|(version, count)| format!("`{}` ({})", version, count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if self.strict_match_workspace {
+                return Err(ErrorKind::WorkspaceVersionMismatch(name.to_owned(), listed).into());
+            }
+            warn(format!(
+                "Members of the workspace use different versions of `{}`: {}, \
+                 using the most common one",
+                name, listed
+            ))?;
+        }
+        Ok(versions.into_iter().next().map(|(version, _)| version))
+    }
+
+    /// Get the URL of the registry to add dependencies from. A vendor directory replacing it
+    /// only has the versions vendored before, so the original registry is used instead, except
+    /// offline.
+    pub fn get_registry_url(&self) -> Result<Url> {
+        let manifest_path = find(&self.manifest_path)?;
+        let registry = self.registry.as_ref().map(String::as_ref);
+        let url = if self.no_source_replacement {
+            original_registry_url(&manifest_path, registry, self.lenient_config)?
+        } else {
+            upstream_registry_url(&manifest_path, registry, self.lenient_config)?
+        };
+        Ok(url)
+    }
+
+    /// Get the URL of crates.io, or of the source replacing it unless `--no-source-replacement`
+    /// is given, for `--default-registry-fallback`
+    pub fn get_crates_io_url(&self) -> Result<Url> {
+        let manifest_path = find(&self.manifest_path)?;
+        let url = if self.no_source_replacement {
+            original_registry_url(&manifest_path, None, self.lenient_config)?
+        } else {
+            upstream_registry_url(&manifest_path, None, self.lenient_config)?
+        };
+        Ok(url)
+    }
+
+    /// Get the directory of vendored crates, like `cargo vendor` creates, that the registry to
+    /// add dependencies from is replaced with in the cargo config, if there is one. With
+    /// `--no-source-replacement`, the replacement isn't followed, so there is none.
+    pub fn vendor_directory(&self) -> Result<Option<PathBuf>> {
+        if self.no_source_replacement {
+            return Ok(None);
+        }
+        let manifest_path = find(&self.manifest_path)?;
+        let registry = self.registry.as_ref().map(String::as_ref);
+        Ok(vendor_directory(
+            &manifest_path,
+            registry,
+            self.lenient_config,
+        )?)
+    }
+
+    /// Get the URL of the registry `dep` is resolved from, which is crates.io for dependencies
+    /// that `--default-registry-fallback` added without a registry.
+    pub fn get_registry_url_for(&self, dep: &Dependency) -> Result<Url> {
+        if self.default_registry_fallback && dep.registry().is_none() {
+            self.get_crates_io_url()
+        } else {
+            self.get_registry_url()
+        }
+    }
+
+    /// Get the Rust version that picked versions have to support: `--rust-version`, or the
+    /// `package.rust-version` of the manifest at `manifest_path`, unless `--ignore-rust-version`
+    /// is given.
+    fn rust_version(&self, manifest_path: &Path) -> Option<String> {
+        if self.ignore_rust_version {
+            return None;
+        }
+        self.rust_version.clone().or_else(|| {
+            Manifest::open(&Some(manifest_path.to_owned()))
+                .ok()?
+                .resolved_rust_version(manifest_path)
+        })
+    }
+
+    /// Resolve `--package` to the directory of that workspace member, for `--manifest-path`.
+    pub fn resolve_package(&mut self) -> Result<()> {
+        if let Some(package) = &self.package {
+            let manifest_path = find(&self.manifest_path)?;
+            let member_dir = find_workspace_member(&manifest_path, package)?
+                .ok_or_else(|| ErrorKind::UnknownMember(package.clone()))?;
+            self.manifest_path = Some(member_dir);
+        }
+        Ok(())
+    }
+
+    /// Resolve `--manifest-file-name` to the path of the manifest, so that all further lookups
+    /// use it.
+    pub fn resolve_manifest_file_name(&mut self) -> Result<()> {
+        if let Some(file_name) = &self.manifest_file_name {
+            self.manifest_path = Some(find_named(&self.manifest_path, file_name)?);
+        }
+        Ok(())
+    }
+
+    /// Use the `req-style` of the manifest's settings when the form of the requirement isn't
+    /// chosen on the command line.
+    pub fn resolve_req_style(&mut self) -> Result<()> {
+        if !self.has_requirement_style() {
+            let manifest_path = find(&self.manifest_path)?;
+            let path = Some(manifest_path.clone());
+            let manifest = if self.merge_duplicates {
+                Manifest::open_merging_duplicates(&path)?.0
+            } else {
+                Manifest::open(&path)?
+            };
+            self.req_style = manifest.edit_settings(&manifest_path).req_style()?;
+        }
+        Ok(())
+    }
+
+    /// Use the registry configured as `registry.default` when no `--registry` (or other source) is
+    /// given, like cargo does.
+    pub fn resolve_default_registry(&mut self) -> Result<()> {
+        if self.registry.is_none()
+            && self.git.is_none()
+            && self.path.is_none()
+            && self.patch.is_none()
+        {
+            self.registry = default_registry_name(&find(&self.manifest_path)?)?;
+        }
+        Ok(())
+    }
+
+    /// Get the index to resolve versions from, which honours `--refresh-index`, `--registry-api`,
+    /// `--registry-api-only` and `--index-rev`
+    pub fn registry_index(&self, manifest_path: &Path, registry: &Option<Url>) -> RegistryIndex {
+        let mut index = RegistryIndex::new(manifest_path, registry);
+        let rev = self.index_rev.clone().or_else(|| {
+            Manifest::open(&Some(manifest_path.to_owned()))
+                .ok()?
+                .edit_settings(manifest_path)
+                .index_rev()
+                .map(String::from)
+        });
+        if let Some(rev) = rev {
+            index = index.at_rev(&rev);
+        }
+        if let Some(max_age) = self.refresh_index {
+            index = index.refresh_after(Duration::from_secs(max_age));
+        }
+        if self.registry_api {
+            index = index.api_fallback();
+        }
+        if self.registry_api_only {
+            index = index.api_only();
+        }
+        index
+    }
+
+    /// Get the crates to add along with their inline features and the tables each one goes to,
+    /// in the order of `parse_dependencies`: the crates given as arguments go to the tables of
+    /// `get_sections`, those given with `--dev-crate` and `--build-crate` to the dev and build
+    /// tables. Arguments like `+derive` enable features of the crate before them.
+    pub fn crate_sections(&self) -> Vec<(String, Vec<String>, Vec<Vec<String>>)> {
+        let sections = self.get_sections();
+        let dev = vec![dependency_table_path(
+            DependencyKind::Development,
+            self.target.as_deref(),
+        )];
+        let build = vec![dependency_table_path(
+            DependencyKind::Build,
+            self.target.as_deref(),
+        )];
+        let with = |crates: &[String], sections: &[Vec<String>]| {
+            let mut with_sections: Vec<(String, Vec<String>, Vec<Vec<String>>)> = Vec::new();
+            for arg in crates {
+                match (arg.starts_with('+'), with_sections.last_mut()) {
+                    (true, Some((_, features, _))) => features.extend(split_features(&arg[1..])),
+                    _ => with_sections.push((arg.clone(), Vec::new(), sections.to_vec())),
+                }
+            }
+            with_sections
+        };
+        let mut crates = with(&self.crates, &sections);
+        // With nothing but `--path`, the package at the path is added.
+        if self.crates.is_empty() {
+            crates.extend(
+                self.path_package_name()
+                    .map(|name| (name, Vec::new(), sections.clone())),
+            );
+        }
+        crates.extend(with(&self.dev_crates, &dev));
+        crates.extend(with(&self.build_crates, &build));
+        crates
+    }
+
     /// Build dependencies from arguments
     pub fn parse_dependencies(&self) -> Result<Vec<Dependency>> {
-        if self.crates.len() > 1
-            && (self.git.is_some() || self.path.is_some() || self.vers.is_some())
+        let features = self.check_dependency_args()?;
+
+        self.crate_sections()
+            .iter()
+            .map(|(crate_name, inline_features, sections)| {
+                self.parse_dependency(crate_name, inline_features, sections, &features)
+            })
+            .collect()
+    }
+
+    /// Build dependencies from arguments like `parse_dependencies`, but resolve every crate, even
+    /// if resolving another one failed. The results are returned along with the crate names given
+    /// on the command line.
+    pub fn parse_dependencies_keep_going(&self) -> Result<Vec<(String, Result<Dependency>)>> {
+        let features = self.check_dependency_args()?;
+
+        Ok(self
+            .crate_sections()
+            .into_iter()
+            .map(|(crate_name, inline_features, sections)| {
+                let dep =
+                    self.parse_dependency(&crate_name, &inline_features, &sections, &features);
+                (crate_name, dep)
+            })
+            .collect())
+    }
+
+    /// Check that the arguments can be applied to all crates, and get the features to enable.
+    fn check_dependency_args(&self) -> Result<Option<Vec<String>>> {
+        let lists = [&self.crates, &self.dev_crates, &self.build_crates];
+        if let Some(feature) = lists
+            .iter()
+            .filter_map(|crates| crates.first())
+            .find(|arg| arg.starts_with('+'))
         {
+            return Err(ErrorKind::FeaturesWithoutCrate(feature.clone()).into());
+        }
+        let crate_count = self.crate_sections().len();
+        if self.patch.is_some() && self.git.is_none() && self.path.is_none() {
+            if let Some(name) = self
+                .crates
+                .iter()
+                .find(|name| !name.starts_with('+') && !CrateName::new(name).is_url_or_path())
+            {
+                return Err(ErrorKind::PatchWithoutSource(name.clone()).into());
+            }
+        }
+        if crate_count == 0 {
+            if let Some(path) = &self.path {
+                return Err(ErrorKind::NoPackageAtPath(path.display().to_string()).into());
+            }
+        }
+        if crate_count > 1 && (self.git.is_some() || self.path.is_some() || self.vers.is_some()) {
             return Err(ErrorKind::MultipleCratesWithGitOrPathOrVers.into());
         }
 
-        if self.crates.len() > 1 && self.rename.is_some() {
+        if crate_count > 1 && self.rename.is_some() {
             return Err(ErrorKind::MultipleCratesWithRename.into());
         }
 
-        self.crates
+        if crate_count > 1 && self.require_checksum.is_some() {
+            return Err(ErrorKind::MultipleCratesWithChecksum.into());
+        }
+
+        let features = self.get_features();
+        if self.no_default_features {
+            if let Some(ref features) = features {
+                if features.iter().any(|f| f == "default") {
+                    return Err(ErrorKind::DefaultFeatureWithNoDefaultFeatures.into());
+                }
+            }
+        }
+
+        Ok(features)
+    }
+
+    fn parse_dependency(
+        &self,
+        crate_name: &str,
+        inline_features: &[String],
+        sections: &[Vec<String>],
+        features: &Option<Vec<String>>,
+    ) -> Result<Dependency> {
+        let dep = self.parse_single_dependency(crate_name, sections)?;
+        // Features given along with the crate (`serde/derive@1` or `serde +derive`) come on top
+        // of the ones of `--features`.
+        let inline: Vec<String> = dep
+            .features()
+            .unwrap_or_default()
             .iter()
-            .map(|crate_name| {
-                self.parse_single_dependency(crate_name).map(|x| {
-                    let mut x = x
-                        .set_optional(self.optional)
-                        .set_default_features(!self.no_default_features);
-                    if let Some(ref rename) = self.rename {
-                        x = x.set_rename(rename);
-                    }
-                    x
-                })
-            })
-            .collect()
+            .chain(inline_features)
+            .cloned()
+            .collect();
+        let features = match features {
+            Some(features) => Some(features.iter().chain(&inline).cloned().collect()),
+            None if inline.is_empty() => None,
+            None => Some(inline),
+        };
+        let mut dep = dep
+            .set_optional(self.optional)
+            .set_default_features(!self.no_default_features)
+            .set_features(features);
+        if let Some(ref rename) = self.rename {
+            dep = dep.set_rename(rename);
+        }
+
+        if let Some(checksum) = &self.require_checksum {
+            let manifest_path = find(&self.manifest_path)?;
+            let registry_url = Some(self.get_registry_url_for(&dep)?);
+            let version = dep
+                .version()
+                .chain_err(|| ErrorKind::ChecksumWithoutVersion(dep.name.clone()))?;
+            verify_checksum(&dep.name, version, checksum, &manifest_path, &registry_url)?;
+        }
+
+        Ok(dep)
+    }
+
+    /// Get the features to enable, split into individual feature names.
+    fn get_features(&self) -> Option<Vec<String>> {
+        self.features
+            .as_ref()
+            .map(|features| features.iter().flat_map(|f| split_features(f)).collect())
+    }
+
+    /// Render a resolved version into the requirement to write, following the requirement style
+    /// flags, or `--upgrade` if none is given.
+    fn format_requirement(&self, version: &str) -> String {
+        if self.wildcard {
+            return wildcard_requirement(version);
+        }
+        if let Some(style) = self.req_style {
+            return style.requirement(version);
+        }
+        let prefix = if self.caret {
+            "^"
+        } else if self.tilde {
+            "~"
+        } else if self.exact {
+            "="
+        } else {
+            self.get_upgrade_prefix()
+        };
+        format!("{}{}", prefix, version)
+    }
+
+    /// Whether the form of the requirement is chosen explicitly, with `--caret`, `--tilde`,
+    /// `--exact`, `--wildcard`, `--req-style` or `--upgrade`.
+    fn has_requirement_style(&self) -> bool {
+        self.caret
+            || self.tilde
+            || self.exact
+            || self.wildcard
+            || self.req_style.is_some()
+            || self.upgrade != "default"
+    }
+
+    /// Get the requirement `name` already has in the first of the tables in `sections`, the ones
+    /// it is added to, if any.
+    fn existing_requirement(&self, name: &str, sections: &[Vec<String>]) -> Option<String> {
+        let manifest = Manifest::open(&self.manifest_path).ok()?;
+        sections.iter().find_map(|section| {
+            let mut item = &manifest.data.root;
+            for segment in section {
+                item = &item[segment.as_str()];
+            }
+            let entry = &item[name];
+            entry
+                .as_str()
+                .or_else(|| entry["version"].as_str())
+                .map(String::from)
+        })
     }
 
     fn get_upgrade_prefix(&self) -> &'static str {
@@ -250,27 +1235,161 @@ impl Args {
     }
 }
 
+/// Ask on the terminal whether to add `closest` instead of `name`, which doesn't exist.
+fn confirm_suggestion(name: &str, closest: &str) -> Result<bool> {
+    print!(
+        "The crate `{}` could not be found, add `{}` instead? [y/N] ",
+        name, closest
+    );
+    std::io::stdout()
+        .flush()
+        .chain_err(|| "Failed to flush stdout")?;
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .chain_err(|| "Failed to read answer")?;
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
+/// Write `version` with the operator and precision of the `existing` requirement, e.g. `1.3.0`
+/// becomes `~1.3` if the existing one is `~1.2`. Returns `None` for plain versions and for
+/// requirements made of several comparators, which get the default form instead.
+fn keep_operator(existing: &str, version: &str) -> Option<String> {
+    let existing = existing.trim();
+    if existing.contains(',') {
+        return None;
+    }
+    let (operator, rest) = if existing.starts_with(['~', '^', '=']) {
+        existing.split_at(1)
+    } else if existing.ends_with(".*") {
+        ("*", existing)
+    } else {
+        return None;
+    };
+    let rest = rest.trim().trim_end_matches(".*");
+    if rest.contains(['<', '>', '=']) {
+        return None;
+    }
+
+    let version = match semver::Version::parse(version) {
+        Ok(version) => version,
+        // Versions that aren't semver, like the fake ones used in tests, are kept as they are.
+        Err(_) if operator == "*" => return Some(version.to_owned()),
+        Err(_) => return Some(format!("{}{}", operator, version)),
+    };
+    let precision = rest.split(['-', '+']).next()?.split('.').count();
+    let version = if !version.pre.is_empty() || precision >= 3 {
+        version.to_string()
+    } else if precision == 2 {
+        format!("{}.{}", version.major, version.minor)
+    } else {
+        version.major.to_string()
+    };
+    Some(if operator == "*" {
+        format!("{}.*", version)
+    } else {
+        format!("{}{}", operator, version)
+    })
+}
+
+/// Replace the patch version of `version` by a wildcard, e.g. `1.2.3` becomes `1.2.*`.
+fn wildcard_requirement(version: &str) -> String {
+    match semver::Version::parse(version) {
+        Ok(version) => format!("{}.{}.*", version.major, version.minor),
+        // Versions that aren't semver, like the fake ones used in tests, are kept as they are.
+        Err(_) => version.to_owned(),
+    }
+}
+
+/// Split a list of features, separated by commas or spaces, into the individual features.
+fn split_features(features: &str) -> Vec<String> {
+    features
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|f| !f.is_empty())
+        .map(String::from)
+        .collect()
+}
+
 #[cfg(test)]
 impl Default for Args {
     fn default() -> Args {
         Args {
             crates: vec!["demo".to_owned()],
+            dev_crates: vec![],
+            build_crates: vec![],
             rename: None,
             dev: false,
             build: false,
+            normal: false,
+            section: None,
+            move_from: None,
+            workspace: false,
+            workspace_dep: false,
+            patch: None,
             vers: None,
             git: None,
+            branch: None,
+            tag: None,
+            rev: None,
+            at_latest_tag: false,
+            verify_git: false,
             path: None,
+            path_version: false,
             target: None,
             optional: false,
+            enable_feature: None,
             manifest_path: None,
+            package: None,
+            manifest_file_name: None,
             upgrade: "minor".to_string(),
+            caret: false,
+            tilde: false,
+            exact: false,
+            reason: None,
+            clear_reason: false,
+            wildcard: false,
+            req_style: None,
             allow_prerelease: false,
+            rust_version: None,
+            ignore_rust_version: false,
+            compatible_only: false,
+            incompatible: false,
             no_default_features: false,
+            default_features: false,
+            default_features_style: None,
+            features: None,
             quiet: false,
+            dry_run: false,
+            message_format: "human".to_owned(),
+            report_file: None,
             offline: true,
+            timeout: None,
+            refresh_index: None,
             sort: false,
             registry: None,
+            default_registry_fallback: false,
+            registry_api: false,
+            registry_api_only: false,
+            index_rev: None,
+            no_cache: false,
+            require_checksum: None,
+            lenient_config: false,
+            no_source_replacement: false,
+            revendor: false,
+            keep_going: false,
+            suggest: false,
+            table_always: false,
+            sort_features: false,
+            strict_features: false,
+            strict: false,
+            match_workspace: false,
+            strict_match_workspace: false,
+            reject_yanked: false,
+            print: false,
+            merge_duplicates: false,
+            show_diff: false,
+            no_verify: false,
         }
     }
 }
@@ -280,6 +1399,49 @@ mod tests {
     use super::*;
     use cargo_edit::Dependency;
 
+    #[test]
+    fn test_requirement_styles() {
+        let style = |args: Args| args.format_requirement("1.2.3");
+        assert_eq!(style(Args::default()), "^1.2.3");
+        let tilde = Args {
+            tilde: true,
+            ..Args::default()
+        };
+        assert_eq!(style(tilde), "~1.2.3");
+        let exact = Args {
+            exact: true,
+            ..Args::default()
+        };
+        assert_eq!(style(exact), "=1.2.3");
+        let wildcard = Args {
+            wildcard: true,
+            ..Args::default()
+        };
+        assert_eq!(style(wildcard), "1.2.*");
+        let minor = Args {
+            req_style: Some(RequirementStyle::Minor),
+            ..Args::default()
+        };
+        assert_eq!(style(minor), "~1.2");
+    }
+
+    #[test]
+    fn test_keep_operator() {
+        assert_eq!(keep_operator("~1.2", "1.3.4").as_deref(), Some("~1.3"));
+        assert_eq!(keep_operator("~1.2.0", "1.3.4").as_deref(), Some("~1.3.4"));
+        assert_eq!(keep_operator("=0.5.1", "0.6.0").as_deref(), Some("=0.6.0"));
+        assert_eq!(keep_operator("^1", "2.0.1").as_deref(), Some("^2"));
+        assert_eq!(keep_operator("1.2.*", "1.4.0").as_deref(), Some("1.4.*"));
+        assert_eq!(keep_operator("1.*", "2.1.0").as_deref(), Some("2.*"));
+        assert_eq!(
+            keep_operator("~1.2", "2.0.0-beta.1").as_deref(),
+            Some("~2.0.0-beta.1")
+        );
+        assert_eq!(keep_operator("1.2", "1.3.0"), None);
+        assert_eq!(keep_operator(">=1.2, <2", "1.3.0"), None);
+        assert_eq!(keep_operator(">=1.2", "1.3.0"), None);
+    }
+
     #[test]
     fn test_dependency_parsing() {
         let args = Args {
@@ -15,15 +15,27 @@
 extern crate error_chain;
 
 use crate::args::{Args, Command};
-use cargo_edit::{find, registry_url, update_registry_index, Dependency, Manifest};
+use cargo_edit::{
+    configure_index_cache, configure_offline, configure_warnings, dependency_table_path, find,
+    get_available_features, get_default_timeout, get_links_collisions, get_unknown_features,
+    get_yanked_dependencies, index_is_stale, is_offline, relative_path, revendor,
+    sort_dependencies, update_registry_index, warn, workspace_root_path, write_report, Dependency,
+    Manifest,
+};
+use std::collections::HashMap;
+use std::env;
 use std::io::Write;
+use std::path::Path;
 use std::process;
+use std::time::Duration;
 use structopt::StructOpt;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 use toml_edit::Item as TomlItem;
 
 mod args;
 
+// `error_chain` implements the deprecated `Error::description` for foreign links.
+#[allow(deprecated)]
 mod errors {
     error_chain! {
         errors {
@@ -37,6 +49,113 @@ mod errors {
                 description("Specified multiple crates with path or git or vers")
                 display("Cannot specify multiple crates with path or git or vers")
             }
+            /// Gave features like `+derive` before any crate.
+            FeaturesWithoutCrate(features: String) {
+                description("Specified features without a crate")
+                display("`{}` enables features of the crate before it, but no crate was given \
+                         before it", features)
+            }
+            /// The package at `--path` has a different name than the crate to add.
+            PathPackageMismatch(path: String, package: String, name: String) {
+                description("The package at the path has a different name")
+                display("The package at `{}` is `{}`, not `{}`. Add it as `{}`, and use \
+                         `--rename {}` to give it another key.", path, package, name, package, name)
+            }
+            /// Only `--path` was given, but there is no package at that path.
+            NoPackageAtPath(path: String) {
+                description("No package at the path")
+                display("Found no package at `{}` to add", path)
+            }
+            /// `--patch` was given without a git repository or path to take the crate from.
+            PatchWithoutSource(name: String) {
+                description("Patch without a git repository or path")
+                display("`--patch` overrides `{}` with a crate from `--git` or `--path`, but \
+                         neither was given", name)
+            }
+            /// `--patch` was given for a member of a workspace.
+            PatchOutsideRoot(path: String, root: String) {
+                description("Patch outside of the workspace root")
+                display("Cargo only reads `[patch]` from the workspace root, use \
+                         `--manifest-path {}` instead of `{}`", root, path)
+            }
+            /// Tried to add dependencies to a virtual workspace manifest.
+            AddingToVirtualManifest(path: String) {
+                description("Tried to add dependencies to a virtual manifest")
+                display("`{}` is a virtual manifest; choose the workspace member to add the \
+                         dependency to with `-p <member>`, or pass `--manifest-path` pointing at \
+                         its Cargo.toml, or `--workspace` to add it to `[workspace.dependencies]`.",
+                        path)
+            }
+            /// The package passed to `--package` is not in the workspace.
+            UnknownMember(name: String) {
+                description("Package is not a member of the workspace")
+                display("`{}` is not a member of the workspace", name)
+            }
+            /// The crate to add is a member of the workspace, but `--path` wasn't given.
+            WorkspaceMemberByName(name: String, path: String) {
+                description("Crate is a member of the workspace")
+                display("`{}` is a member of the workspace, add it with `--path {}`", name, path)
+            }
+            /// Tried to add workspace dependencies to a manifest without `[workspace]`.
+            NotAWorkspaceRoot(path: String) {
+                description("Tried to add workspace dependencies outside of a workspace root")
+                display("`{}` has no `[workspace]` table to add `[workspace.dependencies]` to",
+                        path)
+            }
+            /// Tried to inherit a dependency in a manifest that isn't part of a workspace.
+            NotInWorkspace(path: String) {
+                description("Tried to inherit a dependency outside of a workspace")
+                display("`{}` is not part of a workspace to add `{}` to", path,
+                        "[workspace.dependencies]")
+            }
+            /// Explicitly enabled the `default` feature while disabling default features.
+            DefaultFeatureWithNoDefaultFeatures {
+                description("Specified the `default` feature with --no-default-features")
+                display("Cannot enable the `default` feature together with --no-default-features")
+            }
+            /// Specified multiple crates with a required checksum.
+            MultipleCratesWithChecksum {
+                description("Specified multiple crates with a required checksum")
+                display("Cannot specify multiple crates with require-checksum")
+            }
+            /// Required a checksum for a dependency that isn't resolved from a registry.
+            ChecksumWithoutVersion(name: String) {
+                description("Required a checksum for a dependency without a version")
+                display("Cannot verify the checksum of `{}`, as it has no version", name)
+            }
+            /// Gave a reason for pinning a dependency whose requirement isn't exact.
+            ReasonWithoutPin(name: String) {
+                description("Gave a pin reason for a requirement that isn't exact")
+                display("`--reason` needs an exact requirement for `{}`, e.g. `{}@=1.2.3`",
+                        name, name)
+            }
+            /// Some crates couldn't be added with `--keep-going`.
+            FailedToAdd(crates: Vec<String>) {
+                description("Failed to add some crates")
+                display("Failed to add {}", crates.iter()
+                    .map(|c| format!("`{}`", c))
+                    .collect::<Vec<_>>()
+                    .join(", "))
+            }
+            /// Members of the workspace disagree on a version with `--strict-match-workspace`.
+            WorkspaceVersionMismatch(name: String, versions: String) {
+                description("Workspace members use different versions")
+                display("Members of the workspace use different versions of `{}`: {}", name, versions)
+            }
+            /// Requested features that don't exist with `--strict-features`.
+            UnknownFeatures(name: String, features: Vec<String>, available: Vec<String>) {
+                description("Requested features that don't exist")
+                display("`{}` has no feature {}{}", name, crate::quoted_list(features),
+                        crate::available_suffix(available))
+            }
+            /// A dependency of an added crate only has yanked versions, with `--reject-yanked`.
+            YankedDependencies(name: String, deps: Vec<String>) {
+                description("An added crate depends on yanked versions")
+                display("`{}` depends on {}, which only match yanked versions", name, deps.iter()
+                    .map(|d| format!("`{}`", d))
+                    .collect::<Vec<_>>()
+                    .join(", "))
+            }
             /// Specified multiple crates with renaming.
             MultipleCratesWithRename {
                 description("Specified multiple crates with rename")
@@ -73,43 +192,603 @@ fn print_msg(dep: &Dependency, section: &[String], optional: bool) -> Result<()>
     if optional {
         write!(output, " optional")?;
     }
-    let section = if section.len() == 1 {
-        section[0].clone()
-    } else {
-        format!("{} for target `{}`", &section[2], &section[1])
+    let section = match section {
+        [table] => table.clone(),
+        [target, name, table] if target == "target" => {
+            format!("{} for target `{}`", table, name)
+        }
+        _ => section.join("."),
     };
     writeln!(output, " {}", section)?;
     Ok(())
 }
 
+/// Move the entry of `dep` from the table at `from` to the tables at `to`, so that adding `dep`
+/// there keeps the features and options it had. Tables that already declare `dep` keep their own
+/// entry.
+fn move_dependency(
+    manifest: &mut Manifest,
+    from: &[String],
+    to: &[Vec<String>],
+    dep: &Dependency,
+) -> Result<()> {
+    let missing = || {
+        ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::NonExistentDependency(
+            dep.name.clone(),
+            from.join("."),
+        ))
+    };
+    if !manifest.has_table(from) {
+        return Err(missing().into());
+    }
+    let table = manifest.get_table(from)?;
+    let key = match Manifest::find_dep(table, &dep.name) {
+        Some((key, _)) => key,
+        None => return Err(missing().into()),
+    };
+    let item = std::mem::replace(&mut table[key.as_str()], TomlItem::None);
+    if table.as_table_like().is_some_and(|table| table.is_empty()) {
+        let (last, parent) = from.split_last().expect("table paths are not empty");
+        manifest.get_table(parent)?[last.as_str()] = TomlItem::None;
+    }
+
+    for section in to {
+        let table = manifest.get_table(section)?;
+        if Manifest::find_dep(table, &dep.name).is_none() {
+            table[key.as_str()] = item.clone();
+        }
+    }
+    Ok(())
+}
+
+/// The version of the records printed with `--message-format json`
+const MESSAGE_SCHEMA: u32 = 1;
+
+/// Describe how `dep` is written to `section` as a JSON record, e.g.
+///
+/// ```json
+/// {"schema":1,"crate":"serde","name":"serde","table":["dependencies"],"action":"add",
+///  "old_requirement":null,"requirement":"1.0","features":["derive"],"optional":false}
+/// ```
+///
+/// `action` is `update` if the manifest already had an entry for the dependency in that table,
+/// the `existing` one.
+fn json_record(dep: &Dependency, section: &[String], existing: &TomlItem) -> serde_json::Value {
+    let old_requirement = existing.as_str().or_else(|| existing["version"].as_str());
+    serde_json::json!({
+        "schema": MESSAGE_SCHEMA,
+        "crate": dep.name,
+        "name": dep.name_in_manifest(),
+        "table": section,
+        "action": if existing.is_none() { "add" } else { "update" },
+        "old_requirement": old_requirement,
+        "requirement": dep.version(),
+        "git": dep.git(),
+        "path": dep.path(),
+        "registry": dep.registry(),
+        "features": dep.features().unwrap_or_default(),
+        "optional": dep.is_optional(),
+    })
+}
+
+/// Whether `manifest` has an entry called `name` in the table at `section`.
+fn has_dependency(manifest: &Manifest, section: &[String], name: &str) -> bool {
+    !existing_entry(manifest, section, name).is_none()
+}
+
+/// The entry called `name` in the table at `section` of `manifest`, which is `None` if there is
+/// none.
+fn existing_entry<'m>(manifest: &'m Manifest, section: &[String], name: &str) -> &'m TomlItem {
+    let mut item = &manifest.data.root;
+    for segment in section {
+        item = &item[segment.as_str()];
+    }
+    &item[name]
+}
+
+/// Warn about new dependencies that link to the same native library as an existing one, which
+/// Cargo would reject when building. The check is best effort, so lookup failures are ignored.
+fn warn_links_collisions(args: &Args, manifest: &Manifest, deps: &[Dependency]) -> Result<()> {
+    let existing = match manifest.get_dependencies() {
+        Ok(existing) => existing
+            .into_iter()
+            .map(|(_, dep)| dep)
+            .filter(|dep| dep.path().is_none() && dep.git().is_none())
+            .collect::<Vec<_>>(),
+        Err(_) => return Ok(()),
+    };
+    let manifest_path = match find(&args.manifest_path) {
+        Ok(manifest_path) => manifest_path,
+        Err(_) => return Ok(()),
+    };
+
+    for dep in deps
+        .iter()
+        .filter(|dep| dep.path().is_none() && dep.git().is_none())
+    {
+        let source = match args.get_registry_url_for(dep) {
+            Ok(registry_url) => args.registry_index(&manifest_path, &Some(registry_url)),
+            Err(_) => continue,
+        };
+        if let Ok(collisions) = get_links_collisions(dep, &existing, &source) {
+            for (name, links) in collisions {
+                warn(format!(
+                    "`{}` and `{}` both link to the native library `{}`, \
+                     which Cargo does not allow",
+                    dep.name, name, links
+                ))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Quote each of `names` and separate them with commas, e.g. `` `std`, `derive` ``.
+fn quoted_list(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| format!("`{}`", name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The end of a message about unknown features that lists the `available` ones, if any are known.
+fn available_suffix(available: &[String]) -> String {
+    if available.is_empty() {
+        String::new()
+    } else {
+        format!(", it has {}", quoted_list(available))
+    }
+}
+
+/// The available and the default features of crates, by crate name.
+type CrateFeatures = HashMap<String, (Vec<String>, Vec<String>)>;
+
+/// Check that the features requested for the new registry dependencies exist in the versions
+/// being added. Unknown features are a warning, or an error with `--strict-features`; failing to
+/// look them up is ignored.
+///
+/// Returns the available and default features of each registry dependency by name, as far as
+/// they could be looked up.
+fn check_features(args: &Args, deps: &[Dependency]) -> Result<CrateFeatures> {
+    let mut features = HashMap::new();
+    let manifest_path = match find(&args.manifest_path) {
+        Ok(manifest_path) => manifest_path,
+        Err(_) => return Ok(features),
+    };
+
+    for dep in deps
+        .iter()
+        .filter(|dep| dep.path().is_none() && dep.git().is_none())
+    {
+        let source = match args.get_registry_url_for(dep) {
+            Ok(registry_url) => args.registry_index(&manifest_path, &Some(registry_url)),
+            Err(_) => continue,
+        };
+        let (available, defaults) = match get_available_features(dep, &source) {
+            Ok(Some(found)) => found,
+            _ => (Vec::new(), Vec::new()),
+        };
+        let unknown = get_unknown_features(dep, &source).unwrap_or_default();
+        if !unknown.is_empty() {
+            if args.strict_features {
+                return Err(
+                    ErrorKind::UnknownFeatures(dep.name.clone(), unknown, available).into(),
+                );
+            }
+            for feature in unknown {
+                warn(format!(
+                    "`{}` has no feature `{}`, Cargo will fail to resolve it{}",
+                    dep.name,
+                    feature,
+                    available_suffix(&available)
+                ))?;
+            }
+        }
+        if !available.is_empty() {
+            features.insert(dep.name.clone(), (available, defaults));
+        }
+    }
+    Ok(features)
+}
+
+/// Print the features `dep` has, and which of them it enables by default, after adding it.
+fn print_features(dep: &Dependency, available: &[String], defaults: &[String]) {
+    println!("    Features of `{}`: {}", dep.name, quoted_list(available));
+    if !defaults.is_empty() {
+        println!("    Enabled by default: {}", quoted_list(defaults));
+    }
+}
+
+/// Fail if a required dependency of one of the new registry dependencies can only be resolved to
+/// yanked versions. This is best effort, so failing to look them up is ignored.
+fn reject_yanked(args: &Args, deps: &[Dependency]) -> Result<()> {
+    let manifest_path = match find(&args.manifest_path) {
+        Ok(manifest_path) => manifest_path,
+        Err(_) => return Ok(()),
+    };
+
+    for dep in deps
+        .iter()
+        .filter(|dep| dep.path().is_none() && dep.git().is_none())
+    {
+        let source = match args.get_registry_url_for(dep) {
+            Ok(registry_url) => args.registry_index(&manifest_path, &Some(registry_url)),
+            Err(_) => continue,
+        };
+        match get_yanked_dependencies(dep, &source) {
+            Ok(yanked) if !yanked.is_empty() => {
+                let yanked = yanked
+                    .into_iter()
+                    .map(|(name, req)| format!("{} {}", name, req))
+                    .collect();
+                return Err(ErrorKind::YankedDependencies(dep.name.clone(), yanked).into());
+            }
+            _ => continue,
+        }
+    }
+    Ok(())
+}
+
+/// Find the entries for `name` in the tables at `sections`, matching either the key or the
+/// `package` of renamed dependencies. Without `--target`, the `[target.*.dependencies]` tables
+/// are searched as well.
+fn find_existing(
+    manifest: &Manifest,
+    sections: &[Vec<String>],
+    name: &str,
+    with_targets: bool,
+) -> Vec<(Vec<String>, Dependency)> {
+    let mut tables = sections.to_vec();
+    if with_targets {
+        if let Some(targets) = manifest.data["target"].as_table_like() {
+            for (target, _) in targets.iter() {
+                tables.push(vec![
+                    "target".to_owned(),
+                    target.to_owned(),
+                    "dependencies".to_owned(),
+                ]);
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    for table_path in tables {
+        let table = table_path
+            .iter()
+            .fold(&manifest.data.root, |item, key| &item[key.as_str()]);
+        let table = match table.as_table_like() {
+            Some(table) => table,
+            None => continue,
+        };
+        for (key, item) in table.iter() {
+            let dep = match Dependency::from_toml(key, item) {
+                Ok(dep) if key == name || dep.name == name => dep,
+                _ => continue,
+            };
+            let dep = if table_path.len() == 3 {
+                dep.set_target(&table_path[1])
+            } else {
+                dep
+            };
+            found.push((table_path.clone(), dep));
+        }
+    }
+    found
+}
+
+/// Print the existing entries of the given crates in a normalized form, without changing the
+/// manifest.
+fn print_existing(args: &Args, manifest: &Manifest) -> Result<()> {
+    let sections = args.get_sections();
+    let with_targets = args.target.is_none()
+        && sections
+            .iter()
+            .any(|section| section.len() == 1 && section[0] == "dependencies");
+    for name in &args.crates {
+        let found = find_existing(manifest, &sections, name, with_targets);
+        if found.is_empty() {
+            let tables = sections
+                .iter()
+                .map(|section| section.join("."))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(
+                cargo_edit::Error::from(cargo_edit::ErrorKind::NonExistentDependency(
+                    name.clone(),
+                    tables,
+                ))
+                .into(),
+            );
+        }
+
+        for (table_path, dep) in found {
+            let source = if let Some(git) = dep.git() {
+                format!("git {}", git)
+            } else if let Some(path) = dep.path() {
+                format!("path {}", path)
+            } else {
+                format!("registry {}", dep.registry().unwrap_or("crates-io"))
+            };
+            println!("{}", dep.name_in_manifest());
+            println!("    table: {}", table_path.last().unwrap());
+            println!("    package: {}", dep.name);
+            println!("    source: {}", source);
+            println!("    version: {}", dep.version().unwrap_or("none"));
+            println!("    default-features: {}", dep.default_features());
+            println!(
+                "    features: [{}]",
+                dep.features().map(|f| f.join(", ")).unwrap_or_default()
+            );
+            println!("    optional: {}", dep.is_optional());
+            println!("    target: {}", dep.target().unwrap_or("none"));
+        }
+    }
+    Ok(())
+}
+
+/// The entry of `dep` for `[workspace.dependencies]` of the root in `root_dir`: its source and
+/// version, without the features and `optional` that members set themselves.
+fn workspace_entry(dep: &Dependency, member_dir: &Path, root_dir: &Path) -> Result<Dependency> {
+    let mut entry = dep.clone().set_features(None).set_optional(false);
+    if let Some(path) = dep.path() {
+        entry = entry.set_path(&relative_path(root_dir, &member_dir.join(path))?);
+    }
+    Ok(entry)
+}
+
+/// Add `deps` to `[workspace.dependencies]` of `root`, unless it already has them, for
+/// `--workspace-dep`.
+fn add_to_workspace(
+    args: &Args,
+    root: &mut Manifest,
+    deps: &[Dependency],
+    member_dir: &Path,
+    root_dir: &Path,
+) -> Result<()> {
+    let section = ["workspace".to_owned(), "dependencies".to_owned()];
+    for dep in deps {
+        if has_dependency(root, &section, dep.name_in_manifest()) {
+            continue;
+        }
+        let entry = workspace_entry(dep, member_dir, root_dir)?;
+        if args.message_format != "json" && !args.quiet {
+            print_msg(&entry, &section, false)?;
+        }
+        root.insert_into_table(&section, &entry)?;
+    }
+    Ok(())
+}
+
+/// Re-parse `manifest`, which is about to be written to `path`, unless `--no-verify` is given.
+fn verify_edit(args: &Args, manifest: &Manifest, path: &Path) -> Result<()> {
+    if args.no_verify {
+        return Ok(());
+    }
+    // Lets the tests make the check fail for one manifest, to see whether it runs.
+    if env::var("CARGO_IS_TEST").is_ok() {
+        if let Some(failing) = env::var_os("CARGO_EDIT_TEST_FAIL_VERIFY") {
+            if Path::new(&failing).canonicalize()? == path.canonicalize()? {
+                return Err("The edited manifest is not valid TOML".into());
+            }
+        }
+    }
+    Ok(manifest.verify()?)
+}
+
+/// Refresh the vendored crates with `--revendor` after the manifest at `manifest_path` changed,
+/// if the registry is replaced with a vendor directory, or else remind that they may be stale.
+fn refresh_vendor_directory(args: &Args, manifest_path: &Path) -> Result<()> {
+    let directory = match args.vendor_directory()? {
+        Some(directory) => directory,
+        None => return Ok(()),
+    };
+    if args.revendor {
+        revendor(manifest_path, &directory)?;
+    } else if args.message_format != "json" && !args.quiet {
+        println!(
+            "NOTE: The dependencies are vendored in `{}`, which may be out of date now. Run \
+             `cargo vendor` or add `--revendor` to refresh it.",
+            directory.display()
+        );
+    }
+    Ok(())
+}
+
 fn handle_add(args: &Args) -> Result<()> {
+    // Warnings would break up the JSON records on stdout.
+    configure_warnings(args.strict, args.quiet || args.message_format == "json");
+    configure_offline(args.offline);
+    configure_index_cache(!args.no_cache);
     let manifest_path = &args.manifest_path;
-    let mut manifest = Manifest::open(manifest_path)?;
-    let deps = &args.parse_dependencies()?;
+    let mut manifest = if args.merge_duplicates {
+        let (manifest, merged) = Manifest::open_merging_duplicates(manifest_path)?;
+        for (table, name) in merged {
+            warn(format!(
+                "`{}` was declared more than once in `{}`, keeping the last declaration",
+                name, table
+            ))?;
+        }
+        manifest
+    } else {
+        Manifest::open(manifest_path)?
+    };
+    if args.print {
+        return print_existing(args, &manifest);
+    }
+    if args.workspace && !manifest.is_workspace_root() {
+        let path = find(manifest_path)?;
+        return Err(ErrorKind::NotAWorkspaceRoot(path.display().to_string()).into());
+    }
+    if args.patch.is_some() {
+        let path = find(manifest_path)?.canonicalize()?;
+        if let Some(root) = workspace_root_path(&path).filter(|root| *root != path) {
+            return Err(ErrorKind::PatchOutsideRoot(
+                path.display().to_string(),
+                root.display().to_string(),
+            )
+            .into());
+        }
+    }
+    if manifest.is_virtual() && !args.workspace && args.patch.is_none() {
+        let path = find(manifest_path)?;
+        return Err(ErrorKind::AddingToVirtualManifest(path.display().to_string()).into());
+    }
+    let refresh_index = args.refresh_index.map(Duration::from_secs);
+    if (!is_offline() || refresh_index.is_some())
+        && !args.registry_api_only
+        && env::var("CARGO_IS_TEST").is_err()
+    {
+        let timeout = args
+            .timeout
+            .map(Duration::from_secs)
+            .unwrap_or_else(get_default_timeout);
+        let mut urls = vec![args.get_registry_url()?];
+        if args.default_registry_fallback {
+            urls.push(args.get_crates_io_url()?);
+        }
+        for url in urls {
+            if refresh_index.map_or(Ok(true), |max_age| index_is_stale(&url, max_age))? {
+                update_registry_index(&url, timeout)?;
+            }
+        }
+    }
+
+    // Every crate is routed to its own tables, but all of them are inserted before the manifest
+    // is written once.
+    let sections = args
+        .crate_sections()
+        .into_iter()
+        .map(|(_, _, sections)| sections);
+    let (deps, failures) = if args.keep_going {
+        let mut deps = Vec::new();
+        let mut failures = Vec::new();
+        for ((crate_name, dep), sections) in args
+            .parse_dependencies_keep_going()?
+            .into_iter()
+            .zip(sections)
+        {
+            match dep {
+                Ok(dep) => deps.push((dep, sections)),
+                Err(err) => failures.push((crate_name, err)),
+            }
+        }
+        (deps, failures)
+    } else {
+        let deps = args.parse_dependencies()?;
+        (deps.into_iter().zip(sections).collect(), Vec::new())
+    };
 
-    if !args.offline && std::env::var("CARGO_IS_TEST").is_err() {
-        let url = registry_url(
-            &find(&manifest_path)?,
-            args.registry.as_ref().map(String::as_ref),
-        )?;
-        update_registry_index(&url)?;
+    let settings = manifest.edit_settings(&find(manifest_path)?);
+    let table_always = args.table_always || settings.table_always();
+    let sort_features = args.sort_features || settings.sort_features();
+    let explicit_default_features = if args.default_features {
+        true
+    } else {
+        match args.default_features_style.as_deref() {
+            Some(style) => style == "explicit",
+            None => settings.explicit_default_features().unwrap_or(false),
+        }
+    };
+    let (deps, dep_sections): (Vec<_>, Vec<_>) = deps
+        .into_iter()
+        .map(|(dep, sections)| {
+            let dep = dep
+                .set_table_always(table_always)
+                .set_explicit_default_features(explicit_default_features);
+            if sort_features {
+                (dep.sort_features(), sections)
+            } else {
+                (dep, sections)
+            }
+        })
+        .unzip();
+
+    let mut crate_features = check_features(args, &deps)?;
+    if args.reason.is_some() {
+        if let Some(dep) = deps.iter().find(|dep| {
+            !dep.version()
+                .is_some_and(|version| version.trim_start().starts_with('='))
+        }) {
+            return Err(ErrorKind::ReasonWithoutPin(dep.name.clone()).into());
+        }
+    }
+    if args.reject_yanked {
+        reject_yanked(args, &deps)?;
     }
+    warn_links_collisions(args, &manifest, &deps)?;
+
+    let group_order = settings.group_order();
+    let sort = args.sort || settings.sort();
 
+    // With `--workspace-dep`, the crates go to the workspace root first. The root is edited in the
+    // same manifest if it is this package.
+    let mut workspace_root = None;
+    if args.workspace_dep {
+        let member_path = find(manifest_path)?.canonicalize()?;
+        let member_dir = member_path.parent().expect("manifests are in a directory");
+        let root_path = workspace_root_path(&member_path)
+            .chain_err(|| ErrorKind::NotInWorkspace(member_path.display().to_string()))?;
+        let root_dir = root_path.parent().expect("manifests are in a directory");
+        if root_path == member_path {
+            add_to_workspace(args, &mut manifest, &deps, member_dir, root_dir)?;
+        } else {
+            let mut root = Manifest::open(&Some(root_path.clone()))?;
+            add_to_workspace(args, &mut root, &deps, member_dir, root_dir)?;
+            workspace_root = Some((root_path, root));
+        }
+    }
+
+    let mut created_tables = Vec::new();
+    for section in dep_sections.iter().flatten() {
+        if !manifest.has_table(section) && !created_tables.contains(section) {
+            created_tables.push(section.clone());
+        }
+    }
+    if args.enable_feature.is_some() && !manifest.has_table(&["features".to_owned()]) {
+        created_tables.push(vec!["features".to_owned()]);
+    }
+    if let Some(kind) = args.move_from {
+        let source = dependency_table_path(kind, args.target.as_deref());
+        for (dep, sections) in deps.iter().zip(&dep_sections) {
+            move_dependency(&mut manifest, &source, sections, dep)?;
+        }
+    }
+    let mut records = Vec::new();
     deps.iter()
-        .map(|dep| {
-            if !args.quiet {
-                print_msg(dep, &args.get_section(), args.optional)?;
+        .zip(&dep_sections)
+        .flat_map(|(dep, sections)| sections.iter().map(move |section| (dep, section)))
+        .map(|(dep, section)| {
+            if args.message_format == "json" || args.report_file.is_some() {
+                let existing = existing_entry(&manifest, section, dep.name_in_manifest());
+                let record = json_record(dep, section, existing);
+                if args.message_format == "json" {
+                    println!("{}", record);
+                }
+                records.push(record);
             }
-            manifest
-                .insert_into_table(&args.get_section(), dep)
+            if args.message_format != "json" && !args.quiet {
+                print_msg(dep, section, args.optional)?;
+                // Listed once, even if the crate goes to several tables.
+                if let Some((available, defaults)) = crate_features.remove(&dep.name) {
+                    print_features(dep, &available, &defaults);
+                }
+            }
+            let inserted = if args.workspace_dep {
+                manifest
+                    .insert_workspace_reference(section, &dep.clone().set_default_features(true))
+            } else {
+                manifest.insert_into_table(section, dep)
+            };
+            inserted
                 .map(|_| {
                     manifest
-                        .get_table(&args.get_section())
+                        .get_table(section)
                         .map(TomlItem::as_table_mut)
                         .map(|table_option| {
                             table_option.map(|table| {
-                                if args.sort {
-                                    table.sort_values();
+                                if sort {
+                                    sort_dependencies(table, &group_order);
                                 }
                             })
                         })
@@ -122,17 +801,116 @@ fn handle_add(args: &Args) -> Result<()> {
             err
         })?;
 
-    let mut file = Manifest::find_file(manifest_path)?;
-    manifest.write_to_file(&mut file)?;
+    if let Some(feature) = &args.enable_feature {
+        let dep_syntax = manifest.uses_dep_syntax(&find(manifest_path)?);
+        for dep in &deps {
+            manifest.enable_in_feature(feature, &dep.to_toml().0, dep_syntax)?;
+        }
+    }
+
+    if args.reason.is_some() || args.clear_reason {
+        for (dep, section) in deps
+            .iter()
+            .zip(&dep_sections)
+            .flat_map(|(dep, sections)| sections.iter().map(move |section| (dep, section)))
+        {
+            manifest.set_pin_reason(section, dep.name_in_manifest(), args.reason.as_deref())?;
+        }
+    }
 
-    Ok(())
+    if let Some(header) = settings.table_header() {
+        for table in &created_tables {
+            manifest.add_table_comment(table, header)?;
+        }
+    }
+
+    if let Some((root_path, root)) = &workspace_root {
+        verify_edit(args, root, root_path)?;
+    }
+    verify_edit(args, &manifest, &find(manifest_path)?)?;
+    // A dry run shows the diff, unless it would mix with records or was asked to be quiet.
+    let show_diff =
+        args.show_diff || (args.dry_run && args.message_format != "json" && !args.quiet);
+    if show_diff {
+        if let Some((root_path, root)) = &workspace_root {
+            print!("{}", root.diff(root_path)?);
+        }
+        print!("{}", manifest.diff(&find(manifest_path)?)?);
+    }
+    // The root is written first, so that the member never refers to missing entries.
+    if let (false, Some((root_path, root))) = (args.dry_run, &workspace_root) {
+        root.write_workspace_atomically(root_path)?;
+    }
+    let changed = !args.dry_run && !manifest.is_unchanged(&find(manifest_path)?);
+    if args.dry_run {
+        if args.message_format != "json" && !args.quiet {
+            println!("Dry run, the manifest was not changed.");
+        }
+    } else if !changed {
+        // Leave the file alone, so that its mtime doesn't trigger rebuilds.
+        if args.message_format != "json" && !args.quiet {
+            println!("No changes, the manifest was not written.");
+        }
+    } else if args.workspace || manifest.is_virtual() {
+        manifest.write_workspace_atomically(&find(manifest_path)?)?;
+    } else {
+        manifest.write_atomically(&find(manifest_path)?)?;
+    }
+    if let Some(report_file) = &args.report_file {
+        write_report(report_file, &records)?;
+    }
+    if changed {
+        refresh_vendor_directory(args, &find(manifest_path)?)?;
+    }
+
+    let adds_build_dependencies = args.build || !args.build_crates.is_empty();
+    if adds_build_dependencies
+        && !args.workspace
+        && !args.quiet
+        && args.message_format != "json"
+        && !manifest.has_build_script(&find(manifest_path)?)
+    {
+        println!(
+            "NOTE: Build dependencies are only available to a build script. \
+                 Create `build.rs` next to `Cargo.toml` to use them."
+        );
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    eprintln!(
+        "Added {} of {} crates, these failed:",
+        deps.len(),
+        deps.len() + failures.len()
+    );
+    for (crate_name, err) in &failures {
+        eprintln!("    {}: {}", crate_name, err);
+        for e in err.iter().skip(1) {
+            eprintln!("        caused by: {}", e);
+        }
+    }
+    Err(ErrorKind::FailedToAdd(
+        failures
+            .into_iter()
+            .map(|(crate_name, _)| crate_name)
+            .collect(),
+    )
+    .into())
 }
 
 fn main() {
     let args: Command = Command::from_args();
-    let Command::Add(args) = args;
+    let Command::Add(mut args) = args;
 
-    if let Err(err) = handle_add(&args) {
+    if let Err(err) = args
+        .resolve_package()
+        .and_then(|()| args.resolve_manifest_file_name())
+        .and_then(|()| args.resolve_default_registry())
+        .and_then(|()| args.resolve_req_style())
+        .and_then(|()| handle_add(&args))
+    {
         eprintln!("Command failed due to unhandled error: {}\n", err);
 
         for e in err.iter().skip(1) {
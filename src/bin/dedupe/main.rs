@@ -0,0 +1,356 @@
+//! `cargo dedupe`
+#![warn(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    trivial_casts,
+    trivial_numeric_casts,
+    unsafe_code,
+    unstable_features,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+#[macro_use]
+extern crate error_chain;
+
+use crate::errors::*;
+use cargo_edit::version::ranges;
+use cargo_edit::{
+    compatible_requirement, find, iter_dependencies, table_display, warn, Dependency, LocalManifest,
+};
+use failure::Fail;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::process;
+use structopt::StructOpt;
+
+// `error_chain` implements the deprecated `Error::description` for foreign links.
+#[allow(deprecated)]
+mod errors {
+    error_chain! {
+        links {
+            CargoEditLib(::cargo_edit::Error, ::cargo_edit::ErrorKind);
+        }
+        foreign_links {
+            CargoMetadata(::failure::Compat<::cargo_metadata::Error>);
+            Io(::std::io::Error);
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(bin_name = "cargo")]
+enum Command {
+    /// Find the dependencies that the members of a workspace declare with different version
+    /// requirements, and make them agree on one.
+    #[structopt(name = "dedupe")]
+    #[structopt(
+        after_help = "Every dependency table of the workspace members and `[workspace.dependencies]`
+of the root manifest is scanned. Path and git dependencies, and dependencies inherited with
+`workspace = true`, have no requirement of their own and are left out.
+
+With `--fix`, the requirements of a dependency are rewritten to the newest one of them that the
+others are semver compatible with, e.g. `1.0.100` for `1.0.50` and `1.0.100`. Dependencies whose
+requirements aren't compatible, like `0.2` and `0.3`, are only reported, as agreeing on one would
+be a breaking upgrade for some members.
+
+With `--hoist`, the agreed requirement is moved into `[workspace.dependencies]` of the root
+manifest instead, and the members inherit it with `workspace = true`, keeping their own
+features."
+    )]
+    Dedupe(Args),
+}
+
+#[derive(Debug, StructOpt)]
+struct Args {
+    /// Path to the manifest of the workspace, or the directory containing it.
+    #[structopt(long = "manifest-path", value_name = "path")]
+    manifest_path: Option<PathBuf>,
+
+    /// Rewrite the divergent requirements of each dependency to a single agreed requirement.
+    #[structopt(long = "fix")]
+    fix: bool,
+
+    /// Move the agreed requirements into `[workspace.dependencies]` of the root manifest, and let
+    /// the members inherit them.
+    #[structopt(long = "hoist", requires = "fix")]
+    hoist: bool,
+
+    /// Print the changes to be made without making them.
+    #[structopt(long = "dry-run", requires = "fix")]
+    dry_run: bool,
+}
+
+/// An entry of a dependency table that declares a version requirement
+#[derive(Debug)]
+struct Declaration {
+    /// The manifest declaring it
+    manifest: PathBuf,
+    /// The package of that manifest, or `workspace` for `[workspace.dependencies]`
+    package: String,
+    /// The path of the table it is declared in
+    table: Vec<String>,
+    /// The key of the entry, which differs from the crate name if it is renamed
+    key: String,
+    /// The version requirement
+    requirement: String,
+}
+
+/// The declarations of each dependency, by name and registry, in the manifests of a workspace
+fn find_declarations(
+    manifests: &BTreeMap<PathBuf, (String, LocalManifest)>,
+) -> BTreeMap<(String, Option<String>), Vec<Declaration>> {
+    let mut declarations = BTreeMap::new();
+    for (path, (package, manifest)) in manifests {
+        for (context, dep) in iter_dependencies(manifest) {
+            if dep.path().is_some() || dep.git().is_some() {
+                continue;
+            }
+            let requirement = match dep.version() {
+                Some(requirement) => requirement.to_owned(),
+                None => continue,
+            };
+            declarations
+                .entry((dep.name.clone(), dep.registry().map(String::from)))
+                .or_insert_with(Vec::new)
+                .push(Declaration {
+                    manifest: path.clone(),
+                    package: if context.workspace {
+                        "workspace".to_owned()
+                    } else {
+                        package.clone()
+                    },
+                    table: context.table_path(),
+                    key: dep.name_in_manifest().to_owned(),
+                    requirement,
+                });
+        }
+    }
+    declarations
+}
+
+/// The distinct requirements of `declarations`, the most common one first.
+///
+/// Requirements used as often are ordered like their first declaration.
+fn distinct_requirements(declarations: &[Declaration]) -> Vec<&str> {
+    let mut requirements: Vec<(&str, usize)> = Vec::new();
+    for declaration in declarations {
+        match requirements
+            .iter_mut()
+            .find(|(known, _)| *known == declaration.requirement)
+        {
+            Some((_, count)) => *count += 1,
+            None => requirements.push((&declaration.requirement, 1)),
+        }
+    }
+    // The sort is stable, so ties keep the order of the declarations.
+    requirements.sort_by(|(_, a), (_, b)| b.cmp(a));
+    requirements.into_iter().map(|(req, _)| req).collect()
+}
+
+/// The requirement for `requirements` to agree on: the newest one that every other one is semver
+/// compatible with, or `None` if there is none, e.g. for `0.2` and `0.3`.
+///
+/// A requirement is a candidate if it only allows versions compatible with the lowest version of
+/// each requirement, following `compatible_requirement`. Of the candidates, the one that is a
+/// subset of the others is the newest, or else the most common one is taken.
+fn agreed_requirement(requirements: &[&str]) -> Option<String> {
+    let is_subset = |a: &str, b: &str| ranges::is_subset(a, b).unwrap_or(false);
+    let candidates: Vec<&str> = requirements
+        .iter()
+        .copied()
+        .filter(|a| {
+            requirements
+                .iter()
+                .all(|b| is_subset(a, &compatible_requirement(b)))
+        })
+        .collect();
+    candidates
+        .iter()
+        .find(|a| candidates.iter().all(|b| is_subset(a, b)))
+        .or_else(|| candidates.first())
+        .map(|req| (*req).to_owned())
+}
+
+/// Main processing function. Allows us to return a `Result` so that `main` can print pretty error
+/// messages.
+fn process(args: Args) -> Result<()> {
+    let Args {
+        manifest_path,
+        fix,
+        hoist,
+        dry_run,
+    } = args;
+
+    let mut cmd = cargo_metadata::MetadataCommand::new();
+    cmd.no_deps();
+    if let Some(path) = &manifest_path {
+        cmd.manifest_path(find(&Some(path.clone()))?);
+    }
+    let metadata = cmd
+        .exec()
+        .map_err(|e| Error::from(e.compat()).chain_err(|| "Failed to get workspace metadata"))?;
+
+    let root_manifest_path = metadata.workspace_root.join("Cargo.toml").canonicalize()?;
+    let mut manifests = BTreeMap::new();
+    for package in metadata
+        .packages
+        .iter()
+        .filter(|p| metadata.workspace_members.contains(&p.id))
+    {
+        let path = package.manifest_path.canonicalize()?;
+        let manifest = LocalManifest::try_new(&path)?;
+        manifests.insert(path, (package.name.clone(), manifest));
+    }
+    if !manifests.contains_key(&root_manifest_path) {
+        let root = LocalManifest::try_new(&root_manifest_path)?;
+        manifests.insert(root_manifest_path.clone(), ("workspace".to_owned(), root));
+    }
+
+    let declarations = find_declarations(&manifests);
+    let total = declarations.len();
+    let mut divergent = 0;
+    let mut fixes = Vec::new();
+    for ((name, registry), declarations) in &declarations {
+        let requirements = distinct_requirements(declarations);
+        if requirements.len() < 2 {
+            continue;
+        }
+        divergent += 1;
+
+        println!("{}: {} requirements", name, requirements.len());
+        let width = requirements.iter().map(|req| req.len()).max().unwrap_or(0);
+        for requirement in &requirements {
+            let declared_by = declarations
+                .iter()
+                .filter(|declaration| declaration.requirement == *requirement)
+                .map(|declaration| {
+                    format!(
+                        "{} ({})",
+                        declaration.package,
+                        table_display(&declaration.table)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!(
+                "    {:<width$}  {}",
+                requirement,
+                declared_by,
+                width = width
+            );
+        }
+
+        if !fix {
+            continue;
+        }
+        match agreed_requirement(&requirements) {
+            Some(agreed) => {
+                println!("    agreeing on {}", agreed);
+                fixes.push((name, registry, declarations, agreed));
+            }
+            None => warn(format!(
+                "The requirements of `{}` aren't compatible with each other, upgrade them with \
+                 `cargo upgrade` to agree on one",
+                name
+            ))?,
+        }
+    }
+    println!(
+        "{} of {} dependencies have divergent requirements",
+        divergent, total
+    );
+
+    let mut changed = BTreeSet::new();
+    for (name, registry, declarations, agreed) in fixes {
+        let renamed = declarations
+            .iter()
+            .any(|declaration| declaration.key != *name);
+        if hoist && renamed {
+            warn(format!(
+                "`{}` is renamed by some members, so it is not hoisted into \
+                 `[workspace.dependencies]`",
+                name
+            ))?;
+        }
+        let hoist = hoist && !renamed;
+
+        let mut dependency = Dependency::new(name).set_version(&agreed);
+        if let Some(registry) = registry {
+            dependency = dependency.set_registry(registry);
+        }
+        let workspace_table = ["workspace".to_owned(), "dependencies".to_owned()];
+        if hoist && !declarations.iter().any(|d| d.table == workspace_table) {
+            let (_, root) = manifests
+                .get_mut(&root_manifest_path)
+                .expect("the root manifest is read");
+            root.insert_into_table(&workspace_table, &dependency)?;
+            changed.insert(root_manifest_path.clone());
+        }
+        for declaration in declarations {
+            let (_, manifest) = manifests
+                .get_mut(&declaration.manifest)
+                .expect("the manifests with declarations are read");
+            if hoist && declaration.table != workspace_table {
+                manifest.insert_workspace_reference(&declaration.table, &Dependency::new(name))?;
+            } else if declaration.requirement != agreed {
+                manifest.apply_upgrade_to_entry(
+                    &declaration.table,
+                    &declaration.key,
+                    &Dependency::new(name).set_version(&agreed),
+                )?;
+            } else {
+                continue;
+            }
+            changed.insert(declaration.manifest.clone());
+        }
+    }
+
+    if !dry_run {
+        for path in &changed {
+            manifests[path].1.write()?;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args: Command = Command::from_args();
+    let Command::Dedupe(args) = args;
+
+    if let Err(err) = process(args) {
+        eprintln!("Command failed due to unhandled error: {}\n", err);
+
+        for e in err.iter().skip(1) {
+            eprintln!("Caused by: {}", e);
+        }
+
+        if let Some(backtrace) = err.backtrace() {
+            eprintln!("Backtrace: {:?}", backtrace);
+        }
+
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agree_on_newest_compatible_requirement() {
+        assert_eq!(
+            agreed_requirement(&["1.0.50", "1.0.100", "1"]).as_deref(),
+            Some("1.0.100")
+        );
+        assert_eq!(agreed_requirement(&["~1.2", "1.3"]).as_deref(), Some("1.3"));
+        assert_eq!(
+            agreed_requirement(&["0.3.1", "0.3"]).as_deref(),
+            Some("0.3.1")
+        );
+        assert_eq!(agreed_requirement(&["0.2", "0.3"]), None);
+        assert_eq!(agreed_requirement(&["1", "2"]), None);
+    }
+}
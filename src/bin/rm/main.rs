@@ -14,13 +14,20 @@
 #[macro_use]
 extern crate error_chain;
 
-use cargo_edit::Manifest;
+use cargo_edit::{
+    configure_warnings, dependency_table_path, find, find_named, table_display, warn,
+    DependencyKind, Manifest,
+};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 use structopt::StructOpt;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
+mod usage;
+
+// `error_chain` implements the deprecated `Error::description` for foreign links.
+#[allow(deprecated)]
 mod errors {
     error_chain! {
         links {
@@ -29,6 +36,25 @@ mod errors {
         foreign_links {
             Io(::std::io::Error);
         }
+        errors {
+            /// A pattern given with `--matching` matched no dependency
+            NoMatchingDependency(pattern: String, table: String) {
+                description("no dependency matches the pattern")
+                display("No dependency in `{}` matches `{}`", table, pattern)
+            }
+            /// `--target` was given an empty string
+            EmptyTarget {
+                description("empty target")
+                display("Target specification may not be empty")
+            }
+            /// `--check-usage` found code that still refers to a removed dependency
+            StillUsed(name: String, usages: String) {
+                description("the removed dependency is still used")
+                display("`{}` is still used by the code of the package:\n{}\n\
+                         Remove these references first, or pass `--force` to remove it anyway",
+                        name, usages)
+            }
+        }
     }
 }
 use crate::errors::*;
@@ -44,9 +70,14 @@ enum Command {
 #[derive(Debug, StructOpt)]
 struct Args {
     /// Crates to be removed.
-    #[structopt(name = "crates", required = true)]
+    #[structopt(name = "crates", required_unless = "unused")]
     crates: Vec<String>,
 
+    /// Remove the optional dependencies that no feature refers to. Cargo still gives them an
+    /// implicit feature, so make sure nothing enables it with `--features <crate>`.
+    #[structopt(long = "unused", conflicts_with = "crates", conflicts_with = "dev")]
+    unused: bool,
+
     /// Remove crate as development dependency.
     #[structopt(long = "dev", short = "D", conflicts_with = "build")]
     dev: bool,
@@ -55,26 +86,156 @@ struct Args {
     #[structopt(long = "build", short = "B", conflicts_with = "dev")]
     build: bool,
 
-    /// Path to the manifest to remove a dependency from.
+    /// Remove crate from the dependencies of the given target platform, e.g. `cfg(windows)`.
+    #[structopt(long = "target", value_name = "target")]
+    target: Option<String>,
+
+    /// Remove the override of the crate from `[patch.<source>]`, e.g. `[patch.crates-io]`.
+    #[structopt(
+        long = "patch",
+        value_name = "source",
+        conflicts_with_all = &["dev", "build", "target", "unused"]
+    )]
+    patch: Option<String>,
+
+    /// Treat the crates as glob patterns, where `*` matches any sequence of characters and `?` a
+    /// single one, and remove every dependency whose name matches.
+    #[structopt(long = "matching")]
+    matching: bool,
+
+    /// Refuse to remove a dependency that the code of the package still refers to with `use`,
+    /// `extern crate` or a path: `build.rs` for build dependencies, or else the files in `src/`,
+    /// `tests/`, `benches/` and `examples/`.
+    #[structopt(long = "check-usage", conflicts_with = "patch")]
+    check_usage: bool,
+
+    /// With `--check-usage`, only warn about the references and remove the dependency anyway.
+    #[structopt(long = "force", requires = "check-usage")]
+    force: bool,
+
+    /// Path to the manifest to remove a dependency from, or the directory containing it.
     #[structopt(long = "manifest-path", value_name = "path")]
     manifest_path: Option<PathBuf>,
 
+    /// Look for manifests with this file name instead of `Cargo.toml`, e.g. for templates like
+    /// `Cargo.toml.in` that follow the structure of a Cargo manifest.
+    #[structopt(long = "manifest-file-name", value_name = "name")]
+    manifest_file_name: Option<String>,
+
+    /// Keep only the last entry of dependencies that are listed more than once in the same
+    /// table, instead of refusing to edit the manifest.
+    #[structopt(long = "merge-duplicates")]
+    merge_duplicates: bool,
+
+    /// Print a unified diff of the changes to the manifest.
+    #[structopt(long = "show-diff")]
+    show_diff: bool,
+
+    /// Print a unified diff of what would be removed without changing the manifest, or only the
+    /// messages with `--quiet` or `--message-format json`.
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+
+    /// Skip re-parsing the edited manifest before writing it, which is otherwise done to make
+    /// sure the edit produced a valid manifest.
+    #[structopt(long = "no-verify")]
+    no_verify: bool,
+
     /// Do not print any output in case of success.
     #[structopt(long = "quiet", short = "q")]
     quiet: bool,
+
+    /// How to report each removed dependency. `json` prints one JSON record per crate, whose
+    /// `schema` field is bumped when the format changes.
+    #[structopt(
+        long = "message-format",
+        visible_alias = "output-format",
+        value_name = "fmt",
+        possible_value = "human",
+        possible_value = "json"
+    )]
+    message_format: Option<String>,
+
+    /// Turn warnings into errors, e.g. for CI.
+    #[structopt(long = "strict")]
+    strict: bool,
 }
 
 impl Args {
-    /// Get depenency section
-    pub fn get_section(&self) -> &'static str {
+    /// Resolve `--manifest-file-name` to the path of the manifest, so that all further lookups
+    /// use it.
+    fn resolve_manifest_file_name(&mut self) -> Result<()> {
+        if let Some(file_name) = &self.manifest_file_name {
+            self.manifest_path = Some(find_named(&self.manifest_path, file_name)?);
+        }
+        Ok(())
+    }
+
+    /// Get the kind of the dependencies to remove
+    fn kind(&self) -> DependencyKind {
         if self.dev {
-            "dev-dependencies"
+            DependencyKind::Development
         } else if self.build {
-            "build-dependencies"
+            DependencyKind::Build
         } else {
-            "dependencies"
+            DependencyKind::Normal
         }
     }
+
+    /// Get the path of the dependency table, e.g. `["target", "cfg(unix)", "dependencies"]`
+    fn get_table_path(&self) -> Vec<String> {
+        match &self.patch {
+            Some(source) => vec!["patch".to_owned(), source.clone()],
+            None => dependency_table_path(self.kind(), self.target.as_deref()),
+        }
+    }
+
+    /// Get depenency section, e.g. `target.'cfg(unix)'.dependencies`
+    pub fn get_section(&self) -> String {
+        match (&self.patch, &self.target) {
+            (Some(source), _) if is_bare_key(source) => format!("patch.{}", source),
+            (Some(source), _) => format!("patch.'{}'", source),
+            (None, Some(target)) => format!("target.'{}'.{}", target, self.kind().table_name()),
+            (None, None) => self.kind().table_name().to_owned(),
+        }
+    }
+
+    /// Whether the human-oriented messages are printed, instead of nothing or JSON records.
+    fn is_human(&self) -> bool {
+        !self.quiet && !self.is_json()
+    }
+
+    /// Whether JSON records are printed, with `--message-format json`.
+    fn is_json(&self) -> bool {
+        self.message_format.as_deref() == Some("json")
+    }
+}
+
+/// Whether `key` can be written in a TOML table header without quotes.
+fn is_bare_key(key: &str) -> bool {
+    key.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The version of the records printed with `--message-format json`
+const MESSAGE_SCHEMA: u32 = 1;
+
+/// Describe the removal of the `entry` called `key` from `section` as a JSON record, e.g.
+///
+/// ```json
+/// {"schema":1,"crate":"serde","name":"serde","table":["dependencies"],"action":"remove",
+///  "old_requirement":"1.0","requirement":null}
+/// ```
+fn json_record(key: &str, table_path: &[String], entry: &toml_edit::Item) -> serde_json::Value {
+    serde_json::json!({
+        "schema": MESSAGE_SCHEMA,
+        "crate": entry["package"].as_str().unwrap_or(key),
+        "name": key,
+        "table": table_path,
+        "action": "remove",
+        "old_requirement": entry.as_str().or_else(|| entry["version"].as_str()),
+        "requirement": null,
+    })
 }
 
 fn print_msg(name: &str, section: &str) -> Result<()> {
@@ -91,19 +252,165 @@ fn print_msg(name: &str, section: &str) -> Result<()> {
     Ok(())
 }
 
+/// The most references to a removed dependency that are listed
+const MAX_LISTED_USAGES: usize = 5;
+
+/// Check the code of the package at `package_root` for references to the removed dependency
+/// called `key`, and fail if there are any, or only warn with `--force`.
+fn check_usage(args: &Args, package_root: &Path, key: &str) -> Result<()> {
+    let usages = usage::find_usages(package_root, key, args.build)?;
+    if usages.is_empty() {
+        return Ok(());
+    }
+    let mut listed = usages
+        .iter()
+        .take(MAX_LISTED_USAGES)
+        .map(|usage| {
+            format!(
+                "    {}:{}: {}",
+                usage.path.display(),
+                usage.line,
+                usage.text
+            )
+        })
+        .collect::<Vec<_>>();
+    if usages.len() > MAX_LISTED_USAGES {
+        listed.push(format!("    and {} more", usages.len() - MAX_LISTED_USAGES));
+    }
+    let listed = listed.join("\n");
+    if args.force {
+        warn(format!(
+            "`{}` is still used by the code of the package:\n{}",
+            key, listed
+        ))?;
+        return Ok(());
+    }
+    Err(ErrorKind::StillUsed(key.to_owned(), listed).into())
+}
+
 fn handle_rm(args: &Args) -> Result<()> {
+    // Warnings would break up the JSON records on stdout.
+    configure_warnings(args.strict, args.quiet || args.is_json());
+    if args.target.as_deref() == Some("") {
+        return Err(ErrorKind::EmptyTarget.into());
+    }
+    let table_path = args.get_table_path();
     let manifest_path = &args.manifest_path;
-    let mut manifest = Manifest::open(manifest_path)?;
-    let deps = &args.crates;
+    let mut manifest = if args.merge_duplicates {
+        let (manifest, merged) = Manifest::open_merging_duplicates(manifest_path)?;
+        for (table, name) in merged {
+            warn(format!(
+                "`{}` was declared more than once in `{}`, keeping the last declaration",
+                name, table
+            ))?;
+        }
+        manifest
+    } else {
+        Manifest::open(manifest_path)?
+    };
+    let deps = if args.matching {
+        let mut deps = Vec::new();
+        for pattern in &args.crates {
+            let matched = manifest.matching_dependencies(&table_path, pattern);
+            if matched.is_empty() {
+                return Err(
+                    ErrorKind::NoMatchingDependency(pattern.clone(), args.get_section()).into(),
+                );
+            }
+            for name in matched {
+                if !deps.contains(&name) {
+                    deps.push(name);
+                }
+            }
+        }
+        deps
+    } else if args.unused {
+        let deps = manifest.unreferenced_optional_dependencies(&table_path);
+        if deps.is_empty() && args.is_human() {
+            println!(
+                "No optional dependency in `{}` is unused",
+                args.get_section()
+            );
+        }
+        deps
+    } else {
+        args.crates.clone()
+    };
 
     deps.iter()
         .map(|dep| {
-            if !args.quiet {
-                print_msg(&dep, args.get_section())?;
+            let before = manifest.table_at(&table_path).clone();
+            let key = manifest.remove_from_table(&table_path, dep)?;
+            // The code may still use the dependency through another table it can see, e.g. a
+            // normal dependency that is removed from the development dependencies.
+            let still_visible = manifest
+                .get_sections()
+                .iter()
+                .filter(|(path, _)| {
+                    path.last().is_some_and(|table| table.starts_with("build")) == args.build
+                })
+                .any(|(_, table)| !table[key.as_str()].is_none());
+            if args.check_usage && !still_visible {
+                let manifest_file = find(manifest_path)?;
+                let package_root = manifest_file.parent().unwrap_or_else(|| Path::new("."));
+                check_usage(args, package_root, &key)?;
+            }
+
+            if args.is_json() {
+                println!("{}", json_record(&key, &table_path, &before[key.as_str()]));
+            }
+            if args.is_human() {
+                print_msg(dep, &args.get_section())?;
+                if key != *dep {
+                    println!("    Matched `{}` by its package name", key);
+                }
+            }
+
+            // Overrides are no dependencies of the package, so its features are left alone.
+            if args.patch.is_some() {
+                return Ok(());
+            }
+
+            if args.is_human() {
+                for (path, table) in manifest.get_sections() {
+                    if !table[key.as_str()].is_none() {
+                        println!(
+                            "    `{}` is still declared in `{}`",
+                            key,
+                            table_display(&path)
+                        );
+                    }
+                }
             }
-            manifest
-                .remove_from_table(args.get_section(), dep)
-                .map_err(Into::into)
+
+            // Features can only refer to normal and build dependencies, of any target.
+            let still_used = manifest
+                .get_sections()
+                .iter()
+                .filter(|(path, _)| !path.last().is_some_and(|table| table.starts_with("dev")))
+                .any(|(_, table)| !table[key.as_str()].is_none());
+            if !args.dev && !still_used {
+                let features = manifest.remove_from_features(&key);
+                if args.is_human() {
+                    for feature in features {
+                        println!("    Removed `{}` from feature `{}`", key, feature);
+                    }
+                }
+            }
+
+            let declared_anywhere = manifest
+                .get_sections()
+                .iter()
+                .any(|(_, table)| !table[key.as_str()].is_none());
+            if !declared_anywhere {
+                for (table, name, value) in manifest.dependency_feature_references(&key) {
+                    warn(format!(
+                        "`{}` in `{}` enables `{}`, which refers to the removed dependency `{}`",
+                        name, table, value, key
+                    ))?;
+                }
+            }
+            Ok(())
         })
         .collect::<Result<Vec<_>>>()
         .map_err(|err| {
@@ -111,6 +418,19 @@ fn handle_rm(args: &Args) -> Result<()> {
             err
         })?;
 
+    if !args.no_verify {
+        manifest.verify()?;
+    }
+    // A dry run shows the diff, unless it would mix with records or was asked to be quiet.
+    if args.show_diff || (args.dry_run && args.is_human()) {
+        print!("{}", manifest.diff(&find(manifest_path)?)?);
+    }
+    if args.dry_run {
+        if args.is_human() {
+            println!("Dry run, the manifest was not changed.");
+        }
+        return Ok(());
+    }
     let mut file = Manifest::find_file(manifest_path)?;
     manifest.write_to_file(&mut file)?;
 
@@ -119,9 +439,12 @@ fn handle_rm(args: &Args) -> Result<()> {
 
 fn main() {
     let args: Command = Command::from_args();
-    let Command::Rm(args) = args;
+    let Command::Rm(mut args) = args;
 
-    if let Err(err) = handle_rm(&args) {
+    if let Err(err) = args
+        .resolve_manifest_file_name()
+        .and_then(|()| handle_rm(&args))
+    {
         eprintln!("Command failed due to unhandled error: {}\n", err);
 
         for e in err.iter().skip(1) {
@@ -0,0 +1,155 @@
+//! Find the code of a package that still refers to a dependency, for `--check-usage`
+use crate::errors::*;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A line of code referring to a dependency
+#[derive(Debug, Clone, PartialEq)]
+pub struct Usage {
+    /// The file, relative to the package root
+    pub path: PathBuf,
+    /// The number of the line, counting from 1
+    pub line: usize,
+    /// The line, without surrounding whitespace
+    pub text: String,
+}
+
+/// The directories of a package whose code can use its normal and development dependencies
+const TARGET_DIRS: &[&str] = &["src", "tests", "benches", "examples"];
+
+/// Get the Rust files of the package at `package_root` that could use a dependency: `build.rs` for
+/// build dependencies, or else the files in `src/`, `tests/`, `benches/` and `examples/`.
+fn source_files(package_root: &Path, build: bool) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if build {
+        let build_script = package_root.join("build.rs");
+        if build_script.is_file() {
+            files.push(build_script);
+        }
+        return Ok(files);
+    }
+    for dir in TARGET_DIRS {
+        collect_rust_files(&package_root.join(dir), &mut files)?;
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn collect_rust_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_rust_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// A pattern matching the references to the dependency called `key` in the manifest: `use` and
+/// `extern crate` items, and paths starting with it. The code refers to it with `_` instead of
+/// `-`, under the name it is renamed to if it is.
+fn usage_pattern(key: &str) -> Regex {
+    let name = regex::escape(&key.replace('-', "_"));
+    Regex::new(&format!(
+        r"\bextern\s+crate\s+{name}\b|\buse\s+(?:::)?{name}\b|(?:^|[^\w:])(?:::)?{name}\s*::",
+        name = name
+    ))
+    .unwrap()
+}
+
+/// Find the lines of the code of the package at `package_root` that refer to the dependency
+/// called `key`, a build dependency if `build` is set. Line comments are skipped.
+pub fn find_usages(package_root: &Path, key: &str, build: bool) -> Result<Vec<Usage>> {
+    let pattern = usage_pattern(key);
+    let mut usages = Vec::new();
+    for file in source_files(package_root, build)? {
+        let content = fs::read_to_string(&file)
+            .chain_err(|| format!("Failed to read `{}`", file.display()))?;
+        let path = file.strip_prefix(package_root).unwrap_or(&file);
+        for (index, line) in content.lines().enumerate() {
+            let code = line.trim();
+            if code.starts_with("//") || !pattern.is_match(code) {
+                continue;
+            }
+            usages.push(Usage {
+                path: path.to_owned(),
+                line: index + 1,
+                text: code.to_owned(),
+            });
+        }
+    }
+    Ok(usages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_references_to_a_crate() {
+        let pattern = usage_pattern("rustc-serialize");
+        for code in &[
+            "extern crate rustc_serialize;",
+            "use rustc_serialize::json;",
+            "use ::rustc_serialize::{Decodable, Encodable};",
+            "pub use rustc_serialize as serialize;",
+            "#[derive(rustc_serialize::RustcDecodable)]",
+            "let value = rustc_serialize::json::decode(&input)?;",
+        ] {
+            assert!(pattern.is_match(code), "`{}` should match", code);
+        }
+        for code in &[
+            "use crate::rustc_serialize::json;",
+            "let rustc_serialize = 1;",
+            "use my_rustc_serialize::json;",
+            "self::rustc_serialize::decode();",
+        ] {
+            assert!(!pattern.is_match(code), "`{}` should not match", code);
+        }
+    }
+
+    #[test]
+    fn find_usages_in_package() {
+        let package = tempdir::TempDir::new("rm-usage").unwrap();
+        let root = package.path();
+        fs::create_dir_all(root.join("src").join("bin")).unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::write(
+            root.join("src").join("lib.rs"),
+            "// docopt::Docopt is gone\nuse pad::PadStr;\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("src").join("bin").join("main.rs"),
+            "fn main() {\n    let args = docopt::Docopt::new(USAGE);\n}\n",
+        )
+        .unwrap();
+        fs::write(root.join("tests").join("it.rs"), "extern crate docopt;\n").unwrap();
+        fs::write(root.join("build.rs"), "use docopt;\n").unwrap();
+
+        let usages = find_usages(root, "docopt", false).unwrap();
+        assert_eq!(
+            usages,
+            vec![
+                Usage {
+                    path: Path::new("src").join("bin").join("main.rs"),
+                    line: 2,
+                    text: "let args = docopt::Docopt::new(USAGE);".to_owned(),
+                },
+                Usage {
+                    path: Path::new("tests").join("it.rs"),
+                    line: 1,
+                    text: "extern crate docopt;".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(find_usages(root, "docopt", true).unwrap().len(), 1);
+        assert!(find_usages(root, "semver", false).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,349 @@
+//! `cargo set-version`
+#![warn(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    trivial_casts,
+    trivial_numeric_casts,
+    unsafe_code,
+    unstable_features,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+#[macro_use]
+extern crate error_chain;
+
+use crate::errors::*;
+use cargo_edit::version::bump::{bump_version, BumpLevel};
+use cargo_edit::{find, parse_rust_version, LocalManifest};
+use failure::Fail;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use structopt::StructOpt;
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+// `error_chain` implements the deprecated `Error::description` for foreign links.
+#[allow(deprecated)]
+mod errors {
+    error_chain! {
+        links {
+            CargoEditLib(::cargo_edit::Error, ::cargo_edit::ErrorKind);
+        }
+        foreign_links {
+            CargoMetadata(::failure::Compat<::cargo_metadata::Error>);
+            Io(::std::io::Error);
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(bin_name = "cargo")]
+enum Command {
+    /// Change a package's version in the local manifest file (i.e. Cargo.toml).
+    #[structopt(name = "set-version")]
+    #[structopt(
+        after_help = "Members that inherit their version from the workspace (`version.workspace = true`)
+are not modified. Instead, `version` in the `[workspace.package]` table of the root manifest is
+changed, which affects all members inheriting it. The same applies to `rust-version` when it is
+changed with `--rust-version`.
+
+All packages in the workspace will be modified if the `--workspace` flag is supplied. The
+`--workspace` flag may be supplied in the presence of a virtual manifest.
+
+Path dependencies of workspace members on a package whose version changed keep their version
+requirement in sync, e.g. `version = \"=0.1.0\"` becomes `version = \"=0.2.0\"`. Path
+dependencies without a version requirement are not given one."
+    )]
+    SetVersion(Args),
+}
+
+#[derive(Debug, StructOpt)]
+struct Args {
+    /// Version to change manifests to, e.g. `1.2.3` or `1.2.3-beta.1+build.5`.
+    #[structopt(
+        parse(try_from_str = parse_version),
+        required_unless_one = &["rust-version", "bump"],
+        conflicts_with = "bump"
+    )]
+    target: Option<String>,
+
+    /// Bump the current version instead of setting it: `major`, `minor` or `patch` release it,
+    /// `rc` makes the next release candidate, e.g. `1.2.4-rc.1` from `1.2.3`.
+    #[structopt(
+        long = "bump",
+        value_name = "level",
+        possible_values = &["major", "minor", "patch", "rc"]
+    )]
+    bump: Option<BumpLevel>,
+
+    /// Minimum supported Rust version to change manifests to, e.g. `1.70`.
+    #[structopt(
+        long = "rust-version",
+        value_name = "version",
+        parse(try_from_str = parse_rust_version_arg)
+    )]
+    rust_version: Option<String>,
+
+    /// Path to the manifest to modify, or the directory containing it.
+    #[structopt(long = "manifest-path", value_name = "path")]
+    manifest_path: Option<PathBuf>,
+
+    /// Package to modify, can be given several times.
+    #[structopt(
+        short = "p",
+        long = "package",
+        value_name = "member",
+        number_of_values = 1,
+        conflicts_with = "workspace"
+    )]
+    package: Vec<String>,
+
+    /// Modify all packages in the workspace.
+    #[structopt(long = "workspace", alias = "all")]
+    workspace: bool,
+
+    /// Print changes to be made without making them.
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+}
+
+/// Check that `version` is a valid semver version, keeping it as written so that its prerelease
+/// and build metadata are preserved exactly.
+fn parse_version(version: &str) -> Result<String> {
+    semver::Version::parse(version).chain_err(|| {
+        format!(
+            "`{}` is not a valid semver version, expected e.g. `1.2.3` or `1.2.3-beta.1+build.5`",
+            version
+        )
+    })?;
+    Ok(version.to_owned())
+}
+
+/// The version to change `current` to: `target` if given, else `current` bumped at `bump`.
+fn new_version(
+    current: Option<&str>,
+    target: &Option<String>,
+    bump: Option<BumpLevel>,
+) -> Result<String> {
+    if let Some(target) = target {
+        return Ok(target.clone());
+    }
+    let bump = bump.expect("either a target or a bump level is required");
+    let current = current.chain_err(|| "The package has no version to bump")?;
+    let current = semver::Version::parse(current).chain_err(|| {
+        format!(
+            "The current version `{}` is not a valid semver version",
+            current
+        )
+    })?;
+    Ok(bump_version(&current, bump).to_string())
+}
+
+/// Check that `version` is a Rust version such as `1.70` or `1.70.0`, keeping it as written.
+fn parse_rust_version_arg(version: &str) -> Result<String> {
+    parse_rust_version(version)?;
+    Ok(version.to_owned())
+}
+
+fn print_msg(name: &str, old: Option<&str>, new: &str) -> Result<()> {
+    let colorchoice = if atty::is(atty::Stream::Stdout) {
+        ColorChoice::Auto
+    } else {
+        ColorChoice::Never
+    };
+    let mut output = StandardStream::stdout(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+    write!(output, "{:>12}", "Upgrading")?;
+    output.reset()?;
+    match old {
+        Some(old) => writeln!(output, " {} from {} to {}", name, old, new)?,
+        None => writeln!(output, " {} to {}", name, new)?,
+    }
+    Ok(())
+}
+
+/// Main processing function. Allows us to return a `Result` so that `main` can print pretty error
+/// messages.
+fn process(args: Args) -> Result<()> {
+    let Args {
+        target,
+        bump,
+        rust_version,
+        manifest_path,
+        package: selected,
+        workspace,
+        dry_run,
+    } = args;
+    let changes_version = target.is_some() || bump.is_some();
+
+    let mut cmd = cargo_metadata::MetadataCommand::new();
+    cmd.no_deps();
+    if let Some(path) = &manifest_path {
+        cmd.manifest_path(path);
+    }
+    let metadata = cmd
+        .exec()
+        .map_err(|e| Error::from(e.compat()).chain_err(|| "Failed to get workspace metadata"))?;
+
+    let cargo_metadata::Metadata {
+        packages,
+        workspace_members,
+        workspace_root,
+        ..
+    } = metadata;
+
+    let members = packages
+        .into_iter()
+        .filter(|p| workspace_members.contains(&p.id))
+        .collect::<Vec<_>>();
+    let packages = if workspace {
+        members.clone()
+    } else if !selected.is_empty() {
+        selected
+            .iter()
+            .map(|name| {
+                members
+                    .iter()
+                    .find(|p| p.name == *name)
+                    .cloned()
+                    .chain_err(|| format!("`{}` is not a member of the workspace", name))
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        let resolved_manifest_path = find(&manifest_path)?.canonicalize()?;
+        let package = members
+            .iter()
+            .find(|&p| {
+                p.manifest_path
+                    .canonicalize()
+                    .map(|path| path == resolved_manifest_path)
+                    .unwrap_or(false)
+            })
+            .cloned()
+            // If we have successfully got metadata, but our manifest path does not correspond to a
+            // package, we must have been called against a virtual manifest.
+            .chain_err(|| {
+                "Found virtual manifest, but this command requires running against an \
+                 actual package in this workspace. Try adding `--workspace`."
+            })?;
+        vec![package]
+    };
+
+    let root_manifest_path = workspace_root.join("Cargo.toml");
+    let mut root_manifest = LocalManifest::try_new(&root_manifest_path)?;
+    let mut root_changed = false;
+    let mut inherited = false;
+    let mut rust_version_inherited = false;
+    // The new versions of packages, whose dependents are updated at the end
+    let mut new_versions = Vec::new();
+
+    for package in packages {
+        let is_root = Path::new(&package.manifest_path) == root_manifest_path.as_path();
+        let mut manifest = if is_root {
+            None
+        } else {
+            Some(LocalManifest::try_new(Path::new(&package.manifest_path))?)
+        };
+        let manifest = manifest.as_mut().unwrap_or(&mut root_manifest);
+
+        let mut changed = false;
+        if changes_version {
+            if manifest.is_version_inherited() {
+                // The shared version is updated once, below.
+                inherited = true;
+            } else {
+                let old = manifest.data["package"]["version"].as_str();
+                let version = new_version(old, &target, bump)
+                    .chain_err(|| format!("Failed to bump the version of {}", package.name))?;
+                print_msg(&package.name, old, &version)?;
+                manifest.set_package_version(&version);
+                new_versions.push((package.name.clone(), version));
+                changed = true;
+            }
+        }
+        if let Some(rust_version) = &rust_version {
+            if manifest.is_rust_version_inherited() {
+                rust_version_inherited = true;
+            } else {
+                print_msg(
+                    &format!("{} rust-version", package.name),
+                    manifest.rust_version(),
+                    rust_version,
+                )?;
+                manifest.set_package_rust_version(rust_version);
+                changed = true;
+            }
+        }
+
+        if !changed {
+            continue;
+        }
+        if is_root {
+            root_changed = true;
+        } else if !dry_run {
+            manifest.write()?;
+        }
+    }
+
+    if inherited {
+        let old = root_manifest.data["workspace"]["package"]["version"].as_str();
+        let version = new_version(old, &target, bump)
+            .chain_err(|| "Failed to bump the version of workspace.package")?;
+        print_msg("workspace.package", old, &version)?;
+        root_manifest.set_workspace_package_version(&version)?;
+        root_changed = true;
+
+        // Every member inheriting the version changes, not only the selected ones.
+        for member in &members {
+            if LocalManifest::try_new(Path::new(&member.manifest_path))?.is_version_inherited() {
+                new_versions.push((member.name.clone(), version.clone()));
+            }
+        }
+    }
+
+    if let (true, Some(rust_version)) = (rust_version_inherited, &rust_version) {
+        print_msg(
+            "workspace.package rust-version",
+            root_manifest.data["workspace"]["package"]["rust-version"].as_str(),
+            rust_version,
+        )?;
+        root_manifest.set_workspace_package_rust_version(rust_version)?;
+        root_changed = true;
+    }
+
+    if root_changed && !dry_run {
+        root_manifest.write()?;
+    }
+
+    for member in &members {
+        let mut manifest = LocalManifest::try_new(Path::new(&member.manifest_path))?;
+        for (name, version) in &new_versions {
+            if *name != member.name {
+                manifest.sync_path_dependency_requirement(name, version, dry_run)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args: Command = Command::from_args();
+    let Command::SetVersion(args) = args;
+
+    if let Err(err) = process(args) {
+        eprintln!("Command failed due to unhandled error: {}\n", err);
+
+        for e in err.iter().skip(1) {
+            eprintln!("Caused by: {}", e);
+        }
+
+        if let Some(backtrace) = err.backtrace() {
+            eprintln!("Backtrace: {:?}", backtrace);
+        }
+
+        process::exit(1);
+    }
+}
@@ -15,21 +15,93 @@
 extern crate error_chain;
 
 use crate::errors::*;
+use cargo_edit::version::ranges;
+use cargo_edit::version::style::RequirementStyle;
 use cargo_edit::{
-    find, get_latest_dependency, registry_url, update_registry_index, CrateName, Dependency,
-    LocalManifest,
+    changelog_url, compatible_requirement, configure_index_cache, configure_offline,
+    configure_warnings, default_registry_name, find, get_advisory_safe_dependency_from_source,
+    get_default_features_change, get_default_timeout, get_latest_dependency,
+    get_latest_dependency_and_versions_from_source, get_latest_dependency_compatible_from_source,
+    get_latest_dependency_from_source, get_latest_dependency_matching_from_source,
+    get_latest_git_commit, get_latest_git_tag, get_minimal_dependency_from_source,
+    get_repository_from_source, get_yank_safe_dependency_from_source,
+    get_yanked_selection_from_source, hide_status_messages, index_is_stale, is_offline, is_quiet,
+    iter_dependency_sources, lookup_concurrently, parse_rust_version, pinned_reason, registry_url,
+    registry_url_lenient, requirement_changes, revendor, source_records, table_display,
+    update_registry_index, update_registry_index_quietly, upstream_registry_url, vendor_directory,
+    verify_version_exists, warn, workspace_root_path, write_manifests_atomically, write_report,
+    AdvisoryDatabase, CrateName, DefaultFeaturesChange, Dependency, GitReference, LocalManifest,
+    RegistryIndex, VersionSource, DEFAULT_LOOKUP_JOBS,
 };
 use failure::Fail;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 use structopt::StructOpt;
 use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
 use url::Url;
 
+// `error_chain` implements the deprecated `Error::description` for foreign links.
+#[allow(deprecated)]
 mod errors {
     error_chain! {
+        errors {
+            /// An upgrade would require a different version than the one in the lock file.
+            LockedVersionIncompatible(name: String, locked: String, req: String) {
+                description("Upgrade is incompatible with the locked version")
+                display("Upgrading `{}` to `{}` is incompatible with the locked version {}, \
+                         which `--frozen` forbids", name, req, locked)
+            }
+            /// `--check` found dependencies with newer incompatible versions.
+            OutdatedDependencies(count: usize) {
+                description("Dependencies are outdated")
+                display("{} dependencies are outdated", count)
+            }
+            /// `--dry-run --exit-code` found requirements that would be upgraded.
+            PendingUpgrades(count: usize) {
+                description("Requirements would be upgraded")
+                display("{} requirements would be upgraded", count)
+            }
+            /// `--audit-yanks` found dependencies whose selected version is yanked.
+            YankedSelections(count: usize) {
+                description("Dependencies select yanked versions")
+                display("{} dependencies select a yanked version", count)
+            }
+            /// A package passed to `--package` or `--exclude-member` is not in the workspace.
+            UnknownMember(name: String) {
+                description("Package is not a member of the workspace")
+                display("`{}` is not a member of the workspace", name)
+            }
+            /// The crate passed to `--compatible-with` is not a dependency.
+            UnknownPeerDependency(name: String) {
+                description("Peer crate is not a dependency")
+                display("`{}` is not a dependency of the selected manifests", name)
+            }
+            /// `--interactive` needs a terminal to ask on.
+            NotInteractive {
+                description("stdin is not a terminal")
+                display("`--interactive` requires stdin to be a terminal")
+            }
+            /// There is no lock file to check upgrades against.
+            MissingLockfile {
+                description("Cargo.lock not found")
+                display("`--frozen` requires a Cargo.lock")
+            }
+            /// `--precise` was not given exactly one dependency without a version.
+            PreciseNeedsOneDependency {
+                description("`--precise` needs one dependency")
+                display("`--precise` upgrades exactly one dependency, given without a version")
+            }
+            /// The version given to `--precise` is not compatible with the current requirement.
+            PreciseIncompatible(name: String, version: String, req: String) {
+                description("Precise version is incompatible with the requirement")
+                display("`{}` {} is not semver compatible with the requirement `{}`, use \
+                         --incompatible to upgrade to it anyway", name, version, req)
+            }
+        }
         links {
             CargoEditLib(::cargo_edit::Error, ::cargo_edit::ErrorKind);
         }
@@ -57,10 +129,75 @@ supported. Git/path dependencies will be ignored.
 All packages in the workspace will be upgraded if the `--all` flag is supplied. The `--all` flag may
 be supplied in the presence of a virtual manifest.
 
+With `-p <member>`, only the named packages of the workspace are upgraded, and with
+`--exclude-member <member>` all packages except the named ones. Members are found with `cargo
+metadata`, so nested workspaces are handled like cargo does.
+
+If the manifest sets `package.rust-version`, or inherits it from `workspace.package`, only versions
+that support this Rust version are considered, and a warning names the newer release that was
+skipped. Use `--rust-version` to check against another Rust version, `--rust-version active` to
+check against the version of the active toolchain's `rustc`, or `--ignore-rust-version` to consider
+all versions.
+
+Dependencies with an exact (`=`) or pre-release requirement are considered pinned and are only
+upgraded if the `--force` flag is supplied or an explicit version is requested for them.
+
+Dependencies with a `*` requirement already match the latest version, so they are left alone and
+only their latest version is reported. With `--pin-wildcards`, `*` is replaced with a requirement
+for the latest version.
+
+With '--dry-run --exit-code', nothing is changed either, but the exit status is 2 if any
+requirement would be upgraded, e.g. to check in CI that the manifests are up to date.
+
+If the '--check' flag is supplied, nothing is changed. Dependencies whose latest version doesn't
+match their requirement are listed instead, and the exit status is 2 if there are any. Together
+with '--quiet', only the exit status is reported, e.g. for use in a pre-commit hook.
+
+If the '--minimal' flag is supplied, each requirement is set to the lowest version that still
+satisfies it, skipping yanked versions. This helps to catch lower bounds that are too low.
+
+Requirements that only match yanked versions are reported. With '--fix-yanked', only those are
+upgraded, to the next version that isn't yanked. With '--allow-yanked', yanked versions are
+considered like any other instead.
+
+With '--to <req>', dependencies are upgraded to the latest version that also satisfies `req`, e.g.
+`cargo upgrade foo --to '<2'` to stay below `2.0.0`.
+
+With '--compatible-with <crate>', dependencies are upgraded to the latest version whose dependency
+on `crate` accepts the version of it locked in Cargo.lock, or its requirement if it isn't locked.
+This only checks the registry index entries of the upgraded dependencies, not the whole dependency
+graph.
+
+Upgrades to a version that is incompatible with the current requirement have to be confirmed for
+each crate, unless the `--yes` or `--skip-compatible` flag is supplied. When not running in a
+terminal, they are skipped with a warning instead, unless `CARGO_EDIT_INTERACTIVE` is set to read
+the answers from stdin anyway.
+
+With '--interactive', every upgrade is shown with the current requirement, the latest compatible
+version and the version it would be upgraded to, and nothing is written before each one is
+answered: `y` to upgrade, `n` to skip, `c` to upgrade to the latest compatible version, or another
+version to upgrade to that one.
+
 If the '--to-lockfile' flag is supplied, all dependencies will be upgraded to the currently locked
 version as recorded in the Cargo.lock file. This flag requires that the Cargo.lock file is
 up-to-date. If the lock file is missing, or it needs to be updated, cargo-upgrade will exit with an
-error. If the '--to-lockfile' flag is supplied then the network won't be accessed."
+error. If the '--to-lockfile' flag is supplied then the network won't be accessed.
+
+The registry indices are updated before the latest versions are looked up, unless
+'--no-update' is supplied. With '--locked-index', they are used exactly as they are on disk, and
+crates missing from them fail to resolve, so that repeated runs pick the same versions.
+
+If the '--registry' flag is supplied, only dependencies from the named registry are upgraded. Use
+`--registry crates-io` to upgrade only the dependencies without an explicit registry.
+
+Dependencies without an explicit registry are looked up in the registry named by
+`registry.default` in the cargo config (or `CARGO_REGISTRY_DEFAULT`), or in crates.io if it is not
+set.
+Dependencies with a `registry` key are looked up in that registry, with a git or a sparse index.
+
+If the '--to-registry' flag is supplied, path dependencies on crates that are available on the
+registry get a version requirement for their latest version, as needed for publishing. The path is
+kept for local development."
     )]
     Upgrade(Args),
 }
@@ -70,46 +207,550 @@ struct Args {
     /// Crates to be upgraded.
     dependency: Vec<String>,
 
-    /// Path to the manifest to upgrade
+    /// Path to the manifest to upgrade, or the directory containing it
     #[structopt(long = "manifest-path", value_name = "path")]
     manifest_path: Option<PathBuf>,
 
     /// Upgrade all packages in the workspace.
-    #[structopt(long = "all")]
+    #[structopt(long = "all", visible_alias = "workspace")]
     all: bool,
 
+    /// Package of the workspace to upgrade, can be given several times.
+    #[structopt(
+        short = "p",
+        long = "package",
+        value_name = "member",
+        number_of_values = 1,
+        conflicts_with = "all"
+    )]
+    package: Vec<String>,
+
+    /// Upgrade all packages in the workspace except this one, can be given several times.
+    #[structopt(
+        long = "exclude-member",
+        value_name = "member",
+        number_of_values = 1,
+        conflicts_with = "package"
+    )]
+    exclude_member: Vec<String>,
+
+    /// Never touch this dependency, e.g. to hold it back on purpose. Dependencies listed in `skip`
+    /// of `[package.metadata.cargo-edit.upgrade]` are left alone as well.
+    #[structopt(long = "exclude", value_name = "crate", number_of_values = 1)]
+    exclude: Vec<String>,
+
     /// Include prerelease versions when fetching from crates.io (e.g. 0.6.0-alpha').
     #[structopt(long = "allow-prerelease")]
     allow_prerelease: bool,
 
+    /// Only consider prereleases on this channel with `--allow-prerelease`, i.e. those whose
+    /// prerelease identifier starts with it, such as `rc` for `1.0.0-rc.1`.
+    #[structopt(
+        long = "prerelease-channel",
+        value_name = "channel",
+        requires = "allow-prerelease",
+        conflicts_with = "to_lockfile",
+        conflicts_with = "to_registry"
+    )]
+    prerelease_channel: Option<String>,
+
     /// Print changes to be made without making them.
     #[structopt(long = "dry-run")]
     dry_run: bool,
 
+    /// With `--dry-run`, exit with status 2 if any requirement would change, e.g. to check in CI
+    /// that the manifests are up to date.
+    #[structopt(long = "exit-code", requires = "dry-run")]
+    exit_code: bool,
+
+    /// Print a unified diff of the changes to each manifest.
+    #[structopt(long = "show-diff", conflicts_with = "to_registry")]
+    show_diff: bool,
+
+    /// Write a JSON record for each changed requirement to this file, e.g. to collect it as a CI
+    /// artifact.
+    #[structopt(
+        long = "report-file",
+        value_name = "path",
+        conflicts_with = "to_registry"
+    )]
+    report_file: Option<PathBuf>,
+
     /// Only update a dependency if the new version is semver incompatible.
     #[structopt(long = "skip-compatible", conflicts_with = "to_lockfile")]
     skip_compatible: bool,
 
-    /// Run without accessing the network
+    /// Only upgrade to versions that are semver compatible with the current requirement, i.e.
+    /// never across a major version (or minor version before 1.0).
+    #[structopt(
+        long = "compatible-only",
+        conflicts_with_all = &["to_lockfile", "to_registry", "skip_compatible", "minimal"],
+        conflicts_with = "compatible_with"
+    )]
+    compatible_only: bool,
+
+    /// Allow upgrades across semver-major boundaries. This is the default unless
+    /// `--compatible-only` is given, e.g. to spell it out in scripts. With `--precise`, allow a
+    /// version that is not compatible with the current requirement.
+    #[structopt(long = "incompatible", conflicts_with = "compatible_only")]
+    incompatible: bool,
+
+    /// Write new requirements with as many components as the old ones, e.g. upgrade `1.2.3` to
+    /// `1.2.9` and `1.2` to `1.3`.
+    #[structopt(
+        long = "preserve-precision",
+        conflicts_with = "to_lockfile",
+        conflicts_with = "to_registry"
+    )]
+    preserve_precision: bool,
+
+    /// Write new requirements in this form: `caret` (`^1.2.3`), `tilde` (`~1.2.3`), `exact`
+    /// (`=1.2.3`) or `minor` (`~1.2`). Defaults to `req-style` of
+    /// `[package.metadata.cargo-edit]`, or else to the plain version.
+    #[structopt(
+        long = "req-style",
+        value_name = "style",
+        possible_values = &["caret", "tilde", "exact", "minor"],
+        conflicts_with_all = &["to_lockfile", "to_registry", "git", "list_sources", "check"]
+    )]
+    req_style: Option<RequirementStyle>,
+
+    /// Only upgrade to versions that support this Rust version. `active` uses the version of the
+    /// active toolchain's `rustc`. Defaults to the lowest `package.rust-version` of the upgraded
+    /// manifests.
+    #[structopt(
+        long = "rust-version",
+        value_name = "version",
+        conflicts_with = "ignore-rust-version"
+    )]
+    rust_version: Option<String>,
+
+    /// Upgrade to the latest versions, even if they require a newer Rust version than
+    /// `package.rust-version`.
+    #[structopt(long = "ignore-rust-version")]
+    ignore_rust_version: bool,
+
+    /// Set each requirement to the lowest version that still satisfies it, to check that the
+    /// lower bounds are not under-specified.
+    #[structopt(
+        long = "minimal",
+        conflicts_with = "to_lockfile",
+        conflicts_with = "to_registry",
+        conflicts_with = "skip_compatible"
+    )]
+    minimal: bool,
+
+    /// Upgrade to the latest version that also satisfies this requirement, e.g. `<2` to stay
+    /// below the next major version.
+    #[structopt(
+        long = "to",
+        value_name = "req",
+        conflicts_with = "to_lockfile",
+        conflicts_with = "to_registry",
+        conflicts_with = "minimal"
+    )]
+    to: Option<String>,
+
+    /// Upgrade the one dependency given to exactly this version, like `cargo update --precise`,
+    /// instead of the latest one. The version has to exist in the registry and, unless
+    /// `--incompatible` is given, be semver compatible with the current requirement.
+    #[structopt(
+        long = "precise",
+        value_name = "version",
+        requires = "dependency",
+        conflicts_with_all = &[
+            "to_lockfile", "to_registry", "git", "minimal", "to", "compatible_with",
+            "compatible_only", "preserve_precision", "fix_yanked", "fix_advisories", "check",
+            "audit_yanks", "list_sources", "interactive",
+        ]
+    )]
+    precise: Option<String>,
+
+    /// Upgrade to the latest version whose dependency on this crate accepts the version of it
+    /// recorded in Cargo.lock, or else its requirement in the manifest.
+    #[structopt(
+        long = "compatible-with",
+        value_name = "crate",
+        conflicts_with_all = &["to_lockfile", "to_registry", "minimal", "to"]
+    )]
+    compatible_with: Option<String>,
+
+    /// Fail if an upgraded requirement doesn't match the version locked in Cargo.lock.
+    #[structopt(long = "frozen", conflicts_with = "to_lockfile")]
+    frozen: bool,
+
+    /// Upgrade across breaking versions without asking for confirmation. Without this flag,
+    /// breaking upgrades are skipped when not running in a terminal, unless
+    /// `CARGO_EDIT_INTERACTIVE` is set to ask anyway.
+    #[structopt(long = "yes", short = "y")]
+    yes: bool,
+
+    /// Only upgrade dependencies whose requirement matches nothing but yanked versions, to the
+    /// next version that isn't yanked.
+    #[structopt(
+        long = "fix-yanked",
+        conflicts_with_all = &["to_lockfile", "to_registry", "minimal", "to"]
+    )]
+    fix_yanked: bool,
+
+    /// Consider yanked versions like any other when picking the versions to upgrade to, e.g. to
+    /// keep up with a crate that yanked its latest release by mistake. This also silences the
+    /// warnings about requirements that only match yanked versions.
+    #[structopt(
+        long = "allow-yanked",
+        conflicts_with_all = &["fix_yanked", "audit_yanks", "to_lockfile"]
+    )]
+    allow_yanked: bool,
+
+    /// Skip the versions affected by a security advisory of the RustSec advisory database when
+    /// picking the versions to upgrade to. The database is fetched to `$CARGO_HOME/advisory-db`,
+    /// where `cargo audit` keeps it, unless `--advisory-db` is given.
+    #[structopt(long = "avoid-advisories")]
+    avoid_advisories: bool,
+
+    /// Only upgrade dependencies whose requirement matches a version affected by a security
+    /// advisory, to the lowest patched version. This implies `--avoid-advisories`.
+    #[structopt(
+        long = "fix-advisories",
+        conflicts_with_all = &["to_lockfile", "to_registry", "minimal", "to", "fix_yanked"]
+    )]
+    fix_advisories: bool,
+
+    /// Read the advisory database from the checkout at `path` instead of fetching it. This
+    /// implies `--avoid-advisories`.
+    #[structopt(long = "advisory-db", value_name = "path")]
+    advisory_db: Option<PathBuf>,
+
+    /// Also upgrade dependencies with exact (`=`) or pre-release requirements.
+    #[structopt(long = "force")]
+    force: bool,
+
+    /// Leave dependencies with exact (`=`) or pre-release requirements untouched and list them.
+    /// This is the default unless `--force` is given, e.g. to spell it out in scripts. Entries of
+    /// the same crate in other tables or workspace members are still upgraded.
+    #[structopt(long = "skip-pinned", conflicts_with = "force")]
+    skip_pinned: bool,
+
+    /// Replace `*` requirements with a requirement for the latest version.
+    #[structopt(long = "pin-wildcards")]
+    pin_wildcards: bool,
+
+    /// Also remove keys that restate the default from the dependency entries of the upgraded
+    /// manifests, i.e. `default-features = true` and empty `features` lists.
+    #[structopt(
+        long = "tidy",
+        conflicts_with = "to_lockfile",
+        conflicts_with = "to_registry",
+        conflicts_with = "check"
+    )]
+    tidy: bool,
+
+    /// Normalize the dependency tables of the upgraded manifests: tidy their entries like
+    /// `--tidy`, write inline tables holding only a version as a plain requirement, and sort the
+    /// tables, keeping the groups of `[package.metadata.cargo-edit] group-order` together.
+    /// Comments stay with their entries.
+    #[structopt(
+        long = "normalize",
+        conflicts_with = "to_lockfile",
+        conflicts_with = "to_registry",
+        conflicts_with = "check"
+    )]
+    normalize: bool,
+
+    /// Print a link to the changes of each upgraded crate, e.g. a GitHub compare view between the
+    /// release tags `v<old>` and `v<new>`, found through the repository the crate was published
+    /// with. JSON records get `repository` and `changelog` URLs.
+    #[structopt(
+        long = "changelog",
+        conflicts_with_all = &["to_lockfile", "to_registry", "check", "audit_yanks", "list_sources"]
+    )]
+    changelog: bool,
+
+    /// Don't change anything, but exit with status 2 if any dependency has a newer version that
+    /// doesn't match its current requirement.
+    #[structopt(
+        long = "check",
+        conflicts_with = "to_lockfile",
+        conflicts_with = "to_registry"
+    )]
+    check: bool,
+
+    /// Don't change anything, but report the dependencies whose newest version matching their
+    /// requirement is yanked, and fail if there are any.
+    #[structopt(
+        long = "audit-yanks",
+        conflicts_with_all = &["to_lockfile", "to_registry", "check", "fix_yanked"]
+    )]
+    audit_yanks: bool,
+
+    /// Don't change anything, but print the source of every dependency: crates.io, a named
+    /// registry, git with its reference, a path, or the workspace.
+    #[structopt(
+        long = "list-sources",
+        conflicts_with_all = &["to_lockfile", "to_registry", "check", "audit_yanks"]
+    )]
+    list_sources: bool,
+
+    /// How to report the changes: `human`, or `json` for one JSON record per changed requirement
+    /// instead of the upgrade messages. With `--list-sources`, `json` prints one record per
+    /// dependency.
+    #[structopt(
+        long = "message-format",
+        visible_alias = "output-format",
+        value_name = "fmt",
+        possible_value = "human",
+        possible_value = "json"
+    )]
+    message_format: Option<String>,
+
+    /// With `--check`, print nothing and only report through the exit status.
+    #[structopt(long = "quiet", short = "q", requires = "check")]
+    quiet: bool,
+
+    /// Turn warnings into errors, e.g. for CI.
+    #[structopt(long = "strict")]
+    strict: bool,
+
+    /// Run without accessing the network, like with `CARGO_NET_OFFLINE=true`. Versions are only
+    /// looked up in the registry indices that were downloaded before.
     #[structopt(long = "offline")]
     pub offline: bool,
 
+    /// Timeout in seconds for network operations. Defaults to the value of
+    /// `CARGO_HTTP_TIMEOUT`, or 30 seconds.
+    #[structopt(long = "timeout", value_name = "seconds")]
+    timeout: Option<u64>,
+
+    /// How many dependencies to look up in the registries at once. Defaults to 8.
+    #[structopt(long = "jobs", short = "j", value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Only refresh registry indices if the local copy is older than this many seconds, even
+    /// with `--offline`. Cached entries of sparse indices are reused until they are this old.
+    #[structopt(long = "refresh-index", value_name = "seconds")]
+    refresh_index: Option<u64>,
+
+    /// Don't update the registry indices before looking up the latest versions. Entries of
+    /// sparse indices are still fetched as they are needed.
+    #[structopt(long = "no-update", conflicts_with = "refresh_index")]
+    no_update: bool,
+
+    /// Resolve versions against the registry indices exactly as they are on disk, e.g. for
+    /// reproducible CI runs: they aren't updated, and entries of sparse indices that aren't cached
+    /// fail instead of being fetched. Like `--offline`, this forbids network access.
+    #[structopt(
+        long = "locked-index",
+        conflicts_with_all = &["refresh_index", "no_update", "git"]
+    )]
+    locked_index: bool,
+
+    /// Resolve versions against the git index at this commit, e.g. for reproducible audits.
+    /// Defaults to `index-rev` in `[package.metadata.cargo-edit]` or
+    /// `[workspace.metadata.cargo-edit]`.
+    #[structopt(long = "index-rev", value_name = "rev")]
+    index_rev: Option<String>,
+
+    /// Read every entry from the git indices instead of the entries cached from them the last
+    /// time they were at the same commit.
+    #[structopt(long = "no-cache")]
+    no_cache: bool,
+
+    /// Read the versions of crates from the web API of the registry instead of its index, which
+    /// then isn't updated, e.g. where cloning a git index takes too long.
+    #[structopt(
+        long = "registry-api-only",
+        conflicts_with_all = &["index_rev", "locked_index", "refresh_index", "to_lockfile", "git"]
+    )]
+    registry_api_only: bool,
+
+    /// Skip cargo config files that cannot be parsed instead of failing.
+    #[structopt(long = "lenient-config")]
+    lenient_config: bool,
+
     /// Upgrade all packages to the version in the lockfile.
     #[structopt(long = "to-lockfile", conflicts_with = "dependency")]
     pub to_lockfile: bool,
+
+    /// With `--to-lockfile`, keep the operator of each requirement so that it never allows a
+    /// version it didn't before, e.g. `~1.2` becomes `~1.2.5` instead of `1.2.5`. Requirements
+    /// that can't be pinned this way, like `<1.5`, are left alone.
+    #[structopt(long = "tighten-only", requires = "to-lockfile")]
+    tighten_only: bool,
+
+    /// Add the latest registry version to path dependencies, keeping their path.
+    #[structopt(
+        long = "to-registry",
+        conflicts_with = "to_lockfile",
+        conflicts_with = "skip_compatible"
+    )]
+    to_registry: bool,
+
+    /// Only upgrade dependencies from this registry, as named in `[registries]` of the cargo
+    /// config. Use `crates-io` for dependencies without an explicit registry.
+    #[structopt(
+        long = "registry",
+        value_name = "name",
+        conflicts_with = "to_lockfile",
+        conflicts_with = "to_registry"
+    )]
+    registry: Option<String>,
+
+    /// Move the pins of git dependencies instead: a `tag` to the newest semver tag of the
+    /// repository, and a `rev` to the latest commit of its default branch. The repositories are
+    /// asked with `git ls-remote`.
+    #[structopt(
+        long = "git",
+        conflicts_with_all = &["to_lockfile", "to_registry", "list_sources", "check"]
+    )]
+    git: bool,
+
+    /// Ask for each upgrade whether to perform it, showing the current requirement, the latest
+    /// compatible version and the version it would be upgraded to, before anything is written.
+    #[structopt(
+        long = "interactive",
+        short = "i",
+        conflicts_with_all = &["yes", "to_lockfile", "to_registry", "list_sources", "check"],
+        conflicts_with_all = &["git", "audit_yanks", "exit_code"]
+    )]
+    interactive: bool,
+
+    /// Print the upgrades grouped by crate once all manifests are upgraded, with the members they
+    /// changed and the counts of unchanged and skipped dependencies, instead of listing each
+    /// manifest as it is upgraded.
+    #[structopt(
+        long = "summary",
+        conflicts_with_all = &["git", "to_lockfile", "to_registry", "list_sources", "check"],
+        conflicts_with_all = &["audit_yanks", "message_format"]
+    )]
+    summary: bool,
+
+    /// Refresh the vendored crates with `cargo vendor` after upgrading, if the registry is
+    /// replaced with a vendor directory in the cargo config.
+    #[structopt(long = "revendor", conflicts_with_all = &["check", "audit_yanks", "list_sources"])]
+    revendor: bool,
+}
+
+/// How `Manifests::upgrade` and `Manifests::sync_to_lockfile` edit the manifests, as asked for on
+/// the command line.
+#[derive(Debug, Clone, Copy)]
+struct UpgradeOptions<'a> {
+    exclude: &'a [String],
+    dry_run: bool,
+    show_diff: bool,
+    skip_compatible: bool,
+    preserve_precision: bool,
+    req_style: Option<RequirementStyle>,
+    tidy: bool,
+    normalize: bool,
+    report_file: Option<&'a Path>,
+    json: bool,
+    summary: bool,
+}
+
+/// Which versions `DesiredUpgrades::get_upgraded` picks.
+#[derive(Debug, Clone, Copy, Default)]
+struct VersionSelection<'a> {
+    allow_prerelease: bool,
+    /// Only pick versions supporting this Rust version.
+    rust_version: Option<&'a str>,
+    /// Pick the lowest version satisfying the current requirement instead of the latest one.
+    minimal: bool,
+    /// Pick the latest version satisfying this requirement.
+    constraint: Option<&'a str>,
+    /// Pick the latest version compatible with the current requirement.
+    compatible_only: bool,
+    /// The name of a crate and a requirement: pick the latest version whose dependency on that
+    /// crate overlaps it.
+    peer: Option<(&'a str, &'a str)>,
+}
+
+/// The dependency entries whose requirement is pinned and left alone, by the path of their
+/// manifest, their table and their key, with the name of their package and why they are pinned.
+#[derive(Debug, Default)]
+struct PinnedEntries(BTreeMap<(PathBuf, Vec<String>, String), (String, String)>);
+
+impl PinnedEntries {
+    /// Add the entries of `sections`, the dependency tables of the manifest at `path`, whose
+    /// requirement is pinned, unless `keep` says otherwise for their package name and key.
+    fn insert_from(
+        &mut self,
+        path: &Path,
+        sections: Vec<(Vec<String>, toml_edit::Item)>,
+        keep: impl Fn(&str, &str) -> bool,
+    ) {
+        for (table_path, table) in sections {
+            let table = match table.as_table_like() {
+                Some(table) => table,
+                None => continue,
+            };
+            for (key, item) in table.iter() {
+                let name = item["package"].as_str().unwrap_or(key);
+                let is_local = !item["path"].is_none() || !item["git"].is_none();
+                let req = item.as_str().or_else(|| item["version"].as_str());
+                if let (false, Some(req)) = (is_local, req) {
+                    match pinned_reason(req) {
+                        Some(reason) if keep(name, key) => {
+                            let entry = (path.to_owned(), table_path.clone(), key.to_owned());
+                            self.0.insert(entry, (name.to_owned(), reason.to_owned()));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// The tables and keys of the pinned entries of the manifest at `path`.
+    fn of(&self, path: &Path) -> Vec<(Vec<String>, String)> {
+        self.0
+            .keys()
+            .filter(|(manifest, _, _)| manifest == path)
+            .map(|(_, table, key)| (table.clone(), key.clone()))
+            .collect()
+    }
 }
 
 /// A collection of manifests.
 struct Manifests(Vec<(LocalManifest, cargo_metadata::Package)>);
 
+/// Get the dependencies that `manifest` holds back, given with `--exclude` or listed in `skip` of
+/// `[package.metadata.cargo-edit.upgrade]`.
+fn held_back(manifest: &LocalManifest, exclude: &[String]) -> Vec<String> {
+    let mut held_back = exclude.to_vec();
+    held_back.extend(manifest.edit_settings(&manifest.path).upgrade_skip());
+    held_back
+}
+
+/// Get the style to write the new requirements of `manifest` in: `req_style` if it is given with
+/// `--req-style`, or else `req-style` of `[package.metadata.cargo-edit]`.
+fn styled_by(
+    manifest: &LocalManifest,
+    req_style: Option<RequirementStyle>,
+) -> Result<Option<RequirementStyle>> {
+    match req_style {
+        Some(style) => Ok(Some(style)),
+        None => Ok(manifest.edit_settings(&manifest.path).req_style()?),
+    }
+}
+
+/// Whether the dependency on `name`, renamed to `rename` if it is, is among `held_back`, by
+/// either name.
+fn is_held_back(held_back: &[String], name: &str, rename: Option<&str>) -> bool {
+    held_back
+        .iter()
+        .any(|held| held == name || Some(held.as_str()) == rename)
+}
+
 /// Helper function to check whether a `cargo_metadata::Dependency` is a version dependency.
 fn is_version_dep(dependency: &cargo_metadata::Dependency) -> bool {
-    match dependency.source {
-        // This is the criterion cargo uses (in `SourceId::from_url`) to decide whether a
-        // dependency has the 'registry' kind.
-        Some(ref s) => s.splitn(2, '+').next() == Some("registry"),
-        _ => false,
-    }
+    dependency.source.as_deref().is_some_and(is_registry_source)
+}
+
+/// Whether a source id of `cargo metadata` belongs to a registry, with a git index or a sparse
+/// one. This is the criterion cargo uses (in `SourceId::from_url`) to decide whether a dependency
+/// has the 'registry' or 'sparse' kind.
+fn is_registry_source(source: &str) -> bool {
+    matches!(source.split('+').next(), Some("registry") | Some("sparse"))
 }
 
 fn dry_run_message() -> Result<()> {
@@ -153,12 +794,57 @@ impl Manifests {
             .map(Manifests)
     }
 
+    /// The names of the packages, by the paths of their manifests.
+    fn package_names(&self) -> HashMap<PathBuf, String> {
+        self.0
+            .iter()
+            .map(|(manifest, package)| (manifest.path.clone(), package.name.clone()))
+            .collect()
+    }
+
+    /// The names of the registry dependencies the manifests declare, only counting the ones in
+    /// `only`, by name or rename, unless it is empty.
+    fn registry_dependency_names(&self, only: &[String]) -> BTreeSet<String> {
+        self.0
+            .iter()
+            .flat_map(|(_, package)| &package.dependencies)
+            .filter(|dependency| is_version_dep(dependency))
+            .filter(|dependency| {
+                only.is_empty()
+                    || only.iter().any(|name| {
+                        let name = name.split('@').next().unwrap_or_default();
+                        dependency.name == name || dependency.rename.as_deref() == Some(name)
+                    })
+            })
+            .map(|dependency| dependency.name.clone())
+            .collect()
+    }
+
+    /// Keep only the members called like one of `packages`, unless it is empty, and drop the ones
+    /// called like one of `excluded`.
+    fn select_members(self, packages: &[String], excluded: &[String]) -> Result<Self> {
+        if let Some(unknown) = packages
+            .iter()
+            .chain(excluded)
+            .find(|name| !self.0.iter().any(|(_, package)| package.name == **name))
+        {
+            return Err(ErrorKind::UnknownMember(unknown.clone()).into());
+        }
+        Ok(Manifests(
+            self.0
+                .into_iter()
+                .filter(|(_, package)| packages.is_empty() || packages.contains(&package.name))
+                .filter(|(_, package)| !excluded.contains(&package.name))
+                .collect(),
+        ))
+    }
+
     /// Get the manifest specified by the manifest path. Try to make an educated guess if no path is
     /// provided.
     fn get_local_one(manifest_path: &Option<PathBuf>) -> Result<Self> {
-        let resolved_manifest_path: String = find(&manifest_path)?.to_string_lossy().into();
+        let resolved_manifest_path: String = find(manifest_path)?.to_string_lossy().into();
 
-        let manifest = LocalManifest::find(&manifest_path)?;
+        let manifest = LocalManifest::find(manifest_path)?;
 
         let mut cmd = cargo_metadata::MetadataCommand::new();
         cmd.no_deps();
@@ -182,9 +868,50 @@ impl Manifests {
         Ok(Manifests(vec![(manifest, package.to_owned())]))
     }
 
+    /// Get the requirement that upgrades have to stay compatible with for `--compatible-with
+    /// <name>`: the version of `name` locked in the `Cargo.lock` next to `manifest_path` if there
+    /// is exactly one, or else its requirement in the manifests.
+    fn peer_requirement(&self, name: &str, manifest_path: &Path) -> Result<String> {
+        if let Ok(locked) = read_locked_versions(manifest_path) {
+            if let Some(versions) = locked.get(name) {
+                if versions.len() == 1 {
+                    return Ok(format!("={}", versions[0]));
+                }
+            }
+        }
+        let reqs = self
+            .0
+            .iter()
+            .flat_map(|(_, package)| package.dependencies.iter())
+            .filter(|dependency| {
+                dependency.name == name || dependency.rename.as_deref() == Some(name)
+            })
+            .map(|dependency| dependency.req.to_string())
+            .collect::<BTreeSet<_>>();
+        if reqs.is_empty() {
+            return Err(ErrorKind::UnknownPeerDependency(name.to_owned()).into());
+        }
+        Ok(reqs.into_iter().collect::<Vec<_>>().join(", "))
+    }
+
+    /// Get the lowest `package.rust-version` of the manifests, if any of them sets it.
+    fn rust_version(&self) -> Result<Option<String>> {
+        lowest_rust_version(self.0.iter().map(|(manifest, _)| manifest))
+    }
+
     /// Get the the combined set of dependencies to upgrade. If the user has specified
     /// per-dependency desired versions, extract those here.
-    fn get_dependencies(&self, only_update: Vec<String>) -> Result<DesiredUpgrades> {
+    ///
+    /// Pinned dependencies are skipped unless `force` is set or a version was requested for them.
+    /// Their entries are returned as well, so that they are left alone when the same crate is
+    /// upgraded elsewhere.
+    fn get_dependencies(
+        &self,
+        only_update: Vec<String>,
+        exclude: &[String],
+        force: bool,
+        quiet: bool,
+    ) -> Result<(DesiredUpgrades, PinnedEntries)> {
         // Map the names of user-specified dependencies to the (optionally) requested version.
         let selected_dependencies = only_update
             .into_iter()
@@ -200,13 +927,93 @@ impl Manifests {
             })
             .collect::<Result<HashMap<_, _>>>()?;
 
-        Ok(DesiredUpgrades(
+        let held = self
+            .0
+            .iter()
+            .map(|(manifest, _)| held_back(manifest, exclude))
+            .collect::<Vec<_>>();
+        let dependencies = || {
             self.0
                 .iter()
-                .flat_map(|&(_, ref package)| package.dependencies.clone())
-                .filter(is_version_dep)
+                .zip(&held)
+                .flat_map(|((manifest, package), held)| {
+                    package
+                        .dependencies
+                        .iter()
+                        .filter(move |dependency| {
+                            !is_held_back(held, &dependency.name, dependency.rename.as_deref())
+                        })
+                        .map(move |dependency| (manifest, dependency))
+                })
+        };
+
+        // Pinned requirements are left alone unless the user insists. A crate pinned in one
+        // manifest or table may still be upgraded in the others.
+        let keeps_pin = |name: &str, key: &str| {
+            let selected = selected_dependencies
+                .get(name)
+                .or_else(|| selected_dependencies.get(key));
+            let considered = selected_dependencies.is_empty() || selected.is_some();
+            let requested_version = selected.is_some_and(Option::is_some);
+            considered && !force && !requested_version
+        };
+        let mut pinned = PinnedEntries::default();
+        for ((manifest, _), held) in self.0.iter().zip(&held) {
+            let keep = |name: &str, key: &str| {
+                let rename = Some(key).filter(|key| *key != name);
+                !is_held_back(held, name, rename) && keeps_pin(name, key)
+            };
+            let mut sections = manifest.get_sections();
+            sections.extend(manifest.workspace_dependencies_section());
+            pinned.insert_from(&manifest.path, sections, keep);
+        }
+        // Inherited requirements are pinned in the workspace root, which need not be one of the
+        // manifests.
+        let is_member = |root: &Path| {
+            self.0
+                .iter()
+                .any(|(manifest, _)| manifest.path.canonicalize().ok() == root.canonicalize().ok())
+        };
+        let roots = self
+            .0
+            .iter()
+            .filter_map(|(manifest, _)| workspace_root_path(&manifest.path))
+            .filter(|root| !is_member(root))
+            .collect::<BTreeSet<_>>();
+        for root_path in roots {
+            let root = LocalManifest::try_new(&root_path)?;
+            let held = held_back(&root, exclude);
+            let keep = |name: &str, key: &str| {
+                let rename = Some(key).filter(|key| *key != name);
+                !is_held_back(&held, name, rename) && keeps_pin(name, key)
+            };
+            let sections = root.workspace_dependencies_section().into_iter().collect();
+            pinned.insert_from(&root_path, sections, keep);
+        }
+
+        let mut reported = BTreeMap::new();
+        for (name, reason) in pinned.0.values() {
+            reported.entry(name).or_insert(reason);
+        }
+        for (name, reason) in reported.iter().filter(|_| !quiet) {
+            println!(
+                "Skipping {}: it has {}, use --force to upgrade it",
+                name, reason
+            );
+        }
+
+        let is_pinned = |dependency: &cargo_metadata::Dependency| {
+            let key = dependency.rename.as_deref().unwrap_or(&dependency.name);
+            keeps_pin(&dependency.name, key) && pinned_reason(&dependency.req.to_string()).is_some()
+        };
+        let desired = DesiredUpgrades(
+            dependencies()
+                .map(|(_, dependency)| dependency)
+                .filter(|dependency| is_version_dep(dependency) && !is_pinned(dependency))
+                .cloned()
                 .filter_map(|dependency| {
                     let is_prerelease = dependency.req.to_string().contains('-');
+                    let old_req = dependency.req.to_string();
                     if selected_dependencies.is_empty() {
                         // User hasn't asked for any specific dependencies to be upgraded,
                         // so upgrade all the dependencies.
@@ -220,18 +1027,31 @@ impl Manifests {
                                 registry: dependency.registry,
                                 version: None,
                                 is_prerelease,
+                                old_req,
                             },
                         ))
                     } else {
                         // User has asked for specific dependencies. Check if this dependency
                         // was specified, populating the registry from the lockfile metadata.
-                        match selected_dependencies.get(&dependency.name) {
+                        // Renamed dependencies may be specified by their package name or alias.
+                        let selected = selected_dependencies.get(&dependency.name).or_else(|| {
+                            dependency
+                                .rename
+                                .as_ref()
+                                .and_then(|rename| selected_dependencies.get(rename))
+                        });
+                        let mut dep = Dependency::new(&dependency.name);
+                        if let Some(rename) = &dependency.rename {
+                            dep = dep.set_rename(rename);
+                        }
+                        match selected {
                             Some(version) => Some((
-                                Dependency::new(&dependency.name),
+                                dep,
                                 UpgradeMetadata {
                                     registry: dependency.registry,
                                     version: version.clone(),
                                     is_prerelease,
+                                    old_req,
                                 },
                             )),
                             None => None,
@@ -239,45 +1059,169 @@ impl Manifests {
                     }
                 })
                 .collect(),
-        ))
+        );
+        Ok((desired, pinned))
     }
 
-    /// Upgrade the manifests on disk following the previously-determined upgrade schema.
+    /// Upgrade the manifests on disk following the previously-determined upgrade schema, and get
+    /// the records of the requirements that changed. With `summary`, the manifests aren't listed
+    /// one by one, as the records are summarized afterwards.
     fn upgrade(
         self,
         upgraded_deps: &ActualUpgrades,
-        dry_run: bool,
-        skip_compatible: bool,
-    ) -> Result<()> {
-        if dry_run {
+        pinned: &PinnedEntries,
+        changelogs: &Changelogs,
+        options: &UpgradeOptions<'_>,
+    ) -> Result<Vec<serde_json::Value>> {
+        let UpgradeOptions {
+            exclude,
+            dry_run,
+            show_diff,
+            skip_compatible,
+            preserve_precision,
+            req_style,
+            tidy,
+            normalize,
+            report_file,
+            json,
+            summary,
+        } = *options;
+        if dry_run && !json {
             dry_run_message()?;
         }
 
+        // All manifests are edited in memory first, also for dry runs, and only written once every
+        // edit succeeded.
+        let mut manifests = Vec::new();
         for (mut manifest, package) in self.0 {
-            println!("{}:", package.name);
+            if !json && !summary {
+                println!("{}:", package.name);
+            }
 
+            let held = held_back(&manifest, exclude);
+            let style = styled_by(&manifest, req_style)?;
+            let kept = pinned.of(&manifest.path);
             for (dep, version) in &upgraded_deps.0 {
+                if is_held_back(&held, &dep.name, dep.rename()) {
+                    continue;
+                }
                 let mut new_dep = Dependency::new(&dep.name).set_version(version);
                 if let Some(rename) = dep.rename() {
-                    new_dep = new_dep.set_rename(&rename);
+                    new_dep = new_dep.set_rename(rename);
+                }
+                manifest.apply_upgrade_except(
+                    &new_dep,
+                    &kept,
+                    skip_compatible,
+                    preserve_precision,
+                    style,
+                )?;
+            }
+            if !json {
+                changelogs.print_links(&manifest)?;
+            }
+            if tidy || normalize {
+                for (table, name) in manifest.tidy_dependencies()? {
+                    if !json {
+                        println!("    Tidying {} in {}", name, table);
+                    }
+                }
+            }
+            if normalize {
+                for (table, name) in manifest.shorten_dependencies()? {
+                    if !json {
+                        println!("    Shortening {} in {}", name, table);
+                    }
+                }
+                let group_order = manifest.edit_settings(&manifest.path).group_order();
+                for table in manifest.sort_dependency_tables(&group_order)? {
+                    if !json {
+                        println!("    Sorting {}", table);
+                    }
                 }
-                manifest.upgrade(&new_dep, dry_run, skip_compatible)?;
             }
+            manifests.push(manifest);
         }
 
-        Ok(())
+        // Requirements that members inherit are upgraded in the workspace root, which need not be
+        // one of the manifests, e.g. if it is virtual.
+        let inherits = |manifest: &LocalManifest| {
+            upgraded_deps
+                .0
+                .iter()
+                .any(|(dep, _)| manifest.inherits_dependency(dep.name_in_manifest()))
+        };
+        let root_path = manifests
+            .iter()
+            .filter(|manifest| inherits(manifest))
+            .find_map(|manifest| workspace_root_path(&manifest.path));
+        let is_root = |manifest: &LocalManifest, root: &Path| {
+            manifest.path.canonicalize().ok() == root.canonicalize().ok()
+        };
+        let root_path = root_path.filter(|path| !manifests.iter().any(|m| is_root(m, path)));
+        if let Some(root_path) = root_path {
+            if !json && !summary {
+                println!("workspace.dependencies:");
+            }
+            let mut root = LocalManifest::try_new(&root_path)?;
+            let held = held_back(&root, exclude);
+            let style = styled_by(&root, req_style)?;
+            let kept = pinned.of(&root_path);
+            for (dep, version) in &upgraded_deps.0 {
+                if is_held_back(&held, &dep.name, dep.rename()) {
+                    continue;
+                }
+                let mut new_dep = Dependency::new(&dep.name).set_version(version);
+                if let Some(rename) = dep.rename() {
+                    new_dep = new_dep.set_rename(rename);
+                }
+                root.apply_workspace_upgrade(
+                    &new_dep,
+                    &kept,
+                    skip_compatible,
+                    preserve_precision,
+                    style,
+                )?;
+            }
+            if !json {
+                changelogs.print_links(&root)?;
+            }
+            manifests.push(root);
+        }
+
+        write_manifests(
+            &manifests,
+            dry_run,
+            show_diff,
+            report_file,
+            json,
+            changelogs,
+        )
     }
 
     /// Update dependencies in Cargo.toml file(s) to match the corresponding
-    /// version in Cargo.lock.
-    fn sync_to_lockfile(self, dry_run: bool, skip_compatible: bool) -> Result<()> {
+    /// version in Cargo.lock, and get the number of requirements that changed.
+    fn sync_to_lockfile(
+        self,
+        options: &UpgradeOptions<'_>,
+        force: bool,
+        tighten_only: bool,
+    ) -> Result<usize> {
+        let UpgradeOptions {
+            exclude,
+            dry_run,
+            show_diff,
+            skip_compatible,
+            report_file,
+            json,
+            ..
+        } = *options;
         // Get locked dependencies. For workspaces with multiple Cargo.toml
         // files, there is only a single lockfile, so it suffices to get
         // metadata for any one of Cargo.toml files.
-        let (manifest, _package) =
-            self.0.iter().next().ok_or_else(|| {
-                ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
-            })?;
+        let (manifest, _package) = self.0.first().ok_or(ErrorKind::CargoEditLib(
+            ::cargo_edit::ErrorKind::InvalidCargoConfig,
+        ))?;
         let mut cmd = cargo_metadata::MetadataCommand::new();
         cmd.manifest_path(manifest.path.clone());
         cmd.other_options(vec!["--locked".to_string()]);
@@ -292,37 +1236,337 @@ impl Manifests {
             .filter(|p| p.source.is_some()) // Source is none for local packages
             .collect::<Vec<_>>();
 
-        if dry_run {
+        if dry_run && !json {
             dry_run_message()?;
         }
 
+        let mut manifests = Vec::new();
         for (mut manifest, package) in self.0 {
-            println!("{}:", package.name);
+            if !json {
+                println!("{}:", package.name);
+            }
+            let held = held_back(&manifest, exclude);
 
             // Upgrade the manifests one at a time, as multiple manifests may
             // request the same dependency at differing versions.
-            for (name, version) in package
+            for (name, rename, old_req, version) in package
                 .dependencies
                 .clone()
                 .into_iter()
                 .filter(is_version_dep)
+                .filter(|d| !is_held_back(&held, &d.name, d.rename.as_deref()))
                 .filter_map(|d| {
                     for p in &locked {
                         // The requested dependency may be present in the lock file with different versions,
                         // but only one will be semver-compatible with the requested version.
                         if d.name == p.name && d.req.matches(&p.version) {
-                            return Some((d.name, p.version.to_string()));
+                            let old_req = d.req.to_string();
+                            return Some((d.name, d.rename, old_req, p.version.to_string()));
                         }
                     }
                     None
                 })
             {
-                manifest.upgrade(
-                    &Dependency::new(&name).set_version(&version),
-                    dry_run,
-                    skip_compatible,
-                )?;
-            }
+                if let Some(reason) = pinned_reason(&old_req).filter(|_| !force) {
+                    if !json {
+                        println!(
+                            "Skipping {}: it has {}, use --force to upgrade it",
+                            name, reason
+                        );
+                    }
+                    continue;
+                }
+                let version = if tighten_only {
+                    match tightened_requirement(&old_req, &version) {
+                        Some(version) => version,
+                        None => {
+                            if !json {
+                                println!(
+                                    "Skipping {}: `{}` can't be pinned to {} without allowing \
+                                     other versions",
+                                    name, old_req, version
+                                );
+                            }
+                            continue;
+                        }
+                    }
+                } else {
+                    version
+                };
+                // Several aliases of a package may be locked to different versions.
+                let mut new_dep = Dependency::new(&name).set_version(&version);
+                if let Some(rename) = rename {
+                    new_dep = new_dep.set_rename(&rename);
+                }
+                manifest.apply_upgrade(&new_dep, false, skip_compatible, false, None)?;
+            }
+            manifests.push(manifest);
+        }
+
+        write_manifests(
+            &manifests,
+            dry_run,
+            show_diff,
+            report_file,
+            json,
+            &Changelogs::default(),
+        )
+        .map(|records| records.len())
+    }
+
+    /// Move the pins of the git dependencies with a `tag` or `rev`, see `--git`. Dependencies on
+    /// a branch already follow their repository. Returns the number of moved pins.
+    fn upgrade_git(
+        self,
+        only_update: &[String],
+        exclude: &[String],
+        timeout: Duration,
+        dry_run: bool,
+        show_diff: bool,
+    ) -> Result<usize> {
+        if dry_run {
+            dry_run_message()?;
+        }
+
+        let mut changed = 0;
+        let mut manifests = Vec::new();
+        for (mut manifest, package) in self.0 {
+            println!("{}:", package.name);
+            let held = held_back(&manifest, exclude);
+
+            for (table_path, dependency) in manifest.get_dependencies()? {
+                let name = dependency.name_in_manifest().to_owned();
+                let requested = only_update.is_empty()
+                    || only_update.contains(&dependency.name)
+                    || only_update.contains(&name);
+                if !requested || is_held_back(&held, &dependency.name, dependency.rename()) {
+                    continue;
+                }
+                let repo = match dependency.git() {
+                    Some(repo) => repo,
+                    None => continue,
+                };
+                let reference = match dependency.git_reference() {
+                    Some(GitReference::Tag(tag)) => match tag_version(tag) {
+                        Some(version) => {
+                            let latest = get_latest_git_tag(repo, timeout)?;
+                            Some(latest)
+                                .filter(|latest| tag_version(latest).is_some_and(|v| v > version))
+                                .map(GitReference::Tag)
+                        }
+                        None => {
+                            println!(
+                                "    Skipping {}: tag `{}` is not a semver version",
+                                name, tag
+                            );
+                            None
+                        }
+                    },
+                    Some(GitReference::Rev(rev)) if is_commit_hash(rev) => {
+                        let head = get_latest_git_commit(repo, timeout)?;
+                        upgraded_rev(rev, &head).map(GitReference::Rev)
+                    }
+                    Some(GitReference::Rev(rev)) => {
+                        println!("    Skipping {}: rev `{}` is not a commit hash", name, rev);
+                        None
+                    }
+                    _ => None,
+                };
+                if let Some(reference) = reference {
+                    manifest.set_git_reference(&table_path, &name, &reference)?;
+                    changed += 1;
+                }
+            }
+            manifests.push(manifest);
+        }
+
+        write_manifests(
+            &manifests,
+            dry_run,
+            show_diff,
+            None,
+            false,
+            &Changelogs::default(),
+        )?;
+        Ok(changed)
+    }
+
+    /// Print the source of each dependency of every manifest, in the order they are declared.
+    fn list_sources(&self, json: bool) -> Result<()> {
+        for (manifest, package) in &self.0 {
+            if json {
+                for record in source_records(&manifest.path, manifest) {
+                    println!("{}", record);
+                }
+                continue;
+            }
+            println!("{}:", package.name);
+            for (context, name, source) in iter_dependency_sources(manifest) {
+                println!(
+                    "    {} ({}): {}",
+                    name,
+                    context.table_path().join("."),
+                    source
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Give path dependencies the version requirement of their latest release on the registry,
+    /// so that the manifests can be published. Path dependencies on crates that are not available
+    /// on the registry are left alone.
+    fn sync_to_registry(
+        self,
+        only_update: &[String],
+        allow_prerelease: bool,
+        registry: &Option<Url>,
+        dry_run: bool,
+    ) -> Result<()> {
+        if dry_run {
+            dry_run_message()?;
+        }
+
+        for (mut manifest, package) in self.0 {
+            println!("{}:", package.name);
+
+            let path_dependencies = package
+                .dependencies
+                .iter()
+                // Path dependencies are the only ones without a source.
+                .filter(|d| d.source.is_none())
+                .filter(|d| only_update.is_empty() || only_update.contains(&d.name))
+                .map(|d| d.name.clone())
+                .collect::<BTreeSet<_>>();
+
+            for name in path_dependencies {
+                match get_latest_dependency(&name, allow_prerelease, &manifest.path, registry) {
+                    Ok(ref dep) if dep.name == name => {
+                        manifest.set_path_dependency_version(dep, dry_run)?;
+                    }
+                    Ok(_) => println!("    Skipping {}: not available on the registry", name),
+                    Err(e) => {
+                        if let ::cargo_edit::ErrorKind::NoCrate(_) = e.kind() {
+                            println!("    Skipping {}: not available on the registry", name);
+                        } else {
+                            return Err(e).chain_err(|| "Failed to get new version");
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Print the diffs of the edited `manifests` with `--show-diff`, report the changed requirements
+/// with `--report-file` or as `json` records on stdout, and write the manifests unless this is a
+/// dry run. Returns the records of the changed requirements.
+///
+/// Dry runs still edit the manifests in memory, so that there is something to diff and count.
+fn write_manifests(
+    manifests: &[LocalManifest],
+    dry_run: bool,
+    show_diff: bool,
+    report_file: Option<&Path>,
+    json: bool,
+    changelogs: &Changelogs,
+) -> Result<Vec<serde_json::Value>> {
+    if show_diff {
+        for manifest in manifests {
+            print!("{}", manifest.diff(&manifest.path)?);
+        }
+    }
+    let mut records = Vec::new();
+    for manifest in manifests {
+        let original = LocalManifest::try_new(&manifest.path)?;
+        records.extend(requirement_changes(&manifest.path, &original, manifest));
+    }
+    for record in &mut records {
+        changelogs.annotate(record);
+    }
+    if !dry_run {
+        write_manifests_atomically(manifests)?;
+    }
+    if let Some(report_file) = report_file {
+        write_report(report_file, &records)?;
+    }
+    if json {
+        for record in &records {
+            println!("{}", record);
+        }
+    }
+    Ok(records)
+}
+
+/// The source repositories of the upgraded crates and their new versions, by crate name, to link
+/// to their changes with `--changelog`
+#[derive(Debug, Default)]
+struct Changelogs(HashMap<String, (String, String)>);
+
+impl Changelogs {
+    /// Look up the repositories of `upgrades`. Crates whose repository isn't known, or can't be
+    /// looked up, get no link.
+    fn get<S: VersionSource>(
+        upgrades: &ActualUpgrades,
+        registries: &HashMap<String, Option<String>>,
+        jobs: usize,
+        source_for: impl Fn(Option<Url>) -> S + Sync,
+    ) -> Changelogs {
+        let lookups = lookup_concurrently(
+            upgrades.0.iter().collect(),
+            jobs,
+            |(dep, version): (&Dependency, &String)| {
+                let registry = registries
+                    .get(&dep.name)
+                    .cloned()
+                    .flatten()
+                    .and_then(|url| Url::parse(&url).ok());
+                // The links are purely informational, so don't fail the upgrade without them.
+                let repository = get_repository_from_source(&dep.name, &source_for(registry))
+                    .ok()
+                    .flatten()?;
+                Some((dep.name.clone(), (repository, version.clone())))
+            },
+        );
+        Changelogs(lookups.into_iter().flatten().collect())
+    }
+
+    /// The link for the change of the requirement of `name` from `old_req`: the changes between
+    /// the versions if the repository is known to show them, or else the repository itself.
+    fn link(&self, name: &str, old_req: Option<&str>) -> Option<(&str, Option<String>)> {
+        let (repository, version) = self.0.get(name)?;
+        let changes = old_req.and_then(|old_req| changelog_url(repository, old_req, version));
+        Some((repository, changes))
+    }
+
+    /// Add the `repository` and `changelog` of the crate to a record of a changed requirement.
+    fn annotate(&self, record: &mut serde_json::Value) {
+        let name = record["crate"].as_str().unwrap_or_default().to_owned();
+        let old_req = record["old_requirement"].as_str().map(String::from);
+        if let Some((repository, changes)) = self.link(&name, old_req.as_deref()) {
+            record["repository"] = serde_json::json!(repository);
+            record["changelog"] = serde_json::json!(changes);
+        }
+    }
+
+    /// Print the links for the requirements of `manifest` that were changed in memory.
+    fn print_links(&self, manifest: &LocalManifest) -> Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        let original = LocalManifest::try_new(&manifest.path)?;
+        for record in requirement_changes(&manifest.path, &original, manifest) {
+            let name = record["crate"].as_str().unwrap_or_default();
+            if let Some((repository, changes)) = self.link(name, record["old_requirement"].as_str())
+            {
+                println!(
+                    "    Changes of {}: {}",
+                    name,
+                    changes.as_deref().unwrap_or(repository)
+                );
+            }
         }
         Ok(())
     }
@@ -336,6 +1580,8 @@ struct UpgradeMetadata {
     // version to upgrade to.
     version: Option<String>,
     is_prerelease: bool,
+    // The requirement currently written in the manifest.
+    old_req: String,
 }
 
 /// The set of dependencies to be upgraded, alongside the registries returned from cargo metadata, and
@@ -346,53 +1592,956 @@ struct DesiredUpgrades(HashMap<Dependency, UpgradeMetadata>);
 /// to the new versions.
 struct ActualUpgrades(HashMap<Dependency, String>);
 
+/// A dependency to upgrade past an advisory: its name, its requirement, the minimal patched
+/// version and the ids of the advisories.
+type AdvisoryFix = (String, String, String, Vec<String>);
+
+/// Print a note if upgrading a dependency changes its default features.
+fn print_default_features_change(name: &str, new_version: &str, change: &DefaultFeaturesChange) {
+    if change.is_empty() || is_quiet() {
+        return;
+    }
+    let mut note = format!(
+        "    Note: {} v{} changes its default features:",
+        name, new_version
+    );
+    if !change.added.is_empty() {
+        note.push_str(&format!(" added `{}`", change.added.join("`, `")));
+    }
+    if !change.removed.is_empty() {
+        if !change.added.is_empty() {
+            note.push(';');
+        }
+        note.push_str(&format!(" removed `{}`", change.removed.join("`, `")));
+    }
+    println!("{}", note);
+}
+
 impl DesiredUpgrades {
     /// Transform the dependencies into their upgraded forms. If a version is specified, all
     /// dependencies will get that version.
-    fn get_upgraded(self, allow_prerelease: bool, manifest_path: &Path) -> Result<ActualUpgrades> {
-        self.0
-            .into_iter()
-            .map(
-                |(
-                    dep,
-                    UpgradeMetadata {
-                        registry,
-                        version,
-                        is_prerelease,
-                    },
-                )| {
-                    if let Some(v) = version {
-                        Ok((dep, v))
-                    } else {
-                        let registry_url = match registry {
-                            Some(x) => Some(Url::parse(&x).map_err(|_| {
-                                ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
-                            })?),
-                            None => None,
-                        };
-                        let allow_prerelease = allow_prerelease || is_prerelease;
-                        get_latest_dependency(
-                            &dep.name,
-                            allow_prerelease,
-                            manifest_path,
-                            &registry_url,
-                        )
-                        .map(|new_dep| {
-                            (
-                                dep,
-                                new_dep
-                                    .version()
-                                    .expect("Invalid dependency type")
-                                    .to_string(),
-                            )
-                        })
-                        .chain_err(|| "Failed to get new version")
-                    }
+    ///
+    /// The versions of each dependency are looked up in the source `source_for` returns for its
+    /// registry, which is `None` for dependencies without an explicit registry, and picked as
+    /// `selection` says.
+    fn get_upgraded<S: VersionSource>(
+        self,
+        selection: VersionSelection<'_>,
+        jobs: usize,
+        source_for: impl Fn(Option<Url>) -> S + Sync,
+    ) -> Result<ActualUpgrades> {
+        let VersionSelection {
+            allow_prerelease,
+            rust_version,
+            minimal,
+            constraint,
+            compatible_only,
+            peer,
+        } = selection;
+        // The lookups are independent of each other, so they run concurrently. Notes are only
+        // printed afterwards, so that they don't depend on which lookup finishes first.
+        let lookups = lookup_concurrently(
+            self.0.into_iter().collect(),
+            jobs,
+            |(
+                dep,
+                UpgradeMetadata {
+                    registry,
+                    version,
+                    is_prerelease,
+                    old_req,
                 },
+            )|
+             -> Result<(Dependency, String, Option<DefaultFeaturesChange>)> {
+                if let Some(v) = version {
+                    return Ok((dep, v, None));
+                }
+                let registry_url = match registry {
+                    Some(x) => Some(Url::parse(&x).map_err(|_| {
+                        ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
+                    })?),
+                    None => None,
+                };
+                let source = source_for(registry_url);
+                let allow_prerelease = allow_prerelease || is_prerelease;
+                let version_of = |new_dep: Dependency| {
+                    new_dep
+                        .version()
+                        .expect("Invalid dependency type")
+                        .to_string()
+                };
+                if minimal {
+                    return get_minimal_dependency_from_source(&dep.name, &old_req, &source)
+                        .map(|new_dep| (dep, version_of(new_dep), None))
+                        .chain_err(|| "Failed to get minimal version");
+                }
+                if let Some((peer, peer_req)) = peer {
+                    return get_latest_dependency_compatible_from_source(
+                        &dep.name,
+                        peer,
+                        peer_req,
+                        allow_prerelease,
+                        rust_version,
+                        &source,
+                    )
+                    .map(|new_dep| (dep, version_of(new_dep), None))
+                    .chain_err(|| "Failed to get new version");
+                }
+                // With `--compatible-only`, the versions must match `--to` too, if it is given.
+                let constraint = match (constraint, compatible_only) {
+                    (Some(constraint), true) => Some(format!(
+                        "{}, {}",
+                        constraint,
+                        compatible_requirement(&old_req)
+                    )),
+                    (None, true) => Some(compatible_requirement(&old_req)),
+                    (constraint, false) => constraint.map(String::from),
+                };
+                if let Some(constraint) = constraint {
+                    return get_latest_dependency_matching_from_source(
+                        &dep.name,
+                        &constraint,
+                        allow_prerelease,
+                        rust_version,
+                        &source,
+                    )
+                    .map(|new_dep| (dep, version_of(new_dep), None))
+                    .chain_err(|| "Failed to get new version");
+                }
+                get_latest_dependency_and_versions_from_source(
+                    &dep.name,
+                    allow_prerelease,
+                    rust_version,
+                    &source,
+                )
+                .map(|(new_dep, versions)| {
+                    let new_version = version_of(new_dep);
+                    // The report is purely informational, so don't fail the upgrade when it
+                    // can't be produced.
+                    let change =
+                        get_default_features_change(&dep.name, &old_req, &new_version, &versions)
+                            .ok();
+                    (dep, new_version, change)
+                })
+                .chain_err(|| "Failed to get new version")
+            },
+        );
+
+        let mut upgrades = HashMap::new();
+        for lookup in lookups {
+            let (dep, new_version, change) = lookup?;
+            if let Some(change) = change {
+                print_default_features_change(&dep.name, &new_version, &change);
+            }
+            upgrades.insert(dep, new_version);
+        }
+        Ok(ActualUpgrades(upgrades))
+    }
+}
+
+impl DesiredUpgrades {
+    /// Find the dependencies whose latest version doesn't match the requirement currently written
+    /// in the manifest, returning their names, requirements and latest versions. Dependencies
+    /// with an explicitly requested version are not checked.
+    fn get_outdated<S: VersionSource>(
+        &self,
+        allow_prerelease: bool,
+        rust_version: Option<&str>,
+        source_for: impl Fn(Option<Url>) -> S,
+    ) -> Result<Vec<(String, String, String)>> {
+        let mut outdated = Vec::new();
+        for (dep, metadata) in self.0.iter().filter(|(_, m)| m.version.is_none()) {
+            let registry_url = match &metadata.registry {
+                Some(x) => Some(Url::parse(x).map_err(|_| {
+                    ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
+                })?),
+                None => None,
+            };
+            let latest = get_latest_dependency_from_source(
+                &dep.name,
+                allow_prerelease || metadata.is_prerelease,
+                rust_version,
+                &source_for(registry_url),
+            )
+            .chain_err(|| "Failed to get new version")?;
+            let latest = latest.version().expect("Invalid dependency type");
+            if is_breaking(&metadata.old_req, latest) {
+                outdated.push((
+                    dep.name.clone(),
+                    metadata.old_req.clone(),
+                    latest.to_owned(),
+                ));
+            }
+        }
+        outdated.sort();
+        Ok(outdated)
+    }
+
+    /// Find the dependencies whose requirement only matches yanked versions, returning their
+    /// names, requirements and the next version that isn't yanked. Dependencies with an
+    /// explicitly requested version are not checked.
+    fn get_yanked_floors<S: VersionSource>(
+        &self,
+        allow_prerelease: bool,
+        source_for: impl Fn(Option<Url>) -> S,
+    ) -> Result<Vec<(String, String, String)>> {
+        let mut floors = Vec::new();
+        for (dep, metadata) in self.0.iter().filter(|(_, m)| m.version.is_none()) {
+            let registry_url = match &metadata.registry {
+                Some(x) => Some(Url::parse(x).map_err(|_| {
+                    ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
+                })?),
+                None => None,
+            };
+            match get_yank_safe_dependency_from_source(
+                &dep.name,
+                &metadata.old_req,
+                allow_prerelease || metadata.is_prerelease,
+                &source_for(registry_url),
+            ) {
+                Ok(Some(next)) => floors.push((
+                    dep.name.clone(),
+                    metadata.old_req.clone(),
+                    next.version().expect("Invalid dependency type").to_owned(),
+                )),
+                Ok(None) => {}
+                Err(::cargo_edit::Error(::cargo_edit::ErrorKind::NoVersionsAvailable, _)) => {
+                    warn(format!(
+                        "Every version of `{}` matching `{}` is yanked, and there is no newer \
+                         release",
+                        dep.name, metadata.old_req
+                    ))?;
+                }
+                Err(e) => return Err(e).chain_err(|| "Failed to check for yanked versions"),
+            }
+        }
+        floors.sort();
+        Ok(floors)
+    }
+
+    /// Find the dependencies whose requirement matches a version affected by one of `advisories`,
+    /// returning their names, requirements, the minimal patched versions and the advisory ids.
+    fn get_advisory_fixes<S: VersionSource>(
+        &self,
+        advisories: &AdvisoryDatabase,
+        allow_prerelease: bool,
+        source_for: impl Fn(Option<Url>) -> S,
+    ) -> Result<Vec<AdvisoryFix>> {
+        let mut fixes = Vec::new();
+        for (dep, metadata) in self.0.iter().filter(|(_, m)| m.version.is_none()) {
+            let registry_url = match &metadata.registry {
+                Some(x) => Some(Url::parse(x).map_err(|_| {
+                    ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
+                })?),
+                None => None,
+            };
+            match get_advisory_safe_dependency_from_source(
+                &dep.name,
+                &metadata.old_req,
+                allow_prerelease || metadata.is_prerelease,
+                advisories,
+                &source_for(registry_url),
+            ) {
+                Ok(Some((next, ids))) => fixes.push((
+                    dep.name.clone(),
+                    metadata.old_req.clone(),
+                    next.version().expect("Invalid dependency type").to_owned(),
+                    ids,
+                )),
+                Ok(None) => {}
+                Err(::cargo_edit::Error(::cargo_edit::ErrorKind::NoVersionsAvailable, _)) => {
+                    warn(format!(
+                        "`{}` matches versions of `{}` with advisories, and there is no patched \
+                         release",
+                        metadata.old_req, dep.name
+                    ))?;
+                }
+                Err(e) => return Err(e).chain_err(|| "Failed to check for advisories"),
+            }
+        }
+        fixes.sort();
+        Ok(fixes)
+    }
+
+    /// Find the dependencies whose requirement selects a yanked version, i.e. whose newest
+    /// matching version is yanked, returning their names, requirements and that version.
+    fn get_yanked_selections<S: VersionSource>(
+        &self,
+        source_for: impl Fn(Option<Url>) -> S,
+    ) -> Result<Vec<(String, String, String)>> {
+        let mut yanked = Vec::new();
+        for (dep, metadata) in &self.0 {
+            let registry_url = match &metadata.registry {
+                Some(x) => Some(Url::parse(x).map_err(|_| {
+                    ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
+                })?),
+                None => None,
+            };
+            let selected = get_yanked_selection_from_source(
+                &dep.name,
+                &metadata.old_req,
+                &source_for(registry_url),
+            )
+            .chain_err(|| "Failed to check for yanked versions")?;
+            if let Some(selected) = selected {
+                yanked.push((
+                    dep.name.clone(),
+                    metadata.old_req.clone(),
+                    selected
+                        .version()
+                        .expect("Invalid dependency type")
+                        .to_owned(),
+                ));
+            }
+        }
+        yanked.sort();
+        Ok(yanked)
+    }
+
+    /// Split off the dependencies with a `*` requirement whose new version would be looked up.
+    /// Returns the remaining dependencies and the wildcard ones.
+    fn split_wildcards(self) -> (Self, Self) {
+        let (wildcards, rest) = self.0.into_iter().partition(|(_, metadata)| {
+            metadata.version.is_none() && metadata.old_req.trim() == "*"
+        });
+        (DesiredUpgrades(rest), DesiredUpgrades(wildcards))
+    }
+
+    /// Get the latest version of each dependency, sorted by name.
+    fn get_latest<S: VersionSource>(
+        &self,
+        allow_prerelease: bool,
+        rust_version: Option<&str>,
+        source_for: impl Fn(Option<Url>) -> S,
+    ) -> Result<Vec<(String, String)>> {
+        let mut latest = Vec::new();
+        for (dep, metadata) in &self.0 {
+            let registry_url = match &metadata.registry {
+                Some(x) => Some(Url::parse(x).map_err(|_| {
+                    ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
+                })?),
+                None => None,
+            };
+            let new_dep = get_latest_dependency_from_source(
+                &dep.name,
+                allow_prerelease || metadata.is_prerelease,
+                rust_version,
+                &source_for(registry_url),
+            )
+            .chain_err(|| "Failed to get new version")?;
+            let version = new_dep.version().expect("Invalid dependency type");
+            latest.push((dep.name.clone(), version.to_owned()));
+        }
+        latest.sort();
+        Ok(latest)
+    }
+
+    /// Get the latest version that is semver compatible with the current requirement of each
+    /// dependency, or `None` if there is none that can be used.
+    fn get_latest_compatible<S: VersionSource>(
+        &self,
+        allow_prerelease: bool,
+        rust_version: Option<&str>,
+        source_for: impl Fn(Option<Url>) -> S,
+    ) -> Result<HashMap<String, Option<String>>> {
+        let mut latest = HashMap::new();
+        for (dep, metadata) in &self.0 {
+            let registry_url = match &metadata.registry {
+                Some(x) => Some(Url::parse(x).map_err(|_| {
+                    ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
+                })?),
+                None => None,
+            };
+            let version = get_latest_dependency_matching_from_source(
+                &dep.name,
+                &compatible_requirement(&metadata.old_req),
+                allow_prerelease || metadata.is_prerelease,
+                rust_version,
+                &source_for(registry_url),
+            )
+            .ok()
+            .and_then(|new_dep| new_dep.version().map(String::from));
+            latest.insert(dep.name.clone(), version);
+        }
+        Ok(latest)
+    }
+
+    /// Keep only the dependencies in `floors`, and upgrade them to the versions given there.
+    fn keep_fixes(self, floors: &[(String, String, String)]) -> Self {
+        DesiredUpgrades(
+            self.0
+                .into_iter()
+                .filter_map(|(dep, metadata)| {
+                    let (_, _, next) = floors.iter().find(|(name, _, _)| *name == dep.name)?;
+                    let metadata = UpgradeMetadata {
+                        version: Some(next.clone()),
+                        ..metadata
+                    };
+                    Some((dep, metadata))
+                })
+                .collect(),
+        )
+    }
+
+    /// The requirements currently written in the manifest, for the dependencies whose new version
+    /// is looked up rather than given by the user.
+    fn old_requirements(&self) -> HashMap<String, String> {
+        self.0
+            .iter()
+            .filter(|(_, metadata)| metadata.version.is_none())
+            .map(|(dep, metadata)| (dep.name.clone(), metadata.old_req.clone()))
+            .collect()
+    }
+
+    /// Check that the versions requested for the dependencies exist, and unless `incompatible` is
+    /// set, that they are semver compatible with the current requirements, for `--precise`.
+    fn check_precise<S: VersionSource>(
+        &self,
+        incompatible: bool,
+        source_for: impl Fn(Option<Url>) -> S,
+    ) -> Result<()> {
+        for (dep, metadata) in &self.0 {
+            let version = match &metadata.version {
+                Some(version) => version,
+                None => continue,
+            };
+            let registry_url = match &metadata.registry {
+                Some(x) => Some(Url::parse(x).map_err(|_| {
+                    ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
+                })?),
+                None => None,
+            };
+            verify_version_exists(
+                &dep.name,
+                &format!("={}", version),
+                &source_for(registry_url),
+            )?;
+            let compatible = ranges::is_subset(
+                &format!("={}", version),
+                &compatible_requirement(&metadata.old_req),
+            )?;
+            if !incompatible && !compatible {
+                return Err(ErrorKind::PreciseIncompatible(
+                    dep.name.clone(),
+                    version.clone(),
+                    metadata.old_req.clone(),
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// The registries of the dependencies, `None` for the ones without an explicit registry.
+    fn registries(&self) -> HashMap<String, Option<String>> {
+        self.0
+            .iter()
+            .map(|(dep, metadata)| (dep.name.clone(), metadata.registry.clone()))
+            .collect()
+    }
+
+    /// Keep only the dependencies from the registry at `registry`, or the ones without an
+    /// explicit registry if `registry` is `None`.
+    fn retain_registry(self, registry: Option<&Url>) -> Self {
+        DesiredUpgrades(
+            self.0
+                .into_iter()
+                .filter(|(_, metadata)| {
+                    let url = metadata
+                        .registry
+                        .as_ref()
+                        .and_then(|url| Url::parse(url).ok());
+                    url.as_ref() == registry
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Whether upgrading from the requirement `old_req` to `new_version` is a breaking change.
+fn is_breaking(old_req: &str, new_version: &str) -> bool {
+    match (
+        semver::VersionReq::parse(old_req),
+        semver::Version::parse(new_version),
+    ) {
+        (Ok(req), Ok(version)) => !req.matches(&version),
+        _ => false,
+    }
+}
+
+/// Whether to ask about upgrades on stdin: when it is a terminal, or `CARGO_EDIT_INTERACTIVE` is
+/// set, e.g. to script the answers.
+fn is_interactive() -> bool {
+    atty::is(atty::Stream::Stdin) || std::env::var_os("CARGO_EDIT_INTERACTIVE").is_some()
+}
+
+/// Ask on the terminal whether to perform a breaking upgrade.
+fn confirm(name: &str, old_req: &str, new_version: &str) -> Result<bool> {
+    print!(
+        "Upgrade {} from {} to {}? This is a breaking change [y/N] ",
+        name, old_req, new_version
+    );
+    std::io::stdout()
+        .flush()
+        .chain_err(|| "Failed to flush stdout")?;
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .chain_err(|| "Failed to read answer")?;
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
+/// Drop the breaking upgrades that were not confirmed. Unless `yes` is set, each one is confirmed
+/// on the terminal, or skipped with a warning when not running in one.
+fn confirm_breaking_upgrades(
+    upgrades: ActualUpgrades,
+    old_reqs: &HashMap<String, String>,
+    yes: bool,
+) -> Result<ActualUpgrades> {
+    if yes {
+        return Ok(upgrades);
+    }
+    let interactive = is_interactive();
+
+    // Ask in a stable order.
+    let mut upgrades = upgrades.0.into_iter().collect::<Vec<_>>();
+    upgrades.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+
+    let mut confirmed = HashMap::new();
+    for (dep, new_version) in upgrades {
+        if let Some(old_req) = old_reqs.get(&dep.name) {
+            if is_breaking(old_req, &new_version) {
+                if !interactive {
+                    warn(format!(
+                        "Skipping {}: {} -> {} is a breaking change, use --yes to upgrade it",
+                        dep.name, old_req, new_version
+                    ))?;
+                    continue;
+                }
+                if !confirm(&dep.name, old_req, &new_version)? {
+                    continue;
+                }
+            }
+        }
+        confirmed.insert(dep, new_version);
+    }
+    Ok(ActualUpgrades(confirmed))
+}
+
+/// An answer to the prompt of `--interactive`.
+#[derive(Debug, PartialEq)]
+enum Selection {
+    /// Upgrade to this version.
+    Upgrade(String),
+    /// Leave the requirement alone.
+    Skip,
+    /// The answer was not understood.
+    Invalid,
+}
+
+/// Parse an answer to the prompt of `--interactive`: `y` (or nothing) for `new_version`, `n` to
+/// skip, `c` for the latest `compatible` version, or a version.
+fn parse_selection(answer: &str, new_version: &str, compatible: Option<&str>) -> Selection {
+    let answer = answer.trim();
+    match answer.to_lowercase().as_str() {
+        "" | "y" | "yes" => Selection::Upgrade(new_version.to_owned()),
+        "n" | "no" | "s" | "skip" => Selection::Skip,
+        "c" | "compatible" => match compatible {
+            Some(compatible) => Selection::Upgrade(compatible.to_owned()),
+            None => Selection::Invalid,
+        },
+        _ => match semver::Version::parse(answer.trim_start_matches('v')) {
+            Ok(version) => Selection::Upgrade(version.to_string()),
+            Err(_) => Selection::Invalid,
+        },
+    }
+}
+
+/// Ask on the terminal which of the upgrades to perform, and to which version.
+fn select_upgrades(
+    upgrades: ActualUpgrades,
+    old_reqs: &HashMap<String, String>,
+    compatible: &HashMap<String, Option<String>>,
+) -> Result<ActualUpgrades> {
+    if !is_interactive() {
+        return Err(ErrorKind::NotInteractive.into());
+    }
+
+    // Ask in a stable order.
+    let mut upgrades = upgrades.0.into_iter().collect::<Vec<_>>();
+    upgrades.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+
+    let mut selected = HashMap::new();
+    for (dep, new_version) in upgrades {
+        let old_req = old_reqs.get(&dep.name).map(String::as_str);
+        let compatible = compatible.get(&dep.name).and_then(Option::as_deref);
+        println!(
+            "{} {}: latest compatible {}, latest {}",
+            dep.name,
+            old_req.unwrap_or("(explicit version)"),
+            compatible.unwrap_or("none"),
+            new_version
+        );
+        loop {
+            print!(
+                "Upgrade {} to {}? [Y/n/c/<version>] ",
+                dep.name, new_version
+            );
+            std::io::stdout()
+                .flush()
+                .chain_err(|| "Failed to flush stdout")?;
+            let mut answer = String::new();
+            let read = std::io::stdin()
+                .read_line(&mut answer)
+                .chain_err(|| "Failed to read answer")?;
+            // Skip the rest once the input is closed.
+            if read == 0 {
+                println!();
+                return Ok(ActualUpgrades(selected));
+            }
+            match parse_selection(&answer, &new_version, compatible) {
+                Selection::Upgrade(version) => {
+                    selected.insert(dep, version);
+                    break;
+                }
+                Selection::Skip => break,
+                Selection::Invalid => println!(
+                    "    Invalid answer `{}`, enter y, n, c or a version",
+                    answer.trim()
+                ),
+            }
+        }
+    }
+    Ok(ActualUpgrades(selected))
+}
+
+/// Read the versions of all packages in the `Cargo.lock` closest to `manifest_path`.
+fn read_locked_versions(manifest_path: &Path) -> Result<HashMap<String, Vec<String>>> {
+    let lockfile = manifest_path
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|path| path.is_file())
+        .ok_or(ErrorKind::MissingLockfile)?;
+    let contents = std::fs::read_to_string(&lockfile).chain_err(|| "Failed to read Cargo.lock")?;
+    let lock: toml_edit::Document = contents.parse().chain_err(|| "Invalid Cargo.lock")?;
+
+    let mut locked = HashMap::new();
+    if let Some(packages) = lock["package"].as_array_of_tables() {
+        for package in packages.iter() {
+            if let (Some(name), Some(version)) =
+                (package["name"].as_str(), package["version"].as_str())
+            {
+                locked
+                    .entry(name.to_owned())
+                    .or_insert_with(Vec::new)
+                    .push(version.to_owned());
+            }
+        }
+    }
+    Ok(locked)
+}
+
+/// Make sure that every upgraded requirement is still satisfied by a locked version, so that the
+/// lock file stays valid. Requirements that only widen the old one can't invalidate it.
+fn check_frozen(
+    upgrades: &ActualUpgrades,
+    old_reqs: &HashMap<String, String>,
+    manifest_path: &Path,
+) -> Result<()> {
+    let locked = read_locked_versions(manifest_path)?;
+    for (dep, new_req) in &upgrades.0 {
+        if let Some(old_req) = old_reqs.get(&dep.name) {
+            if ranges::is_subset(old_req, new_req).unwrap_or(false) {
+                continue;
+            }
+        }
+        let versions = match locked.get(&dep.name) {
+            Some(versions) => versions,
+            // Not locked yet, so there is nothing to invalidate.
+            None => continue,
+        };
+        let req = semver::VersionReq::parse(new_req).chain_err(|| {
+            ::cargo_edit::ErrorKind::ParseVersion(new_req.clone(), dep.name.clone())
+        })?;
+        let satisfied = versions.iter().any(|version| {
+            semver::Version::parse(version)
+                .map(|version| req.matches(&version))
+                .unwrap_or(false)
+        });
+        if !satisfied {
+            return Err(ErrorKind::LockedVersionIncompatible(
+                dep.name.clone(),
+                versions.join(", "),
+                new_req.clone(),
             )
-            .collect::<Result<_>>()
-            .map(ActualUpgrades)
+            .into());
+        }
     }
+    Ok(())
+}
+
+/// Get the lowest `package.rust-version` of `manifests`, following `rust-version.workspace = true`
+/// to the workspace root.
+fn lowest_rust_version<'a>(
+    manifests: impl IntoIterator<Item = &'a LocalManifest>,
+) -> Result<Option<String>> {
+    let mut lowest: Option<(semver::Version, String)> = None;
+    for manifest in manifests {
+        if let Some(rust_version) = manifest.resolved_rust_version(&manifest.path) {
+            let version = parse_rust_version(&rust_version)?;
+            if lowest.as_ref().is_none_or(|(lowest, _)| version < *lowest) {
+                lowest = Some((version, rust_version));
+            }
+        }
+    }
+    Ok(lowest.map(|(_, rust_version)| rust_version))
+}
+
+/// Get the Rust version to filter upgrades by from `--rust-version`.
+///
+/// `active` is looked up with `active`, falling back to `manifest_default` with a warning if that
+/// fails. Without `--rust-version`, `manifest_default` is used.
+fn resolve_rust_version(
+    arg: Option<String>,
+    active: impl FnOnce() -> Option<String>,
+    manifest_default: impl FnOnce() -> Result<Option<String>>,
+) -> Result<Option<String>> {
+    match arg.as_deref() {
+        Some("active") => match active() {
+            Some(rust_version) => Ok(Some(rust_version)),
+            None => {
+                warn("Could not determine the version of `rustc`, using `rust-version`")?;
+                manifest_default()
+            }
+        },
+        Some(_) => Ok(arg),
+        None => manifest_default(),
+    }
+}
+
+/// Ask the active toolchain's `rustc` (or `RUSTC`) for its version. This runs `rustc` once per
+/// invocation of `cargo upgrade`.
+fn active_rust_version() -> Option<String> {
+    let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = process::Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_rustc_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Extract the version from the output of `rustc --version`, e.g. `1.70.0` from
+/// `rustc 1.70.0 (90c541806 2023-05-31)`. Pre-release tags like `-nightly` are dropped.
+fn parse_rustc_version(output: &str) -> Option<String> {
+    let version = output.split_whitespace().nth(1)?;
+    let version = version.split('-').next()?;
+    parse_rust_version(version).ok()?;
+    Some(version.to_owned())
+}
+
+/// Get the URL of the registry called `name`, or `None` for `crates-io`, which is what cargo
+/// metadata reports for dependencies without an explicit registry.
+fn named_registry_url(
+    manifest_path: &Option<PathBuf>,
+    name: &str,
+    lenient_config: bool,
+) -> Result<Option<Url>> {
+    if name == "crates-io" {
+        return Ok(None);
+    }
+    let manifest_path = find(manifest_path)?;
+    let url = if lenient_config {
+        registry_url_lenient(&manifest_path, Some(name))?
+    } else {
+        registry_url(&manifest_path, Some(name))?
+    };
+    Ok(Some(url))
+}
+
+/// Get the URL of the registry used for dependencies without an explicit registry, which is the
+/// one configured as `registry.default` if there is one, or crates.io. If it is replaced with a
+/// vendor directory, which only has the versions vendored before, it is the original registry.
+fn default_registry_url(manifest_path: &Option<PathBuf>, lenient_config: bool) -> Result<Url> {
+    let manifest_path = find(manifest_path)?;
+    let default = default_registry_name(&manifest_path)?;
+    Ok(upstream_registry_url(
+        &manifest_path,
+        default.as_deref(),
+        lenient_config,
+    )?)
+}
+
+/// Get the directory of vendored crates that the registry used for dependencies without an
+/// explicit registry is replaced with, if there is one.
+fn default_vendor_directory(
+    manifest_path: &Option<PathBuf>,
+    lenient_config: bool,
+) -> Result<Option<PathBuf>> {
+    let manifest_path = find(manifest_path)?;
+    let default = default_registry_name(&manifest_path)?;
+    Ok(vendor_directory(
+        &manifest_path,
+        default.as_deref(),
+        lenient_config,
+    )?)
+}
+
+/// Get the registry to look up dependencies without an explicit registry in, if the lookups
+/// can't find it themselves: when `registry.default` names a registry other than crates.io, when
+/// invalid cargo configs have to be skipped, or when the registry is replaced with a vendor
+/// directory.
+fn explicit_default_registry(
+    manifest_path: &Option<PathBuf>,
+    lenient_config: bool,
+) -> Result<Option<Url>> {
+    if lenient_config
+        || default_registry_name(&find(manifest_path)?)?.is_some()
+        || default_vendor_directory(manifest_path, lenient_config)?.is_some()
+    {
+        Ok(Some(default_registry_url(manifest_path, lenient_config)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Refresh the vendored crates with `--revendor` after the manifests were changed, if the
+/// registry is replaced with a vendor directory, or else remind that they may be stale.
+fn refresh_vendor_directory(
+    manifest_path: &Option<PathBuf>,
+    lenient_config: bool,
+    revendor_crates: bool,
+    quiet: bool,
+) -> Result<()> {
+    let directory = match default_vendor_directory(manifest_path, lenient_config)? {
+        Some(directory) => directory,
+        None => return Ok(()),
+    };
+    if revendor_crates {
+        revendor(&find(manifest_path)?, &directory)?;
+    } else if !quiet {
+        println!(
+            "NOTE: The dependencies are vendored in `{}`, which may be out of date now. Run \
+             `cargo vendor` or add `--revendor` to refresh it.",
+            directory.display()
+        );
+    }
+    Ok(())
+}
+
+/// Fail with `PendingUpgrades` for `--exit-code` if `changed` requirements would be upgraded.
+/// Print the requirements that changed according to `records`, grouped by crate, with the
+/// package or path of each manifest from `package_names`. Then count the crates of `declared`
+/// that were upgraded, the ones of `resolved` that already were up to date, and the rest, which
+/// were skipped.
+fn print_summary(
+    records: &[serde_json::Value],
+    package_names: &HashMap<PathBuf, String>,
+    resolved: &BTreeSet<String>,
+    declared: &BTreeSet<String>,
+) {
+    let mut by_crate = BTreeMap::<&str, Vec<String>>::new();
+    for record in records {
+        let manifest = record["manifest"].as_str().unwrap_or_default();
+        let member = package_names
+            .get(Path::new(manifest))
+            .cloned()
+            .unwrap_or_else(|| manifest.to_owned());
+        let table = record["table"]
+            .as_array()
+            .map(|table| {
+                table
+                    .iter()
+                    .filter_map(|segment| segment.as_str().map(String::from))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let table = match table_display(&table).as_str() {
+            "dependencies" => String::new(),
+            table => format!(" ({})", table),
+        };
+        by_crate
+            .entry(record["crate"].as_str().unwrap_or_default())
+            .or_default()
+            .push(format!(
+                "{}{}: {} -> {}",
+                member,
+                table,
+                record["old_requirement"].as_str().unwrap_or("*"),
+                record["requirement"].as_str().unwrap_or("*")
+            ));
+    }
+
+    println!("Summary:");
+    for (name, changes) in &by_crate {
+        println!("    {}", name);
+        for change in changes {
+            println!("        {}", change);
+        }
+    }
+    let unchanged = resolved
+        .iter()
+        .filter(|name| !by_crate.contains_key(name.as_str()))
+        .count();
+    let skipped = declared
+        .iter()
+        .filter(|name| !by_crate.contains_key(name.as_str()) && !resolved.contains(*name))
+        .count();
+    println!(
+        "    {} upgraded, {} unchanged, {} skipped (pinned, excluded, `*` or not confirmed)",
+        by_crate.len(),
+        unchanged,
+        skipped
+    );
+}
+
+fn pending_upgrades(changed: usize, exit_code: bool) -> Result<()> {
+    if exit_code && changed > 0 {
+        Err(ErrorKind::PendingUpgrades(changed).into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Get the requirement that pins `old_req` to the `locked` version it resolved to, keeping its
+/// operator so that it doesn't allow any version `old_req` excludes, e.g. `~1.2` becomes `~1.2.5`.
+/// Caret, wildcard and lower bound requirements become the plain locked version.
+///
+/// Returns `None` for requirements with an upper bound or several comparators, like `<1.5`.
+fn tightened_requirement(old_req: &str, locked: &str) -> Option<String> {
+    if old_req.contains(',') {
+        return None;
+    }
+    let old_req = old_req.trim();
+    let op_end = old_req
+        .find(|c: char| c.is_ascii_digit() || c == '*')
+        .unwrap_or(old_req.len());
+    match old_req[..op_end].trim() {
+        "" | "^" | ">" | ">=" => Some(locked.to_owned()),
+        op @ "~" | op @ "=" => Some(format!("{}{}", op, locked)),
+        _ => None,
+    }
+}
+
+/// The version of a git tag like `v1.2.0` or `1.2.0`.
+fn tag_version(tag: &str) -> Option<semver::Version> {
+    semver::Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok()
+}
+
+/// Whether the `rev` of a git dependency is a commit hash rather than a reference name.
+fn is_commit_hash(rev: &str) -> bool {
+    (7..=40).contains(&rev.len()) && rev.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// The commit hash `rev` moved to the commit `head`, abbreviated to as many digits as `rev`, or
+/// `None` if it already is `head`.
+fn upgraded_rev(rev: &str, head: &str) -> Option<String> {
+    let new = &head[..rev.len().min(head.len())];
+    Some(new.to_owned()).filter(|new| !new.eq_ignore_ascii_case(rev))
 }
 
 /// Main processing function. Allows us to return a `Result` so that `main` can print pretty error
@@ -402,48 +2551,393 @@ fn process(args: Args) -> Result<()> {
         dependency,
         manifest_path,
         all,
+        package,
+        exclude_member,
+        exclude,
         allow_prerelease,
+        prerelease_channel,
         dry_run,
+        exit_code,
+        show_diff,
+        report_file,
         skip_compatible,
+        compatible_only,
+        incompatible,
+        preserve_precision,
+        req_style,
         to_lockfile,
+        tighten_only,
+        timeout,
+        jobs,
+        lenient_config,
+        force,
+        skip_pinned,
+        pin_wildcards,
+        tidy,
+        normalize,
+        changelog,
+        to_registry,
+        rust_version,
+        ignore_rust_version,
+        frozen,
+        yes,
+        minimal,
+        to,
+        precise,
+        compatible_with,
+        fix_yanked,
+        allow_yanked,
+        avoid_advisories,
+        fix_advisories,
+        advisory_db,
+        audit_yanks,
+        list_sources,
+        message_format,
+        check,
+        quiet,
+        strict,
+        registry,
+        git,
+        interactive,
+        summary,
+        registry_api_only,
+        revendor,
         ..
     } = args;
+    // Records replace the messages, except for `--list-sources`, which has records of its own.
+    let json = message_format.as_deref() == Some("json");
+    // `--incompatible` only spells out the default.
+    let compatible_only = compatible_only && !incompatible;
+    configure_warnings(strict, quiet || json);
+    configure_offline(args.offline || args.locked_index);
+    configure_index_cache(!args.no_cache);
+    hide_status_messages(summary);
+    // `--skip-pinned` only spells out the default.
+    let force = force && !skip_pinned;
+    let timeout = timeout
+        .map(Duration::from_secs)
+        .unwrap_or_else(get_default_timeout);
+    let options = UpgradeOptions {
+        exclude: &exclude,
+        dry_run,
+        show_diff,
+        skip_compatible,
+        preserve_precision,
+        req_style,
+        tidy,
+        normalize,
+        report_file: report_file.as_deref(),
+        json,
+        summary,
+    };
 
-    if !args.offline && !to_lockfile && std::env::var("CARGO_IS_TEST").is_err() {
-        let url = registry_url(&find(&manifest_path)?, None)?;
-        update_registry_index(&url)?;
+    let update_index: fn(&Url, Duration) -> ::cargo_edit::Result<()> = if quiet || json {
+        update_registry_index_quietly
+    } else {
+        update_registry_index
+    };
+    let refresh_index = args.refresh_index.map(Duration::from_secs);
+    let may_update = (!is_offline() || refresh_index.is_some())
+        && !args.no_update
+        && !args.locked_index
+        && !registry_api_only
+        && std::env::var("CARGO_IS_TEST").is_err();
+    let should_update = |url: &Url| -> Result<bool> {
+        Ok(refresh_index.map_or(Ok(true), |max_age| index_is_stale(url, max_age))?)
+    };
+    if may_update && !to_lockfile && !git {
+        let url = default_registry_url(&manifest_path, lenient_config)?;
+        if should_update(&url)? {
+            update_index(&url, timeout)?;
+        }
     }
 
-    let manifests = if all {
-        Manifests::get_all(&manifest_path)
+    // The precise version is requested like `<dependency>@<version>`, which also upgrades pinned
+    // requirements.
+    let dependency = match &precise {
+        Some(version) => {
+            if dependency.len() != 1 || dependency[0].contains('@') {
+                return Err(ErrorKind::PreciseNeedsOneDependency.into());
+            }
+            semver::Version::parse(version).chain_err(|| {
+                ::cargo_edit::ErrorKind::ParseVersion(version.clone(), dependency[0].clone())
+            })?;
+            vec![format!("{}@{}", dependency[0], version)]
+        }
+        None => dependency,
+    };
+
+    let manifests = if all || !package.is_empty() || !exclude_member.is_empty() {
+        Manifests::get_all(&manifest_path)?.select_members(&package, &exclude_member)
     } else {
         Manifests::get_local_one(&manifest_path)
     }?;
 
-    if to_lockfile {
-        manifests.sync_to_lockfile(dry_run, skip_compatible)
+    if list_sources {
+        manifests.list_sources(json)
+    } else if git {
+        let changed = manifests.upgrade_git(&dependency, &exclude, timeout, dry_run, show_diff)?;
+        pending_upgrades(changed, exit_code)
+    } else if to_lockfile {
+        let changed = manifests.sync_to_lockfile(&options, force, tighten_only)?;
+        pending_upgrades(changed, exit_code)
+    } else if to_registry {
+        let default_registry = explicit_default_registry(&manifest_path, lenient_config)?;
+        manifests.sync_to_registry(&dependency, allow_prerelease, &default_registry, dry_run)
     } else {
-        let existing_dependencies = manifests.get_dependencies(dependency)?;
+        let package_names = manifests.package_names();
+        let declared = manifests.registry_dependency_names(&dependency);
+        let (mut existing_dependencies, pinned) =
+            manifests.get_dependencies(dependency, &exclude, force, quiet || json || summary)?;
+        if let Some(name) = &registry {
+            let url = named_registry_url(&manifest_path, name, lenient_config)?;
+            existing_dependencies = existing_dependencies.retain_registry(url.as_ref());
+        }
 
         // Update indices for any alternative registries, unless
-        // we're offline.
-        if !args.offline && std::env::var("CARGO_IS_TEST").is_err() {
+        // we're offline, asked not to, or they are fresh enough.
+        if may_update {
             for registry_url in existing_dependencies
                 .0
                 .values()
                 .filter_map(|UpgradeMetadata { registry, .. }| registry.as_ref())
                 .collect::<HashSet<_>>()
             {
-                update_registry_index(&Url::parse(registry_url).map_err(|_| {
+                let url = Url::parse(registry_url).map_err(|_| {
                     ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
-                })?)?;
+                })?;
+                if should_update(&url)? {
+                    update_index(&url, timeout)?;
+                }
+            }
+        }
+
+        let default_registry = explicit_default_registry(&manifest_path, lenient_config)?;
+        let rust_version = if ignore_rust_version {
+            None
+        } else {
+            resolve_rust_version(rust_version, active_rust_version, || {
+                manifests.rust_version()
+            })?
+        };
+        let root_manifest_path = find(&manifest_path)?;
+        let index_rev = match &args.index_rev {
+            Some(rev) => Some(rev.clone()),
+            None => LocalManifest::try_new(&root_manifest_path)?
+                .edit_settings(&root_manifest_path)
+                .index_rev()
+                .map(String::from),
+        };
+        let advisories = if avoid_advisories || fix_advisories || advisory_db.is_some() {
+            let db = match &advisory_db {
+                Some(path) => AdvisoryDatabase::open(path),
+                None => AdvisoryDatabase::fetch(&AdvisoryDatabase::default_path()?, timeout),
+            };
+            Some(Arc::new(
+                db.chain_err(|| "Failed to read the advisory database")?,
+            ))
+        } else {
+            None
+        };
+        // Versions with advisories are left out, except to find the dependencies that match them.
+        let source_with_advisories = |registry: Option<Url>, skip_advisories: bool| {
+            let mut index = RegistryIndex::new(
+                &root_manifest_path,
+                &registry.or_else(|| default_registry.clone()),
+            );
+            if let Some(rev) = &index_rev {
+                index = index.at_rev(rev);
+            }
+            if let Some(channel) = &prerelease_channel {
+                index = index.prerelease_channel(channel);
+            }
+            if allow_yanked {
+                index = index.allow_yanked();
+            }
+            if registry_api_only {
+                index = index.api_only();
+            }
+            if let (true, Some(advisories)) = (skip_advisories, &advisories) {
+                index = index.skip_advisories(Arc::clone(advisories));
+            }
+            match refresh_index {
+                Some(max_age) => index.refresh_after(max_age),
+                None => index,
+            }
+        };
+        let source_for = |registry: Option<Url>| source_with_advisories(registry, true);
+
+        if check {
+            let outdated = existing_dependencies.get_outdated(
+                allow_prerelease,
+                rust_version.as_deref(),
+                source_for,
+            )?;
+            if !quiet {
+                for (name, old_req, latest) in &outdated {
+                    println!("{}: {} -> {}", name, old_req, latest);
+                }
+            }
+            return if outdated.is_empty() {
+                Ok(())
+            } else {
+                Err(ErrorKind::OutdatedDependencies(outdated.len()).into())
+            };
+        }
+
+        if audit_yanks {
+            let yanked = existing_dependencies.get_yanked_selections(source_for)?;
+            for (name, old_req, version) in &yanked {
+                println!("{}: {} selects yanked {}", name, old_req, version);
+            }
+            println!(
+                "{} of {} dependencies select a yanked version",
+                yanked.len(),
+                existing_dependencies.0.len()
+            );
+            return if yanked.is_empty() {
+                Ok(())
+            } else {
+                Err(ErrorKind::YankedSelections(yanked.len()).into())
+            };
+        }
+
+        if precise.is_some() {
+            existing_dependencies.check_precise(incompatible, source_for)?;
+        }
+
+        let floors = existing_dependencies.get_yanked_floors(allow_prerelease, source_for)?;
+        for (name, old_req, next) in &floors {
+            if fix_yanked && !json {
+                println!(
+                    "    Widening {}: every version matching `{}` is yanked, upgrading to {}",
+                    name, old_req, next
+                );
+            } else if !fix_yanked {
+                warn(format!(
+                    "Every version of `{}` matching `{}` is yanked, run with `--fix-yanked` to \
+                     upgrade to {}",
+                    name, old_req, next
+                ))?;
+            }
+        }
+        if fix_yanked {
+            existing_dependencies = existing_dependencies.keep_fixes(&floors);
+        }
+
+        if let (true, Some(advisories)) = (fix_advisories, &advisories) {
+            let fixes = existing_dependencies.get_advisory_fixes(
+                advisories,
+                allow_prerelease,
+                |registry| source_with_advisories(registry, false),
+            )?;
+            for (name, old_req, next, ids) in &fixes {
+                if !json {
+                    println!(
+                        "    Fixing {}: `{}` matches versions affected by {}, upgrading to {}",
+                        name,
+                        old_req,
+                        ids.join(", "),
+                        next
+                    );
+                }
+            }
+            let fixes: Vec<_> = fixes
+                .into_iter()
+                .map(|(name, old_req, next, _)| (name, old_req, next))
+                .collect();
+            existing_dependencies = existing_dependencies.keep_fixes(&fixes);
+        }
+
+        // `*` already matches the latest version, so it is only replaced when asked to.
+        if !pin_wildcards {
+            let (rest, wildcards) = existing_dependencies.split_wildcards();
+            existing_dependencies = rest;
+            for (name, latest) in
+                wildcards.get_latest(allow_prerelease, rust_version.as_deref(), source_for)?
+            {
+                if !json {
+                    println!(
+                        "{}: `*` matches the latest version {}, use --pin-wildcards to pin it",
+                        name, latest
+                    );
+                }
             }
         }
 
-        let upgraded_dependencies =
-            existing_dependencies.get_upgraded(allow_prerelease, &find(&manifest_path)?)?;
+        let peer = match &compatible_with {
+            Some(name) => Some((
+                name.clone(),
+                manifests.peer_requirement(name, &root_manifest_path)?,
+            )),
+            None => None,
+        };
 
-        manifests.upgrade(&upgraded_dependencies, dry_run, skip_compatible)
+        let old_reqs = existing_dependencies.old_requirements();
+        let registries = existing_dependencies.registries();
+        let latest_compatible = if interactive {
+            existing_dependencies.get_latest_compatible(
+                allow_prerelease,
+                rust_version.as_deref(),
+                source_for,
+            )?
+        } else {
+            HashMap::new()
+        };
+        let selection = VersionSelection {
+            allow_prerelease,
+            rust_version: rust_version.as_deref(),
+            minimal,
+            constraint: to.as_deref(),
+            compatible_only,
+            peer: peer
+                .as_ref()
+                .map(|(name, req)| (name.as_str(), req.as_str())),
+        };
+        let upgraded_dependencies = existing_dependencies.get_upgraded(
+            selection,
+            jobs.unwrap_or(DEFAULT_LOOKUP_JOBS),
+            source_for,
+        )?;
+        // The answers already decide about the breaking upgrades.
+        let upgraded_dependencies = if interactive {
+            let selected = select_upgrades(upgraded_dependencies, &old_reqs, &latest_compatible)?;
+            if frozen {
+                check_frozen(&selected, &old_reqs, &root_manifest_path)?;
+            }
+            selected
+        } else {
+            // Don't ask about upgrades that `--frozen` refuses anyway.
+            if frozen {
+                check_frozen(&upgraded_dependencies, &old_reqs, &root_manifest_path)?;
+            }
+            // `--skip-compatible` asks for nothing but the breaking upgrades.
+            confirm_breaking_upgrades(upgraded_dependencies, &old_reqs, yes || skip_compatible)?
+        };
+        let changelogs = if changelog {
+            Changelogs::get(
+                &upgraded_dependencies,
+                &registries,
+                jobs.unwrap_or(DEFAULT_LOOKUP_JOBS),
+                source_for,
+            )
+        } else {
+            Changelogs::default()
+        };
+
+        let resolved = upgraded_dependencies
+            .0
+            .keys()
+            .map(|dependency| dependency.name.clone())
+            .collect();
+        let records = manifests.upgrade(&upgraded_dependencies, &pinned, &changelogs, &options)?;
+        if summary {
+            print_summary(&records, &package_names, &resolved, &declared);
+        }
+        if !dry_run && !records.is_empty() {
+            refresh_vendor_directory(&manifest_path, lenient_config, revendor, quiet || json)?;
+        }
+        pending_upgrades(records.len(), exit_code)
     }
 }
 
@@ -452,6 +2946,11 @@ fn main() {
     let Command::Upgrade(args) = args;
 
     if let Err(err) = process(args) {
+        match err.kind() {
+            ErrorKind::OutdatedDependencies(_) | ErrorKind::PendingUpgrades(_) => process::exit(2),
+            _ => {}
+        }
+
         eprintln!("Command failed due to unhandled error: {}\n", err);
 
         for e in err.iter().skip(1) {
@@ -465,3 +2964,596 @@ fn main() {
         process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargo_edit::{Advisory, CrateVersion};
+    use std::fs;
+
+    #[test]
+    fn detect_registry_sources() {
+        assert!(is_registry_source(
+            "registry+https://github.com/rust-lang/crates.io-index"
+        ));
+        assert!(is_registry_source(
+            "sparse+https://my-registry.example/index/"
+        ));
+        assert!(!is_registry_source(
+            "git+https://github.com/killercup/cargo-edit?branch=master"
+        ));
+        assert!(!is_registry_source("path+file:///tmp/cargo-edit"));
+    }
+
+    #[test]
+    fn parse_interactive_selection() {
+        let upgrade = |v: &str| Selection::Upgrade(v.to_owned());
+        assert_eq!(parse_selection("\n", "2.0.0", None), upgrade("2.0.0"));
+        assert_eq!(parse_selection("Y\n", "2.0.0", None), upgrade("2.0.0"));
+        assert_eq!(parse_selection("n", "2.0.0", None), Selection::Skip);
+        assert_eq!(
+            parse_selection("c", "2.0.0", Some("1.4.0")),
+            upgrade("1.4.0")
+        );
+        assert_eq!(parse_selection("c", "2.0.0", None), Selection::Invalid);
+        assert_eq!(parse_selection(" v1.5.2 ", "2.0.0", None), upgrade("1.5.2"));
+        assert_eq!(parse_selection("later", "2.0.0", None), Selection::Invalid);
+    }
+
+    #[test]
+    fn upgrade_git_pins() {
+        assert_eq!(tag_version("v1.2.0"), semver::Version::parse("1.2.0").ok());
+        assert_eq!(tag_version("nightly"), None);
+
+        let head = "0123456789abcdef0123456789abcdef01234567";
+        assert!(is_commit_hash("abcdef0"));
+        assert!(is_commit_hash(head));
+        assert!(!is_commit_hash("refs/pull/1/head"));
+        assert!(!is_commit_hash("abc"));
+        assert_eq!(upgraded_rev("abcdef0", head).as_deref(), Some("0123456"));
+        assert_eq!(upgraded_rev(head, head), None);
+        assert_eq!(upgraded_rev("0123456", head), None);
+    }
+
+    #[test]
+    fn tighten_requirements_to_locked_versions() {
+        assert_eq!(
+            tightened_requirement("^1.2", "1.2.5").as_deref(),
+            Some("1.2.5")
+        );
+        assert_eq!(
+            tightened_requirement("1.*", "1.4.0").as_deref(),
+            Some("1.4.0")
+        );
+        assert_eq!(
+            tightened_requirement(">= 1.0", "1.4.0").as_deref(),
+            Some("1.4.0")
+        );
+        assert_eq!(
+            tightened_requirement("~1.2", "1.2.5").as_deref(),
+            Some("~1.2.5")
+        );
+        assert_eq!(
+            tightened_requirement("=1.2.0", "1.2.0").as_deref(),
+            Some("=1.2.0")
+        );
+        assert_eq!(tightened_requirement("<1.5", "1.4.0"), None);
+        assert_eq!(tightened_requirement(">=1.0, <1.5", "1.4.0"), None);
+    }
+
+    #[test]
+    fn parse_rustc_versions() {
+        assert_eq!(
+            parse_rustc_version("rustc 1.70.0 (90c541806 2023-05-31)\n").as_deref(),
+            Some("1.70.0")
+        );
+        assert_eq!(
+            parse_rustc_version("rustc 1.72.0-nightly (065a1f5df 2023-06-21)").as_deref(),
+            Some("1.72.0")
+        );
+        assert_eq!(parse_rustc_version("error: no such command"), None);
+    }
+
+    #[test]
+    fn resolve_active_rust_version() {
+        let manifest = || Ok(Some("1.56".to_owned()));
+        let rustc = || Some("1.70.0".to_owned());
+        let no_rustc = || None;
+
+        let active = || Some("active".to_owned());
+        assert_eq!(
+            resolve_rust_version(active(), rustc, manifest)
+                .unwrap()
+                .as_deref(),
+            Some("1.70.0")
+        );
+        // Without a usable `rustc`, the manifest's `rust-version` is used.
+        assert_eq!(
+            resolve_rust_version(active(), no_rustc, manifest)
+                .unwrap()
+                .as_deref(),
+            Some("1.56")
+        );
+        assert_eq!(
+            resolve_rust_version(active(), no_rustc, || Ok(None)).unwrap(),
+            None
+        );
+        // An explicit version doesn't run `rustc`.
+        assert_eq!(
+            resolve_rust_version(
+                Some("1.60".to_owned()),
+                || panic!("rustc must not run"),
+                manifest
+            )
+            .unwrap()
+            .as_deref(),
+            Some("1.60")
+        );
+        assert_eq!(
+            resolve_rust_version(None, || panic!("rustc must not run"), manifest)
+                .unwrap()
+                .as_deref(),
+            Some("1.56")
+        );
+    }
+
+    /// Knows a single crate, `foo`, instead of reading a registry index.
+    struct MockSource;
+
+    impl VersionSource for MockSource {
+        fn versions(&self, crate_name: &str) -> cargo_edit::Result<Vec<CrateVersion>> {
+            match crate_name {
+                "foo" => Ok(["0.1.0", "0.2.0", "0.3.0-beta"]
+                    .iter()
+                    .map(|v| CrateVersion::new("foo", semver::Version::parse(v).unwrap()))
+                    .collect()),
+                _ => Err(cargo_edit::ErrorKind::NoCrate(crate_name.into()).into()),
+            }
+        }
+    }
+
+    #[test]
+    fn check_precise_versions() {
+        let precise = |version: &str| {
+            let mut desired = HashMap::new();
+            desired.insert(
+                Dependency::new("foo"),
+                UpgradeMetadata {
+                    registry: None,
+                    version: Some(version.to_owned()),
+                    is_prerelease: false,
+                    old_req: "^0.1".to_owned(),
+                },
+            );
+            DesiredUpgrades(desired)
+        };
+
+        assert!(precise("0.1.0")
+            .check_precise(false, |_| MockSource)
+            .is_ok());
+        match precise("0.2.0").check_precise(false, |_| MockSource) {
+            Err(Error(ErrorKind::PreciseIncompatible(name, version, req), _)) => {
+                assert_eq!((name.as_str(), version.as_str()), ("foo", "0.2.0"));
+                assert_eq!(req, "^0.1");
+            }
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+        assert!(precise("0.2.0").check_precise(true, |_| MockSource).is_ok());
+        // Versions that were never published are refused either way.
+        assert!(precise("0.1.7")
+            .check_precise(true, |_| MockSource)
+            .is_err());
+    }
+
+    #[test]
+    fn upgrade_from_mock_source() {
+        let tmpdir = tempdir::TempDir::new("upgrade-mock-source").unwrap();
+        let manifest_path = tmpdir.path().join("Cargo.toml");
+        fs::write(
+            &manifest_path,
+            "[package]\nname = \"mock\"\nversion = \"0.1.0\"\n\n[dependencies]\nfoo = \"0.1\"\n",
+        )
+        .unwrap();
+
+        let mut desired = HashMap::new();
+        desired.insert(
+            Dependency::new("foo"),
+            UpgradeMetadata {
+                registry: None,
+                version: None,
+                is_prerelease: false,
+                old_req: "^0.1".to_owned(),
+            },
+        );
+        let upgrades = DesiredUpgrades(desired)
+            .get_upgraded(VersionSelection::default(), 4, |_| MockSource)
+            .unwrap();
+        assert_eq!(upgrades.0.get(&Dependency::new("foo")).unwrap(), "0.2.0");
+
+        let mut manifest = LocalManifest::try_new(&manifest_path).unwrap();
+        for (dep, version) in &upgrades.0 {
+            manifest
+                .upgrade(
+                    &Dependency::new(&dep.name).set_version(version),
+                    false,
+                    false,
+                )
+                .unwrap();
+        }
+        let upgraded = fs::read_to_string(&manifest_path).unwrap();
+        assert!(upgraded.contains("foo = \"0.2.0\""));
+
+        let mut missing = HashMap::new();
+        missing.insert(
+            Dependency::new("bar"),
+            UpgradeMetadata {
+                registry: None,
+                version: None,
+                is_prerelease: false,
+                old_req: "^0.1".to_owned(),
+            },
+        );
+        assert!(DesiredUpgrades(missing)
+            .get_upgraded(VersionSelection::default(), 1, |_| MockSource)
+            .is_err());
+    }
+
+    #[test]
+    fn upgrade_compatible_only_from_mock_source() {
+        let desired = |old_req: &str| {
+            let mut desired = HashMap::new();
+            desired.insert(
+                Dependency::new("foo"),
+                UpgradeMetadata {
+                    registry: None,
+                    version: None,
+                    is_prerelease: false,
+                    old_req: old_req.to_owned(),
+                },
+            );
+            DesiredUpgrades(desired)
+        };
+
+        let upgrades = desired("^0.1")
+            .get_upgraded(
+                VersionSelection {
+                    compatible_only: true,
+                    ..VersionSelection::default()
+                },
+                1,
+                |_| MockSource,
+            )
+            .unwrap();
+        assert_eq!(upgrades.0.get(&Dependency::new("foo")).unwrap(), "0.1.0");
+        let upgrades = desired("^0.1")
+            .get_upgraded(VersionSelection::default(), 1, |_| MockSource)
+            .unwrap();
+        assert_eq!(upgrades.0.get(&Dependency::new("foo")).unwrap(), "0.2.0");
+    }
+
+    #[test]
+    fn annotate_records_with_changelogs() {
+        let mut repositories = HashMap::new();
+        repositories.insert(
+            "serde".to_owned(),
+            (
+                "https://github.com/serde-rs/serde".to_owned(),
+                "1.0.104".to_owned(),
+            ),
+        );
+        repositories.insert(
+            "internal".to_owned(),
+            (
+                "https://git.example.com/internal".to_owned(),
+                "0.4.0".to_owned(),
+            ),
+        );
+        let changelogs = Changelogs(repositories);
+
+        let mut serde = serde_json::json!({"crate": "serde", "old_requirement": "1.0"});
+        changelogs.annotate(&mut serde);
+        assert_eq!(serde["repository"], "https://github.com/serde-rs/serde");
+        assert_eq!(
+            serde["changelog"],
+            "https://github.com/serde-rs/serde/compare/v1.0.0...v1.0.104"
+        );
+
+        let mut internal = serde_json::json!({"crate": "internal", "old_requirement": "0.3"});
+        changelogs.annotate(&mut internal);
+        assert_eq!(internal["repository"], "https://git.example.com/internal");
+        assert_eq!(internal["changelog"], serde_json::Value::Null);
+
+        let mut log = serde_json::json!({"crate": "log", "old_requirement": "0.4"});
+        changelogs.annotate(&mut log);
+        assert!(log.get("repository").is_none());
+    }
+
+    #[test]
+    fn report_latest_version_of_wildcard_requirements() {
+        let mut desired = HashMap::new();
+        for (name, old_req, version) in &[
+            ("foo", "*", None),
+            ("bar", "^0.1", None),
+            ("baz", "*", Some("1.0.0")),
+        ] {
+            desired.insert(
+                Dependency::new(name),
+                UpgradeMetadata {
+                    registry: None,
+                    version: version.map(String::from),
+                    is_prerelease: false,
+                    old_req: (*old_req).to_owned(),
+                },
+            );
+        }
+        let (rest, wildcards) = DesiredUpgrades(desired).split_wildcards();
+
+        // Explicitly requested versions are still applied.
+        let mut rest = rest
+            .0
+            .keys()
+            .map(|dep| dep.name.clone())
+            .collect::<Vec<_>>();
+        rest.sort();
+        assert_eq!(rest, vec!["bar", "baz"]);
+        assert_eq!(
+            wildcards.get_latest(false, None, |_| MockSource).unwrap(),
+            vec![("foo".to_owned(), "0.2.0".to_owned())]
+        );
+    }
+
+    /// Knows `foo`, whose latest version needs Rust 1.60.
+    struct RustVersionSource;
+
+    impl VersionSource for RustVersionSource {
+        fn versions(&self, crate_name: &str) -> cargo_edit::Result<Vec<CrateVersion>> {
+            Ok([("0.1.0", "1.50"), ("0.2.0", "1.56"), ("0.3.0", "1.60")]
+                .iter()
+                .map(|&(version, rust_version)| CrateVersion {
+                    rust_version: Some(rust_version.to_owned()),
+                    ..CrateVersion::new(crate_name, semver::Version::parse(version).unwrap())
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn upgrade_for_rust_version_inherited_from_workspace() {
+        let tmpdir = tempdir::TempDir::new("upgrade-inherited-rust-version").unwrap();
+        fs::write(
+            tmpdir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"member\"]\n\n\
+             [workspace.package]\nrust-version = \"1.56\"\n",
+        )
+        .unwrap();
+        let member_path = tmpdir.path().join("member").join("Cargo.toml");
+        fs::create_dir_all(member_path.parent().unwrap()).unwrap();
+        fs::write(
+            &member_path,
+            "[package]\nname = \"member\"\nversion = \"0.1.0\"\n\
+             rust-version = { workspace = true }\n\n\
+             [dependencies]\nfoo = \"0.1\"\n",
+        )
+        .unwrap();
+
+        let member = LocalManifest::try_new(&member_path).unwrap();
+        let rust_version = lowest_rust_version(vec![&member]).unwrap();
+        assert_eq!(rust_version.as_deref(), Some("1.56"));
+
+        let mut desired = HashMap::new();
+        desired.insert(
+            Dependency::new("foo"),
+            UpgradeMetadata {
+                registry: None,
+                version: None,
+                is_prerelease: false,
+                old_req: "^0.1".to_owned(),
+            },
+        );
+        let upgrades = DesiredUpgrades(desired)
+            .get_upgraded(
+                VersionSelection {
+                    rust_version: rust_version.as_deref(),
+                    ..VersionSelection::default()
+                },
+                1,
+                |_| RustVersionSource,
+            )
+            .unwrap();
+        assert_eq!(upgrades.0.get(&Dependency::new("foo")).unwrap(), "0.2.0");
+    }
+
+    /// Knows `foo`, whose whole `1.2` line is yanked.
+    struct YankedSource;
+
+    impl VersionSource for YankedSource {
+        fn versions(&self, crate_name: &str) -> cargo_edit::Result<Vec<CrateVersion>> {
+            Ok([
+                ("1.2.0", true),
+                ("1.2.1", true),
+                ("1.3.0", false),
+                ("1.4.0", false),
+            ]
+            .iter()
+            .map(|&(version, yanked)| CrateVersion {
+                yanked,
+                ..CrateVersion::new(crate_name, semver::Version::parse(version).unwrap())
+            })
+            .collect())
+        }
+    }
+
+    #[test]
+    fn fix_requirement_matching_only_yanked_versions() {
+        let tmpdir = tempdir::TempDir::new("upgrade-yanked").unwrap();
+        let manifest_path = tmpdir.path().join("Cargo.toml");
+        fs::write(
+            &manifest_path,
+            "[package]\nname = \"mock\"\nversion = \"0.1.0\"\n\n\
+             [dependencies]\nfoo = \"~1.2\"\nbar = \"1.3\"\n",
+        )
+        .unwrap();
+
+        let mut desired = HashMap::new();
+        for (name, old_req) in &[("foo", "~1.2"), ("bar", "^1.3")] {
+            desired.insert(
+                Dependency::new(name),
+                UpgradeMetadata {
+                    registry: None,
+                    version: None,
+                    is_prerelease: false,
+                    old_req: (*old_req).to_owned(),
+                },
+            );
+        }
+        let desired = DesiredUpgrades(desired);
+        let floors = desired.get_yanked_floors(false, |_| YankedSource).unwrap();
+        assert_eq!(
+            floors,
+            vec![("foo".to_owned(), "~1.2".to_owned(), "1.3.0".to_owned())]
+        );
+
+        // Only `foo` is upgraded, to the next version that isn't yanked rather than the latest.
+        let upgrades = desired
+            .keep_fixes(&floors)
+            .get_upgraded(VersionSelection::default(), 1, |_| YankedSource)
+            .unwrap();
+        assert_eq!(upgrades.0.len(), 1);
+        assert_eq!(upgrades.0.get(&Dependency::new("foo")).unwrap(), "1.3.0");
+
+        let mut manifest = LocalManifest::try_new(&manifest_path).unwrap();
+        for (dep, version) in &upgrades.0 {
+            manifest
+                .upgrade(
+                    &Dependency::new(&dep.name).set_version(version),
+                    false,
+                    false,
+                )
+                .unwrap();
+        }
+        let upgraded = fs::read_to_string(&manifest_path).unwrap();
+        assert!(upgraded.contains("foo = \"1.3.0\""));
+        assert!(upgraded.contains("bar = \"1.3\""));
+    }
+
+    #[test]
+    fn fix_requirement_matching_versions_with_advisories() {
+        let mut advisories = AdvisoryDatabase::default();
+        advisories.insert(Advisory {
+            id: "RUSTSEC-2021-0001".to_owned(),
+            package: "foo".to_owned(),
+            patched: vec![semver::VersionReq::parse(">= 1.3.0").unwrap()],
+            unaffected: vec![],
+        });
+
+        let mut desired = HashMap::new();
+        for (name, old_req) in &[("foo", "~1.2"), ("bar", "~1.2")] {
+            desired.insert(
+                Dependency::new(name),
+                UpgradeMetadata {
+                    registry: None,
+                    version: None,
+                    is_prerelease: false,
+                    old_req: (*old_req).to_owned(),
+                },
+            );
+        }
+        let desired = DesiredUpgrades(desired);
+        let fixes = desired
+            .get_advisory_fixes(&advisories, false, |_| YankedSource)
+            .unwrap();
+        assert_eq!(
+            fixes,
+            vec![(
+                "foo".to_owned(),
+                "~1.2".to_owned(),
+                "1.3.0".to_owned(),
+                vec!["RUSTSEC-2021-0001".to_owned()]
+            )]
+        );
+    }
+
+    #[test]
+    fn audit_requirements_selecting_yanked_versions() {
+        let mut desired = HashMap::new();
+        for (name, old_req) in &[("foo", "~1.2"), ("bar", "^1.3"), ("baz", "=1.2.0")] {
+            desired.insert(
+                Dependency::new(name),
+                UpgradeMetadata {
+                    registry: None,
+                    version: None,
+                    is_prerelease: false,
+                    old_req: (*old_req).to_owned(),
+                },
+            );
+        }
+        let yanked = DesiredUpgrades(desired)
+            .get_yanked_selections(|_| YankedSource)
+            .unwrap();
+        assert_eq!(
+            yanked,
+            vec![
+                ("baz".to_owned(), "=1.2.0".to_owned(), "1.2.0".to_owned()),
+                ("foo".to_owned(), "~1.2".to_owned(), "1.2.1".to_owned()),
+            ]
+        );
+    }
+
+    /// Knows what `MockSource` knows if it is the expected registry, and no crates otherwise.
+    struct ExpectedSource(bool);
+
+    impl VersionSource for ExpectedSource {
+        fn versions(&self, crate_name: &str) -> cargo_edit::Result<Vec<CrateVersion>> {
+            if self.0 {
+                MockSource.versions(crate_name)
+            } else {
+                Err(cargo_edit::ErrorKind::NoCrate(crate_name.into()).into())
+            }
+        }
+    }
+
+    #[test]
+    fn upgrade_plain_dependency_from_default_registry() {
+        let tmpdir = tempdir::TempDir::new("upgrade-default-registry").unwrap();
+        let manifest_path = tmpdir.path().join("Cargo.toml");
+        fs::write(
+            &manifest_path,
+            "[package]\nname = \"mock\"\nversion = \"0.1.0\"\n\n[dependencies]\nfoo = \"0.1\"\n",
+        )
+        .unwrap();
+        fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+        fs::write(
+            tmpdir.path().join(".cargo").join("config"),
+            "[registry]\n\
+             default = \"private\"\n\
+             [registries.private]\n\
+             index = \"https://example.com/private-index\"\n",
+        )
+        .unwrap();
+
+        let private = Url::parse("https://example.com/private-index").unwrap();
+        let default_registry =
+            explicit_default_registry(&Some(manifest_path.clone()), false).unwrap();
+        assert_eq!(default_registry.as_ref(), Some(&private));
+
+        let mut desired = HashMap::new();
+        desired.insert(
+            Dependency::new("foo"),
+            UpgradeMetadata {
+                registry: None,
+                version: None,
+                is_prerelease: false,
+                old_req: "^0.1".to_owned(),
+            },
+        );
+        // Only the private registry knows `foo`, like in the `source_for` of `process`.
+        let upgrades = DesiredUpgrades(desired)
+            .get_upgraded(VersionSelection::default(), 1, |registry: Option<Url>| {
+                ExpectedSource(
+                    registry.or_else(|| default_registry.clone()).as_ref() == Some(&private),
+                )
+            })
+            .unwrap();
+        assert_eq!(upgrades.0.get(&Dependency::new("foo")).unwrap(), "0.2.0");
+    }
+}
@@ -1,9 +1,7 @@
 //! Crate name parsing.
-use semver;
-
 use crate::errors::*;
-use crate::Dependency;
 use crate::{get_crate_name_from_github, get_crate_name_from_gitlab, get_crate_name_from_path};
+use crate::{CrateSpec, Dependency};
 
 /// A crate specifier. This can be a plain name (e.g. `docopt`), a name and a versionreq (e.g.
 /// `docopt@^0.8`), a URL, or a path.
@@ -46,13 +44,18 @@ impl<'a> CrateName<'a> {
 
     /// If this crate specifier includes a version (e.g. `docopt@0.8`), extract the name and
     /// version.
+    ///
+    /// The specifier is parsed with `CrateSpec::parse`.
     pub fn parse_as_version(&self) -> Result<Option<Dependency>> {
         if self.has_version() {
-            let xs: Vec<_> = self.0.splitn(2, '@').collect();
-            let (name, version) = (xs[0], xs[1]);
-            semver::VersionReq::parse(version).chain_err(|| "Invalid crate version requirement")?;
-
-            Ok(Some(Dependency::new(name).set_version(version)))
+            let spec = CrateSpec::parse(self.0)?;
+            let version = spec.version_req.as_deref().unwrap_or_default();
+            let dependency = Dependency::new(&spec.name).set_version(version);
+            if spec.features.is_empty() {
+                Ok(Some(dependency))
+            } else {
+                Ok(Some(dependency.set_features(Some(spec.features))))
+            }
         } else {
             Ok(None)
         }
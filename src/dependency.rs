@@ -1,4 +1,5 @@
-use toml_edit;
+use crate::errors::*;
+use std::fmt;
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
 enum DependencySource {
@@ -7,7 +8,41 @@ enum DependencySource {
         path: Option<String>,
         registry: Option<String>,
     },
-    Git(String),
+    Git {
+        repo: String,
+        reference: Option<GitReference>,
+    },
+}
+
+/// The branch, tag or revision of a git dependency
+#[derive(Debug, Hash, PartialEq, Eq, Clone)]
+pub enum GitReference {
+    /// A branch, written as `branch = "..."`
+    Branch(String),
+    /// A tag, written as `tag = "..."`
+    Tag(String),
+    /// A commit or any other revision, written as `rev = "..."`
+    Rev(String),
+}
+
+impl GitReference {
+    /// The key the reference is written with in the manifest
+    pub fn key(&self) -> &'static str {
+        match self {
+            GitReference::Branch(_) => "branch",
+            GitReference::Tag(_) => "tag",
+            GitReference::Rev(_) => "rev",
+        }
+    }
+
+    /// The name of the branch or tag, or the revision
+    pub fn value(&self) -> &str {
+        match self {
+            GitReference::Branch(value) | GitReference::Tag(value) | GitReference::Rev(value) => {
+                value
+            }
+        }
+    }
 }
 
 /// A dependency handled by Cargo
@@ -17,10 +52,18 @@ pub struct Dependency {
     pub name: String,
     optional: bool,
     default_features: bool,
+    /// List of features to enable, `None` if no `features` key should be written
+    features: Option<Vec<String>>,
     source: DependencySource,
     /// If the dependency is renamed, this is the new name for the dependency
     /// as a string.  None if it is not renamed.
     rename: Option<String>,
+    /// The `cfg` expression or target triple the dependency is restricted to, if any
+    target: Option<String>,
+    /// Whether to write an inline table even if only a version is set
+    table_always: bool,
+    /// Write `default-features = true` instead of leaving it implicit
+    explicit_default_features: bool,
 }
 
 impl Default for Dependency {
@@ -30,11 +73,15 @@ impl Default for Dependency {
             rename: None,
             optional: false,
             default_features: true,
+            features: None,
             source: DependencySource::Version {
                 version: None,
                 path: None,
                 registry: None,
             },
+            target: None,
+            table_always: false,
+            explicit_default_features: false,
         }
     }
 }
@@ -68,7 +115,22 @@ impl Dependency {
 
     /// Set dependency to a given repository
     pub fn set_git(mut self, repo: &str) -> Dependency {
-        self.source = DependencySource::Git(repo.into());
+        self.source = DependencySource::Git {
+            repo: repo.into(),
+            reference: None,
+        };
+        self
+    }
+
+    /// Set the branch, tag or revision of a git dependency. This has no effect on other
+    /// dependencies.
+    pub fn set_git_reference(mut self, reference: GitReference) -> Dependency {
+        if let DependencySource::Git { repo, .. } = self.source {
+            self.source = DependencySource::Git {
+                repo,
+                reference: Some(reference),
+            };
+        }
         self
     }
 
@@ -98,6 +160,24 @@ impl Dependency {
         self
     }
 
+    /// Set the list of features to enable for the dependency
+    ///
+    /// The `default` feature is kept like any other feature, so it is written to the manifest
+    /// as an explicit member of the `features` array.
+    pub fn set_features(mut self, features: Option<Vec<String>>) -> Dependency {
+        self.features = features;
+        self
+    }
+
+    /// Sort the list of features to enable alphabetically, removing duplicates
+    pub fn sort_features(mut self) -> Dependency {
+        if let Some(features) = &mut self.features {
+            features.sort();
+            features.dedup();
+        }
+        self
+    }
+
     /// Set the alias for the dependency
     pub fn set_rename(mut self, rename: &str) -> Dependency {
         self.rename = Some(rename.into());
@@ -108,7 +188,7 @@ impl Dependency {
     /// that is, either the alias (rename field if Some),
     /// or the official package name (name field).
     pub fn name_in_manifest(&self) -> &str {
-        &self.rename().unwrap_or(&self.name)
+        self.rename().unwrap_or(&self.name)
     }
 
     /// Set the value of registry for the dependency
@@ -125,6 +205,107 @@ impl Dependency {
         self
     }
 
+    /// Set the target the dependency is restricted to
+    ///
+    /// The target is not part of the TOML representation of the dependency, but determines the
+    /// `[target.<target>.dependencies]` table it belongs to.
+    pub fn set_target(mut self, target: &str) -> Dependency {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Set whether the dependency is always written as an inline table, i.e.
+    /// `dep = { version = "1.0" }` instead of `dep = "1.0"`
+    pub fn set_table_always(mut self, table_always: bool) -> Dependency {
+        self.table_always = table_always;
+        self
+    }
+
+    /// Set whether enabled default features are spelled out as `default-features = true` instead
+    /// of omitting the key
+    pub fn set_explicit_default_features(mut self, explicit: bool) -> Dependency {
+        self.explicit_default_features = explicit;
+        self
+    }
+
+    /// Parse a dependency from its entry in a manifest
+    ///
+    /// `name_in_manifest` is the key of the entry, which is the alias if the dependency is renamed.
+    pub fn from_toml(name_in_manifest: &str, item: &toml_edit::Item) -> Result<Dependency> {
+        let invalid = || ErrorKind::InvalidDependency(name_in_manifest.into());
+
+        if let Some(version) = item.as_str() {
+            return Ok(Dependency::new(name_in_manifest).set_version(version));
+        }
+
+        let table = item.as_table_like().ok_or_else(invalid)?;
+        let get_str = |key: &str| -> Result<Option<String>> {
+            match table.get(key) {
+                Some(value) => value
+                    .as_str()
+                    .map(|s| Some(s.to_owned()))
+                    .ok_or_else(|| invalid().into()),
+                None => Ok(None),
+            }
+        };
+        let get_bool = |key: &str| -> Result<Option<bool>> {
+            match table.get(key) {
+                Some(value) => value.as_bool().map(Some).ok_or_else(|| invalid().into()),
+                None => Ok(None),
+            }
+        };
+
+        let (name, rename) = match get_str("package")? {
+            Some(package) => (package, Some(name_in_manifest.to_owned())),
+            None => (name_in_manifest.to_owned(), None),
+        };
+        let source = match get_str("git")? {
+            Some(git) => {
+                let reference = if let Some(branch) = get_str("branch")? {
+                    Some(GitReference::Branch(branch))
+                } else if let Some(tag) = get_str("tag")? {
+                    Some(GitReference::Tag(tag))
+                } else {
+                    get_str("rev")?.map(GitReference::Rev)
+                };
+                DependencySource::Git {
+                    repo: git,
+                    reference,
+                }
+            }
+            None => DependencySource::Version {
+                version: get_str("version")?,
+                path: get_str("path")?,
+                registry: get_str("registry")?,
+            },
+        };
+        let default_features = match get_bool("default-features")? {
+            Some(default_features) => Some(default_features),
+            None => get_bool("default_features")?,
+        };
+        let features = match table.get("features") {
+            Some(features) => Some(
+                features
+                    .as_array()
+                    .ok_or_else(invalid)?
+                    .iter()
+                    .map(|feature| feature.as_str().map(String::from).ok_or_else(invalid))
+                    .collect::<::std::result::Result<Vec<_>, _>>()?,
+            ),
+            None => None,
+        };
+
+        Ok(Dependency {
+            name,
+            optional: get_bool("optional")?.unwrap_or(false),
+            default_features: default_features.unwrap_or(true),
+            features,
+            source,
+            rename,
+            ..Dependency::default()
+        })
+    }
+
     /// Get version of dependency
     pub fn version(&self) -> Option<&str> {
         if let DependencySource::Version {
@@ -138,10 +319,67 @@ impl Dependency {
         }
     }
 
+    /// Get the path of the dependency (if any)
+    pub fn path(&self) -> Option<&str> {
+        match &self.source {
+            DependencySource::Version {
+                path: Some(path), ..
+            } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Get the git repository of the dependency (if any)
+    pub fn git(&self) -> Option<&str> {
+        match &self.source {
+            DependencySource::Git { repo, .. } => Some(repo),
+            _ => None,
+        }
+    }
+
+    /// Get the branch, tag or revision of a git dependency (if any)
+    pub fn git_reference(&self) -> Option<&GitReference> {
+        match &self.source {
+            DependencySource::Git { reference, .. } => reference.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Get the registry of the dependency (if any)
+    pub fn registry(&self) -> Option<&str> {
+        match &self.source {
+            DependencySource::Version {
+                registry: Some(registry),
+                ..
+            } => Some(registry),
+            _ => None,
+        }
+    }
+
+    /// Whether the dependency is optional
+    pub fn is_optional(&self) -> bool {
+        self.optional
+    }
+
+    /// Whether the default features of the dependency are enabled
+    pub fn default_features(&self) -> bool {
+        self.default_features
+    }
+
+    /// Get the target the dependency is restricted to (if any)
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    /// Get the features enabled for the dependency (if any)
+    pub fn features(&self) -> Option<&[String]> {
+        self.features.as_deref()
+    }
+
     /// Get the alias for the dependency (if any)
     pub fn rename(&self) -> Option<&str> {
         match &self.rename {
-            Some(rename) => Some(&rename),
+            Some(rename) => Some(rename),
             None => None,
         }
     }
@@ -150,28 +388,32 @@ impl Dependency {
     ///
     /// Returns a tuple with the dependency's name and either the version as a `String`
     /// or the path/git repository as an `InlineTable`.
-    /// (If the dependency is set as `optional` or `default-features` is set to `false`,
-    /// an `InlineTable` is returned in any case.)
+    /// (If the dependency is set as `optional`, `default-features` is set to `false` or features
+    /// are enabled, an `InlineTable` is returned in any case.)
     pub fn to_toml(&self) -> (String, toml_edit::Item) {
         let data: toml_edit::Item = match (
             self.optional,
             self.default_features,
+            self.features.as_ref(),
             self.source.clone(),
             self.rename.as_ref(),
+            self.table_always || self.explicit_default_features,
         ) {
             // Extra short when version flag only
             (
                 false,
                 true,
+                None,
                 DependencySource::Version {
                     version: Some(v),
                     path: None,
                     registry: None,
                 },
                 None,
+                false,
             ) => toml_edit::value(v),
             // Other cases are represented as an inline table
-            (optional, default_features, source, rename) => {
+            (optional, default_features, features, source, rename, _) => {
                 let mut data = toml_edit::InlineTable::default();
 
                 match source {
@@ -190,16 +432,23 @@ impl Dependency {
                             data.get_or_insert("registry", r);
                         }
                     }
-                    DependencySource::Git(v) => {
-                        data.get_or_insert("git", v);
+                    DependencySource::Git { repo, reference } => {
+                        data.get_or_insert("git", repo);
+                        if let Some(reference) = reference {
+                            data.get_or_insert(reference.key(), reference.value());
+                        }
                     }
                 }
                 if self.optional {
                     data.get_or_insert("optional", optional);
                 }
-                if !self.default_features {
+                if !self.default_features || self.explicit_default_features {
                     data.get_or_insert("default-features", default_features);
                 }
+                if let Some(features) = features {
+                    let features: toml_edit::Value = features.iter().map(String::as_str).collect();
+                    data.get_or_insert("features", features);
+                }
                 if rename.is_some() {
                     data.get_or_insert("package", self.name.clone());
                 }
@@ -213,9 +462,174 @@ impl Dependency {
     }
 }
 
+/// Where Cargo gets a dependency from, as declared by its entry in a manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolvedSource {
+    /// crates.io, or the source it is replaced with in the cargo config
+    CratesIo,
+    /// A registry other than crates.io, by name
+    Registry(String),
+    /// A git repository, at a branch, tag or revision if given
+    Git {
+        /// The URL of the repository
+        repo: String,
+        /// The branch, tag or revision, if any
+        reference: Option<GitReference>,
+    },
+    /// A local path, which Cargo prefers over a version or registry given as well
+    Path(String),
+    /// Inherited from `[workspace.dependencies]` with `workspace = true`
+    Workspace,
+}
+
+impl ResolvedSource {
+    /// Classify the entry `item` of a dependency table, whose key is `name_in_manifest`
+    pub fn from_toml(name_in_manifest: &str, item: &toml_edit::Item) -> Result<ResolvedSource> {
+        if item["workspace"].as_bool() == Some(true) {
+            return Ok(ResolvedSource::Workspace);
+        }
+        let dependency = Dependency::from_toml(name_in_manifest, item)?;
+        Ok(if let Some(repo) = dependency.git() {
+            ResolvedSource::Git {
+                repo: repo.to_owned(),
+                reference: dependency.git_reference().cloned(),
+            }
+        } else if let Some(path) = dependency.path() {
+            ResolvedSource::Path(path.to_owned())
+        } else if let Some(registry) = dependency.registry() {
+            ResolvedSource::Registry(registry.to_owned())
+        } else {
+            ResolvedSource::CratesIo
+        })
+    }
+
+    /// The kind of source: `crates-io`, `registry`, `git`, `path` or `workspace`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ResolvedSource::CratesIo => "crates-io",
+            ResolvedSource::Registry(_) => "registry",
+            ResolvedSource::Git { .. } => "git",
+            ResolvedSource::Path(_) => "path",
+            ResolvedSource::Workspace => "workspace",
+        }
+    }
+}
+
+impl fmt::Display for ResolvedSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolvedSource::CratesIo | ResolvedSource::Workspace => f.write_str(self.kind()),
+            ResolvedSource::Registry(name) => write!(f, "registry {}", name),
+            ResolvedSource::Git {
+                repo,
+                reference: Some(reference),
+            } => write!(
+                f,
+                "git {} ({} {})",
+                repo,
+                reference.key(),
+                reference.value()
+            ),
+            ResolvedSource::Git { repo, .. } => write!(f, "git {}", repo),
+            ResolvedSource::Path(path) => write!(f, "path {}", path),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::dependency::Dependency;
+    use crate::dependency::{Dependency, GitReference};
+
+    /// Parse the only dependency in `manifest` and write it back in its place.
+    fn round_trip(manifest: &str) -> (Dependency, String) {
+        let mut doc: toml_edit::Document = manifest.parse().unwrap();
+        let name = doc["dependencies"]
+            .as_table()
+            .unwrap()
+            .iter()
+            .next()
+            .unwrap()
+            .0
+            .to_owned();
+        let dep = Dependency::from_toml(&name, &doc["dependencies"][name.as_str()]).unwrap();
+        let (key, item) = dep.to_toml();
+        doc["dependencies"][key.as_str()] = item;
+        (dep, doc.to_string())
+    }
+
+    #[test]
+    fn round_trip_simple_dep() {
+        let manifest = "[dependencies]\ndep = \"1.0\"\n";
+        let (dep, rendered) = round_trip(manifest);
+
+        assert_eq!(dep.name, "dep");
+        assert_eq!(dep.version(), Some("1.0"));
+        assert_eq!(rendered, manifest);
+    }
+
+    #[test]
+    fn round_trip_inline_table_dep() {
+        let manifest = "[dependencies]\n\
+                        d = { version = \"1.0\", optional = true, default-features = false, \
+                        package = \"dep\" }\n";
+        let (dep, rendered) = round_trip(manifest);
+
+        assert_eq!(dep.name, "dep");
+        assert_eq!(dep.rename(), Some("d"));
+        assert_eq!(dep.version(), Some("1.0"));
+        assert!(dep.is_optional());
+        assert!(!dep.default_features());
+        assert_eq!(rendered, manifest);
+    }
+
+    #[test]
+    fn from_toml_dep_with_features() {
+        let doc: toml_edit::Document = "[dependencies]\n\
+                                        dep = { path = \"../dep\", features = [\"a\", \"b\"] }\n"
+            .parse()
+            .unwrap();
+        let dep = Dependency::from_toml("dep", &doc["dependencies"]["dep"]).unwrap();
+
+        assert_eq!(dep.path(), Some("../dep"));
+        assert_eq!(dep.features(), Some(&["a".to_owned(), "b".to_owned()][..]));
+
+        let toml = dep.to_toml();
+        let rendered = toml.1.as_inline_table().unwrap();
+        assert_eq!(rendered.get("path").unwrap().as_str(), Some("../dep"));
+        assert_eq!(
+            rendered.get("features").unwrap().as_array().unwrap().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn round_trip_git_dep_with_branch() {
+        let manifest =
+            "[dependencies]\ndep = { git = \"https://foor/bar.git\", branch = \"next\" }\n";
+        let (dep, rendered) = round_trip(manifest);
+
+        assert_eq!(
+            dep.git_reference(),
+            Some(&GitReference::Branch("next".to_owned()))
+        );
+        assert_eq!(rendered, manifest);
+    }
+
+    #[test]
+    fn round_trip_git_dep() {
+        let manifest = "[dependencies]\ndep = { git = \"https://foor/bar.git\" }\n";
+        let (dep, rendered) = round_trip(manifest);
+
+        assert_eq!(dep.git(), Some("https://foor/bar.git"));
+        assert_eq!(dep.version(), None);
+        assert_eq!(rendered, manifest);
+    }
+
+    #[test]
+    fn from_toml_invalid_dep() {
+        let doc: toml_edit::Document = "[dependencies]\ndep = 1\n".parse().unwrap();
+        assert!(Dependency::from_toml("dep", &doc["dependencies"]["dep"]).is_err());
+    }
 
     #[test]
     fn to_toml_simple_dep() {
@@ -232,6 +646,57 @@ mod tests {
         assert_eq!(toml.1.as_str(), Some("1.0"));
     }
 
+    #[test]
+    fn to_toml_dep_with_version_as_table() {
+        let toml = Dependency::new("dep")
+            .set_version("1.0")
+            .set_table_always(true)
+            .to_toml();
+
+        assert_eq!(toml.0, "dep".to_owned());
+        assert!(toml.1.is_inline_table());
+
+        let dep = toml.1.as_inline_table().unwrap();
+        assert_eq!(dep.get("version").unwrap().as_str(), Some("1.0"));
+    }
+
+    #[test]
+    fn to_toml_dep_with_explicit_default_features() {
+        let dep = Dependency::new("dep").set_version("1.0");
+        let toml = dep.clone().set_explicit_default_features(true).to_toml();
+        let table = toml.1.as_inline_table().unwrap();
+        assert_eq!(table.get("default-features").unwrap().as_bool(), Some(true));
+
+        let toml = dep
+            .clone()
+            .set_explicit_default_features(true)
+            .set_default_features(false)
+            .to_toml();
+        let table = toml.1.as_inline_table().unwrap();
+        assert_eq!(
+            table.get("default-features").unwrap().as_bool(),
+            Some(false)
+        );
+
+        let toml = dep.set_features(Some(vec!["std".to_owned()])).to_toml();
+        let table = toml.1.as_inline_table().unwrap();
+        assert!(table.get("default-features").is_none());
+    }
+
+    #[test]
+    fn sort_features_removes_duplicates() {
+        let features = vec!["zeta".to_owned(), "alpha".to_owned(), "zeta".to_owned()];
+        let toml = Dependency::new("dep")
+            .set_features(Some(features))
+            .sort_features()
+            .to_toml();
+
+        let dep = toml.1.as_inline_table().unwrap();
+        let features = dep.get("features").unwrap().as_array().unwrap();
+        let features = features.iter().map(|f| f.as_str()).collect::<Vec<_>>();
+        assert_eq!(features, vec![Some("alpha"), Some("zeta")]);
+    }
+
     #[test]
     fn to_toml_optional_dep() {
         let toml = Dependency::new("dep").set_optional(true).to_toml();
@@ -254,6 +719,28 @@ mod tests {
         assert_eq!(dep.get("default-features").unwrap().as_bool(), Some(false));
     }
 
+    #[test]
+    fn to_toml_dep_with_features() {
+        let toml = Dependency::new("dep")
+            .set_version("1.0")
+            .set_features(Some(vec!["default".to_owned(), "extra".to_owned()]))
+            .to_toml();
+
+        assert_eq!(toml.0, "dep".to_owned());
+        assert!(toml.1.is_inline_table());
+
+        let dep = toml.1.as_inline_table().unwrap();
+        let features: Vec<_> = dep
+            .get("features")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f.as_str().unwrap())
+            .collect();
+        assert_eq!(features, vec!["default", "extra"]);
+    }
+
     #[test]
     fn to_toml_dep_with_path_source() {
         let toml = Dependency::new("dep").set_path("~/foo/bar").to_toml();
@@ -0,0 +1,159 @@
+//! Unified diffs of manifest contents
+
+/// The number of unchanged lines shown around each change
+const CONTEXT: usize = 3;
+
+/// A line of a diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+impl<'a> Line<'a> {
+    fn in_old(self) -> bool {
+        !matches!(self, Line::Added(_))
+    }
+
+    fn in_new(self) -> bool {
+        !matches!(self, Line::Removed(_))
+    }
+}
+
+/// Render the changes from `old` to `new` as a unified diff, labelling both sides with `path`.
+///
+/// Returns an empty string if there are no changes.
+pub fn unified_diff(old: &str, new: &str, path: &str) -> String {
+    let old_lines = old.lines().collect::<Vec<_>>();
+    let new_lines = new.lines().collect::<Vec<_>>();
+    let lines = diff_lines(&old_lines, &new_lines);
+
+    // Changes closer to each other than twice the context share a hunk.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (index, _) in lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !line.in_old() || !line.in_new())
+    {
+        let start = index.saturating_sub(CONTEXT);
+        let end = (index + CONTEXT + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let range = |before: usize, len: usize| {
+        if len == 0 {
+            format!("{},0", before)
+        } else {
+            format!("{},{}", before + 1, len)
+        }
+    };
+    let mut diff = format!("--- {}\n+++ {}\n", path, path);
+    for (start, end) in hunks {
+        let (before, hunk) = (&lines[..start], &lines[start..end]);
+        let old_range = range(
+            before.iter().filter(|line| line.in_old()).count(),
+            hunk.iter().filter(|line| line.in_old()).count(),
+        );
+        let new_range = range(
+            before.iter().filter(|line| line.in_new()).count(),
+            hunk.iter().filter(|line| line.in_new()).count(),
+        );
+        diff.push_str(&format!("@@ -{} +{} @@\n", old_range, new_range));
+        for line in &lines[start..end] {
+            let (prefix, text) = match line {
+                Line::Same(text) => (' ', text),
+                Line::Removed(text) => ('-', text),
+                Line::Added(text) => ('+', text),
+            };
+            diff.push(prefix);
+            diff.push_str(text);
+            diff.push('\n');
+        }
+    }
+    diff
+}
+
+/// Find the lines to remove and add to turn `old` into `new`, from their longest common
+/// subsequence.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // `common[i][j]` is the length of the longest common subsequence of `old[i..]` and `new[j..]`.
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_changes() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "Cargo.toml"), "");
+    }
+
+    #[test]
+    fn added_line_with_context() {
+        let old = "[package]\nname = \"foo\"\n\n[dependencies]\nbar = \"1.0\"\n";
+        let new = "[package]\nname = \"foo\"\n\n[dependencies]\nbar = \"1.0\"\nbaz = \"0.2\"\n";
+        assert_eq!(
+            unified_diff(old, new, "Cargo.toml"),
+            "--- Cargo.toml\n\
+             +++ Cargo.toml\n\
+             @@ -3,3 +3,4 @@\n \n [dependencies]\n bar = \"1.0\"\n+baz = \"0.2\"\n"
+        );
+    }
+
+    #[test]
+    fn separate_hunks_for_distant_changes() {
+        let old = (1..=20).map(|n| format!("{}\n", n)).collect::<String>();
+        let new = (1..=20)
+            .filter(|&n| n != 19)
+            .map(|n| match n {
+                2 => "two\n".to_owned(),
+                n => format!("{}\n", n),
+            })
+            .collect::<String>();
+        let diff = unified_diff(&old, &new, "Cargo.toml");
+        assert!(diff.contains("@@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n"));
+        assert!(diff.contains("@@ -16,5 +16,4 @@\n 16\n 17\n 18\n-19\n 20\n"));
+    }
+
+    #[test]
+    fn removed_everything() {
+        assert_eq!(
+            unified_diff("a\n", "", "Cargo.toml"),
+            "--- Cargo.toml\n+++ Cargo.toml\n@@ -1,1 +0,0 @@\n-a\n"
+        );
+    }
+}
@@ -17,11 +17,28 @@ error_chain! {
         EmptyCrateName{
             description("Found empty crate name")
         }
+        /// A crate specifier like `name@version` doesn't follow the grammar of `cargo add`
+        InvalidCrateSpec(spec: String, reason: String) {
+            description("Invalid crate specifier")
+            display("`{}` is not a valid crate specifier: {}", spec, reason)
+        }
         /// No crate by that name exists
         NoCrate(name: String) {
             description("The crate could not be found in registry index.")
             display("The crate `{}` could not be found in registry index.", name)
         }
+        /// No crate by that name exists, but some are called similarly
+        NoCrateButSimilar(name: String, similar: String) {
+            description("The crate could not be found in registry index.")
+            display("The crate `{}` could not be found in registry index, did you mean `{}`?",
+                    name, similar)
+        }
+        /// The versions are to be read from the web API of a registry that has none
+        NoRegistryApi(registry: String) {
+            description("The registry has no web API")
+            display("The registry `{}` has no web API to read the versions of crates from, \
+                     as its `config.json` has no `api`", registry)
+        }
         /// No versions available
         NoVersionsAvailable {
             description("No available versions exist. Either all were yanked \
@@ -29,6 +46,25 @@ error_chain! {
                          --allow-prerelease flag might solve the issue."
             )
         }
+        /// No version that isn't yanked satisfies the requirement
+        NoMatchingVersion(name: String, req: String) {
+            description("No available version matches the requirement")
+            display("No available version of `{}` matches `{}`", name, req)
+        }
+        /// No version of a crate depends on a version of a peer that the manifest allows
+        NoCompatibleVersion(name: String, peer: String, req: String) {
+            description("No available version is compatible with the peer dependency")
+            display("No available version of `{}` is compatible with `{}` `{}`", name, peer, req)
+        }
+        /// The `config.json` of a registry index is missing or malformed
+        InvalidIndexConfig {
+            description("Invalid registry index config.json")
+        }
+        /// A feature in the `[features]` table is not a list
+        InvalidFeature(name: String) {
+            description("Feature is not a list")
+            display("The feature `{}` is not a list of features and dependencies", name)
+        }
         /// Unable to parse external Cargo.toml
         ParseCargoToml {
             description("Unable to parse external Cargo.toml")
@@ -37,6 +73,16 @@ error_chain! {
         MissingManifest {
             description("Unable to find Cargo.toml")
         }
+        /// A dependency kind other than `normal`, `dev` or `build` was given
+        InvalidDependencyKind(kind: String) {
+            description("Invalid dependency kind")
+            display("`{}` is not a dependency kind, expected `normal`, `dev` or `build`", kind)
+        }
+        /// The given manifest path is neither a file nor a directory containing Cargo.toml
+        InvalidManifestPath(path: String) {
+            description("Invalid manifest path")
+            display("`{}` is neither a manifest nor a directory containing `Cargo.toml`", path)
+        }
         /// Cargo.toml is valid toml, but doesn't contain the expected fields
         InvalidManifest {
             description("Cargo.toml missing expected `package` or `project` fields")
@@ -56,6 +102,46 @@ error_chain! {
             description("non existent dependency")
             display("The dependency `{}` could not be found in `{}`.", name, table)
         }
+        /// A dependency is declared more than once in the same table
+        DuplicateDependency(name: String, table: String) {
+            description("duplicate dependency")
+            display("The dependency `{}` is declared more than once in `{}`", name, table)
+        }
+        /// Several renamed dependencies refer to the same package
+        AmbiguousDependency(name: String, table: String, keys: String) {
+            description("ambiguous dependency")
+            display("Several dependencies in `{}` refer to the package `{}`: `{}`. \
+                     Pass the one to remove by its key.", table, name, keys)
+        }
+        /// The git repository of a dependency doesn't contain a package of that name
+        NoCrateInGitRepository(name: String, repo: String, found: String) {
+            description("crate not found in git repository")
+            display("The git repository `{}` contains no package `{}`, only `{}`", repo, name, found)
+        }
+        /// A registry refused a request for lack of a valid token
+        RegistryUnauthorized(url: String, sent_token: bool) {
+            description("the registry requires authentication")
+            display("`{}` requires authentication, {}", url, if *sent_token {
+                "but the token was rejected"
+            } else {
+                "set a token with `CARGO_REGISTRIES_<NAME>_TOKEN` or in `credentials.toml`"
+            })
+        }
+        /// The references of a git repository couldn't be listed
+        UnreachableGitRepository(repo: String, reason: String) {
+            description("failed to list the references of a git repository")
+            display("Failed to list the references of the git repository `{}`: {}", repo, reason)
+        }
+        /// The branch, tag or revision of a git dependency doesn't exist
+        MissingGitReference(repo: String, kind: String, name: String) {
+            description("git reference not found")
+            display("The git repository `{}` has no {} `{}`", repo, kind, name)
+        }
+        /// No tag of a git repository is a semver version
+        NoSemverGitTag(repo: String) {
+            description("no semver tag in git repository")
+            display("The git repository `{}` has no tag that is a semver version", repo)
+        }
         /// Config of cargo is invalid
         InvalidCargoConfig {
             description("Invalid cargo config")
@@ -69,10 +155,100 @@ error_chain! {
         NoSuchRegistryFound(name: String) {
             display("The registry '{}' could not be found", name)
         }
+        /// A registry index URL refers to an environment variable that is not set
+        UnsetRegistryEnvVar(var: String, url: String) {
+            description("Environment variable in registry URL is not set")
+            display("The environment variable `{}` used in the registry index `{}` is not set", var, url)
+        }
+        /// The git index has no commit matching the revision it was pinned to
+        NoSuchIndexRev(rev: String) {
+            description("Index revision not found")
+            display("The registry index has no commit `{}`, fetch it or pick another one", rev)
+        }
+        /// An index revision was given for a sparse registry, which has no history
+        IndexRevOnSparseRegistry(url: String) {
+            description("Index revision for a sparse registry")
+            display("The sparse registry `{}` has no history to pin to a revision", url)
+        }
+        /// A warning was reported with `--strict`, which makes warnings fatal
+        Warning(message: String) {
+            description("Warning treated as an error")
+            display("{} (warnings are errors with `--strict`)", message)
+        }
+        /// A network operation took longer than the configured timeout
+        FetchTimeout(name: String, url: String) {
+            description("Network operation timed out")
+            display("Timed out while fetching {} from `{}`", name, url)
+        }
+        /// The checksum of a crate in the registry index differs from the expected one
+        ChecksumMismatch(name: String, version: String, expected: String, actual: String) {
+            description("Checksum mismatch")
+            display("The checksum of `{}` v{} is `{}`, but `{}` was required", name, version, actual, expected)
+        }
         /// Failed to parse a version for a dependency
         ParseVersion(version: String, dep: String) {
             description("Failed to parse a version for a dependency")
             display("The version `{}` for the dependency `{}` couldn't be parsed", version, dep)
         }
+        /// A version requirement couldn't be parsed
+        ParseVersionReq(req: String) {
+            description("Failed to parse a version requirement")
+            display("The version requirement `{}` couldn't be parsed", req)
+        }
+        /// A Rust version (e.g. for `rust-version`) couldn't be parsed
+        InvalidRustVersion(version: String) {
+            description("Invalid Rust version")
+            display("`{}` is not a valid Rust version, expected e.g. `1.56` or `1.56.1`", version)
+        }
+        /// A dependency entry in a manifest has an unexpected format
+        InvalidDependency(name: String) {
+            description("Invalid dependency entry in manifest")
+            display("The dependency `{}` has an invalid format", name)
+        }
+        /// A version bump level other than `major`, `minor`, `patch` or `rc`
+        InvalidBumpLevel(level: String) {
+            description("Invalid version bump level")
+            display("`{}` is not a bump level, expected `major`, `minor`, `patch` or `rc`", level)
+        }
+        /// A requirement style other than `caret`, `tilde`, `exact` or `minor`
+        InvalidRequirementStyle(style: String) {
+            description("Invalid requirement style")
+            display("`{}` is not a requirement style, expected `caret`, `tilde`, `exact` or \
+                     `minor`", style)
+        }
+        /// An advisory has no valid TOML front matter
+        InvalidAdvisory {
+            description("Invalid advisory, expected TOML front matter with an `[advisory]` table")
+        }
+        /// There is no copy of the advisory database at a path
+        AdvisoryDbMissing(path: String) {
+            description("Advisory database not found")
+            display("There is no advisory database at `{}`, expected a `crates` directory in \
+                     it", path)
+        }
+        /// Something needs the network, but running offline forbids it
+        Offline(what: String) {
+            description("Network access is forbidden offline")
+            display("Cannot {} offline, as `--offline`, `--locked-index` or `CARGO_NET_OFFLINE` \
+                     forbids network access", what)
+        }
+        /// A crate is not in the cached copy of a registry index, and running offline that copy
+        /// can't be updated
+        OfflineIndexEntryMissing(name: String) {
+            description("Crate is not in the cached registry index")
+            display("The crate `{}` is not in the cached registry index, which cannot be updated \
+                     offline", name)
+        }
+        /// A registry index has never been downloaded, and running offline it can't be
+        OfflineIndexMissing(registry: String) {
+            description("Registry index has not been downloaded")
+            display("The index of `{}` has not been downloaded, which cannot be done offline",
+                    registry)
+        }
+        /// `cargo vendor` failed to refresh a directory of vendored crates
+        VendorFailed(directory: String) {
+            description("Failed to refresh the vendored crates")
+            display("`cargo vendor` failed to refresh the vendored crates in `{}`", directory)
+        }
     }
 }
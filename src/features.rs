@@ -0,0 +1,120 @@
+//! Parsing of the values listed in a `[features]` table.
+use std::fmt;
+
+/// One entry of a feature's list, e.g. `std`, `dep:serde`, `serde/derive` or `serde?/derive`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeatureValue {
+    /// Another feature of this package, or the implicit feature of an optional dependency.
+    Feature(String),
+    /// An optional dependency, enabled without exposing an implicit feature (`dep:name`).
+    Dep(String),
+    /// A feature of a dependency (`name/feature`). A weak entry (`name?/feature`) only enables
+    /// the feature if the dependency is enabled by something else.
+    DepFeature {
+        /// Name of the dependency.
+        dep: String,
+        /// Feature to enable on the dependency.
+        feature: String,
+        /// Whether the dependency itself is left disabled.
+        weak: bool,
+    },
+}
+
+impl FeatureValue {
+    /// Parse an entry of a feature's list.
+    pub fn new(value: &str) -> Self {
+        if let Some(dep) = value.strip_prefix("dep:") {
+            return FeatureValue::Dep(dep.to_owned());
+        }
+        match value.find('/') {
+            Some(pos) => {
+                let (dep, feature) = (&value[..pos], &value[pos + 1..]);
+                let weak = dep.ends_with('?');
+                let dep = dep.trim_end_matches('?');
+                FeatureValue::DepFeature {
+                    dep: dep.to_owned(),
+                    feature: feature.to_owned(),
+                    weak,
+                }
+            }
+            None => FeatureValue::Feature(value.to_owned()),
+        }
+    }
+
+    /// Whether this entry refers to the dependency `dep_name`.
+    ///
+    /// A plain feature name counts as well, since that is how the implicit feature of an
+    /// optional dependency is enabled.
+    pub fn refers_to(&self, dep_name: &str) -> bool {
+        match self {
+            FeatureValue::Feature(name) | FeatureValue::Dep(name) => name == dep_name,
+            FeatureValue::DepFeature { dep, .. } => dep == dep_name,
+        }
+    }
+
+    /// Whether this entry enables the dependency `dep_name` itself.
+    pub fn enables(&self, dep_name: &str) -> bool {
+        match self {
+            FeatureValue::Feature(name) | FeatureValue::Dep(name) => name == dep_name,
+            FeatureValue::DepFeature { dep, weak, .. } => !weak && dep == dep_name,
+        }
+    }
+}
+
+impl fmt::Display for FeatureValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeatureValue::Feature(name) => write!(f, "{}", name),
+            FeatureValue::Dep(name) => write!(f, "dep:{}", name),
+            FeatureValue::DepFeature { dep, feature, weak } => {
+                write!(f, "{}{}/{}", dep, if *weak { "?" } else { "" }, feature)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_feature_values() {
+        assert_eq!(
+            FeatureValue::new("std"),
+            FeatureValue::Feature("std".into())
+        );
+        assert_eq!(
+            FeatureValue::new("dep:serde"),
+            FeatureValue::Dep("serde".into())
+        );
+        assert_eq!(
+            FeatureValue::new("serde/derive"),
+            FeatureValue::DepFeature {
+                dep: "serde".into(),
+                feature: "derive".into(),
+                weak: false,
+            }
+        );
+        assert_eq!(
+            FeatureValue::new("serde?/derive"),
+            FeatureValue::DepFeature {
+                dep: "serde".into(),
+                feature: "derive".into(),
+                weak: true,
+            }
+        );
+
+        for value in &["std", "dep:serde", "serde/derive", "serde?/derive"] {
+            assert_eq!(FeatureValue::new(value).to_string(), *value);
+        }
+    }
+
+    #[test]
+    fn weak_dep_feature_does_not_enable_dep() {
+        let weak = FeatureValue::new("serde?/derive");
+        assert!(weak.refers_to("serde"));
+        assert!(!weak.enables("serde"));
+        assert!(FeatureValue::new("serde/derive").enables("serde"));
+        assert!(!FeatureValue::new("serde_json/std").refers_to("serde"));
+    }
+}
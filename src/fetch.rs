@@ -1,23 +1,625 @@
+use crate::advisory::AdvisoryDatabase;
 use crate::errors::*;
-use crate::registry::{registry_path, registry_path_from_url};
-use crate::{Dependency, Manifest};
-use env_proxy;
+use crate::registry::{
+    ambient_http_config, git_source_reference, http_config, is_crates_io, registry_path_from_url,
+    registry_token, registry_url, source_location, HttpConfig, RegistryToken, CRATES_IO_API,
+    DIRECTORY_PREFIX, GIT_PREFIX,
+};
+use crate::version::ranges::VersionRanges;
+use crate::warning::warn;
+use crate::{Dependency, FeatureValue, GitReference, Manifest};
 use regex::Regex;
-use reqwest;
-use semver;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::env;
+use std::fs;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 use url::Url;
 
-#[derive(Deserialize)]
-struct CrateVersion {
-    name: String,
+/// A published version of a crate, as recorded in a registry index
+///
+/// Only the fields cargo-edit uses are read. Unknown fields are ignored and optional fields of an
+/// unexpected type are treated as missing, so that registries adding their own fields to index
+/// entries don't break parsing.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CrateVersion {
+    /// The name of the crate
+    pub name: String,
+    /// The version
     #[serde(rename = "vers")]
-    version: semver::Version,
+    pub version: semver::Version,
+    /// Whether the version has been yanked
+    #[serde(default)]
+    pub yanked: bool,
+    /// The features of the crate, mapped to the features and dependencies they enable
+    #[serde(default, deserialize_with = "lenient")]
+    pub features: HashMap<String, Vec<String>>,
+    /// The SHA256 checksum of the `.crate` file
+    #[serde(rename = "cksum", default, deserialize_with = "lenient")]
+    pub checksum: Option<String>,
+    /// The minimum supported Rust version, if declared
+    #[serde(default, deserialize_with = "lenient")]
+    pub rust_version: Option<String>,
+    /// The native library the crate links to, if declared
+    #[serde(default, deserialize_with = "lenient")]
+    pub links: Option<String>,
+    /// Features using the newer `dep:` and `?` syntax, which the index keeps apart from
+    /// `features`
+    #[serde(default, deserialize_with = "lenient")]
+    pub features2: HashMap<String, Vec<String>>,
+    /// The dependencies of this version
+    #[serde(default, deserialize_with = "lenient")]
+    pub deps: Vec<IndexDependency>,
+}
+
+/// A dependency of a published crate version, as recorded in the registry index
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct IndexDependency {
+    /// The name the dependency is used under, which may be a rename
+    pub name: String,
+    /// Whether the dependency is optional, which gives it an implicit feature
+    #[serde(default)]
+    pub optional: bool,
+    /// The version requirement on the dependency
+    #[serde(default)]
+    pub req: String,
+    /// `normal`, `build` or `dev`, where a missing kind means `normal`
+    #[serde(default)]
+    pub kind: Option<String>,
+    /// The name of the crate if the dependency is renamed
+    #[serde(default)]
+    pub package: Option<String>,
+    /// The index of the registry the dependency comes from, if it is not the same one
+    #[serde(default)]
+    pub registry: Option<String>,
+}
+
+impl IndexDependency {
+    /// The name of the crate, which is `name` unless the dependency is renamed
+    pub fn crate_name(&self) -> &str {
+        self.package.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Deserialize an optional field of an index entry, falling back to the default if it has an
+/// unexpected type.
+fn lenient<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::de::DeserializeOwned + Default,
+{
+    let value = <serde_json::Value as serde::Deserialize>::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).unwrap_or_default())
+}
+
+impl CrateVersion {
+    /// Create a version without features, checksum, `rust-version` or `links` that is not yanked
+    pub fn new(name: &str, version: semver::Version) -> CrateVersion {
+        CrateVersion {
+            name: name.into(),
+            version,
+            yanked: false,
+            features: HashMap::new(),
+            checksum: None,
+            rust_version: None,
+            links: None,
+            features2: HashMap::new(),
+            deps: Vec::new(),
+        }
+    }
+
+    /// Whether `feature` can be enabled on this version, either because it is declared or
+    /// because it is the implicit feature of an optional dependency that no feature refers to
+    /// with `dep:`.
+    pub fn has_feature(&self, feature: &str) -> bool {
+        if self.features.contains_key(feature) || self.features2.contains_key(feature) {
+            return true;
+        }
+        let hidden = self
+            .features
+            .values()
+            .chain(self.features2.values())
+            .flatten()
+            .any(|value| FeatureValue::new(value) == FeatureValue::Dep(feature.to_owned()));
+        !hidden
+            && self
+                .deps
+                .iter()
+                .any(|dep| dep.optional && dep.name == feature)
+    }
+
+    /// The features that can be enabled on this version, see `has_feature`, sorted by name.
+    /// `default` is left out, see `default_features`.
+    pub fn feature_names(&self) -> Vec<String> {
+        let mut names = self
+            .features
+            .keys()
+            .chain(self.features2.keys())
+            .chain(
+                self.deps
+                    .iter()
+                    .filter(|dep| dep.optional)
+                    .map(|dep| &dep.name),
+            )
+            .filter(|name| *name != "default" && self.has_feature(name))
+            .cloned()
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The features this version enables by default
+    pub fn default_features(&self) -> Vec<String> {
+        self.features
+            .get("default")
+            .or_else(|| self.features2.get("default"))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Something that knows the published versions of crates
+///
+/// The versions of dependencies are resolved through this trait, so a custom implementation can
+/// be used instead of a registry index, e.g. for tests.
+pub trait VersionSource {
+    /// Get all versions of the crate called `crate_name`, or of a crate whose name only differs
+    /// in the use of `-` and `_`.
+    ///
+    /// This fails with `ErrorKind::NoCrate` if there is no such crate.
+    fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>>;
+
+    /// Get the names of the crates called most like `crate_name`, closest first, e.g. to suggest
+    /// them when there is no crate by that name.
+    ///
+    /// Sources that can't be searched find none.
+    fn similar_names(&self, _crate_name: &str) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Get the URL of the source repository that `crate_name` was published with, if any.
+    ///
+    /// Sources that don't know about repositories find none.
+    fn repository(&self, _crate_name: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// The endpoints of a registry, as read from the `config.json` at the root of its index
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct IndexConfig {
+    /// Where `.crate` files are downloaded from, possibly containing markers such as `{crate}`
+    pub dl: String,
+    /// The base URL of the web API, if the registry has one
+    #[serde(default)]
+    pub api: Option<String>,
+}
+
+impl IndexConfig {
+    /// The URL to download `version` from
+    ///
+    /// The markers `{crate}`, `{version}`, `{prefix}`, `{lowerprefix}` and `{sha256-checksum}`
+    /// in `dl` are replaced. If there are none, `/{crate}/{version}/download` is appended, like
+    /// Cargo does.
+    pub fn download_url(&self, version: &CrateVersion) -> String {
+        const MARKERS: [&str; 5] = [
+            "{crate}",
+            "{version}",
+            "{prefix}",
+            "{lowerprefix}",
+            "{sha256-checksum}",
+        ];
+        let name = &version.name;
+        if !MARKERS.iter().any(|marker| self.dl.contains(marker)) {
+            return format!(
+                "{}/{}/{}/download",
+                self.dl.trim_end_matches('/'),
+                name,
+                version.version
+            );
+        }
+
+        let prefix = |name: &str| {
+            let path = summary_raw_path(name);
+            path[..path.len() - name.len() - 1].to_owned()
+        };
+        self.dl
+            .replace("{crate}", name)
+            .replace("{version}", &version.version.to_string())
+            .replace("{prefix}", &prefix(name))
+            .replace("{lowerprefix}", &prefix(&name.to_lowercase()))
+            .replace(
+                "{sha256-checksum}",
+                version.checksum.as_ref().map_or("", String::as_str),
+            )
+    }
+}
+
+/// The registry index used by a manifest, or an explicitly given one
+#[derive(Debug, Clone)]
+pub struct RegistryIndex {
+    manifest_path: PathBuf,
+    registry: Option<Url>,
+    config: RefCell<Option<IndexConfig>>,
+    max_age: Option<Duration>,
+    api_fallback: bool,
+    rev: Option<String>,
+    prerelease_channel: Option<String>,
+    allow_yanked: bool,
+    api_only: bool,
+    advisories: Option<Arc<AdvisoryDatabase>>,
+}
+
+impl RegistryIndex {
+    /// Use the index of `registry`, or that of the registry configured for `manifest_path` if
+    /// `registry` is `None`.
+    pub fn new(manifest_path: &Path, registry: &Option<Url>) -> RegistryIndex {
+        RegistryIndex {
+            manifest_path: manifest_path.to_owned(),
+            registry: registry.clone(),
+            config: RefCell::new(None),
+            max_age: None,
+            api_fallback: false,
+            rev: None,
+            prerelease_channel: None,
+            allow_yanked: false,
+            api_only: false,
+            advisories: None,
+        }
+    }
+
+    /// Read a git index at the commit `rev`, e.g. a commit hash or tag, instead of the latest
+    /// fetched one, so that versions published since then are ignored.
+    ///
+    /// The commit has to be fetched already. Sparse indices have no history, so lookups fail for
+    /// them.
+    pub fn at_rev(mut self, rev: &str) -> RegistryIndex {
+        self.rev = Some(rev.to_owned());
+        self
+    }
+
+    /// Ask the web API of the registry for the features and yanked state of crates whose index
+    /// entries don't list any features, e.g. because they were written in an older format.
+    ///
+    /// This needs an additional request per crate, and does nothing for registries without an
+    /// `api` in their `config.json`.
+    pub fn api_fallback(mut self) -> RegistryIndex {
+        self.api_fallback = true;
+        self
+    }
+
+    /// Reuse cached entries of a sparse index that are younger than `max_age` instead of
+    /// downloading them again. Older entries are always re-downloaded.
+    pub fn refresh_after(mut self, max_age: Duration) -> RegistryIndex {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Only consider pre-releases on the channel `channel`, i.e. those whose first pre-release
+    /// identifier starts with it, like `rc` for `1.0.0-rc.1`. Stable versions are kept.
+    pub fn prerelease_channel(mut self, channel: &str) -> RegistryIndex {
+        self.prerelease_channel = Some(channel.to_owned());
+        self
+    }
+
+    /// Report yanked versions as if they weren't yanked, so that they can be selected like any
+    /// other version.
+    pub fn allow_yanked(mut self) -> RegistryIndex {
+        self.allow_yanked = true;
+        self
+    }
+
+    /// Read the versions from the web API of the registry, `/api/v1/crates/{crate}/versions`,
+    /// instead of from its index, e.g. in CI, where cloning a git index takes too long.
+    ///
+    /// The index is only read for the `api` of its `config.json`, which crates.io doesn't need.
+    /// The API doesn't list the dependencies of the versions.
+    pub fn api_only(mut self) -> RegistryIndex {
+        self.api_only = true;
+        self
+    }
+
+    /// Leave out the versions affected by an advisory in `advisories`, so that they are never
+    /// selected.
+    pub fn skip_advisories(mut self, advisories: Arc<AdvisoryDatabase>) -> RegistryIndex {
+        self.advisories = Some(advisories);
+        self
+    }
+
+    /// Get the `config.json` of the index, which is only read once.
+    pub fn config(&self) -> Result<IndexConfig> {
+        if let Some(config) = &*self.config.borrow() {
+            return Ok(config.clone());
+        }
+        let config = query_index_config(&self.manifest_path, &self.registry)?;
+        *self.config.borrow_mut() = Some(config.clone());
+        Ok(config)
+    }
+}
+
+impl VersionSource for RegistryIndex {
+    fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+        let mut versions = self.all_versions(crate_name)?;
+        if let Some(channel) = &self.prerelease_channel {
+            versions.retain(|v| is_on_prerelease_channel(&v.version, channel));
+        }
+        if self.allow_yanked {
+            for version in &mut versions {
+                version.yanked = false;
+            }
+        }
+        if let Some(advisories) = &self.advisories {
+            versions.retain(|v| !advisories.is_affected(crate_name, &v.version));
+        }
+        Ok(versions)
+    }
+
+    fn similar_names(&self, crate_name: &str) -> Result<Vec<String>> {
+        let registry = self.registry_url()?;
+        let names = if is_sparse(&registry) || self.api_only {
+            // Sparse indices can't be listed, so the web API is searched instead, if there is one.
+            match self.api_url(&registry)? {
+                Some(api) if !is_offline() => search_registry_api(
+                    crate_name,
+                    &api,
+                    &http_config(&self.manifest_path)?,
+                    registry_token(&self.manifest_path, &registry)?.as_ref(),
+                )?,
+                _ => Vec::new(),
+            }
+        } else {
+            registry_crate_names(&registry, self.rev.as_deref())?
+        };
+        Ok(closest_names(crate_name, names))
+    }
+
+    fn repository(&self, crate_name: &str) -> Result<Option<String>> {
+        // The index doesn't record repositories, only the web API does.
+        let registry = self.registry_url()?;
+        match self.api_url(&registry)? {
+            Some(api) if !is_offline() => query_crate_repository(
+                crate_name,
+                &api,
+                &http_config(&self.manifest_path)?,
+                registry_token(&self.manifest_path, &registry)?.as_ref(),
+            ),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl RegistryIndex {
+    /// The versions of `crate_name`, regardless of their pre-release channel
+    fn all_versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+        if self.api_only {
+            return self.api_versions(crate_name);
+        }
+        let mut versions = query_registry_index(
+            crate_name,
+            &self.manifest_path,
+            &self.registry,
+            self.max_age,
+            self.rev.as_deref(),
+        )?;
+        let lacks_features = |v: &CrateVersion| v.features.is_empty() && v.features2.is_empty();
+        if !self.api_fallback || is_offline() || !versions.iter().any(lacks_features) {
+            return Ok(versions);
+        }
+
+        if let Some(api) = self.config()?.api {
+            let registry = match &self.registry {
+                Some(url) => url.clone(),
+                None => registry_url(&self.manifest_path, None)?,
+            };
+            let api_versions = query_registry_api(
+                &versions[0].name,
+                &api,
+                &http_config(&self.manifest_path)?,
+                registry_token(&self.manifest_path, &registry)?.as_ref(),
+            )?;
+            merge_api_versions(&mut versions, api_versions);
+        }
+        Ok(versions)
+    }
+
+    /// The versions of `crate_name` according to the web API of the registry, see `api_only`
+    fn api_versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+        let registry = self.registry_url()?;
+        let api = self
+            .api_url(&registry)?
+            .chain_err(|| ErrorKind::NoRegistryApi(registry.to_string()))?;
+        let mut versions = query_registry_api(
+            crate_name,
+            &api,
+            &http_config(&self.manifest_path)?,
+            registry_token(&self.manifest_path, &registry)?.as_ref(),
+        )?
+        .into_iter()
+        .map(|version| version.into_crate_version(crate_name))
+        .collect::<Vec<_>>();
+        if versions.is_empty() {
+            return Err(ErrorKind::NoCrate(crate_name.to_owned()).into());
+        }
+        versions.sort_by(|a, b| a.version.cmp(&b.version));
+        Ok(versions)
+    }
+
+    /// The registry this index belongs to
+    fn registry_url(&self) -> Result<Url> {
+        match &self.registry {
+            Some(url) => Ok(url.clone()),
+            None => registry_url(&self.manifest_path, None),
+        }
+    }
+
+    /// The base URL of the web API of `registry`, if it has one
+    fn api_url(&self, registry: &Url) -> Result<Option<String>> {
+        if is_crates_io(registry) {
+            Ok(Some(CRATES_IO_API.to_owned()))
+        } else {
+            Ok(self.config()?.api)
+        }
+    }
+}
+
+/// A version of a crate as described by the web API of a registry
+#[derive(Debug, Clone, Deserialize)]
+struct ApiVersion {
+    #[serde(rename = "crate", default, deserialize_with = "lenient")]
+    name: Option<String>,
+    num: semver::Version,
+    #[serde(default)]
     yanked: bool,
+    #[serde(default, deserialize_with = "lenient")]
+    features: HashMap<String, Vec<String>>,
+    #[serde(default, deserialize_with = "lenient")]
+    checksum: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    rust_version: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    links: Option<String>,
+}
+
+impl ApiVersion {
+    /// The version as if it was read from the index, without any dependencies. It belongs to
+    /// `crate_name`, unless the API names the crate.
+    fn into_crate_version(self, crate_name: &str) -> CrateVersion {
+        CrateVersion {
+            name: self.name.unwrap_or_else(|| crate_name.to_owned()),
+            version: self.num,
+            yanked: self.yanked,
+            features: self.features,
+            checksum: self.checksum,
+            rust_version: self.rust_version,
+            links: self.links,
+            features2: HashMap::new(),
+            deps: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiVersions {
+    versions: Vec<ApiVersion>,
+}
+
+/// Query `/api/v1/crates/{crate}/versions` from the web API at `api`.
+fn query_registry_api(
+    crate_name: &str,
+    api: &str,
+    http: &HttpConfig,
+    token: Option<&RegistryToken>,
+) -> Result<Vec<ApiVersion>> {
+    let url = format!(
+        "{}/api/v1/crates/{}/versions",
+        api.trim_end_matches('/'),
+        crate_name
+    );
+    let client = http_client(get_default_timeout(), http)?;
+    let mut request = client.get(&url);
+    if let Some(token) = token {
+        request = request.header(reqwest::header::AUTHORIZATION, token.expose());
+    }
+    let mut res = match request
+        .send()
+        .and_then(reqwest::blocking::Response::error_for_status)
+    {
+        Ok(res) => res,
+        Err(ref e) if e.status().is_some_and(is_unauthorized_status) => {
+            return Err(ErrorKind::RegistryUnauthorized(url, token.is_some()).into())
+        }
+        Err(ref e) if e.status() == Some(reqwest::StatusCode::NOT_FOUND) => {
+            return Err(ErrorKind::NoCrate(crate_name.to_owned()).into())
+        }
+        Err(e) => {
+            return Err(Error::with_chain(
+                e,
+                format!("Failed to fetch `{}` from the registry API", url),
+            ))
+        }
+    };
+
+    let mut content = String::new();
+    res.read_to_string(&mut content)
+        .chain_err(|| format!("Failed to read `{}` from the registry API", url))?;
+    let api_versions: ApiVersions = serde_json::from_str(&content)
+        .chain_err(|| format!("Invalid response from the registry API for `{}`", url))?;
+    Ok(api_versions.versions)
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCrateMetadata {
+    #[serde(default, deserialize_with = "lenient")]
+    repository: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCrateInfo {
+    #[serde(rename = "crate")]
+    krate: ApiCrateMetadata,
+}
+
+/// Query the repository of `crate_name` from `/api/v1/crates/{crate}` of the web API at `api`.
+fn query_crate_repository(
+    crate_name: &str,
+    api: &str,
+    http: &HttpConfig,
+    token: Option<&RegistryToken>,
+) -> Result<Option<String>> {
+    let url = format!("{}/api/v1/crates/{}", api.trim_end_matches('/'), crate_name);
+    let client = http_client(get_default_timeout(), http)?;
+    let mut request = client.get(&url);
+    if let Some(token) = token {
+        request = request.header(reqwest::header::AUTHORIZATION, token.expose());
+    }
+    let mut res = request
+        .send()
+        .and_then(reqwest::blocking::Response::error_for_status)
+        .chain_err(|| format!("Failed to fetch `{}` from the registry API", url))?;
+
+    let mut content = String::new();
+    res.read_to_string(&mut content)
+        .chain_err(|| format!("Failed to read `{}` from the registry API", url))?;
+    let info: ApiCrateInfo = serde_json::from_str(&content)
+        .chain_err(|| format!("Invalid response from the registry API for `{}`", url))?;
+    Ok(info.krate.repository.filter(|repo| !repo.trim().is_empty()))
+}
+
+/// Complete `versions` from the index with what the registry API knows about them.
+///
+/// Features are only taken from the API for versions that have none in the index, while a
+/// version yanked according to either is yanked.
+fn merge_api_versions(versions: &mut [CrateVersion], api_versions: Vec<ApiVersion>) {
+    for api_version in api_versions {
+        if let Some(version) = versions.iter_mut().find(|v| v.version == api_version.num) {
+            if version.features.is_empty() && version.features2.is_empty() {
+                version.features = api_version.features;
+            }
+            version.yanked |= api_version.yanked;
+        }
+    }
+}
+
+/// How the `default` feature of a crate changes between two of its versions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DefaultFeaturesChange {
+    /// Features enabled by default in the new version, but not in the old one
+    pub added: Vec<String>,
+    /// Features enabled by default in the old version, but not in the new one
+    pub removed: Vec<String>,
+}
+
+impl DefaultFeaturesChange {
+    /// Whether the default feature set stays the same
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
 }
 
 /// Query latest version from a registry index
@@ -36,6 +638,62 @@ pub fn get_latest_dependency(
     manifest_path: &Path,
     registry: &Option<Url>,
 ) -> Result<Dependency> {
+    get_latest_dependency_for_rust_version(
+        crate_name,
+        flag_allow_prerelease,
+        None,
+        manifest_path,
+        registry,
+    )
+}
+
+/// Query latest version of a crate that supports the Rust version `rust_version`
+///
+/// Versions whose `rust-version` in the registry index is newer than `rust_version` are skipped.
+/// Versions that don't declare a `rust-version` are assumed to be compatible. If `rust_version`
+/// is `None`, this behaves like `get_latest_dependency`.
+pub fn get_latest_dependency_for_rust_version(
+    crate_name: &str,
+    flag_allow_prerelease: bool,
+    rust_version: Option<&str>,
+    manifest_path: &Path,
+    registry: &Option<Url>,
+) -> Result<Dependency> {
+    get_latest_dependency_from_source(
+        crate_name,
+        flag_allow_prerelease,
+        rust_version,
+        &RegistryIndex::new(manifest_path, registry),
+    )
+}
+
+/// Query latest version of a crate from `source`, see `get_latest_dependency_for_rust_version`
+pub fn get_latest_dependency_from_source(
+    crate_name: &str,
+    flag_allow_prerelease: bool,
+    rust_version: Option<&str>,
+    source: &dyn VersionSource,
+) -> Result<Dependency> {
+    get_latest_dependency_and_versions_from_source(
+        crate_name,
+        flag_allow_prerelease,
+        rust_version,
+        source,
+    )
+    .map(|(dep, _)| dep)
+}
+
+/// Query latest version of a crate from `source` like `get_latest_dependency_from_source`, and
+/// also return the versions of the crate it was picked from, e.g. for
+/// `get_default_features_change`.
+pub fn get_latest_dependency_and_versions_from_source(
+    crate_name: &str,
+    flag_allow_prerelease: bool,
+    rust_version: Option<&str>,
+    source: &dyn VersionSource,
+) -> Result<(Dependency, Vec<CrateVersion>)> {
+    let rust_version = rust_version.map(parse_rust_version).transpose()?;
+
     if env::var("CARGO_IS_TEST").is_ok() {
         // We are in a simulated reality. Nothing is real here.
         // FIXME: Use actual test handling code.
@@ -49,92 +707,1360 @@ pub fn get_latest_dependency(
             }
         };
 
-        return Ok(Dependency::new(crate_name).set_version(&new_version));
+        return Ok((
+            Dependency::new(crate_name).set_version(&new_version),
+            Vec::new(),
+        ));
     }
 
     if crate_name.is_empty() {
         return Err(ErrorKind::EmptyCrateName.into());
     }
 
-    let registry_path = match registry {
-        Some(url) => registry_path_from_url(url)?,
-        None => registry_path(manifest_path, None)?,
-    };
-
-    let crate_versions = fuzzy_query_registry_index(crate_name, &registry_path)?;
+    let crate_versions = source.versions(crate_name)?;
 
-    let dep = read_latest_version(&crate_versions, flag_allow_prerelease)?;
+    let dep = read_latest_version(
+        &crate_versions,
+        flag_allow_prerelease,
+        rust_version.as_ref(),
+    )?;
 
     if dep.name != crate_name {
-        println!("WARN: Added `{}` instead of `{}`", dep.name, crate_name);
+        warn(format!("Added `{}` instead of `{}`", dep.name, crate_name))?;
     }
 
-    Ok(dep)
+    Ok((dep, crate_versions))
 }
 
-// Checks whether a version object is a stable release
-fn version_is_stable(version: &CrateVersion) -> bool {
-    !version.version.is_prerelease()
+/// A crate to resolve with `resolve_versions`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrateSpec {
+    /// The name of the crate
+    pub name: String,
+    /// The requirement the version has to match, or `None` for the latest version
+    pub version_req: Option<String>,
+    /// Whether pre-release versions are considered
+    pub allow_prerelease: bool,
+    /// The features of the crate to enable, from a `name/feature` specifier
+    pub features: Vec<String>,
 }
 
-/// Read latest version from Versions structure
-fn read_latest_version(
-    versions: &[CrateVersion],
-    flag_allow_prerelease: bool,
-) -> Result<Dependency> {
-    let latest = versions
-        .iter()
-        .filter(|&v| flag_allow_prerelease || version_is_stable(v))
-        .filter(|&v| !v.yanked)
-        .max_by_key(|&v| v.version.clone())
-        .ok_or(ErrorKind::NoVersionsAvailable)?;
+impl CrateSpec {
+    /// Specify a crate by its name and an optional version requirement, without pre-releases
+    pub fn new(name: &str, version_req: Option<&str>) -> CrateSpec {
+        CrateSpec {
+            name: name.into(),
+            version_req: version_req.map(String::from),
+            allow_prerelease: false,
+            features: Vec::new(),
+        }
+    }
 
-    let name = &latest.name;
-    let version = latest.version.to_string();
-    Ok(Dependency::new(name).set_version(&version))
+    /// Parse a crate specifier the way `cargo add` does
+    ///
+    /// This accepts a plain name like `serde`, a name with a version requirement like
+    /// `serde@^1.0` or `tokio@1.0.0-rc.1`, and a name with a feature like `serde/derive`, as in
+    /// `--features`. Crate names may contain ASCII letters, digits, `-` and `_`, but must start
+    /// with a letter or `_`. Fails with `InvalidCrateSpec` otherwise.
+    pub fn parse(spec: &str) -> Result<CrateSpec> {
+        let invalid = |reason: &str| -> Error {
+            ErrorKind::InvalidCrateSpec(spec.to_owned(), reason.to_owned()).into()
+        };
+
+        let (name_and_feature, version_req) = match spec.find('@') {
+            Some(at) => (&spec[..at], Some(&spec[at + 1..])),
+            None => (spec, None),
+        };
+        let (name, feature) = match name_and_feature.find('/') {
+            Some(slash) => (
+                &name_and_feature[..slash],
+                Some(&name_and_feature[slash + 1..]),
+            ),
+            None => (name_and_feature, None),
+        };
+
+        match name.chars().next() {
+            None => return Err(invalid("the crate name is empty")),
+            Some(c) if !c.is_ascii_alphabetic() && c != '_' => {
+                return Err(invalid(&format!(
+                    "the crate name must start with a letter or `_`, not `{}`",
+                    c
+                )))
+            }
+            _ => {}
+        }
+        if let Some(c) = name
+            .chars()
+            .find(|&c| !c.is_ascii_alphanumeric() && c != '-' && c != '_')
+        {
+            return Err(invalid(&format!("the crate name must not contain `{}`", c)));
+        }
+
+        let mut crate_spec = CrateSpec::new(name, None);
+        if let Some(version_req) = version_req {
+            if feature.is_some() {
+                return Err(invalid(
+                    "a feature can't be combined with a version requirement",
+                ));
+            }
+            if version_req.is_empty() {
+                return Err(invalid("the version requirement after `@` is empty"));
+            }
+            semver::VersionReq::parse(version_req).map_err(|_| {
+                invalid(&format!(
+                    "`{}` is not a valid version requirement",
+                    version_req
+                ))
+            })?;
+            crate_spec.version_req = Some(version_req.to_owned());
+        }
+        if let Some(feature) = feature {
+            if feature.is_empty() {
+                return Err(invalid("the feature after `/` is empty"));
+            }
+            if let Some(c) = feature
+                .chars()
+                .find(|&c| !c.is_alphanumeric() && c != '-' && c != '_' && c != '+' && c != '.')
+            {
+                return Err(invalid(&format!("the feature must not contain `{}`", c)));
+            }
+            crate_spec.features.push(feature.to_owned());
+        }
+        Ok(crate_spec)
+    }
 }
 
-/// update registry index for given project
-pub fn update_registry_index(registry: &Url) -> Result<()> {
-    let registry_path = registry_path_from_url(registry)?;
+#[test]
+fn parse_valid_crate_specs() {
+    let spec = CrateSpec::parse("serde").unwrap();
+    assert_eq!(spec, CrateSpec::new("serde", None));
 
-    let colorchoice = if atty::is(atty::Stream::Stdout) {
-        ColorChoice::Auto
-    } else {
-        ColorChoice::Never
+    for (spec, name, version_req) in &[
+        ("serde@1", "serde", "1"),
+        ("serde_json@^1.0.40", "serde_json", "^1.0.40"),
+        ("cargo-edit@~0.6", "cargo-edit", "~0.6"),
+        ("tokio@1.0.0-rc.1", "tokio", "1.0.0-rc.1"),
+        ("tokio@=0.3.0-alpha.2", "tokio", "=0.3.0-alpha.2"),
+        ("_private@>=0.9, <2.0", "_private", ">=0.9, <2.0"),
+        ("Inflector@*", "Inflector", "*"),
+    ] {
+        let parsed = CrateSpec::parse(spec).unwrap();
+        assert_eq!(parsed, CrateSpec::new(name, Some(version_req)), "{}", spec);
+    }
+
+    let spec = CrateSpec::parse("serde/derive").unwrap();
+    assert_eq!(spec.name, "serde");
+    assert_eq!(spec.version_req, None);
+    assert_eq!(spec.features, vec!["derive"]);
+    let spec = CrateSpec::parse("tokio_util/io-util").unwrap();
+    assert_eq!(spec.features, vec!["io-util"]);
+}
+
+#[test]
+fn parse_invalid_crate_specs() {
+    for spec in &[
+        "",
+        "@1.0",
+        "/derive",
+        "serde@",
+        "serde@one",
+        "serde@1.0/derive",
+        "serde/derive@1.0",
+        "serde/",
+        "serde/a/b",
+        "1password",
+        "-dash",
+        "foo.bar",
+        "foo bar",
+        "crème",
+        "https://github.com/serde-rs/serde",
+    ] {
+        match CrateSpec::parse(spec) {
+            Err(Error(ErrorKind::InvalidCrateSpec(..), _)) => {}
+            other => panic!("`{}` should be invalid, got {:?}", spec, other),
+        }
+    }
+}
+
+/// The version a `CrateSpec` was resolved to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedDep {
+    /// The name of the crate, which may differ from the requested one in the use of `-` and `_`
+    pub name: String,
+    /// The newest version that isn't yanked and matches the spec
+    pub version: semver::Version,
+}
+
+/// Resolve many crates at once, returning the results in the order of `specs`
+///
+/// The versions of each crate are only requested from `source` once, no matter how many specs
+/// refer to it.
+pub fn resolve_versions(
+    specs: &[CrateSpec],
+    source: &dyn VersionSource,
+) -> Vec<Result<ResolvedDep>> {
+    let mut cache: HashMap<String, Vec<CrateVersion>> = HashMap::new();
+    specs
+        .iter()
+        .map(|spec| {
+            if !cache.contains_key(&spec.name) {
+                let versions = source.versions(&spec.name)?;
+                cache.insert(spec.name.clone(), versions);
+            }
+            resolve_version(spec, &cache[&spec.name])
+        })
+        .collect()
+}
+
+fn resolve_version(spec: &CrateSpec, versions: &[CrateVersion]) -> Result<ResolvedDep> {
+    let req = match &spec.version_req {
+        Some(version_req) => Some(
+            semver::VersionReq::parse(version_req)
+                .chain_err(|| ErrorKind::ParseVersion(version_req.clone(), spec.name.clone()))?,
+        ),
+        None => None,
     };
-    let mut output = StandardStream::stdout(colorchoice);
+    let newest = versions
+        .iter()
+        .filter(|&v| !v.yanked)
+        .filter(|&v| match &req {
+            // Requirements only match pre-releases if they mention one.
+            Some(req) => req.matches(&v.version),
+            None => spec.allow_prerelease || version_is_stable(v),
+        })
+        .max_by_key(|&v| v.version.clone());
+    match (newest, &spec.version_req) {
+        (Some(newest), _) => Ok(ResolvedDep {
+            name: newest.name.clone(),
+            version: newest.version.clone(),
+        }),
+        (None, Some(version_req)) => {
+            Err(ErrorKind::NoMatchingVersion(spec.name.clone(), version_req.clone()).into())
+        }
+        (None, None) => Err(ErrorKind::NoVersionsAvailable.into()),
+    }
+}
 
-    if !registry_path.as_path().exists() {
-        output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
-        write!(output, "{:>12}", "Initializing")?;
-        output.reset()?;
-        writeln!(output, " '{}' index", registry)?;
+/// Query latest version of a crate from the first of `sources` that has it
+///
+/// Sources that don't know the crate are skipped, any other failure is returned right away.
+/// Returns the dependency alongside the index of the source it was found in.
+pub fn get_latest_dependency_from_sources(
+    crate_name: &str,
+    flag_allow_prerelease: bool,
+    rust_version: Option<&str>,
+    sources: &[&dyn VersionSource],
+) -> Result<(Dependency, usize)> {
+    for (index, source) in sources.iter().enumerate() {
+        match get_latest_dependency_from_source(
+            crate_name,
+            flag_allow_prerelease,
+            rust_version,
+            *source,
+        ) {
+            Ok(dep) => return Ok((dep, index)),
+            Err(Error(ErrorKind::NoCrate(_), _)) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(ErrorKind::NoCrate(crate_name.to_owned()).into())
+}
 
-        let mut opts = git2::RepositoryInitOptions::new();
-        opts.bare(true);
-        git2::Repository::init_opts(&registry_path, &opts)?;
-        return Ok(());
+/// Query the latest version of a crate from `source` that also satisfies `version_req`
+///
+/// This allows upgrading within a ceiling such as `<2`. Otherwise this behaves like
+/// `get_latest_dependency_from_source`, and fails with `NoMatchingVersion` if no version matches.
+pub fn get_latest_dependency_matching_from_source(
+    crate_name: &str,
+    version_req: &str,
+    flag_allow_prerelease: bool,
+    rust_version: Option<&str>,
+    source: &dyn VersionSource,
+) -> Result<Dependency> {
+    let rust_version = rust_version.map(parse_rust_version).transpose()?;
+
+    if env::var("CARGO_IS_TEST").is_ok() {
+        return Ok(Dependency::new(crate_name)
+            .set_version(&format!("{}--CURRENT_VERSION_TEST", crate_name)));
     }
 
-    let repo = git2::Repository::open(&registry_path)?;
-    output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
-    write!(output, "{:>12}", "Updating")?;
-    output.reset()?;
-    writeln!(output, " '{}' index", registry)?;
+    let req = semver::VersionReq::parse(version_req)
+        .chain_err(|| ErrorKind::ParseVersionReq(version_req.into()))?;
+    let crate_versions = source
+        .versions(crate_name)?
+        .into_iter()
+        .filter(|version| req.matches(&version.version))
+        .collect::<Vec<_>>();
 
-    let refspec = "refs/heads/master:refs/remotes/origin/master";
-    fetch_with_cli(&repo, registry.as_str(), refspec)?;
+    read_latest_version(
+        &crate_versions,
+        flag_allow_prerelease,
+        rust_version.as_ref(),
+    )
+    .chain_err(|| ErrorKind::NoMatchingVersion(crate_name.into(), version_req.into()))
+}
 
-    Ok(())
+/// Get the requirement for the versions that are semver compatible with the lowest one
+/// `version_req` allows, e.g. `^1.2` for `~1.2.3` or `>=1.2, <1.5`, and `^0.3` for `0.3.*`.
+///
+/// Upgrading within it never crosses a breaking change.
+pub fn compatible_requirement(version_req: &str) -> String {
+    let lowest = version_req
+        .split(',')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_start_matches(|c: char| !c.is_ascii_digit() && c != '*')
+        .trim();
+    let lowest = lowest.trim_end_matches(".*");
+    if lowest.is_empty() || lowest.starts_with('*') {
+        "*".to_owned()
+    } else {
+        format!("^{}", lowest)
+    }
 }
 
-// https://github.com/rust-lang/cargo/blob/57986eac7157261c33f0123bade7ccd20f15200f/src/cargo/sources/git/utils.rs#L758
-fn fetch_with_cli(repo: &git2::Repository, url: &str, refspec: &str) -> Result<()> {
-    let cmd = subprocess::Exec::shell("git")
-        .arg("fetch")
-        .arg("--tags") // fetch all tags
-        .arg("--force") // handle force pushes
+/// Query the latest version of a crate from `source` that is semver compatible with
+/// `version_req`, following `compatible_requirement`.
+///
+/// Otherwise this behaves like `get_latest_dependency_matching_from_source`.
+pub fn get_latest_compatible_dependency_from_source(
+    crate_name: &str,
+    version_req: &str,
+    flag_allow_prerelease: bool,
+    rust_version: Option<&str>,
+    source: &dyn VersionSource,
+) -> Result<Dependency> {
+    get_latest_dependency_matching_from_source(
+        crate_name,
+        &compatible_requirement(version_req),
+        flag_allow_prerelease,
+        rust_version,
+        source,
+    )
+}
+
+/// Query the latest version of a crate from `source` that can be used together with the
+/// requirement `peer_req` on the crate `peer`
+///
+/// A version is compatible if the requirements of its normal and build dependencies on `peer`
+/// match some version that `peer_req` matches as well, or if it doesn't depend on `peer` at all.
+/// This only looks at the index entries of `crate_name`, it doesn't resolve the whole dependency
+/// graph. Fails with `NoCompatibleVersion` if no version is compatible.
+pub fn get_latest_dependency_compatible_from_source(
+    crate_name: &str,
+    peer: &str,
+    peer_req: &str,
+    flag_allow_prerelease: bool,
+    rust_version: Option<&str>,
+    source: &dyn VersionSource,
+) -> Result<Dependency> {
+    let rust_version = rust_version.map(parse_rust_version).transpose()?;
+
+    if env::var("CARGO_IS_TEST").is_ok() {
+        return Ok(Dependency::new(crate_name)
+            .set_version(&format!("{}--CURRENT_VERSION_TEST", crate_name)));
+    }
+
+    let peer_ranges = VersionRanges::parse(peer_req)?;
+    let mut crate_versions = Vec::new();
+    for version in source.versions(crate_name)? {
+        let mut compatible = true;
+        for dep in version
+            .deps
+            .iter()
+            .filter(|dep| dep.crate_name() == peer && dep.kind.as_deref() != Some("dev"))
+        {
+            compatible &= VersionRanges::parse(&dep.req)?.overlaps(&peer_ranges);
+        }
+        if compatible {
+            crate_versions.push(version);
+        }
+    }
+
+    read_latest_version(
+        &crate_versions,
+        flag_allow_prerelease,
+        rust_version.as_ref(),
+    )
+    .chain_err(|| ErrorKind::NoCompatibleVersion(crate_name.into(), peer.into(), peer_req.into()))
+}
+
+/// Query the lowest version of a crate that satisfies `version_req`
+///
+/// Yanked versions are skipped. This is useful to check that the lower bound of a requirement
+/// still builds.
+pub fn get_minimal_dependency(
+    crate_name: &str,
+    version_req: &str,
+    manifest_path: &Path,
+    registry: &Option<Url>,
+) -> Result<Dependency> {
+    get_minimal_dependency_from_source(
+        crate_name,
+        version_req,
+        &RegistryIndex::new(manifest_path, registry),
+    )
+}
+
+/// Query the lowest version of a crate from `source`, see `get_minimal_dependency`
+pub fn get_minimal_dependency_from_source(
+    crate_name: &str,
+    version_req: &str,
+    source: &dyn VersionSource,
+) -> Result<Dependency> {
+    if env::var("CARGO_IS_TEST").is_ok() {
+        return Ok(Dependency::new(crate_name)
+            .set_version(&format!("{}--MINIMAL_VERSION_TEST", crate_name)));
+    }
+
+    let req = semver::VersionReq::parse(version_req)
+        .chain_err(|| ErrorKind::ParseVersion(version_req.into(), crate_name.into()))?;
+    let crate_versions = source.versions(crate_name)?;
+
+    let lowest = crate_versions
+        .iter()
+        .filter(|version| !version.yanked && req.matches(&version.version))
+        .min_by_key(|version| &version.version)
+        .chain_err(|| ErrorKind::NoMatchingVersion(crate_name.into(), version_req.into()))?;
+
+    Ok(Dependency::new(&lowest.name).set_version(&lowest.version.to_string()))
+}
+
+/// Find the version to widen `version_req` to if every version of a crate matching it is yanked
+///
+/// Returns the lowest version newer than the yanked ones that isn't yanked itself, or `None` if
+/// `version_req` still matches a version that isn't yanked, or no version at all. Fails with
+/// `NoVersionsAvailable` if there is no such version.
+pub fn get_yank_safe_dependency_from_source(
+    crate_name: &str,
+    version_req: &str,
+    flag_allow_prerelease: bool,
+    source: &dyn VersionSource,
+) -> Result<Option<Dependency>> {
+    if env::var("CARGO_IS_TEST").is_ok() {
+        return Ok(None);
+    }
+
+    let req = semver::VersionReq::parse(version_req)
+        .chain_err(|| ErrorKind::ParseVersionReq(version_req.into()))?;
+    let crate_versions = source.versions(crate_name)?;
+    let newest_yanked = match crate_versions
+        .iter()
+        .filter(|version| req.matches(&version.version))
+        .map(|version| (version.yanked, &version.version))
+        .max_by_key(|&(yanked, version)| (!yanked, version))
+    {
+        Some((true, version)) => version,
+        _ => return Ok(None),
+    };
+
+    let next = crate_versions
+        .iter()
+        .filter(|version| flag_allow_prerelease || version_is_stable(version))
+        .filter(|version| !version.yanked && version.version > *newest_yanked)
+        .min_by_key(|version| &version.version)
+        .ok_or(ErrorKind::NoVersionsAvailable)?;
+    Ok(Some(
+        Dependency::new(&next.name).set_version(&next.version.to_string()),
+    ))
+}
+
+#[test]
+fn widen_requirement_past_yanked_versions() {
+    struct Yanked;
+
+    impl VersionSource for Yanked {
+        fn versions(&self, _: &str) -> Result<Vec<CrateVersion>> {
+            Ok([
+                ("1.1.0", false),
+                ("1.2.0", true),
+                ("1.2.1", true),
+                ("1.3.0-rc.1", false),
+                ("1.3.0", false),
+                ("2.0.0", true),
+            ]
+            .iter()
+            .map(|&(version, yanked)| CrateVersion {
+                yanked,
+                ..CrateVersion::new("foo", semver::Version::parse(version).unwrap())
+            })
+            .collect())
+        }
+    }
+
+    let widened = |req| {
+        get_yank_safe_dependency_from_source("foo", req, false, &Yanked)
+            .map(|dep| dep.map(|dep| dep.version().unwrap().to_owned()))
+    };
+    assert_eq!(widened("~1.2").unwrap().as_deref(), Some("1.3.0"));
+    assert_eq!(widened("=1.2.0").unwrap().as_deref(), Some("1.3.0"));
+    // `1.1.0` still matches and isn't yanked.
+    assert_eq!(widened("^1.1").unwrap(), None);
+    assert_eq!(widened("^3").unwrap(), None);
+    match widened("^2").unwrap_err().kind() {
+        ErrorKind::NoVersionsAvailable => {}
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+/// Find the version to upgrade `crate_name` to if a version matching `version_req` is affected
+/// by one of `advisories`, returning it with the ids of those advisories.
+///
+/// That version is the lowest one newer than every affected version matching `version_req`
+/// which is neither yanked nor affected itself, i.e. the minimal patched release. Returns
+/// `Ok(None)` if no matching version is affected, and fails with `NoVersionsAvailable` if
+/// there is no patched release yet.
+pub fn get_advisory_safe_dependency_from_source(
+    crate_name: &str,
+    version_req: &str,
+    flag_allow_prerelease: bool,
+    advisories: &AdvisoryDatabase,
+    source: &dyn VersionSource,
+) -> Result<Option<(Dependency, Vec<String>)>> {
+    if env::var("CARGO_IS_TEST").is_ok() {
+        return Ok(None);
+    }
+
+    let req = semver::VersionReq::parse(version_req)
+        .chain_err(|| ErrorKind::ParseVersionReq(version_req.into()))?;
+    let crate_versions = source.versions(crate_name)?;
+    let mut ids = Vec::new();
+    let mut newest_affected = None;
+    for version in crate_versions
+        .iter()
+        .filter(|version| req.matches(&version.version))
+    {
+        let affecting = advisories.affecting(crate_name, &version.version);
+        if affecting.is_empty() {
+            continue;
+        }
+        ids.extend(affecting.into_iter().map(|advisory| advisory.id.clone()));
+        newest_affected = newest_affected.max(Some(&version.version));
+    }
+    let newest_affected = match newest_affected {
+        Some(version) => version,
+        None => return Ok(None),
+    };
+    ids.sort();
+    ids.dedup();
+
+    let next = crate_versions
+        .iter()
+        .filter(|version| flag_allow_prerelease || version_is_stable(version))
+        .filter(|version| !version.yanked && version.version > *newest_affected)
+        .filter(|version| !advisories.is_affected(crate_name, &version.version))
+        .min_by_key(|version| &version.version)
+        .ok_or(ErrorKind::NoVersionsAvailable)?;
+    Ok(Some((
+        Dependency::new(&next.name).set_version(&next.version.to_string()),
+        ids,
+    )))
+}
+
+#[test]
+fn upgrade_past_versions_with_advisories() {
+    struct Published;
+
+    impl VersionSource for Published {
+        fn versions(&self, _: &str) -> Result<Vec<CrateVersion>> {
+            Ok([
+                ("1.1.0", false),
+                ("1.2.0", false),
+                ("1.2.1", true),
+                ("1.2.2", false),
+                ("1.3.0", false),
+                ("2.0.0", false),
+            ]
+            .iter()
+            .map(|&(version, yanked)| CrateVersion {
+                yanked,
+                ..CrateVersion::new("foo", semver::Version::parse(version).unwrap())
+            })
+            .collect())
+        }
+    }
+
+    let mut advisories = AdvisoryDatabase::default();
+    advisories.insert(crate::Advisory {
+        id: "RUSTSEC-2021-0001".to_owned(),
+        package: "foo".to_owned(),
+        patched: vec![semver::VersionReq::parse(">= 1.2.1").unwrap()],
+        unaffected: vec![semver::VersionReq::parse("< 1.1.0").unwrap()],
+    });
+    advisories.insert(crate::Advisory {
+        id: "RUSTSEC-2021-0002".to_owned(),
+        package: "foo".to_owned(),
+        patched: vec![semver::VersionReq::parse(">= 2.0.0").unwrap()],
+        unaffected: vec![semver::VersionReq::parse("< 1.3.0").unwrap()],
+    });
+    let fixed = |req| {
+        get_advisory_safe_dependency_from_source("foo", req, false, &advisories, &Published)
+            .map(|fix| fix.map(|(dep, ids)| (dep.version().unwrap().to_owned(), ids)))
+    };
+
+    // The yanked `1.2.1` is skipped for the minimal patched release.
+    assert_eq!(
+        fixed("~1.1").unwrap(),
+        Some(("1.2.2".to_owned(), vec!["RUSTSEC-2021-0001".to_owned()]))
+    );
+    assert_eq!(fixed("~1.2.2").unwrap(), None);
+    assert_eq!(
+        fixed("^1.1").unwrap(),
+        Some((
+            "2.0.0".to_owned(),
+            vec![
+                "RUSTSEC-2021-0001".to_owned(),
+                "RUSTSEC-2021-0002".to_owned()
+            ]
+        ))
+    );
+    assert_eq!(fixed("^2").unwrap(), None);
+}
+
+/// Check whether a crate called `crate_name` is published to the registry
+///
+/// Like Cargo, names that only differ in the use of `-` and `_` are considered the same. Returns
+/// `Ok(false)` if the index says the crate does not exist, while failing to reach the index is
+/// reported as an error, so callers can tell "absent" apart from "couldn't determine".
+pub fn crate_exists(
+    crate_name: &str,
+    manifest_path: &Path,
+    registry: &Option<Url>,
+) -> Result<bool> {
+    crate_exists_in_source(crate_name, &RegistryIndex::new(manifest_path, registry))
+}
+
+/// Check whether `source` knows a crate called `crate_name`, see `crate_exists`
+pub fn crate_exists_in_source(crate_name: &str, source: &dyn VersionSource) -> Result<bool> {
+    match source.versions(crate_name) {
+        Ok(_) => Ok(true),
+        Err(Error(ErrorKind::NoCrate(_), _)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Find the dependencies in `existing` that link to the same native library as `dependency`,
+/// according to the `links` field of the newest versions matching their requirements.
+///
+/// Cargo refuses to build a package in which two crates declare the same `links` value, so this
+/// is meant to warn before adding such a dependency. Returns the names of the colliding
+/// dependencies alongside the shared `links` value. Dependencies without a version requirement
+/// are ignored.
+pub fn get_links_collisions(
+    dependency: &Dependency,
+    existing: &[Dependency],
+    source: &dyn VersionSource,
+) -> Result<Vec<(String, String)>> {
+    if env::var("CARGO_IS_TEST").is_ok() {
+        return Ok(Vec::new());
+    }
+
+    let links = |dep: &Dependency| -> Result<Option<String>> {
+        let version_req = match dep.version() {
+            Some(version_req) => version_req,
+            None => return Ok(None),
+        };
+        let req = semver::VersionReq::parse(version_req)
+            .chain_err(|| ErrorKind::ParseVersion(version_req.into(), dep.name.clone()))?;
+        Ok(source
+            .versions(&dep.name)?
+            .into_iter()
+            .filter(|version| !version.yanked && req.matches(&version.version))
+            .max_by(|a, b| a.version.cmp(&b.version))
+            .and_then(|version| version.links))
+    };
+
+    let new_links = match links(dependency)? {
+        Some(new_links) => new_links,
+        None => return Ok(Vec::new()),
+    };
+    let mut collisions = Vec::new();
+    for dep in existing.iter().filter(|dep| dep.name != dependency.name) {
+        if links(dep)?.as_ref() == Some(&new_links) {
+            collisions.push((dep.name.clone(), new_links.clone()));
+        }
+    }
+    Ok(collisions)
+}
+
+/// Find the features requested for `dependency` that the newest version matching its
+/// requirement doesn't have, including implicit features of optional dependencies.
+///
+/// Features of the dependency's own dependencies (`dep/feature`) are not checked.
+pub fn get_unknown_features(
+    dependency: &Dependency,
+    source: &dyn VersionSource,
+) -> Result<Vec<String>> {
+    if env::var("CARGO_IS_TEST").is_ok() {
+        // Without an index to look them up in, only features named `unknown*` don't exist.
+        return Ok(dependency
+            .features()
+            .unwrap_or_default()
+            .iter()
+            .filter(|feature| feature.starts_with("unknown"))
+            .cloned()
+            .collect());
+    }
+
+    let (version_req, features) = match (dependency.version(), dependency.features()) {
+        (Some(version_req), Some(features)) => (version_req, features),
+        _ => return Ok(Vec::new()),
+    };
+    let version = match newest_matching(&dependency.name, version_req, source)? {
+        Some(version) => version,
+        None => return Ok(Vec::new()),
+    };
+    Ok(features
+        .iter()
+        .filter(|feature| !feature.contains('/') && !version.has_feature(feature))
+        .cloned()
+        .collect())
+}
+
+/// Get the features of the newest version matching the requirement of `dependency`, see
+/// `CrateVersion::feature_names`, along with the ones it enables by default. `None` if no version
+/// matches.
+pub fn get_available_features(
+    dependency: &Dependency,
+    source: &dyn VersionSource,
+) -> Result<Option<(Vec<String>, Vec<String>)>> {
+    if env::var("CARGO_IS_TEST").is_ok() {
+        return Ok(None);
+    }
+
+    let version = match dependency.version() {
+        Some(version_req) => newest_matching(&dependency.name, version_req, source)?,
+        None => None,
+    };
+    Ok(version.map(|version| (version.feature_names(), version.default_features())))
+}
+
+/// Find the required dependencies of the newest version matching `dependency` that can only be
+/// resolved to yanked versions, as pairs of crate name and requirement.
+///
+/// Optional dependencies, dev-dependencies and dependencies from other registries are not
+/// checked.
+pub fn get_yanked_dependencies(
+    dependency: &Dependency,
+    source: &dyn VersionSource,
+) -> Result<Vec<(String, String)>> {
+    if env::var("CARGO_IS_TEST").is_ok() {
+        return Ok(Vec::new());
+    }
+
+    let version = match dependency.version() {
+        Some(version_req) => newest_matching(&dependency.name, version_req, source)?,
+        None => None,
+    };
+    let version = match version {
+        Some(version) => version,
+        None => return Ok(Vec::new()),
+    };
+    let mut yanked = Vec::new();
+    for dep in version
+        .deps
+        .iter()
+        .filter(|dep| !dep.optional && dep.kind.as_deref() != Some("dev") && dep.registry.is_none())
+    {
+        let req = semver::VersionReq::parse(&dep.req)
+            .chain_err(|| ErrorKind::ParseVersion(dep.req.clone(), dep.crate_name().into()))?;
+        let matching = source
+            .versions(dep.crate_name())?
+            .into_iter()
+            .filter(|version| req.matches(&version.version))
+            .collect::<Vec<_>>();
+        if !matching.is_empty() && matching.iter().all(|version| version.yanked) {
+            yanked.push((dep.crate_name().to_owned(), dep.req.clone()));
+        }
+    }
+    Ok(yanked)
+}
+
+/// Find the version of a crate that `version_req` selects, i.e. the newest version matching it, if
+/// that version is yanked
+///
+/// Returns `None` if the newest matching version isn't yanked, or if no version matches at all.
+pub fn get_yanked_selection_from_source(
+    crate_name: &str,
+    version_req: &str,
+    source: &dyn VersionSource,
+) -> Result<Option<Dependency>> {
+    if env::var("CARGO_IS_TEST").is_ok() {
+        return Ok(None);
+    }
+
+    let req = semver::VersionReq::parse(version_req)
+        .chain_err(|| ErrorKind::ParseVersionReq(version_req.into()))?;
+    let crate_versions = source.versions(crate_name)?;
+    let selected = crate_versions
+        .iter()
+        .filter(|version| req.matches(&version.version))
+        .max_by_key(|version| &version.version);
+    Ok(selected
+        .filter(|version| version.yanked)
+        .map(|version| Dependency::new(&version.name).set_version(&version.version.to_string())))
+}
+
+/// Check that a version of `crate_name` that is not yanked matches `version_req`, e.g. for a
+/// requirement given on the command line.
+pub fn verify_version_exists(
+    crate_name: &str,
+    version_req: &str,
+    source: &dyn VersionSource,
+) -> Result<()> {
+    if env::var("CARGO_IS_TEST").is_ok() {
+        return Ok(());
+    }
+
+    match newest_matching(crate_name, version_req, source)? {
+        Some(_) => Ok(()),
+        None => Err(ErrorKind::NoMatchingVersion(crate_name.into(), version_req.into()).into()),
+    }
+}
+
+/// Get the newest version of `crate_name` matching `version_req` that is not yanked.
+fn newest_matching(
+    crate_name: &str,
+    version_req: &str,
+    source: &dyn VersionSource,
+) -> Result<Option<CrateVersion>> {
+    let req = semver::VersionReq::parse(version_req)
+        .chain_err(|| ErrorKind::ParseVersion(version_req.into(), crate_name.into()))?;
+    Ok(source
+        .versions(crate_name)?
+        .into_iter()
+        .filter(|version| !version.yanked && req.matches(&version.version))
+        .max_by(|a, b| a.version.cmp(&b.version)))
+}
+
+/// Get the source repository of `crate_name` from `source`, see `VersionSource::repository`.
+pub fn get_repository_from_source(
+    crate_name: &str,
+    source: &dyn VersionSource,
+) -> Result<Option<String>> {
+    if env::var("CARGO_IS_TEST").is_ok() {
+        return Ok(Some(format!(
+            "https://github.com/{name}/{name}",
+            name = crate_name
+        )));
+    }
+
+    source.repository(crate_name)
+}
+
+/// Get a link to the changes between the lowest version matching `old_req` and `new_version`
+/// of a crate whose source is in `repository`, e.g.
+/// `https://github.com/x/y/compare/v1.2.0...v1.3.0`, assuming its releases are tagged as
+/// `v<version>`.
+///
+/// This is only known for repositories on GitHub, GitLab and Codeberg, and for requirements with
+/// a lower bound.
+pub fn changelog_url(repository: &str, old_req: &str, new_version: &str) -> Option<String> {
+    let re = Regex::new(
+        r"^https?://(?:www\.)?(github\.com|gitlab\.com|codeberg\.org)/([-_.0-9a-zA-Z]+)/([-_.0-9a-zA-Z]+?)(?:\.git)?(?:[/#?].*)?$",
+    )
+    .unwrap();
+    let cap = re.captures(repository.trim())?;
+    let (host, owner, repo) = (&cap[1], &cap[2], &cap[3]);
+    let old_version = VersionRanges::parse(old_req).ok()?.lowest()?.to_string();
+    let new_version = semver::Version::parse(new_version).ok()?;
+    let compare = if host == "gitlab.com" {
+        "-/compare"
+    } else {
+        "compare"
+    };
+    Some(format!(
+        "https://{}/{}/{}/{}/v{}...v{}",
+        host, owner, repo, compare, old_version, new_version
+    ))
+}
+
+#[test]
+fn link_changes_between_versions() {
+    assert_eq!(
+        changelog_url("https://github.com/serde-rs/serde", "1.0", "1.0.104").as_deref(),
+        Some("https://github.com/serde-rs/serde/compare/v1.0.0...v1.0.104")
+    );
+    assert_eq!(
+        changelog_url("https://github.com/rust-lang/log.git", "~0.4.8", "0.4.11").as_deref(),
+        Some("https://github.com/rust-lang/log/compare/v0.4.8...v0.4.11")
+    );
+    assert_eq!(
+        changelog_url(
+            "https://github.com/tokio-rs/tokio/tree/master/tokio",
+            "0.2",
+            "1.0.0"
+        )
+        .as_deref(),
+        Some("https://github.com/tokio-rs/tokio/compare/v0.2.0...v1.0.0")
+    );
+    assert_eq!(
+        changelog_url("https://gitlab.com/foo/bar/", "2", "2.1.0").as_deref(),
+        Some("https://gitlab.com/foo/bar/-/compare/v2.0.0...v2.1.0")
+    );
+    assert_eq!(
+        changelog_url("https://example.com/foo/bar", "1", "1.1.0"),
+        None
+    );
+    assert_eq!(
+        changelog_url("https://github.com/foo/bar", "*", "1.1.0"),
+        None
+    );
+}
+
+/// Compare the default features of the newest version matching `old_req` with the ones of
+/// `new_version`, as recorded in `crate_versions`, the versions of the crate that were already
+/// read from its registry, e.g. with `get_latest_dependency_and_versions_from_source`.
+pub fn get_default_features_change(
+    crate_name: &str,
+    old_req: &str,
+    new_version: &str,
+    crate_versions: &[CrateVersion],
+) -> Result<DefaultFeaturesChange> {
+    let old_req = semver::VersionReq::parse(old_req)
+        .chain_err(|| ErrorKind::ParseVersion(old_req.to_string(), crate_name.to_string()))?;
+    let new_version = semver::Version::parse(new_version)
+        .chain_err(|| ErrorKind::ParseVersion(new_version.to_string(), crate_name.to_string()))?;
+
+    Ok(default_features_change(
+        crate_versions,
+        &old_req,
+        &new_version,
+    ))
+}
+
+fn default_features_change(
+    versions: &[CrateVersion],
+    old_req: &semver::VersionReq,
+    new_version: &semver::Version,
+) -> DefaultFeaturesChange {
+    fn default_features(version: Option<&CrateVersion>) -> Vec<String> {
+        version
+            .and_then(|v| v.features.get("default"))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    let old = versions
+        .iter()
+        .filter(|v| old_req.matches(&v.version))
+        .max_by_key(|v| v.version.clone());
+    let new = versions.iter().find(|v| v.version == *new_version);
+    if old.is_none() || new.is_none() {
+        return DefaultFeaturesChange::default();
+    }
+
+    let old = default_features(old);
+    let new = default_features(new);
+    DefaultFeaturesChange {
+        added: new.iter().filter(|f| !old.contains(f)).cloned().collect(),
+        removed: old.iter().filter(|f| !new.contains(f)).cloned().collect(),
+    }
+}
+
+#[test]
+fn get_default_features_change_from_fixture_index() {
+    struct FixtureIndex {
+        reads: std::cell::Cell<usize>,
+    }
+
+    impl VersionSource for FixtureIndex {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            self.reads.set(self.reads.get() + 1);
+            parse_summary(&fs::read_to_string(format!(
+                "tests/fixtures/sparse-index/{}",
+                summary_raw_path(crate_name)
+            ))?)
+        }
+    }
+
+    let index = FixtureIndex {
+        reads: Default::default(),
+    };
+    let (dep, versions) =
+        get_latest_dependency_and_versions_from_source("defaults", false, None, &index).unwrap();
+    assert_eq!(dep.version(), Some("0.6.0"));
+    assert_eq!(
+        get_default_features_change("defaults", "0.5", "0.6.0", &versions).unwrap(),
+        DefaultFeaturesChange {
+            added: vec!["alloc".to_string()],
+            removed: vec!["derive".to_string()],
+        }
+    );
+    // The index is only read for the lookup, the comparison reuses its versions.
+    assert_eq!(index.reads.get(), 1);
+}
+
+#[test]
+fn get_default_features_change_from_json() {
+    let versions: Vec<CrateVersion> = serde_json::from_str(
+        r#"[
+        {
+          "name": "foo",
+          "vers": "0.5.0",
+          "yanked": false,
+          "features": { "default": ["std", "derive"], "std": [], "derive": [] }
+        },
+        {
+          "name": "foo",
+          "vers": "0.6.0",
+          "yanked": false,
+          "features": { "default": ["std", "alloc"], "std": [], "alloc": [] }
+        },
+        {
+          "name": "foo",
+          "vers": "0.6.1",
+          "yanked": false
+        }
+      ]"#,
+    )
+    .expect("crate version is correctly parsed");
+    let old_req = semver::VersionReq::parse("0.5").unwrap();
+
+    assert_eq!(
+        default_features_change(
+            &versions,
+            &old_req,
+            &semver::Version::parse("0.6.0").unwrap()
+        ),
+        DefaultFeaturesChange {
+            added: vec!["alloc".to_string()],
+            removed: vec!["derive".to_string()],
+        }
+    );
+    assert_eq!(
+        default_features_change(
+            &versions,
+            &old_req,
+            &semver::Version::parse("0.6.1").unwrap()
+        ),
+        DefaultFeaturesChange {
+            added: vec![],
+            removed: vec!["std".to_string(), "derive".to_string()],
+        }
+    );
+    assert!(default_features_change(
+        &versions,
+        &old_req,
+        &semver::Version::parse("0.5.0").unwrap()
+    )
+    .is_empty());
+}
+
+/// Verify that the version of a crate that `version` resolves to has the given SHA256 checksum
+/// in the registry index.
+///
+/// A full version like `1.2.3`, `^1.2.3` or `=1.2.3` stands for exactly that version, as it is the
+/// one that was resolved and written to the manifest; partial ones like `1.2` for the newest one
+/// matching.
+/// This fails with `ErrorKind::ChecksumMismatch` if the checksums differ.
+pub fn verify_checksum(
+    crate_name: &str,
+    version: &str,
+    expected: &str,
+    manifest_path: &Path,
+    registry: &Option<Url>,
+) -> Result<()> {
+    if env::var("CARGO_IS_TEST").is_ok() {
+        return Ok(());
+    }
+
+    let crate_versions = query_registry_index(crate_name, manifest_path, registry, None, None)?;
+
+    check_checksum(crate_name, &crate_versions, version, expected)
+}
+
+fn check_checksum(
+    crate_name: &str,
+    versions: &[CrateVersion],
+    version: &str,
+    expected: &str,
+) -> Result<()> {
+    let exact = version
+        .trim()
+        .trim_start_matches(|c: char| ['=', '^', '~'].contains(&c) || c.is_whitespace());
+    let resolved = match semver::Version::parse(exact) {
+        Ok(exact) => versions.iter().find(|v| v.version == exact),
+        Err(_) => {
+            let req = semver::VersionReq::parse(version).chain_err(|| {
+                ErrorKind::ParseVersion(version.to_string(), crate_name.to_string())
+            })?;
+            versions
+                .iter()
+                .filter(|v| !v.yanked && req.matches(&v.version))
+                .max_by_key(|v| v.version.clone())
+        }
+    }
+    .ok_or(ErrorKind::NoVersionsAvailable)?;
+
+    let actual = resolved.checksum.clone().unwrap_or_default();
+    if actual.eq_ignore_ascii_case(expected.trim()) {
+        Ok(())
+    } else {
+        Err(ErrorKind::ChecksumMismatch(
+            resolved.name.clone(),
+            resolved.version.to_string(),
+            expected.to_string(),
+            actual,
+        )
+        .into())
+    }
+}
+
+#[test]
+fn check_checksum_from_json() {
+    let versions: Vec<CrateVersion> = serde_json::from_str(
+        r#"[
+        {
+          "name": "foo",
+          "vers": "1.2.3",
+          "yanked": false,
+          "cksum": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        },
+        {
+          "name": "foo",
+          "vers": "1.3.0",
+          "yanked": false,
+          "cksum": "60303ae22b998861bce3b28f33eec1be758a213c86c93c076dbe9f558c11c752"
+        }
+      ]"#,
+    )
+    .expect("crate version is correctly parsed");
+
+    assert!(check_checksum(
+        "foo",
+        &versions,
+        "=1.2.3",
+        "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+    )
+    .is_ok());
+    // `--vers 1.2.3` writes `1.2.3`, which is checked against that version, not `1.3.0`.
+    assert!(check_checksum(
+        "foo",
+        &versions,
+        "1.2.3",
+        "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+    )
+    .is_ok());
+    assert!(check_checksum(
+        "foo",
+        &versions,
+        "1.2",
+        "60303AE22B998861BCE3B28F33EEC1BE758A213C86C93C076DBE9F558C11C752"
+    )
+    .is_ok());
+    match check_checksum(
+        "foo",
+        &versions,
+        "=1.2.3",
+        "60303ae22b998861bce3b28f33eec1be758a213c86c93c076dbe9f558c11c752",
+    )
+    .unwrap_err()
+    .kind()
+    {
+        ErrorKind::ChecksumMismatch(name, version, _, actual) => {
+            assert_eq!(name, "foo");
+            assert_eq!(version, "1.2.3");
+            assert_eq!(
+                actual,
+                "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+            );
+        }
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+// Checks whether a version object is a stable release
+fn version_is_stable(version: &CrateVersion) -> bool {
+    !version.version.is_prerelease()
+}
+
+/// Whether `version` is stable or a pre-release whose first identifier starts with `channel`
+fn is_on_prerelease_channel(version: &semver::Version, channel: &str) -> bool {
+    version
+        .pre
+        .first()
+        .is_none_or(|identifier| identifier.to_string().starts_with(channel))
+}
+
+/// Parse a Rust version like `1.56` or `1.56.1`, as used for `rust-version`.
+pub fn parse_rust_version(rust_version: &str) -> Result<semver::Version> {
+    let components = rust_version.trim().split('.').count();
+    let padded = match components {
+        2 => format!("{}.0", rust_version.trim()),
+        _ => rust_version.trim().to_owned(),
+    };
+    match semver::Version::parse(&padded) {
+        Ok(ref version) if components <= 3 && !version.is_prerelease() => Ok(version.clone()),
+        _ => Err(ErrorKind::InvalidRustVersion(rust_version.into()).into()),
+    }
+}
+
+/// Whether `version` can be built with the Rust version `rust_version`.
+fn version_supports_rust(version: &CrateVersion, rust_version: Option<&semver::Version>) -> bool {
+    match (rust_version, &version.rust_version) {
+        (Some(rust_version), Some(required)) => parse_rust_version(required)
+            .map(|required| required <= *rust_version)
+            .unwrap_or(true),
+        _ => true,
+    }
+}
+
+/// Read latest version from Versions structure
+fn read_latest_version(
+    versions: &[CrateVersion],
+    flag_allow_prerelease: bool,
+    rust_version: Option<&semver::Version>,
+) -> Result<Dependency> {
+    let candidates = versions
+        .iter()
+        .filter(|&v| flag_allow_prerelease || version_is_stable(v))
+        .filter(|&v| !v.yanked);
+    let latest = candidates
+        .clone()
+        .filter(|&v| version_supports_rust(v, rust_version))
+        .max_by_key(|&v| v.version.clone())
+        .ok_or(ErrorKind::NoVersionsAvailable)?;
+
+    // Let the user know what they are missing out on by staying on an older Rust version.
+    if let (Some(rust_version), Some(newest)) =
+        (rust_version, candidates.max_by_key(|&v| v.version.clone()))
+    {
+        if newest.version > latest.version {
+            warn(format!(
+                "Skipped `{}` {}, which requires Rust {}, using {} for Rust {}",
+                newest.name,
+                newest.version,
+                newest.rust_version.as_deref().unwrap_or_default(),
+                latest.version,
+                rust_version
+            ))?;
+        }
+    }
+
+    let name = &latest.name;
+    let version = latest.version.to_string();
+    Ok(Dependency::new(name).set_version(&version))
+}
+
+/// Whether the git index of `registry` was last fetched longer than `max_age` ago
+///
+/// This looks at the modification time of the `FETCH_HEAD` written by `git fetch` and of the
+/// `.last-updated` marker cargo touches, so an index cargo itself refreshed recently is fresh
+/// too. An index that was never fetched is always stale. Sparse indices are never stale as a
+/// whole, their entries are checked one by one when they are queried, and neither are local
+/// indices and sources, which are read in place.
+pub fn index_is_stale(registry: &Url, max_age: Duration) -> Result<bool> {
+    if is_sparse(registry) || is_in_place(registry) {
+        return Ok(false);
+    }
+
+    let registry_path = registry_path_from_url(registry)?;
+    Ok(["FETCH_HEAD", ".last-updated"]
+        .iter()
+        .all(|marker| is_stale(&registry_path.join(marker), max_age)))
+}
+
+/// Whether the file at `path` was modified longer than `max_age` ago, or does not exist.
+fn is_stale(path: &Path, max_age: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_none_or(|age| age >= max_age)
+}
+
+/// update registry index for given project
+///
+/// The fetch is aborted if no data is received for longer than `timeout`.
+///
+/// Sparse registries are queried on demand and local (`file://`) ones are read in place, so there
+/// is nothing to update for them. Git sources are fetched at the branch they are read at.
+pub fn update_registry_index(registry: &Url, timeout: Duration) -> Result<()> {
+    update_index(registry, timeout, false)
+}
+
+/// Update the registry index like `update_registry_index`, without printing anything
+pub fn update_registry_index_quietly(registry: &Url, timeout: Duration) -> Result<()> {
+    update_index(registry, timeout, true)
+}
+
+fn update_index(registry: &Url, timeout: Duration, quiet: bool) -> Result<()> {
+    if is_sparse(registry) || is_in_place(registry) {
+        return Ok(());
+    }
+
+    let registry_path = registry_path_from_url(registry)?;
+
+    let colorchoice = if atty::is(atty::Stream::Stdout) {
+        ColorChoice::Auto
+    } else {
+        ColorChoice::Never
+    };
+    let mut output = StandardStream::stdout(colorchoice);
+
+    if !registry_path.as_path().exists() {
+        if !quiet {
+            output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+            write!(output, "{:>12}", "Initializing")?;
+            output.reset()?;
+            writeln!(output, " '{}' index", registry)?;
+        }
+
+        let mut opts = git2::RepositoryInitOptions::new();
+        opts.bare(true);
+        git2::Repository::init_opts(&registry_path, &opts)?;
+        if !is_git_source(registry) {
+            return Ok(());
+        }
+    }
+
+    let repo = git2::Repository::open(&registry_path)?;
+    if !quiet {
+        output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+        write!(output, "{:>12}", "Updating")?;
+        output.reset()?;
+        writeln!(output, " '{}' index", registry)?;
+    }
+
+    if is_git_source(registry) {
+        let refspec = match git_source_reference(registry) {
+            Some(GitReference::Branch(branch)) => {
+                format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch)
+            }
+            _ => "+HEAD:refs/remotes/origin/HEAD".to_owned(),
+        };
+        fetch_with_cli(
+            &repo,
+            source_location(registry).as_str(),
+            &refspec,
+            "registry index",
+            timeout,
+        )?;
+        return Ok(());
+    }
+
+    let refspec = "refs/heads/master:refs/remotes/origin/master";
+    fetch_with_cli(&repo, registry.as_str(), refspec, "registry index", timeout)?;
+
+    Ok(())
+}
+
+// https://github.com/rust-lang/cargo/blob/57986eac7157261c33f0123bade7ccd20f15200f/src/cargo/sources/git/utils.rs#L758
+/// Fetch `refspec` from `url` into `repo`, naming what is fetched `what` in errors.
+pub(crate) fn fetch_with_cli(
+    repo: &git2::Repository,
+    url: &str,
+    refspec: &str,
+    what: &str,
+    timeout: Duration,
+) -> Result<()> {
+    let cmd = subprocess::Exec::shell("git")
+        .args(&git_http_options(&ambient_http_config()?))
+        .arg("fetch")
+        .arg("--tags") // fetch all tags
+        .arg("--force") // handle force pushes
         .arg("--update-head-ok") // see discussion in rust-lang/cargo#2078
         .arg(url)
         .arg(refspec)
@@ -149,129 +2075,1601 @@ fn fetch_with_cli(repo: &git2::Repository, url: &str, refspec: &str) -> Result<(
         .env_remove("GIT_INDEX_FILE")
         .env_remove("GIT_OBJECT_DIRECTORY")
         .env_remove("GIT_ALTERNATE_OBJECT_DIRECTORIES")
+        // git has no notion of a total timeout, so abort once the transfer stalls instead.
+        .env("GIT_HTTP_LOW_SPEED_LIMIT", "1")
+        .env(
+            "GIT_HTTP_LOW_SPEED_TIME",
+            timeout.as_secs().max(1).to_string(),
+        )
         .cwd(repo.path());
 
-    let _ = cmd.capture().map_err(|e| match e {
-        subprocess::PopenError::IoError(io) => ErrorKind::Io(io),
-        subprocess::PopenError::LogicError(_) | subprocess::PopenError::Utf8Error(_) => {
-            unreachable!("expected only io error")
+    let output = cmd.capture().map_err(|e| match e {
+        subprocess::PopenError::IoError(io) => ErrorKind::Io(io),
+        subprocess::PopenError::LogicError(_) | subprocess::PopenError::Utf8Error(_) => {
+            unreachable!("expected only io error")
+        }
+    })?;
+    if !output.exit_status.success() && output.stderr_str().contains("too slow") {
+        return Err(ErrorKind::FetchTimeout(what.into(), url.into()).into());
+    }
+    Ok(())
+}
+
+#[test]
+fn get_latest_stable_version_from_json() {
+    let versions: Vec<CrateVersion> = serde_json::from_str(
+        r#"[
+        {
+          "name": "foo",
+          "vers": "0.6.0-alpha",
+          "yanked": false
+        },
+        {
+          "name": "foo",
+          "vers": "0.5.0",
+          "yanked": false
+        }
+      ]"#,
+    )
+    .expect("crate version is correctly parsed");
+
+    assert_eq!(
+        read_latest_version(&versions, false, None)
+            .unwrap()
+            .version()
+            .unwrap(),
+        "0.5.0"
+    );
+}
+
+#[test]
+fn get_latest_unstable_or_stable_version_from_json() {
+    let versions: Vec<CrateVersion> = serde_json::from_str(
+        r#"[
+        {
+          "name": "foo",
+          "vers": "0.6.0-alpha",
+          "yanked": false
+        },
+        {
+          "name": "foo",
+          "vers": "0.5.0",
+          "yanked": false
+        }
+      ]"#,
+    )
+    .expect("crate version is correctly parsed");
+
+    assert_eq!(
+        read_latest_version(&versions, true, None)
+            .unwrap()
+            .version()
+            .unwrap(),
+        "0.6.0-alpha"
+    );
+}
+
+#[test]
+fn get_latest_version_from_json_test() {
+    let versions: Vec<CrateVersion> = serde_json::from_str(
+        r#"[
+        {
+          "name": "treexml",
+          "vers": "0.3.1",
+          "yanked": true
+        },
+        {
+          "name": "treexml",
+          "vers": "0.3.0",
+          "yanked": false
+        }
+      ]"#,
+    )
+    .expect("crate version is correctly parsed");
+
+    assert_eq!(
+        read_latest_version(&versions, false, None)
+            .unwrap()
+            .version()
+            .unwrap(),
+        "0.3.0"
+    );
+}
+
+#[test]
+fn get_latest_version_for_rust_version_from_json() {
+    let versions: Vec<CrateVersion> = serde_json::from_str(
+        r#"[
+        {
+          "name": "foo",
+          "vers": "0.4.0",
+          "yanked": false
+        },
+        {
+          "name": "foo",
+          "vers": "0.5.0",
+          "yanked": false,
+          "rust_version": "1.56"
+        },
+        {
+          "name": "foo",
+          "vers": "0.6.0",
+          "yanked": false,
+          "rust_version": "1.70.0"
+        }
+      ]"#,
+    )
+    .expect("crate version is correctly parsed");
+
+    let rust_version = parse_rust_version("1.60").unwrap();
+    assert_eq!(
+        read_latest_version(&versions, false, Some(&rust_version))
+            .unwrap()
+            .version()
+            .unwrap(),
+        "0.5.0"
+    );
+    // Without a Rust version, e.g. with `--ignore-rust-version`, the newest version is selected.
+    assert_eq!(
+        read_latest_version(&versions, false, None)
+            .unwrap()
+            .version()
+            .unwrap(),
+        "0.6.0"
+    );
+}
+
+#[test]
+fn get_latest_dependency_from_mock_source() {
+    struct MockSource;
+
+    impl VersionSource for MockSource {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            assert_eq!(crate_name, "foo");
+            let mut new = CrateVersion::new("foo", semver::Version::parse("0.3.0").unwrap());
+            new.rust_version = Some("1.70".to_owned());
+            Ok(vec![
+                CrateVersion::new("foo", semver::Version::parse("0.2.0").unwrap()),
+                new,
+            ])
+        }
+    }
+
+    let latest = get_latest_dependency_from_source("foo", false, None, &MockSource).unwrap();
+    assert_eq!(latest.version(), Some("0.3.0"));
+    let compatible =
+        get_latest_dependency_from_source("foo", false, Some("1.60"), &MockSource).unwrap();
+    assert_eq!(compatible.version(), Some("0.2.0"));
+}
+
+#[test]
+fn compatible_requirements_keep_the_major_version() {
+    assert_eq!(compatible_requirement("^1.2"), "^1.2");
+    assert_eq!(compatible_requirement("~1.2.3"), "^1.2.3");
+    assert_eq!(compatible_requirement(">= 1.2, < 1.5"), "^1.2");
+    assert_eq!(compatible_requirement("0.3.*"), "^0.3");
+    assert_eq!(compatible_requirement("*"), "*");
+}
+
+#[test]
+fn verify_requested_versions_exist() {
+    struct MockSource;
+
+    impl VersionSource for MockSource {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            let mut yanked =
+                CrateVersion::new(crate_name, semver::Version::parse("1.1.0").unwrap());
+            yanked.yanked = true;
+            Ok(vec![
+                CrateVersion::new(crate_name, semver::Version::parse("1.0.150").unwrap()),
+                yanked,
+            ])
+        }
+    }
+
+    assert!(verify_version_exists("serde", "1.0.150", &MockSource).is_ok());
+    assert!(verify_version_exists("serde", "^1", &MockSource).is_ok());
+    assert!(verify_version_exists("serde", "=1.1.0", &MockSource).is_err());
+    assert!(verify_version_exists("serde", "2", &MockSource).is_err());
+}
+
+#[test]
+fn get_latest_dependency_from_first_source_with_crate() {
+    struct MockSource(&'static [&'static str]);
+
+    impl VersionSource for MockSource {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            if !self.0.contains(&crate_name) {
+                return Err(ErrorKind::NoCrate(crate_name.to_owned()).into());
+            }
+            Ok(vec![CrateVersion::new(
+                crate_name,
+                semver::Version::parse("1.0.0").unwrap(),
+            )])
+        }
+    }
+
+    let internal = MockSource(&["internal-only"]);
+    let crates_io = MockSource(&["internal-only", "public"]);
+    let sources: [&dyn VersionSource; 2] = [&internal, &crates_io];
+
+    let (dep, index) =
+        get_latest_dependency_from_sources("internal-only", false, None, &sources).unwrap();
+    assert_eq!((dep.name.as_str(), index), ("internal-only", 0));
+    let (dep, index) = get_latest_dependency_from_sources("public", false, None, &sources).unwrap();
+    assert_eq!((dep.version(), index), (Some("1.0.0"), 1));
+    match get_latest_dependency_from_sources("missing", false, None, &sources)
+        .unwrap_err()
+        .kind()
+    {
+        ErrorKind::NoCrate(name) => assert_eq!(name, "missing"),
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[test]
+fn get_minimal_dependency_from_fixture_index() {
+    struct FixtureIndex;
+
+    impl VersionSource for FixtureIndex {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            parse_summary(&fs::read_to_string(format!(
+                "tests/fixtures/sparse-index/mi/ni/{}",
+                crate_name
+            ))?)
+        }
+    }
+
+    // 1.2.0 is yanked.
+    let lowest = get_minimal_dependency_from_source("minimal", ">=1.2, <2", &FixtureIndex).unwrap();
+    assert_eq!(lowest.version(), Some("1.2.1"));
+    assert!(get_minimal_dependency_from_source("minimal", ">=3", &FixtureIndex).is_err());
+}
+
+#[test]
+fn find_yanked_dependencies_from_fixture_index() {
+    struct FixtureIndex;
+
+    impl VersionSource for FixtureIndex {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            parse_summary(&fs::read_to_string(format!(
+                "tests/fixtures/sparse-index/{}",
+                summary_raw_path(crate_name)
+            ))?)
+        }
+    }
+
+    // 0.2.0 requires `all-yanked = "^1"`, of which every version is yanked.
+    let yanked = get_yanked_dependencies(
+        &Dependency::new("uses-yanked").set_version("0.2"),
+        &FixtureIndex,
+    )
+    .unwrap();
+    assert_eq!(yanked, vec![("all-yanked".to_owned(), "^1".to_owned())]);
+    // 0.1.0 only has it as an optional or dev-dependency.
+    assert!(get_yanked_dependencies(
+        &Dependency::new("uses-yanked").set_version("=0.1.0"),
+        &FixtureIndex
+    )
+    .unwrap()
+    .is_empty());
+}
+
+#[test]
+fn find_links_collisions_from_mock_source() {
+    struct MockSource;
+
+    impl VersionSource for MockSource {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            let mut version =
+                CrateVersion::new(crate_name, semver::Version::parse("1.0.0").unwrap());
+            version.links = match crate_name {
+                "openssl-sys" | "boring-sys" => Some("openssl".to_owned()),
+                _ => None,
+            };
+            Ok(vec![version])
+        }
+    }
+
+    let existing = vec![
+        Dependency::new("openssl-sys").set_version("1"),
+        Dependency::new("libc").set_version("1"),
+    ];
+    let collisions = get_links_collisions(
+        &Dependency::new("boring-sys").set_version("1.0.0"),
+        &existing,
+        &MockSource,
+    )
+    .unwrap();
+    assert_eq!(
+        collisions,
+        vec![("openssl-sys".to_owned(), "openssl".to_owned())]
+    );
+    assert!(get_links_collisions(
+        &Dependency::new("zlib-sys").set_version("1.0.0"),
+        &existing,
+        &MockSource
+    )
+    .unwrap()
+    .is_empty());
+}
+
+#[test]
+fn find_unknown_features_from_mock_source() {
+    struct MockSource;
+
+    impl VersionSource for MockSource {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            let mut version =
+                CrateVersion::new(crate_name, semver::Version::parse("1.0.0").unwrap());
+            version.features.insert("std".to_owned(), vec![]);
+            version
+                .features
+                .insert("default".to_owned(), vec!["std".to_owned()]);
+            version
+                .features2
+                .insert("tls".to_owned(), vec!["dep:rustls".to_owned()]);
+            version.deps = vec![
+                IndexDependency {
+                    name: "serde".to_owned(),
+                    optional: true,
+                    req: "1".to_owned(),
+                    kind: None,
+                    package: None,
+                    registry: None,
+                },
+                IndexDependency {
+                    name: "rustls".to_owned(),
+                    optional: true,
+                    req: "0.20".to_owned(),
+                    kind: None,
+                    package: None,
+                    registry: None,
+                },
+            ];
+            Ok(vec![version])
+        }
+    }
+
+    let dep = Dependency::new("foo")
+        .set_version("1")
+        .set_features(Some(vec![
+            "std".to_owned(),
+            "tls".to_owned(),
+            "serde".to_owned(),
+            "serde/derive".to_owned(),
+            "rustls".to_owned(),
+            "nonexistent".to_owned(),
+        ]));
+    assert_eq!(
+        get_unknown_features(&dep, &MockSource).unwrap(),
+        vec!["rustls".to_owned(), "nonexistent".to_owned()]
+    );
+    let dep = dep.set_features(Some(vec!["std".to_owned()]));
+    assert!(get_unknown_features(&dep, &MockSource).unwrap().is_empty());
+    assert_eq!(
+        get_available_features(&dep, &MockSource).unwrap(),
+        Some((
+            vec!["serde".to_owned(), "std".to_owned(), "tls".to_owned()],
+            vec!["std".to_owned()]
+        ))
+    );
+}
+
+#[test]
+fn resolve_versions_from_mock_source() {
+    use std::cell::Cell;
+
+    struct MockSource {
+        queries: Cell<usize>,
+    }
+
+    impl VersionSource for MockSource {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            self.queries.set(self.queries.get() + 1);
+            if crate_name != "foo" {
+                return Err(ErrorKind::NoCrate(crate_name.into()).into());
+            }
+            let mut yanked = CrateVersion::new("foo", semver::Version::parse("1.3.0").unwrap());
+            yanked.yanked = true;
+            Ok(vec![
+                CrateVersion::new("foo", semver::Version::parse("1.1.0").unwrap()),
+                CrateVersion::new("foo", semver::Version::parse("1.2.0").unwrap()),
+                yanked,
+                CrateVersion::new("foo", semver::Version::parse("2.0.0").unwrap()),
+            ])
+        }
+    }
+
+    let source = MockSource {
+        queries: Cell::new(0),
+    };
+    let specs = vec![
+        CrateSpec::new("foo", Some("1")),
+        CrateSpec::new("foo", None),
+        CrateSpec::new("foo", Some("=1.1.0")),
+        CrateSpec::new("bar", None),
+        CrateSpec::new("foo", Some("3")),
+    ];
+    let resolved = resolve_versions(&specs, &source);
+
+    let versions = resolved
+        .iter()
+        .map(|r| r.as_ref().ok().map(|dep| dep.version.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        versions,
+        vec![
+            Some("1.2.0".to_owned()),
+            Some("2.0.0".to_owned()),
+            Some("1.1.0".to_owned()),
+            None,
+            None,
+        ]
+    );
+    // `foo` is only looked up once.
+    assert_eq!(source.queries.get(), 2);
+}
+
+#[test]
+fn parse_rust_versions() {
+    assert_eq!(
+        parse_rust_version("1.56").unwrap(),
+        semver::Version::parse("1.56.0").unwrap()
+    );
+    assert_eq!(
+        parse_rust_version("1.56.1").unwrap(),
+        semver::Version::parse("1.56.1").unwrap()
+    );
+    assert!(parse_rust_version("1").is_err());
+    assert!(parse_rust_version("^1.56").is_err());
+    assert!(parse_rust_version("1.56.0-nightly").is_err());
+}
+
+#[test]
+fn get_no_latest_version_from_json_when_all_are_yanked() {
+    let versions: Vec<CrateVersion> = serde_json::from_str(
+        r#"[
+        {
+          "name": "treexml",
+          "vers": "0.3.1",
+          "yanked": true
+        },
+        {
+          "name": "treexml",
+          "vers": "0.3.0",
+          "yanked": true
+        }
+      ]"#,
+    )
+    .expect("crate version is correctly parsed");
+
+    assert!(read_latest_version(&versions, false, None).is_err());
+}
+
+/// Query the versions of a crate from the registry the manifest uses, or from `registry`.
+///
+/// Cached entries of a sparse index that are younger than `max_age` are used as they are.
+fn query_registry_index(
+    crate_name: &str,
+    manifest_path: &Path,
+    registry: &Option<Url>,
+    max_age: Option<Duration>,
+    rev: Option<&str>,
+) -> Result<Vec<CrateVersion>> {
+    let registry = match registry {
+        Some(url) => url.clone(),
+        None => registry_url(manifest_path, None)?,
+    };
+    let registry_path = registry_path_from_url(&registry)?;
+
+    if is_sparse(&registry) {
+        if rev.is_some() {
+            return Err(ErrorKind::IndexRevOnSparseRegistry(registry.to_string()).into());
+        }
+        let index_url = &registry.as_str()[SPARSE_PREFIX.len()..];
+        fuzzy_query_sparse_index(
+            crate_name,
+            index_url,
+            &registry_path.join(".cache"),
+            &http_config(manifest_path)?,
+            registry_token(manifest_path, &registry)?.as_ref(),
+            max_age,
+        )
+    } else if is_local(&registry) && rev.is_none() {
+        fuzzy_query_local_index(crate_name, &registry_path)
+    } else if is_directory(&registry) {
+        fuzzy_query_packages(crate_name, directory_manifests(&registry_path)?)
+    } else if is_git_source(&registry) {
+        fuzzy_query_packages(crate_name, git_source_manifests(&registry, &registry_path)?)
+    } else {
+        if is_offline() && !registry_path.exists() {
+            return Err(ErrorKind::OfflineIndexMissing(registry.to_string()).into());
+        }
+        fuzzy_query_registry_index(crate_name, &registry_path, rev)
+    }
+}
+
+/// Get the names of all crates in `registry`, a registry that isn't sparse, as of the commit
+/// `rev` of a git index if it is given.
+fn registry_crate_names(registry: &Url, rev: Option<&str>) -> Result<Vec<String>> {
+    let registry_path = registry_path_from_url(registry)?;
+    let package_names = |manifests: Vec<String>| {
+        manifests
+            .iter()
+            .filter_map(|content| package_version(content))
+            .map(|version| version.name)
+            .collect()
+    };
+
+    if is_local(registry) && rev.is_none() {
+        let mut names = Vec::new();
+        collect_index_file_names(&registry_path, &mut names)?;
+        Ok(names)
+    } else if is_directory(registry) {
+        Ok(package_names(directory_manifests(&registry_path)?))
+    } else if is_git_source(registry) {
+        Ok(package_names(git_source_manifests(
+            registry,
+            &registry_path,
+        )?))
+    } else {
+        if is_offline() && !registry_path.exists() {
+            return Err(ErrorKind::OfflineIndexMissing(registry.to_string()).into());
+        }
+        let repo = git2::Repository::open(&registry_path)?;
+        let tree = match rev {
+            Some(rev) => repo
+                .revparse_single(rev)
+                .chain_err(|| ErrorKind::NoSuchIndexRev(rev.to_owned()))?
+                .peel_to_tree()?,
+            None => repo
+                .find_reference("refs/remotes/origin/master")?
+                .peel_to_tree()?,
+        };
+        let mut names = Vec::new();
+        tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(git2::ObjectType::Blob) && !dir.is_empty() {
+                if let Some(name) = entry.name() {
+                    names.push(name.to_owned());
+                }
+            }
+            git2::TreeWalkResult::Ok
+        })?;
+        Ok(names)
+    }
+}
+
+/// Add the names of the index files below `dir`, a directory of a local index, to `names`.
+/// `config.json` and hidden files, e.g. of git, are left out.
+fn collect_index_file_names(dir: &Path, names: &mut Vec<String>) -> Result<()> {
+    let entries = fs::read_dir(dir).chain_err(|| format!("Failed to read `{}`", dir.display()))?;
+    for entry in entries {
+        let entry = entry.chain_err(|| format!("Failed to read `{}`", dir.display()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || name == "config.json" {
+            continue;
+        }
+        if entry.path().is_dir() {
+            collect_index_file_names(&entry.path(), names)?;
+        } else {
+            names.push(name);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCrate {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiSearch {
+    crates: Vec<ApiCrate>,
+}
+
+/// Search the web API at `api` for crates called like `crate_name`, with
+/// `/api/v1/crates?q={crate}`.
+fn search_registry_api(
+    crate_name: &str,
+    api: &str,
+    http: &HttpConfig,
+    token: Option<&RegistryToken>,
+) -> Result<Vec<String>> {
+    let url = format!("{}/api/v1/crates", api.trim_end_matches('/'));
+    let client = http_client(get_default_timeout(), http)?;
+    let mut request = client
+        .get(&url)
+        .query(&[("q", crate_name), ("per_page", "20")]);
+    if let Some(token) = token {
+        request = request.header(reqwest::header::AUTHORIZATION, token.expose());
+    }
+    let mut res = request
+        .send()
+        .and_then(reqwest::blocking::Response::error_for_status)
+        .chain_err(|| format!("Failed to search `{}` for `{}`", url, crate_name))?;
+
+    let mut content = String::new();
+    res.read_to_string(&mut content)
+        .chain_err(|| format!("Failed to read the results of searching `{}`", url))?;
+    let search: ApiSearch = serde_json::from_str(&content)
+        .chain_err(|| format!("Invalid response from the registry API for `{}`", url))?;
+    Ok(search.crates.into_iter().map(|krate| krate.name).collect())
+}
+
+/// How many similarly called crates are suggested at most
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Pick the names among `names` that are close to `crate_name`: those a few edits away from it,
+/// and those starting with it. `-` and `_` are treated alike, as are upper and lower case.
+fn closest_names(crate_name: &str, names: Vec<String>) -> Vec<String> {
+    let normalize = |name: &str| name.to_lowercase().replace('-', "_");
+    let wanted = normalize(crate_name);
+    let max_distance = (wanted.chars().count() / 3).max(1);
+
+    let mut candidates = names
+        .into_iter()
+        .filter_map(|name| {
+            let normalized = normalize(&name);
+            if normalized == wanted {
+                return None;
+            }
+            let distance = edit_distance(&wanted, &normalized);
+            if distance <= max_distance || normalized.starts_with(&wanted) {
+                Some((distance, name))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates.dedup_by(|(_, a), (_, b)| a == b);
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, name)| name)
+        .collect()
+}
+
+/// The Levenshtein distance between `a` and `b`, i.e. how many characters have to be inserted,
+/// removed or replaced to turn one into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let replaced = previous[j] + if a == *b { 0 } else { 1 };
+            current.push(replaced.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[test]
+fn suggest_similar_crate_names() {
+    let names = ["serde", "serde_json", "serde-derive", "sered", "toml"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        closest_names("serd", names.clone()),
+        vec!["serde", "sered", "serde_json"]
+    );
+    assert_eq!(closest_names("tmol", names.clone()), Vec::<String>::new());
+    assert_eq!(closest_names("tom", names), vec!["toml"]);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+}
+
+#[test]
+fn search_local_index_for_similar_names() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/file-index");
+    let registry = Url::from_directory_path(&fixture).unwrap();
+    let source = RegistryIndex::new(&fixture.join("Cargo.toml"), &Some(registry));
+
+    assert_eq!(source.similar_names("yankd").unwrap(), vec!["yanked"]);
+    assert_eq!(source.similar_names("local").unwrap(), vec!["local-only"]);
+    assert!(source.similar_names("unrelated").unwrap().is_empty());
+}
+
+/// Read the `config.json` of the index of `registry`, or of the registry configured for
+/// `manifest_path`.
+fn query_index_config(manifest_path: &Path, registry: &Option<Url>) -> Result<IndexConfig> {
+    let registry = match registry {
+        Some(url) => url.clone(),
+        None => registry_url(manifest_path, None)?,
+    };
+    if is_directory(&registry) || is_git_source(&registry) {
+        return Err(ErrorKind::InvalidIndexConfig.into());
+    }
+    let registry_path = registry_path_from_url(&registry)?;
+
+    let content = if is_sparse(&registry) {
+        let index_url = &registry.as_str()[SPARSE_PREFIX.len()..];
+        fetch_sparse_file(
+            "config.json",
+            index_url,
+            &registry_path.join(".cache"),
+            &http_config(manifest_path)?,
+            registry_token(manifest_path, &registry)?.as_ref(),
+            None,
+        )?
+        .chain_err(|| ErrorKind::InvalidIndexConfig)?
+    } else if is_local(&registry) {
+        fs::read_to_string(registry_path.join("config.json"))
+            .chain_err(|| ErrorKind::InvalidIndexConfig)?
+    } else {
+        let repo = git2::Repository::open(&registry_path)?;
+        let tree = repo
+            .find_reference("refs/remotes/origin/master")?
+            .peel_to_tree()?;
+        let file = tree
+            .get_path(Path::new("config.json"))
+            .chain_err(|| ErrorKind::InvalidIndexConfig)?
+            .to_object(&repo)?
+            .peel_to_blob()?;
+        String::from_utf8(file.content().to_vec()).map_err(|_| ErrorKind::InvalidIndexConfig)?
+    };
+    parse_index_config(&content)
+}
+
+fn parse_index_config(content: &str) -> Result<IndexConfig> {
+    serde_json::from_str(content).map_err(|_| ErrorKind::InvalidIndexConfig.into())
+}
+
+const SPARSE_PREFIX: &str = "sparse+";
+
+/// Whether the index at `registry` is served with the sparse HTTP protocol.
+fn is_sparse(registry: &Url) -> bool {
+    registry.as_str().starts_with(SPARSE_PREFIX)
+}
+
+/// Whether the index at `registry` is a local directory, given as a `file://` URL.
+fn is_local(registry: &Url) -> bool {
+    registry.scheme() == "file"
+}
+
+/// Whether `registry` is a directory of unpacked crates rather than an index.
+fn is_directory(registry: &Url) -> bool {
+    registry.as_str().starts_with(DIRECTORY_PREFIX)
+}
+
+/// Whether `registry` is a git repository containing the crates rather than an index.
+fn is_git_source(registry: &Url) -> bool {
+    registry.as_str().starts_with(GIT_PREFIX)
+}
+
+/// Whether the index or crates of `registry` are read from the local file system as they are,
+/// without fetching them first.
+fn is_in_place(registry: &Url) -> bool {
+    source_location(registry).scheme() == "file"
+}
+
+/// Fuzzy query crate from a sparse registry index
+///
+/// Every response is written to `cache_dir`, which is used instead when the index cannot be
+/// reached, or when it is younger than `max_age`. `token` is sent along for registries that
+/// require authentication.
+fn fuzzy_query_sparse_index(
+    crate_name: &str,
+    index_url: &str,
+    cache_dir: &Path,
+    http: &HttpConfig,
+    token: Option<&RegistryToken>,
+    max_age: Option<Duration>,
+) -> Result<Vec<CrateVersion>> {
+    let mut names = gen_fuzzy_crate_names(crate_name.to_owned())?;
+    if let Some(index) = names.iter().position(|x| *x == crate_name) {
+        names.swap(index, 0);
+    }
+
+    for the_name in names {
+        if let Some(content) =
+            fetch_sparse_summary(&the_name, index_url, cache_dir, http, token, max_age)?
+        {
+            return parse_summary(&content);
+        }
+    }
+    if is_offline() {
+        return Err(ErrorKind::OfflineIndexEntryMissing(crate_name.to_owned()).into());
+    }
+    Err(ErrorKind::NoCrate(crate_name.to_owned()).into())
+}
+
+/// Fetch the index file of a single crate, returning `None` if the crate does not exist.
+fn fetch_sparse_summary(
+    crate_name: &str,
+    index_url: &str,
+    cache_dir: &Path,
+    http: &HttpConfig,
+    token: Option<&RegistryToken>,
+    max_age: Option<Duration>,
+) -> Result<Option<String>> {
+    fetch_sparse_file(
+        &summary_raw_path(&crate_name.to_lowercase()),
+        index_url,
+        cache_dir,
+        http,
+        token,
+        max_age,
+    )
+}
+
+/// Fetch the file at `raw_path` in a sparse index, returning `None` if it does not exist.
+///
+/// A cached copy that is younger than `max_age` is returned without asking the index.
+fn fetch_sparse_file(
+    raw_path: &str,
+    index_url: &str,
+    cache_dir: &Path,
+    http: &HttpConfig,
+    token: Option<&RegistryToken>,
+    max_age: Option<Duration>,
+) -> Result<Option<String>> {
+    let url = format!("{}/{}", index_url.trim_end_matches('/'), raw_path);
+    let cache_path = cache_dir.join(raw_path);
+
+    if is_offline() {
+        return Ok(fs::read_to_string(&cache_path).ok());
+    }
+
+    if let Some(max_age) = max_age {
+        if !is_stale(&cache_path, max_age) {
+            if let Ok(content) = fs::read_to_string(&cache_path) {
+                return Ok(Some(content));
+            }
+        }
+    }
+
+    let client = http_client(get_default_timeout(), http)?;
+    let mut request = client.get(&url);
+    if let Some(token) = token {
+        request = request.header(reqwest::header::AUTHORIZATION, token.expose());
+    }
+    let response = request
+        .send()
+        .and_then(reqwest::blocking::Response::error_for_status);
+    let mut res = match response {
+        Ok(res) => res,
+        Err(ref e) if e.status().is_some_and(is_missing_crate_status) => return Ok(None),
+        Err(e) => {
+            return match fs::read_to_string(&cache_path) {
+                Ok(content) => {
+                    warn(format!(
+                        "Failed to fetch `{}`, using the cached index entry instead",
+                        url
+                    ))?;
+                    Ok(Some(content))
+                }
+                Err(_) if e.status().is_some_and(is_unauthorized_status) => {
+                    Err(ErrorKind::RegistryUnauthorized(url, token.is_some()).into())
+                }
+                Err(_) => Err(Error::with_chain(
+                    e,
+                    format!("Failed to fetch `{}` from the sparse index", url),
+                )),
+            }
+        }
+    };
+
+    let mut content = String::new();
+    res.read_to_string(&mut content)
+        .chain_err(|| ErrorKind::InvalidSummaryJson)?;
+
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&cache_path, &content)?;
+
+    Ok(Some(content))
+}
+
+/// Sparse registries answer with one of these if a crate does not exist.
+fn is_missing_crate_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::NOT_FOUND || status == reqwest::StatusCode::GONE
+}
+
+/// Registries that require authentication answer with one of these without a valid token.
+fn is_unauthorized_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN
+}
+
+fn parse_summary(content: &str) -> Result<Vec<CrateVersion>> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line: &str| {
+            serde_json::from_str::<CrateVersion>(line)
+                .map_err(|_| ErrorKind::InvalidSummaryJson.into())
+        })
+        .collect::<Result<Vec<CrateVersion>>>()
+}
+
+#[test]
+fn parse_summary_with_unknown_fields() {
+    let versions = parse_summary(
+        r#"{"name":"custom","vers":"0.1.0","cksum":"abc","features":{"std":[]},"deps":[],"yanked":false,"x-internal":{"owner":"team"},"v":2,"features2":{"serde":["dep:serde"]}}
+{"name":"custom","vers":"0.2.0","features":{"std":"all"},"deps":[],"links":{"name":"z"},"rust_version":170}"#,
+    )
+    .unwrap();
+
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0].checksum.as_deref(), Some("abc"));
+    assert!(versions[0].features.contains_key("std"));
+    // Missing or malformed optional fields fall back to their defaults.
+    assert!(!versions[1].yanked);
+    assert!(versions[1].features.is_empty());
+    assert_eq!(versions[1].links, None);
+    assert_eq!(versions[1].rust_version, None);
+}
+
+/// Serve `tests/fixtures/sparse-index` over HTTP, returning the URL of the index.
+#[cfg(test)]
+fn serve_sparse_index_fixture() -> String {
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::thread;
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind mock server");
+    let index_url = format!("http://{}/", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sparse-index");
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request_line = String::new();
+            std::io::BufReader::new(&stream)
+                .read_line(&mut request_line)
+                .unwrap();
+            let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+            let response = match fs::read_to_string(fixtures.join(&path[1..])) {
+                Ok(body) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                ),
+                Err(_) => {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                }
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    index_url
+}
+
+#[test]
+fn http_client_uses_configured_certificates() {
+    let tmpdir = tempdir::TempDir::new("cargo-edit-certs").unwrap();
+    assert!(http_client(get_default_timeout(), &HttpConfig::default()).is_ok());
+
+    let missing = HttpConfig {
+        ssl_cert: Some(tmpdir.path().join("client.p12")),
+        ..HttpConfig::default()
+    };
+    let err = http_client(get_default_timeout(), &missing).unwrap_err();
+    assert!(err.to_string().contains("client.p12"));
+
+    let invalid_path = tmpdir.path().join("ca.pem");
+    fs::write(&invalid_path, "not a certificate").unwrap();
+    let invalid = HttpConfig {
+        cainfo: Some(invalid_path),
+        ..HttpConfig::default()
+    };
+    let err = http_client(get_default_timeout(), &invalid).unwrap_err();
+    assert!(err.to_string().contains("ca.pem"));
+}
+
+#[test]
+fn read_index_config_from_fixture_server() {
+    let index_url = serve_sparse_index_fixture();
+    let cache_dir = tempdir::TempDir::new("sparse-cache").unwrap();
+
+    let content = fetch_sparse_file(
+        "config.json",
+        &index_url,
+        cache_dir.path(),
+        &HttpConfig::default(),
+        None,
+        None,
+    )
+    .unwrap()
+    .unwrap();
+    let config = parse_index_config(&content).unwrap();
+    assert_eq!(config.api.as_deref(), Some("https://api.example.com"));
+
+    let version = CrateVersion::new("My_Crate", semver::Version::parse("0.2.0").unwrap());
+    assert_eq!(
+        config.download_url(&version),
+        "https://dl.example.com/my/_c/My_Crate-0.2.0.crate"
+    );
+    let plain = IndexConfig {
+        dl: "https://dl.example.com/api/v1/crates/".to_owned(),
+        api: None,
+    };
+    assert_eq!(
+        plain.download_url(&version),
+        "https://dl.example.com/api/v1/crates/My_Crate/0.2.0/download"
+    );
+}
+
+#[test]
+fn report_registries_requiring_authentication() {
+    use std::net::TcpListener;
+    use std::thread;
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind mock server");
+    let index_url = format!("http://{}/", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request_line = String::new();
+            std::io::BufRead::read_line(&mut std::io::BufReader::new(&stream), &mut request_line)
+                .unwrap();
+            let response =
+                "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    let cache_dir = tempdir::TempDir::new("sparse-cache").unwrap();
+
+    let err = fetch_sparse_file(
+        "config.json",
+        &index_url,
+        cache_dir.path(),
+        &HttpConfig::default(),
+        None,
+        None,
+    )
+    .unwrap_err();
+    match err.kind() {
+        ErrorKind::RegistryUnauthorized(url, false) => assert!(url.ends_with("/config.json")),
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn query_sparse_index_from_fixture_server() {
+    let index_url = serve_sparse_index_fixture();
+    let cache_dir = tempdir::TempDir::new("sparse-cache").unwrap();
+
+    let versions = fuzzy_query_sparse_index(
+        "my-crate",
+        &index_url,
+        cache_dir.path(),
+        &HttpConfig::default(),
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0].name, "my_crate");
+    assert_eq!(
+        read_latest_version(&versions, false, None)
+            .unwrap()
+            .version(),
+        Some("0.2.0")
+    );
+    assert!(cache_dir.path().join("my/_c/my_crate").exists());
+
+    match fuzzy_query_sparse_index(
+        "missing",
+        &index_url,
+        cache_dir.path(),
+        &HttpConfig::default(),
+        None,
+        None,
+    )
+    .unwrap_err()
+    .kind()
+    {
+        ErrorKind::NoCrate(name) => assert_eq!(name, "missing"),
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[test]
+fn refresh_stale_sparse_cache_entry() {
+    let index_url = serve_sparse_index_fixture();
+    let cache_dir = tempdir::TempDir::new("sparse-cache").unwrap();
+    let cache_path = cache_dir.path().join("mi/ni/minimal");
+    fs::create_dir_all(cache_path.parent().unwrap()).unwrap();
+    fs::write(
+        &cache_path,
+        r#"{"name":"minimal","vers":"1.1.0","deps":[],"features":{},"yanked":false}"#,
+    )
+    .unwrap();
+    let latest = |max_age| {
+        let versions = fuzzy_query_sparse_index(
+            "minimal",
+            &index_url,
+            cache_dir.path(),
+            &HttpConfig::default(),
+            None,
+            Some(max_age),
+        )
+        .unwrap();
+        read_latest_version(&versions, false, None)
+            .unwrap()
+            .version()
+            .map(ToOwned::to_owned)
+    };
+
+    // The cache is fresh enough, so the newer versions on the index go unnoticed.
+    assert_eq!(latest(Duration::from_secs(3600)).as_deref(), Some("1.1.0"));
+    assert!(!is_stale(&cache_path, Duration::from_secs(3600)));
+
+    // Once it is considered stale, the entry is downloaded again.
+    assert!(is_stale(&cache_path, Duration::from_secs(0)));
+    assert_eq!(latest(Duration::from_secs(0)).as_deref(), Some("2.0.0"));
+    assert!(fs::read_to_string(&cache_path).unwrap().contains("2.0.0"));
+    assert!(is_stale(
+        &cache_dir.path().join("missing"),
+        Duration::from_secs(3600)
+    ));
+}
+
+#[test]
+fn complete_index_entries_from_registry_api() {
+    let index_url = serve_sparse_index_fixture();
+    let cache_dir = tempdir::TempDir::new("sparse-cache").unwrap();
+
+    let mut versions = fuzzy_query_sparse_index(
+        "minimal",
+        &index_url,
+        cache_dir.path(),
+        &HttpConfig::default(),
+        None,
+        None,
+    )
+    .unwrap();
+    assert!(versions.iter().all(|v| v.features.is_empty()));
+
+    // The fixture server serves the API below the index.
+    let api_versions =
+        query_registry_api("minimal", &index_url, &HttpConfig::default(), None).unwrap();
+    merge_api_versions(&mut versions, api_versions);
+
+    let version = |vers: &str| {
+        versions
+            .iter()
+            .find(|v| v.version.to_string() == vers)
+            .unwrap()
+    };
+    assert_eq!(version("1.3.0").features["std"], vec!["alloc".to_owned()]);
+    assert!(version("1.3.0").features.contains_key("alloc"));
+    assert!(version("2.0.0").yanked);
+    assert!(!version("1.1.0").yanked);
+    assert!(version("1.1.0").features.is_empty());
+    assert_eq!(
+        read_latest_version(&versions, false, None)
+            .unwrap()
+            .version(),
+        Some("1.3.0")
+    );
+
+    assert!(query_registry_api("missing", &index_url, &HttpConfig::default(), None).is_err());
+}
+
+#[test]
+fn read_versions_from_registry_api_only() {
+    let index_url = serve_sparse_index_fixture();
+
+    let versions = query_registry_api("minimal", &index_url, &HttpConfig::default(), None)
+        .unwrap()
+        .into_iter()
+        .map(|version| version.into_crate_version("minimal"))
+        .collect::<Vec<_>>();
+    assert!(versions
+        .iter()
+        .all(|v| v.name == "minimal" && v.deps.is_empty()));
+    let version = versions
+        .iter()
+        .find(|v| v.version.to_string() == "1.3.0")
+        .unwrap();
+    assert_eq!(version.rust_version.as_deref(), Some("1.60"));
+    assert_eq!(
+        read_latest_version(&versions, false, None)
+            .unwrap()
+            .version(),
+        Some("1.3.0")
+    );
+
+    match query_registry_api("missing", &index_url, &HttpConfig::default(), None) {
+        Err(Error(ErrorKind::NoCrate(name), _)) => assert_eq!(name, "missing"),
+        other => panic!("expected a missing crate, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn latest_version_compatible_with_peer_from_fixture_server() {
+    struct SparseFixture(String, tempdir::TempDir);
+
+    impl VersionSource for SparseFixture {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            fuzzy_query_sparse_index(
+                crate_name,
+                &self.0,
+                self.1.path(),
+                &HttpConfig::default(),
+                None,
+                None,
+            )
+        }
+    }
+
+    let cache_dir = tempdir::TempDir::new("sparse-cache").unwrap();
+    let source = SparseFixture(serve_sparse_index_fixture(), cache_dir);
+    let latest = |peer_req| {
+        get_latest_dependency_compatible_from_source(
+            "uses-minimal",
+            "minimal",
+            peer_req,
+            false,
+            None,
+            &source,
+        )
+        .map(|dep| dep.version().unwrap().to_owned())
+    };
+
+    // `1.2.0` requires `minimal ^2`, and the dev-dependency of `1.1.0` doesn't count.
+    assert_eq!(latest("=1.3.0").unwrap(), "1.1.0");
+    assert_eq!(latest("=1.1.0").unwrap(), "1.0.0");
+    assert_eq!(latest("^2").unwrap(), "1.2.0");
+    match latest("^3").unwrap_err().kind() {
+        ErrorKind::NoCompatibleVersion(name, peer, req) => {
+            assert_eq!(name, "uses-minimal");
+            assert_eq!(peer, "minimal");
+            assert_eq!(req, "^3");
+        }
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[test]
+fn latest_version_below_ceiling_from_fixture_server() {
+    struct SparseFixture(String, tempdir::TempDir);
+
+    impl VersionSource for SparseFixture {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            fuzzy_query_sparse_index(
+                crate_name,
+                &self.0,
+                self.1.path(),
+                &HttpConfig::default(),
+                None,
+                None,
+            )
+        }
+    }
+
+    let cache_dir = tempdir::TempDir::new("sparse-cache").unwrap();
+    let source = SparseFixture(serve_sparse_index_fixture(), cache_dir);
+    let latest = |req| {
+        get_latest_dependency_matching_from_source("minimal", req, false, None, &source)
+            .map(|dep| dep.version().unwrap().to_owned())
+    };
+
+    // The index also has `2.0.0`.
+    assert_eq!(latest("<2").unwrap(), "1.3.0");
+    // `1.2.0` is yanked.
+    assert_eq!(latest("~1.2").unwrap(), "1.2.1");
+    match latest(">=3").unwrap_err().kind() {
+        ErrorKind::NoMatchingVersion(name, req) => {
+            assert_eq!(name, "minimal");
+            assert_eq!(req, ">=3");
+        }
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[test]
+fn crate_exists_in_fixture_server() {
+    struct SparseFixture(String, tempdir::TempDir);
+
+    impl VersionSource for SparseFixture {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            fuzzy_query_sparse_index(
+                crate_name,
+                &self.0,
+                self.1.path(),
+                &HttpConfig::default(),
+                None,
+                None,
+            )
+        }
+    }
+
+    let cache_dir = tempdir::TempDir::new("sparse-cache").unwrap();
+    let source = SparseFixture(serve_sparse_index_fixture(), cache_dir);
+    assert!(crate_exists_in_source("minimal", &source).unwrap());
+    assert!(crate_exists_in_source("my-crate", &source).unwrap());
+    assert!(!crate_exists_in_source("missing", &source).unwrap());
+
+    // Nothing listens on this port anymore, so there is no way to tell.
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let cache_dir = tempdir::TempDir::new("sparse-cache").unwrap();
+    let unreachable = SparseFixture(format!("http://127.0.0.1:{}/", port), cache_dir);
+    assert!(crate_exists_in_source("minimal", &unreachable).is_err());
+}
+
+/// Fuzzy query crate from an index in the local directory `index_path`, whose files are read as
+/// they are instead of from git. This allows offline indices that aren't git repositories.
+fn fuzzy_query_local_index(crate_name: &str, index_path: &Path) -> Result<Vec<CrateVersion>> {
+    let mut names = gen_fuzzy_crate_names(crate_name.to_owned())?;
+    if let Some(index) = names.iter().position(|x| *x == crate_name) {
+        names.swap(index, 0);
+    }
+
+    for the_name in names {
+        let content = match fs::read_to_string(index_path.join(summary_raw_path(&the_name))) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        return parse_summary(&content);
+    }
+    Err(ErrorKind::NoCrate(crate_name.into()).into())
+}
+
+/// Fuzzy query crate from the packages of a directory or git source, given as the contents of
+/// their manifests.
+///
+/// Only the features, `rust-version` and `links` of the versions are known, not their
+/// dependencies, checksums or whether they are yanked.
+fn fuzzy_query_packages(crate_name: &str, manifests: Vec<String>) -> Result<Vec<CrateVersion>> {
+    let mut versions = manifests
+        .iter()
+        .filter_map(|content| package_version(content))
+        .collect::<Vec<_>>();
+    versions.sort_by(|a, b| a.version.cmp(&b.version));
+
+    let mut names = gen_fuzzy_crate_names(crate_name.to_owned())?;
+    if let Some(index) = names.iter().position(|x| *x == crate_name) {
+        names.swap(index, 0);
+    }
+
+    for the_name in names {
+        let matching = versions
+            .iter()
+            .filter(|version| version.name == the_name)
+            .cloned()
+            .collect::<Vec<_>>();
+        if !matching.is_empty() {
+            return Ok(matching);
+        }
+    }
+    Err(ErrorKind::NoCrate(crate_name.into()).into())
+}
+
+/// The version of the package declared in the manifest `content`, if it is a package with a
+/// version
+fn package_version(content: &str) -> Option<CrateVersion> {
+    let manifest = content.parse::<Manifest>().ok()?;
+    let package = &manifest.data["package"];
+    let version = package["version"].as_str()?.parse().ok()?;
+    let mut crate_version = CrateVersion::new(package["name"].as_str()?, version);
+    crate_version.rust_version = package["rust-version"].as_str().map(ToOwned::to_owned);
+    crate_version.links = package["links"].as_str().map(ToOwned::to_owned);
+    if let Some(features) = manifest.data["features"].as_table() {
+        for (feature, values) in features.iter() {
+            let values = values
+                .as_array()
+                .map(|values| {
+                    values
+                        .iter()
+                        .filter_map(|value| value.as_str().map(ToOwned::to_owned))
+                        .collect()
+                })
+                .unwrap_or_default();
+            crate_version.features.insert(feature.to_owned(), values);
+        }
+    }
+    Some(crate_version)
+}
+
+/// The manifests of the crates in the directory source at `dir`, one per subdirectory.
+fn directory_manifests(dir: &Path) -> Result<Vec<String>> {
+    let mut manifests = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        if let Ok(content) = fs::read_to_string(entry?.path().join("Cargo.toml")) {
+            manifests.push(content);
+        }
+    }
+    Ok(manifests)
+}
+
+/// The manifests of the crates anywhere in the git source `registry`, whose repository is at
+/// `repo_path`, at the branch, tag or revision it is read at.
+fn git_source_manifests(registry: &Url, repo_path: &Path) -> Result<Vec<String>> {
+    let repo = git2::Repository::open(repo_path)?;
+    // Remote repositories are fetched into `refs/remotes/origin`, local ones are read as they are.
+    let remote = if is_in_place(registry) {
+        ""
+    } else {
+        "refs/remotes/origin/"
+    };
+    let rev = match git_source_reference(registry) {
+        Some(GitReference::Branch(branch)) => format!("{}{}", remote, branch),
+        Some(GitReference::Tag(tag)) => format!("refs/tags/{}", tag),
+        Some(GitReference::Rev(rev)) => rev,
+        None => format!("{}HEAD", remote),
+    };
+    let tree = repo
+        .revparse_single(&rev)
+        .chain_err(|| ErrorKind::NoSuchIndexRev(rev.clone()))?
+        .peel_to_tree()?;
+
+    let mut manifests = Vec::new();
+    tree.walk(git2::TreeWalkMode::PreOrder, |_, entry| {
+        if entry.name() == Some("Cargo.toml") {
+            let content = entry
+                .to_object(&repo)
+                .ok()
+                .and_then(|object| object.peel_to_blob().ok())
+                .and_then(|blob| String::from_utf8(blob.content().to_vec()).ok());
+            manifests.extend(content);
         }
+        git2::TreeWalkResult::Ok
     })?;
-    Ok(())
+    Ok(manifests)
 }
 
 #[test]
-fn get_latest_stable_version_from_json() {
-    let versions: Vec<CrateVersion> = serde_json::from_str(
-        r#"[
-        {
-          "name": "foo",
-          "vers": "0.6.0-alpha",
-          "yanked": false
-        },
-        {
-          "name": "foo",
-          "vers": "0.5.0",
-          "yanked": false
-        }
-      ]"#,
-    )
-    .expect("crate version is correctly parsed");
+fn query_directory_and_git_sources() {
+    let tmpdir = tempdir::TempDir::new("replaced-source").unwrap();
+    let crates = tmpdir.path().join("crates");
+    for (dir, version) in &[("my-crate", "0.3.0"), ("my-crate-0.2.1", "0.2.1")] {
+        fs::create_dir_all(crates.join(dir)).unwrap();
+        fs::write(
+            crates.join(dir).join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"my-crate\"\nversion = \"{}\"\n\
+                 [features]\nextra = []\n",
+                version
+            ),
+        )
+        .unwrap();
+    }
+    let repo = git2::Repository::init(&crates).unwrap();
+    let signature = git2::Signature::now("cargo-edit", "cargo-edit@example.com").unwrap();
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "vendor", &tree, &[])
+        .unwrap();
+    let manifest_path = tmpdir.path().join("Cargo.toml");
 
-    assert_eq!(
-        read_latest_version(&versions, false)
-            .unwrap()
-            .version()
-            .unwrap(),
-        "0.5.0"
-    );
+    let directory = Url::parse(&format!(
+        "{}{}",
+        DIRECTORY_PREFIX,
+        Url::from_directory_path(&crates).unwrap()
+    ))
+    .unwrap();
+    let git = Url::parse(&format!(
+        "{}{}",
+        GIT_PREFIX,
+        Url::from_directory_path(&crates).unwrap()
+    ))
+    .unwrap();
+    for registry in [directory, git] {
+        assert!(!index_is_stale(&registry, Duration::from_secs(0)).unwrap());
+        let source = RegistryIndex::new(&manifest_path, &Some(registry));
+        let versions = source.versions("my_crate").unwrap();
+        assert_eq!(versions.len(), 2);
+        assert!(versions[0].has_feature("extra"));
+        let dep = get_latest_dependency_from_source("my_crate", false, None, &source).unwrap();
+        assert_eq!(dep.name, "my-crate");
+        assert_eq!(dep.version(), Some("0.3.0"));
+    }
 }
 
 #[test]
-fn get_latest_unstable_or_stable_version_from_json() {
-    let versions: Vec<CrateVersion> = serde_json::from_str(
-        r#"[
-        {
-          "name": "foo",
-          "vers": "0.6.0-alpha",
-          "yanked": false
-        },
-        {
-          "name": "foo",
-          "vers": "0.5.0",
-          "yanked": false
-        }
-      ]"#,
+fn query_file_index_from_relative_config_url() {
+    let tmpdir = tempdir::TempDir::new("file-index").unwrap();
+    let config_dir = tmpdir.path().join(".cargo");
+    fs::create_dir_all(&config_dir).unwrap();
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/file-index");
+    fs::write(
+        config_dir.join("config"),
+        format!(
+            "[registries.offline]\nindex = \"file:{}\"\n\
+             [registries.local]\nindex = \"file:index\"\n",
+            fixture.display()
+        ),
     )
-    .expect("crate version is correctly parsed");
+    .unwrap();
+    let index_dir = tmpdir.path().join("index").join("lo").join("ca");
+    fs::create_dir_all(&index_dir).unwrap();
+    fs::copy(
+        fixture.join("lo/ca/local-only"),
+        index_dir.join("local-only"),
+    )
+    .unwrap();
+    let manifest_path = tmpdir.path().join("Cargo.toml");
 
+    for name in &["offline", "local"] {
+        let registry = registry_url(&manifest_path, Some(name)).unwrap();
+        assert!(!index_is_stale(&registry, Duration::from_secs(0)).unwrap());
+        let source = RegistryIndex::new(&manifest_path, &Some(registry));
+        let dep = get_latest_dependency_from_source("local_only", false, None, &source).unwrap();
+        assert_eq!(dep.name, "local-only");
+        assert_eq!(dep.version(), Some("0.2.0"));
+    }
+
+    let registry = registry_url(&manifest_path, Some("offline")).unwrap();
+    let source = RegistryIndex::new(&manifest_path, &Some(registry));
     assert_eq!(
-        read_latest_version(&versions, true)
-            .unwrap()
-            .version()
-            .unwrap(),
-        "0.6.0-alpha"
+        source.config().unwrap().dl,
+        "https://dl.example.com/{crate}/{version}/download"
     );
 }
 
 #[test]
-fn get_latest_version_from_json_test() {
-    let versions: Vec<CrateVersion> = serde_json::from_str(
-        r#"[
-        {
-          "name": "treexml",
-          "vers": "0.3.1",
-          "yanked": true
-        },
-        {
-          "name": "treexml",
-          "vers": "0.3.0",
-          "yanked": false
-        }
-      ]"#,
-    )
-    .expect("crate version is correctly parsed");
+fn latest_prerelease_on_channel() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/file-index");
+    let registry = Url::from_directory_path(&fixture).unwrap();
+    let manifest_path = fixture.join("Cargo.toml");
 
-    assert_eq!(
-        read_latest_version(&versions, false)
-            .unwrap()
-            .version()
-            .unwrap(),
-        "0.3.0"
-    );
+    let source = RegistryIndex::new(&manifest_path, &Some(registry.clone()));
+    let dep = get_latest_dependency_from_source("channels", true, None, &source).unwrap();
+    assert_eq!(dep.version(), Some("1.2.0-nightly.1"));
+
+    let source = RegistryIndex::new(&manifest_path, &Some(registry)).prerelease_channel("rc");
+    let dep = get_latest_dependency_from_source("channels", true, None, &source).unwrap();
+    assert_eq!(dep.version(), Some("1.1.0-rc.2"));
+    let dep = get_latest_dependency_from_source("channels", false, None, &source).unwrap();
+    assert_eq!(dep.version(), Some("1.0.0"));
 }
 
 #[test]
-fn get_no_latest_version_from_json_when_all_are_yanked() {
-    let versions: Vec<CrateVersion> = serde_json::from_str(
-        r#"[
-        {
-          "name": "treexml",
-          "vers": "0.3.1",
-          "yanked": true
-        },
-        {
-          "name": "treexml",
-          "vers": "0.3.0",
-          "yanked": true
-        }
-      ]"#,
-    )
-    .expect("crate version is correctly parsed");
+fn latest_version_with_yanked_versions_allowed() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/file-index");
+    let registry = Url::from_directory_path(&fixture).unwrap();
+    let manifest_path = fixture.join("Cargo.toml");
+
+    let source = RegistryIndex::new(&manifest_path, &Some(registry.clone()));
+    let dep = get_latest_dependency_from_source("yanked", false, None, &source).unwrap();
+    assert_eq!(dep.version(), Some("1.0.0"));
 
-    assert!(read_latest_version(&versions, false).is_err());
+    let source = RegistryIndex::new(&manifest_path, &Some(registry)).allow_yanked();
+    let dep = get_latest_dependency_from_source("yanked", false, None, &source).unwrap();
+    assert_eq!(dep.version(), Some("1.1.0"));
 }
 
-/// Fuzzy query crate from registry index
+/// Fuzzy query crate from registry index, at the commit `rev` or else the latest fetched one
+///
+/// The entries found are cached for the commit they were read at, see `configure_index_cache`.
 fn fuzzy_query_registry_index(
     crate_name: impl Into<String>,
     registry_path: impl AsRef<Path>,
+    rev: Option<&str>,
 ) -> Result<Vec<CrateVersion>> {
     let crate_name = crate_name.into();
+    let registry_path = registry_path.as_ref();
     let repo = git2::Repository::open(registry_path)?;
-    let tree = repo
-        .find_reference("refs/remotes/origin/master")?
-        .peel_to_tree()?;
+    let commit = match rev {
+        Some(rev) => repo
+            .revparse_single(rev)
+            .chain_err(|| ErrorKind::NoSuchIndexRev(rev.to_owned()))?
+            .peel_to_commit()?,
+        None => repo
+            .find_reference("refs/remotes/origin/master")?
+            .peel_to_commit()?,
+    };
+    let cache = IndexCache::new(registry_path, commit.id());
+    if let Some(content) = cache.as_ref().and_then(|cache| cache.read(&crate_name)) {
+        return parse_summary(&content);
+    }
+    let tree = commit.tree()?;
 
     let mut names = gen_fuzzy_crate_names(crate_name.clone())?;
     if let Some(index) = names.iter().position(|x| *x == crate_name) {
@@ -287,17 +3685,203 @@ fn fuzzy_query_registry_index(
         let content = String::from_utf8(file.content().to_vec())
             .map_err(|_| ErrorKind::InvalidSummaryJson)?;
 
-        return content
-            .lines()
-            .map(|line: &str| {
-                serde_json::from_str::<CrateVersion>(line)
-                    .map_err(|_| ErrorKind::InvalidSummaryJson.into())
-            })
-            .collect::<Result<Vec<CrateVersion>>>();
+        let versions = parse_summary(&content)?;
+        if let Some(cache) = &cache {
+            cache.write(&crate_name, &content);
+        }
+        return Ok(versions);
     }
     Err(ErrorKind::NoCrate(crate_name).into())
 }
 
+/// The directory of a git index that entries read from it are cached in, below the commit they
+/// were read at
+const INDEX_CACHE_DIR: &str = ".cargo-edit-cache";
+
+static INDEX_CACHE_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Stop caching the entries read from git indices if `enabled` isn't set, e.g. for `--no-cache`.
+///
+/// Reading an entry from the git objects of an index is slow, so they are cached as plain files
+/// for the commit of the index they were read at. Once the index advances to another commit, the
+/// entries cached for older ones are removed.
+pub fn configure_index_cache(enabled: bool) {
+    INDEX_CACHE_DISABLED.store(!enabled, Ordering::Relaxed);
+}
+
+/// The cached entries of a git index at one commit
+#[derive(Debug)]
+struct IndexCache {
+    dir: PathBuf,
+}
+
+impl IndexCache {
+    /// The cache of the index at `registry_path` for `commit`, unless caching is disabled
+    fn new(registry_path: &Path, commit: git2::Oid) -> Option<IndexCache> {
+        if INDEX_CACHE_DISABLED.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some(IndexCache {
+            dir: registry_path.join(INDEX_CACHE_DIR).join(commit.to_string()),
+        })
+    }
+
+    fn entry_path(&self, crate_name: &str) -> PathBuf {
+        self.dir.join(summary_raw_path(&crate_name.to_lowercase()))
+    }
+
+    /// The cached entry of `crate_name`, if there is one
+    fn read(&self, crate_name: &str) -> Option<String> {
+        fs::read_to_string(self.entry_path(crate_name)).ok()
+    }
+
+    /// Cache `content` as the entry of `crate_name`. The cache is only an optimization, so
+    /// failing to write it is ignored.
+    fn write(&self, crate_name: &str, content: &str) {
+        // The first entry cached for a commit means the index advanced, or was reset to another
+        // commit, so the entries of other commits are gone for good.
+        if !self.dir.exists() {
+            if let Some(Ok(entries)) = self.dir.parent().map(fs::read_dir) {
+                for entry in entries.filter_map(|entry| entry.ok()) {
+                    let _ = fs::remove_dir_all(entry.path());
+                }
+            }
+        }
+        let path = self.entry_path(crate_name);
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent).and_then(|()| fs::write(&path, content));
+        }
+    }
+}
+
+#[test]
+fn query_git_index_at_past_commit() {
+    let tmpdir = tempdir::TempDir::new("git-index").unwrap();
+    let repo = git2::Repository::init(tmpdir.path()).unwrap();
+    let signature = git2::Signature::now("cargo-edit", "cargo-edit@example.com").unwrap();
+    let entry_path = tmpdir.path().join(summary_raw_path("minimal"));
+    fs::create_dir_all(entry_path.parent().unwrap()).unwrap();
+
+    // One commit per published version, the newest of which is the fetched index.
+    let mut entries = String::new();
+    let mut commits = Vec::new();
+    for version in &["1.0.0", "1.1.0"] {
+        entries.push_str(&format!(
+            "{{\"name\":\"minimal\",\"vers\":\"{}\",\"deps\":[],\"features\":{{}},\
+             \"cksum\":\"\",\"yanked\":false}}\n",
+            version
+        ));
+        fs::write(&entry_path, &entries).unwrap();
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let parents = commits
+            .last()
+            .map(|id| repo.find_commit(*id).unwrap())
+            .into_iter()
+            .collect::<Vec<_>>();
+        let parents = parents.iter().collect::<Vec<_>>();
+        let id = repo
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                version,
+                &tree,
+                &parents,
+            )
+            .unwrap();
+        commits.push(id);
+    }
+    repo.reference("refs/remotes/origin/master", commits[1], true, "fetch")
+        .unwrap();
+
+    let latest = |rev: Option<&str>| {
+        fuzzy_query_registry_index("minimal", tmpdir.path(), rev)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.version)
+            .max()
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(latest(None), "1.1.0");
+    assert_eq!(latest(Some(&commits[0].to_string())), "1.0.0");
+    assert_eq!(latest(Some("HEAD~1")), "1.0.0");
+
+    match fuzzy_query_registry_index("minimal", tmpdir.path(), Some("v9.9.9")) {
+        Err(Error(ErrorKind::NoSuchIndexRev(rev), _)) => assert_eq!(rev, "v9.9.9"),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn cache_git_index_entries_per_commit() {
+    let tmpdir = tempdir::TempDir::new("git-index-cache").unwrap();
+    let repo = git2::Repository::init(tmpdir.path()).unwrap();
+    let signature = git2::Signature::now("cargo-edit", "cargo-edit@example.com").unwrap();
+    let entry = |version: &str| {
+        format!(
+            "{{\"name\":\"minimal\",\"vers\":\"{}\",\"deps\":[],\"features\":{{}},\
+             \"cksum\":\"\",\"yanked\":false}}\n",
+            version
+        )
+    };
+    let commit = |version: &str| {
+        let mut builder = repo.treebuilder(None).unwrap();
+        let blob = repo.blob(entry(version).as_bytes()).unwrap();
+        let mut inner = repo.treebuilder(None).unwrap();
+        inner.insert("minimal", blob, 0o100_644).unwrap();
+        let mut outer = repo.treebuilder(None).unwrap();
+        outer
+            .insert("ni", inner.write().unwrap(), 0o040_000)
+            .unwrap();
+        builder
+            .insert("mi", outer.write().unwrap(), 0o040_000)
+            .unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let id = repo
+            .commit(None, &signature, &signature, version, &tree, &[])
+            .unwrap();
+        repo.reference("refs/remotes/origin/master", id, true, "fetch")
+            .unwrap();
+        id
+    };
+    let latest = || {
+        fuzzy_query_registry_index("minimal", tmpdir.path(), None)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.version.to_string())
+            .max()
+            .unwrap()
+    };
+    let cache_dir = tmpdir.path().join(INDEX_CACHE_DIR);
+    let cached_entry = |id: git2::Oid| {
+        cache_dir
+            .join(id.to_string())
+            .join(summary_raw_path("minimal"))
+    };
+
+    let first = commit("1.0.0");
+    assert_eq!(latest(), "1.0.0");
+    assert_eq!(
+        fs::read_to_string(cached_entry(first)).unwrap(),
+        entry("1.0.0")
+    );
+
+    // The cached entry is read instead of the index as long as it stays at that commit.
+    fs::write(cached_entry(first), entry("1.0.1")).unwrap();
+    assert_eq!(latest(), "1.0.1");
+
+    // Once the index advances, the entry is read again and the old cache is removed.
+    let second = commit("1.1.0");
+    assert_eq!(latest(), "1.1.0");
+    assert!(cached_entry(second).exists());
+    assert!(!cache_dir.join(first.to_string()).exists());
+}
+
 fn get_crate_name_from_repository<T>(repo: &str, matcher: &Regex, url_template: T) -> Result<String>
 where
     T: Fn(&str, &str) -> String,
@@ -365,27 +3949,367 @@ pub fn get_crate_name_from_path(path: &str) -> Result<String> {
         .and_then(|ref manifest| get_name_from_manifest(manifest))
 }
 
+/// Get the version of the package at the local `path`, which may be inherited from the workspace.
+pub fn get_crate_version_from_path(path: &str) -> Result<String> {
+    let cargo_file = Path::new(path).join("Cargo.toml");
+    let manifest = Manifest::open(&Some(cargo_file.clone()))
+        .chain_err(|| "Unable to open local Cargo.toml")?;
+    manifest
+        .package_field(&cargo_file, "version")
+        .as_str()
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| ErrorKind::ParseCargoToml.into())
+}
+
+/// List the names of the packages in a local git repository, at any depth of its `HEAD` tree
+///
+/// `repo` may be a path or a `file://` URL. This is best effort, `None` is returned if the
+/// repository is remote or cannot be read.
+pub fn get_crate_names_from_git_repo(repo: &str) -> Option<Vec<String>> {
+    let repo = git2::Repository::open(local_git_path(repo)?).ok()?;
+    let tree = repo.head().ok()?.peel_to_tree().ok()?;
+
+    let mut names = Vec::new();
+    tree.walk(git2::TreeWalkMode::PreOrder, |_, entry| {
+        if entry.name() == Some("Cargo.toml") {
+            let name = entry
+                .to_object(&repo)
+                .ok()
+                .and_then(|object| object.peel_to_blob().ok())
+                .and_then(|blob| String::from_utf8(blob.content().to_vec()).ok())
+                .and_then(|content| content.parse::<Manifest>().ok())
+                .and_then(|manifest| get_name_from_manifest(&manifest).ok());
+            names.extend(name);
+        }
+        git2::TreeWalkResult::Ok
+    })
+    .ok()?;
+    names.sort();
+    Some(names)
+}
+
+/// The path of a git repository given as a path or a `file://` URL, or `None` if it is remote.
+fn local_git_path(repo: &str) -> Option<PathBuf> {
+    match Url::parse(repo) {
+        Ok(url) if url.scheme() == "file" => url.to_file_path().ok(),
+        Ok(_) => None,
+        Err(_) => Some(PathBuf::from(repo)),
+    }
+}
+
+/// Make sure that the git repository `repo` has the branch, tag or revision `reference`.
+///
+/// The references of the repository are listed with `git ls-remote`. Revisions that no reference
+/// points to can only be checked in local repositories, and are accepted for remote ones.
+pub fn verify_git_reference(repo: &str, reference: &GitReference, timeout: Duration) -> Result<()> {
+    let refs = list_git_references(repo, timeout)?;
+    let mut refs = refs
+        .iter()
+        .map(|(oid, refname)| (oid.as_str(), refname.as_str()));
+    let found = match reference {
+        GitReference::Branch(branch) => {
+            let name = format!("refs/heads/{}", branch);
+            refs.any(|(_, refname)| refname == name)
+        }
+        GitReference::Tag(tag) => {
+            let name = format!("refs/tags/{}", tag);
+            refs.any(|(_, refname)| refname == name)
+        }
+        GitReference::Rev(rev) => {
+            let hash = Some(rev.to_lowercase())
+                .filter(|rev| rev.len() >= 4 && rev.chars().all(|c| c.is_ascii_hexdigit()));
+            refs.any(|(oid, refname)| {
+                refname == rev.as_str() || hash.as_ref().is_some_and(|hash| oid.starts_with(hash))
+            }) || match local_git_path(repo).and_then(|path| git2::Repository::open(path).ok()) {
+                Some(local) => local.revparse_single(rev).is_ok(),
+                None => true,
+            }
+        }
+    };
+    if found {
+        return Ok(());
+    }
+    let kind = match reference {
+        GitReference::Branch(_) => "branch",
+        GitReference::Tag(_) => "tag",
+        GitReference::Rev(_) => "revision",
+    };
+    Err(ErrorKind::MissingGitReference(repo.into(), kind.into(), reference.value().into()).into())
+}
+
+/// Get the newest tag of the git repository `repo` that is a semver version, optionally prefixed
+/// with `v` like `v1.2.0`. Pre-release tags are skipped.
+pub fn get_latest_git_tag(repo: &str, timeout: Duration) -> Result<String> {
+    let refs = list_git_references(repo, timeout)?;
+    newest_semver_tag(refs.iter().map(|(_, refname)| refname.as_str()))
+        .ok_or_else(|| ErrorKind::NoSemverGitTag(repo.into()).into())
+}
+
+/// Get the commit that `HEAD` of the git repository `repo` points to, i.e. the tip of its default
+/// branch.
+pub fn get_latest_git_commit(repo: &str, timeout: Duration) -> Result<String> {
+    list_git_references(repo, timeout)?
+        .into_iter()
+        .find(|(_, refname)| refname == "HEAD")
+        .map(|(oid, _)| oid)
+        .ok_or_else(|| {
+            ErrorKind::MissingGitReference(repo.into(), "branch".into(), "HEAD".into()).into()
+        })
+}
+
+/// Get the newest tag among the reference names `refnames` that is a semver release.
+fn newest_semver_tag<'a>(refnames: impl Iterator<Item = &'a str>) -> Option<String> {
+    const TAGS: &str = "refs/tags/";
+    refnames
+        .filter(|refname| !refname.ends_with("^{}"))
+        .filter_map(|refname| refname.strip_prefix(TAGS))
+        .filter_map(|tag| {
+            let version = tag.strip_prefix('v').unwrap_or(tag);
+            semver::Version::parse(version)
+                .ok()
+                .filter(|version| !version.is_prerelease())
+                .map(|version| (version, tag))
+        })
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, tag)| tag.to_owned())
+}
+
+/// List the references of the git repository `repo` with `git ls-remote`, as pairs of the object
+/// id and the name of each reference.
+fn list_git_references(repo: &str, timeout: Duration) -> Result<Vec<(String, String)>> {
+    if is_offline() {
+        return Err(ErrorKind::Offline(format!("list the references of `{}`", repo)).into());
+    }
+    let output = subprocess::Exec::cmd("git")
+        .args(&git_http_options(&ambient_http_config()?))
+        .arg("ls-remote")
+        .arg(repo)
+        .env_remove("GIT_DIR")
+        .env("GIT_HTTP_LOW_SPEED_LIMIT", "1")
+        .env(
+            "GIT_HTTP_LOW_SPEED_TIME",
+            timeout.as_secs().max(1).to_string(),
+        )
+        // Fail instead of asking for credentials.
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .capture()
+        .map_err(|e| ErrorKind::UnreachableGitRepository(repo.into(), e.to_string()))?;
+    if !output.exit_status.success() {
+        let reason = output.stderr_str().trim().to_owned();
+        return Err(ErrorKind::UnreachableGitRepository(repo.into(), reason).into());
+    }
+
+    Ok(output
+        .stdout_str()
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            Some((fields.next()?.to_owned(), fields.next()?.to_owned()))
+        })
+        .collect())
+}
+
+#[test]
+fn find_newest_semver_tag() {
+    let refnames = [
+        "HEAD",
+        "refs/heads/v9.0.0",
+        "refs/tags/v1.2.0",
+        "refs/tags/v1.2.0^{}",
+        "refs/tags/1.10.0",
+        "refs/tags/v2.0.0-rc.1",
+        "refs/tags/nightly",
+    ];
+    assert_eq!(
+        newest_semver_tag(refnames.iter().cloned()).as_deref(),
+        Some("1.10.0")
+    );
+    assert_eq!(newest_semver_tag(refnames[..2].iter().cloned()), None);
+}
+
 fn get_name_from_manifest(manifest: &Manifest) -> Result<String> {
     manifest
         .data
         .as_table()
         .get("package")
-        .and_then(|m| m["name"].as_str().map(std::string::ToString::to_string))
+        .and_then(|m| m["name"].as_str().map(ToString::to_string))
         .ok_or_else(|| ErrorKind::ParseCargoToml.into())
 }
 
-const fn get_default_timeout() -> Duration {
-    Duration::from_secs(10)
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Forbid network access if `offline` is set, e.g. for `--offline`, or if `CARGO_NET_OFFLINE` is
+/// `true` like for cargo. Lookups then only read the copies of the registry indices that were
+/// downloaded before, and fail if a crate is missing from them.
+pub fn configure_offline(offline: bool) {
+    let from_env = env::var("CARGO_NET_OFFLINE").is_ok_and(|v| v == "true");
+    OFFLINE.store(offline || from_env, Ordering::Relaxed);
+}
+
+/// Whether `configure_offline` forbade network access.
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Timeout for network operations.
+///
+/// Defaults to 30 seconds and can be overridden by setting `CARGO_HTTP_TIMEOUT` to a number of
+/// seconds.
+pub fn get_default_timeout() -> Duration {
+    env::var("CARGO_HTTP_TIMEOUT")
+        .ok()
+        .and_then(|timeout| timeout.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_else(|| Duration::from_secs(30))
+}
+
+/// How many lookups `lookup_concurrently` runs at once unless told otherwise
+pub const DEFAULT_LOOKUP_JOBS: usize = 8;
+
+/// Run `lookup` for each of `items` on up to `jobs` threads, e.g. to query the registry index for
+/// many independent dependencies at once. The results are in the order of `items`.
+pub fn lookup_concurrently<T, R, F>(items: Vec<T>, jobs: usize, lookup: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let count = items.len();
+    let jobs = jobs.min(count);
+    if jobs <= 1 {
+        return items.into_iter().map(lookup).collect();
+    }
+
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let results = Mutex::new(Vec::with_capacity(count));
+    crossbeam_utils::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|_| loop {
+                let next = queue.lock().expect("lookup queue poisoned").next();
+                let (index, item) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let result = lookup(item);
+                results
+                    .lock()
+                    .expect("lookup results poisoned")
+                    .push((index, result));
+            });
+        }
+    })
+    .expect("a lookup thread panicked");
+
+    let mut results = results.into_inner().expect("lookup results poisoned");
+    results.sort_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[test]
+fn lookups_keep_their_order() {
+    let items = (0..50).collect::<Vec<u64>>();
+    let lookup = |n: u64| {
+        std::thread::sleep(Duration::from_millis(50 - n));
+        n * 2
+    };
+    let expected = (0..50).map(|n| n * 2).collect::<Vec<_>>();
+
+    assert_eq!(lookup_concurrently(items.clone(), 8, lookup), expected);
+    // No threads are started for a single job.
+    assert_eq!(lookup_concurrently(items, 0, lookup), expected);
+}
+
+/// The URL of the proxy `http.proxy`, which may leave out the protocol like cargo allows
+fn proxy_url(proxy: &str) -> String {
+    if proxy.contains("://") {
+        proxy.to_owned()
+    } else {
+        format!("http://{}", proxy)
+    }
+}
+
+/// The `-c` options that make git use the proxy and certificate authorities of `http`, which
+/// it would not read from cargo's config by itself.
+fn git_http_options(http: &HttpConfig) -> Vec<String> {
+    let mut options = Vec::new();
+    if let Some(proxy) = &http.proxy {
+        options.push("-c".to_owned());
+        options.push(format!("http.proxy={}", proxy));
+    }
+    if let Some(cainfo) = &http.cainfo {
+        options.push("-c".to_owned());
+        options.push(format!("http.sslCAInfo={}", cainfo.display()));
+    }
+    options
+}
+
+#[test]
+fn pass_proxy_and_certificates_to_git() {
+    assert!(git_http_options(&HttpConfig::default()).is_empty());
+    let http = HttpConfig {
+        proxy: Some("proxy.example.com:3128".to_owned()),
+        cainfo: Some(PathBuf::from("/etc/ssl/corporate.pem")),
+        ..HttpConfig::default()
+    };
+    assert_eq!(
+        git_http_options(&http),
+        vec![
+            "-c",
+            "http.proxy=proxy.example.com:3128",
+            "-c",
+            "http.sslCAInfo=/etc/ssl/corporate.pem"
+        ]
+    );
+
+    assert_eq!(
+        proxy_url("proxy.example.com:3128"),
+        "http://proxy.example.com:3128"
+    );
+    assert_eq!(
+        proxy_url("socks5://proxy.example.com"),
+        "socks5://proxy.example.com"
+    );
 }
 
-fn get_with_timeout(url: &str, timeout: Duration) -> reqwest::Result<reqwest::blocking::Response> {
-    let client = reqwest::blocking::ClientBuilder::new()
+/// Build an HTTP client that goes through `http.proxy`, or else the proxy of the environment,
+/// trusts `http.cainfo` and authenticates with `http.ssl_cert`.
+fn http_client(timeout: Duration, http: &HttpConfig) -> Result<reqwest::blocking::Client> {
+    if is_offline() {
+        return Err(ErrorKind::Offline("make HTTP requests".into()).into());
+    }
+    let proxy = match &http.proxy {
+        Some(proxy) => reqwest::Proxy::all(&proxy_url(proxy))
+            .chain_err(|| format!("Invalid proxy `{}` in `http.proxy`", proxy))?,
+        None => reqwest::Proxy::custom(|url| env_proxy::for_url(url).to_url()),
+    };
+    let mut builder = reqwest::blocking::ClientBuilder::new()
+        .connect_timeout(timeout)
         .timeout(timeout)
-        .proxy(reqwest::Proxy::custom(|url| {
-            env_proxy::for_url(url).to_url()
-        }))
-        .build()?;
+        .proxy(proxy);
+    if let Some(cainfo) = &http.cainfo {
+        let pem =
+            fs::read(cainfo).chain_err(|| format!("Failed to read `{}`", cainfo.display()))?;
+        let certificate = reqwest::Certificate::from_pem(&pem)
+            .chain_err(|| format!("Invalid certificate in `{}`", cainfo.display()))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    if let Some(ssl_cert) = &http.ssl_cert {
+        let der =
+            fs::read(ssl_cert).chain_err(|| format!("Failed to read `{}`", ssl_cert.display()))?;
+        let password = http.ssl_cert_password.as_deref().unwrap_or("");
+        let identity = reqwest::Identity::from_pkcs12_der(&der, password)
+            .chain_err(|| format!("Invalid client certificate in `{}`", ssl_cert.display()))?;
+        builder = builder.identity(identity);
+    }
+    builder
+        .build()
+        .chain_err(|| "Failed to set up the HTTP client")
+}
 
+fn send(
+    client: &reqwest::blocking::Client,
+    url: &str,
+) -> reqwest::Result<reqwest::blocking::Response> {
     client
         .get(url)
         .send()
@@ -393,14 +4317,53 @@ fn get_with_timeout(url: &str, timeout: Duration) -> reqwest::Result<reqwest::bl
 }
 
 fn get_cargo_toml_from_git_url(url: &str) -> Result<String> {
-    let mut res = get_with_timeout(url, get_default_timeout())
-        .chain_err(|| "Failed to fetch crate from git")?;
+    get_cargo_toml_with_timeout(url, get_default_timeout())
+}
+
+fn get_cargo_toml_with_timeout(url: &str, timeout: Duration) -> Result<String> {
+    let timed_out = || ErrorKind::FetchTimeout("Cargo.toml".into(), url.into());
+    let client = http_client(timeout, &ambient_http_config()?)?;
+    let mut res = send(&client, url).map_err(|e| {
+        if e.is_timeout() {
+            Error::from(timed_out())
+        } else {
+            Error::with_chain(e, "Failed to fetch crate from git")
+        }
+    })?;
     let mut body = String::new();
-    res.read_to_string(&mut body)
-        .chain_err(|| "Git response not a valid `String`")?;
+    res.read_to_string(&mut body).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::TimedOut {
+            Error::from(timed_out())
+        } else {
+            Error::with_chain(e, "Git response not a valid `String`")
+        }
+    })?;
     Ok(body)
 }
 
+#[test]
+fn fetch_times_out_on_slow_server() {
+    use std::net::TcpListener;
+    use std::thread;
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind mock server");
+    let url = format!("http://{}/Cargo.toml", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        // Accept the connection, but never answer.
+        let _stream = listener.accept();
+        thread::sleep(Duration::from_secs(10));
+    });
+
+    let err = get_cargo_toml_with_timeout(&url, Duration::from_secs(1)).unwrap_err();
+    match err.kind() {
+        ErrorKind::FetchTimeout(name, timed_out_url) => {
+            assert_eq!(name, "Cargo.toml");
+            assert_eq!(timed_out_url, &url);
+        }
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
 /// Generate all similar crate names
 ///
 /// Examples:
@@ -1,5 +1,60 @@
 //! Show and Edit Cargo's Manifest Files
-#![recursion_limit = "128"]
+//!
+//! Besides the `cargo add`, `cargo rm`, `cargo upgrade` and `cargo set-version` binaries, this
+//! crate is a library for tools that edit manifests themselves, e.g. for release automation or
+//! bots that open upgrade pull requests. Its public API, which follows semver, is:
+//!
+//! - `Manifest`, which reads and edits a manifest, keeping its formatting and comments.
+//! - `Dependency`, which describes an entry of a dependency table.
+//! - `get_latest_dependency`, which looks up the latest version of a crate in the registry index.
+//! - `upgrade`, which plans and applies the upgrades of a manifest like `cargo upgrade`, as set
+//!   up with `UpgradeOptions`, and lists them in an `UpgradeReport`. The versions come from a
+//!   `VersionSource`, e.g. the `RegistryIndex`.
+//!
+//! The other items are only exported for the binaries and may change in any release.
+//!
+//! # Examples
+//!
+//! Add a dependency to a manifest:
+//!
+//! ```
+//! use cargo_edit::{Dependency, Manifest};
+//!
+//! let mut manifest: Manifest = "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n".parse()?;
+//! let serde = Dependency::new("serde").set_version("1.0");
+//! manifest.insert_into_table(&["dependencies".to_owned()], &serde)?;
+//! assert!(manifest.data.to_string().contains("serde = \"1.0\""));
+//! # Ok::<(), cargo_edit::Error>(())
+//! ```
+//!
+//! Upgrade the dependencies of a manifest on disk to the versions of a custom source instead of
+//! the registry index:
+//!
+//! ```
+//! use cargo_edit::{upgrade, CrateVersion, Result, UpgradeOptions, VersionSource};
+//!
+//! struct Published;
+//!
+//! impl VersionSource for Published {
+//!     fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+//!         let version = |v: &str| CrateVersion::new(crate_name, semver::Version::parse(v).unwrap());
+//!         Ok(vec![version("1.0.0"), version("1.2.0")])
+//!     }
+//! }
+//!
+//! let dir = tempdir::TempDir::new("demo")?;
+//! let manifest_path = dir.path().join("Cargo.toml");
+//! std::fs::write(
+//!     &manifest_path,
+//!     "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n\n[dependencies]\nserde = \"1.0\"\n",
+//! )?;
+//!
+//! let report = upgrade(&manifest_path, &UpgradeOptions::default(), |_registry| Published)?;
+//! let serde = report.applied().next().unwrap();
+//! assert_eq!(serde.new_requirement.as_deref(), Some("1.2.0"));
+//! # Ok::<(), cargo_edit::Error>(())
+//! ```
+#![recursion_limit = "256"]
 #![cfg_attr(test, allow(dead_code))]
 #![warn(
     missing_docs,
@@ -12,25 +67,90 @@
     unused_import_braces,
     unused_qualifications
 )]
+// The impls serde_derive 1.0.104 generates sit in anonymous consts and check the `cargo-clippy`
+// feature, which current compilers warn about in every derive of this crate.
+#![allow(unexpected_cfgs, non_local_definitions)]
 
 #[macro_use]
 extern crate error_chain;
 #[macro_use]
 extern crate serde_derive;
 
+mod advisory;
 mod crate_name;
 mod dependency;
+mod diff;
+// `error_chain` implements the deprecated `Error::description` for foreign links.
+#[allow(deprecated)]
 mod errors;
+mod features;
 mod fetch;
 mod manifest;
 mod registry;
+mod report;
+mod upgrade;
+mod vendor;
+#[doc(hidden)]
+pub mod version;
+mod warning;
 
-pub use crate::crate_name::CrateName;
 pub use crate::dependency::Dependency;
 pub use crate::errors::*;
+pub use crate::fetch::{get_latest_dependency, CrateVersion, RegistryIndex, VersionSource};
+pub use crate::manifest::Manifest;
+pub use crate::upgrade::{
+    upgrade, DependencyUpgrade, UpgradeOptions, UpgradeOutcome, UpgradeReport,
+};
+
+// Shared with the binaries, not covered by semver.
+#[doc(hidden)]
+pub use crate::advisory::{Advisory, AdvisoryDatabase, ADVISORY_DB_URL};
+#[doc(hidden)]
+pub use crate::crate_name::CrateName;
+#[doc(hidden)]
+pub use crate::dependency::{GitReference, ResolvedSource};
+#[doc(hidden)]
+pub use crate::diff::unified_diff;
+#[doc(hidden)]
+pub use crate::features::FeatureValue;
+#[doc(hidden)]
 pub use crate::fetch::{
+    changelog_url, compatible_requirement, configure_index_cache, configure_offline, crate_exists,
+    crate_exists_in_source, get_advisory_safe_dependency_from_source, get_available_features,
     get_crate_name_from_github, get_crate_name_from_gitlab, get_crate_name_from_path,
-    get_latest_dependency, update_registry_index,
+    get_crate_names_from_git_repo, get_crate_version_from_path, get_default_features_change,
+    get_default_timeout, get_latest_compatible_dependency_from_source,
+    get_latest_dependency_and_versions_from_source, get_latest_dependency_compatible_from_source,
+    get_latest_dependency_for_rust_version, get_latest_dependency_from_source,
+    get_latest_dependency_from_sources, get_latest_dependency_matching_from_source,
+    get_latest_git_commit, get_latest_git_tag, get_links_collisions, get_minimal_dependency,
+    get_minimal_dependency_from_source, get_repository_from_source, get_unknown_features,
+    get_yank_safe_dependency_from_source, get_yanked_dependencies,
+    get_yanked_selection_from_source, index_is_stale, is_offline, lookup_concurrently,
+    parse_rust_version, resolve_versions, update_registry_index, update_registry_index_quietly,
+    verify_checksum, verify_git_reference, verify_version_exists, CrateSpec, DefaultFeaturesChange,
+    IndexConfig, IndexDependency, ResolvedDep, DEFAULT_LOOKUP_JOBS,
+};
+#[doc(hidden)]
+pub use crate::manifest::{
+    dependency_table_path, find, find_duplicate_dependencies, find_named, find_workspace_member,
+    iter_dependencies, iter_dependency_sources, merge_duplicate_dependencies, rebase_path,
+    relative_path, sort_dependencies, table_display, workspace_dependency_versions,
+    workspace_root_path, write_manifests_atomically, DependencyKind, EditSettings, LocalManifest,
+    TableContext,
+};
+#[doc(hidden)]
+pub use crate::registry::{
+    default_registry_name, http_config, original_registry_url, registry_token, registry_url,
+    registry_url_lenient, HttpConfig, RegistryToken,
+};
+#[doc(hidden)]
+pub use crate::report::{requirement_changes, source_records, write_report, REPORT_FORMAT_VERSION};
+#[doc(hidden)]
+pub use crate::upgrade::pinned_reason;
+#[doc(hidden)]
+pub use crate::vendor::{revendor, upstream_registry_url, vendor_directory};
+#[doc(hidden)]
+pub use crate::warning::{
+    configure_warnings, hide_status_messages, is_quiet, status_messages_hidden, warn,
 };
-pub use crate::manifest::{find, LocalManifest, Manifest};
-pub use crate::registry::registry_url;
@@ -1,18 +1,113 @@
+use std::collections::HashMap;
 use std::fs::{self, File, OpenOptions};
-use std::io::{Read, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::ops::Deref;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::{env, str};
 
 use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
-use toml_edit;
 
-use crate::dependency::Dependency;
+use crate::dependency::{Dependency, GitReference, ResolvedSource};
+use crate::diff::unified_diff;
 use crate::errors::*;
+use crate::features::FeatureValue;
+use crate::version::style::RequirementStyle;
+use crate::warning::status_messages_hidden;
 
 use semver::{Version, VersionReq};
 
 const MANIFEST_FILENAME: &str = "Cargo.toml";
+/// Starts the comments that explain why a requirement is pinned
+const PIN_COMMENT: &str = "# pinned:";
+
+/// Project-level defaults for cargo-edit's flags.
+///
+/// They are read from `[package.metadata.cargo-edit]`, falling back to
+/// `[workspace.metadata.cargo-edit]` of the workspace root. Flags given on the command line take
+/// precedence over both.
+#[derive(Debug, Clone)]
+pub struct EditSettings {
+    package: toml_edit::Item,
+    workspace: toml_edit::Item,
+}
+
+impl EditSettings {
+    /// Get the value of `key`, from the package if it sets it, or else from the workspace.
+    pub fn get(&self, key: &str) -> &toml_edit::Item {
+        match &self.package[key] {
+            toml_edit::Item::None => &self.workspace[key],
+            item => item,
+        }
+    }
+
+    /// Whether `sort-features` asks for the features of added dependencies to be sorted.
+    pub fn sort_features(&self) -> bool {
+        self.get("sort-features").as_bool() == Some(true)
+    }
+
+    /// Whether `sort` asks for the dependency tables to be kept sorted when adding dependencies.
+    pub fn sort(&self) -> bool {
+        self.get("sort").as_bool() == Some(true)
+    }
+
+    /// Whether `table-always` asks for dependencies to be written as inline tables even if only a
+    /// version is set.
+    pub fn table_always(&self) -> bool {
+        self.get("table-always").as_bool() == Some(true)
+    }
+
+    /// Whether `default-features-style` asks for enabled default features to be written as
+    /// `default-features = true` (`"explicit"`) or omitted (`"auto"`).
+    pub fn explicit_default_features(&self) -> Option<bool> {
+        match self.get("default-features-style").as_str() {
+            Some("explicit") => Some(true),
+            Some("auto") => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Get `table-header`, a comment to write above the tables cargo-edit creates.
+    pub fn table_header(&self) -> Option<&str> {
+        self.get("table-header").as_str()
+    }
+
+    /// Get `req-style`, the form of the requirements `cargo add` and `cargo upgrade` write.
+    pub fn req_style(&self) -> Result<Option<RequirementStyle>> {
+        self.get("req-style").as_str().map(str::parse).transpose()
+    }
+
+    /// Get `index-rev`, the commit of the git index to resolve versions against.
+    pub fn index_rev(&self) -> Option<&str> {
+        self.get("index-rev").as_str()
+    }
+
+    /// Get the dependencies listed in `skip` of the `upgrade` table, which `cargo upgrade` leaves
+    /// alone.
+    pub fn upgrade_skip(&self) -> Vec<String> {
+        self.get("upgrade")["skip"]
+            .as_array()
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(|name| name.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Get the patterns from `group-order`, used to group dependencies when sorting them.
+    pub fn group_order(&self) -> Vec<String> {
+        self.get("group-order")
+            .as_array()
+            .map(|patterns| {
+                patterns
+                    .iter()
+                    .filter_map(|p| p.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
 
 /// A Cargo manifest
 #[derive(Debug, Clone)]
@@ -21,35 +116,434 @@ pub struct Manifest {
     pub data: toml_edit::Document,
 }
 
-/// If a manifest is specified, return that one, otherise perform a manifest search starting from
-/// the current directory.
-/// If a manifest is specified, return that one. If a path is specified, perform a manifest search
-/// starting from there. If nothing is specified, start searching from the current directory
-/// (`cwd`).
+/// If a manifest is specified, return that one. If a directory is specified, return the
+/// `Cargo.toml` in it. If nothing is specified, perform a manifest search starting from the
+/// current directory (`cwd`).
 pub fn find(specified: &Option<PathBuf>) -> Result<PathBuf> {
+    find_named(specified, MANIFEST_FILENAME)
+}
+
+/// Like `find`, but look for manifests called `file_name` instead of `Cargo.toml`, e.g. templates
+/// such as `Cargo.toml.in` that follow the structure of a Cargo manifest.
+pub fn find_named(specified: &Option<PathBuf>, file_name: &str) -> Result<PathBuf> {
     match *specified {
-        Some(ref path)
-            if fs::metadata(&path)
-                .chain_err(|| "Failed to get cargo file metadata")?
-                .is_file() =>
-        {
-            Ok(path.to_owned())
-        }
-        Some(ref path) => search(path),
-        None => search(&env::current_dir().chain_err(|| "Failed to get current directory")?),
+        Some(ref path) if path.is_file() => Ok(path.to_owned()),
+        Some(ref path) if path.join(file_name).is_file() => Ok(path.join(file_name)),
+        Some(ref path) => Err(ErrorKind::InvalidManifestPath(path.display().to_string()).into()),
+        None => search(
+            &env::current_dir().chain_err(|| "Failed to get current directory")?,
+            file_name,
+        ),
     }
 }
 
-/// Search for Cargo.toml in this directory and recursively up the tree until one is found.
-fn search(dir: &Path) -> Result<PathBuf> {
-    let manifest = dir.join(MANIFEST_FILENAME);
+/// Search for `file_name` in this directory and recursively up the tree until one is found.
+fn search(dir: &Path, file_name: &str) -> Result<PathBuf> {
+    let manifest = dir.join(file_name);
 
     if fs::metadata(&manifest).is_ok() {
         Ok(manifest)
     } else {
         dir.parent()
             .ok_or_else(|| ErrorKind::MissingManifest.into())
-            .and_then(|dir| search(dir))
+            .and_then(|dir| search(dir, file_name))
+    }
+}
+
+/// The kind of a dependency, which decides the table it is declared in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyKind {
+    /// A dependency in `[dependencies]`
+    Normal,
+    /// A dependency in `[dev-dependencies]`, only used by tests, examples and benchmarks
+    Development,
+    /// A dependency in `[build-dependencies]`, only used by the build script
+    Build,
+}
+
+impl DependencyKind {
+    /// The name of the table dependencies of this kind are declared in
+    pub fn table_name(self) -> &'static str {
+        match self {
+            DependencyKind::Normal => "dependencies",
+            DependencyKind::Development => "dev-dependencies",
+            DependencyKind::Build => "build-dependencies",
+        }
+    }
+}
+
+impl str::FromStr for DependencyKind {
+    type Err = Error;
+
+    /// Parse a kind from its short name, e.g. `dev`, or from the name of its table.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "normal" | "dependencies" => Ok(DependencyKind::Normal),
+            "dev" | "dev-dependencies" => Ok(DependencyKind::Development),
+            "build" | "build-dependencies" => Ok(DependencyKind::Build),
+            _ => Err(ErrorKind::InvalidDependencyKind(s.into()).into()),
+        }
+    }
+}
+
+/// Get the path of the table that dependencies of `kind` are declared in, e.g.
+/// `["target", "cfg(unix)", "dev-dependencies"]` for development dependencies on `cfg(unix)`.
+pub fn dependency_table_path(kind: DependencyKind, target: Option<&str>) -> Vec<String> {
+    match target {
+        Some(target) => vec![
+            "target".to_owned(),
+            target.to_owned(),
+            kind.table_name().to_owned(),
+        ],
+        None => vec![kind.table_name().to_owned()],
+    }
+}
+
+/// Where a dependency is declared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableContext {
+    /// The kind of the dependency
+    pub kind: DependencyKind,
+    /// The target of a `[target.<target>]` table, if the dependency is declared in one
+    pub target: Option<String>,
+    /// Whether the dependency is declared in `[workspace.dependencies]`
+    pub workspace: bool,
+}
+
+impl TableContext {
+    /// Get the path of the table, e.g. `["target", "cfg(unix)", "dev-dependencies"]`.
+    pub fn table_path(&self) -> Vec<String> {
+        if self.workspace {
+            vec!["workspace".to_owned(), "dependencies".to_owned()]
+        } else {
+            dependency_table_path(self.kind, self.target.as_deref())
+        }
+    }
+}
+
+/// Iterate over the dependencies declared in `manifest`, in `[dependencies]`,
+/// `[dev-dependencies]`, `[build-dependencies]`, their `[target.<target>]` variants and
+/// `[workspace.dependencies]`, in this order.
+///
+/// The tables are walked lazily, parsing each entry only when it is reached. Dependencies from
+/// `[target.<target>]` tables have their target set. Entries that can't be parsed are skipped,
+/// `Manifest::get_dependencies` reports them instead.
+pub fn iter_dependencies(
+    manifest: &Manifest,
+) -> impl Iterator<Item = (TableContext, Dependency)> + '_ {
+    iter_dependency_items(manifest).filter_map(|(context, name, item)| {
+        let mut dependency = Dependency::from_toml(name, item).ok()?;
+        if let Some(target) = &context.target {
+            dependency = dependency.set_target(target);
+        }
+        Some((context, dependency))
+    })
+}
+
+/// Iterate over the dependencies declared in `manifest` like `iter_dependencies`, but get the
+/// key of each entry and the source it declares instead, e.g. for audits.
+pub fn iter_dependency_sources(
+    manifest: &Manifest,
+) -> impl Iterator<Item = (TableContext, String, ResolvedSource)> + '_ {
+    iter_dependency_items(manifest).filter_map(|(context, name, item)| {
+        let source = ResolvedSource::from_toml(name, item).ok()?;
+        Some((context, name.to_owned(), source))
+    })
+}
+
+/// The entries of the dependency tables of `manifest`, in the order of `iter_dependencies`.
+fn iter_dependency_items(
+    manifest: &Manifest,
+) -> impl Iterator<Item = (TableContext, &str, &toml_edit::Item)> + '_ {
+    const KINDS: [DependencyKind; 3] = [
+        DependencyKind::Normal,
+        DependencyKind::Development,
+        DependencyKind::Build,
+    ];
+    let root = &manifest.data;
+    let context = |kind, target: Option<&str>, workspace| TableContext {
+        kind,
+        target: target.map(String::from),
+        workspace,
+    };
+
+    let top_level = KINDS
+        .iter()
+        .map(move |&kind| (context(kind, None, false), &root[kind.table_name()]));
+    let targets = root
+        .as_table()
+        .get("target")
+        .and_then(toml_edit::Item::as_table_like)
+        .into_iter()
+        .flat_map(toml_edit::TableLike::iter)
+        .flat_map(move |(target, table)| {
+            KINDS.iter().map(move |&kind| {
+                (
+                    context(kind, Some(target), false),
+                    &table[kind.table_name()],
+                )
+            })
+        });
+    let workspace = std::iter::once((
+        context(DependencyKind::Normal, None, true),
+        &root["workspace"]["dependencies"],
+    ));
+
+    top_level
+        .chain(targets)
+        .chain(workspace)
+        .filter_map(|(context, table)| table.as_table_like().map(|table| (context, table)))
+        .flat_map(|(context, table)| {
+            table
+                .iter()
+                .map(move |(name, item)| (context.clone(), name, item))
+        })
+}
+
+/// Match a name against a glob pattern, where `*` matches any sequence of characters and `?`
+/// matches a single character.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match (pattern.first(), name.first()) {
+            (None, None) => true,
+            (Some('*'), _) => {
+                matches(&pattern[1..], name) || (!name.is_empty() && matches(pattern, &name[1..]))
+            }
+            (Some('?'), Some(_)) => matches(&pattern[1..], &name[1..]),
+            (Some(p), Some(n)) if p == n => matches(&pattern[1..], &name[1..]),
+            _ => false,
+        }
+    }
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&pattern, &name)
+}
+
+/// Sort the entries of a dependency table.
+///
+/// Entries are grouped by the first pattern of `group_order` matching either their name or their
+/// `path`, and sorted alphabetically within each group. Entries matching no pattern come last.
+/// Without any groups, this is a plain alphabetical sort.
+pub fn sort_dependencies(table: &mut toml_edit::Table, group_order: &[String]) {
+    if group_order.is_empty() {
+        table.sort_values();
+        return;
+    }
+
+    let group_of = |name: &str, item: &toml_edit::Item| {
+        let path = item
+            .as_table_like()
+            .and_then(|t| t.get("path"))
+            .and_then(|p| p.as_str());
+        group_order
+            .iter()
+            .position(|pattern| {
+                glob_match(pattern, name) || path.map(|p| glob_match(pattern, p)).unwrap_or(false)
+            })
+            .unwrap_or(group_order.len())
+    };
+    let mut keys: Vec<(usize, String)> = table
+        .iter()
+        .map(|(name, item)| (group_of(name, item), name.to_owned()))
+        .collect();
+    keys.sort();
+
+    // Re-inserting every entry in order moves it to the end of the table.
+    for (_, key) in keys {
+        if let Some(item) = table.remove(&key) {
+            table[&key] = item;
+        }
+    }
+}
+
+/// Look up a member of the workspace containing `manifest_path` by its package name.
+///
+/// Returns the directory of the member, or `None` if there is no such member (or no workspace).
+/// Members are taken from `workspace.members`, where the last path component may be a glob.
+pub fn find_workspace_member(manifest_path: &Path, name: &str) -> Result<Option<PathBuf>> {
+    for member_dir in workspace_member_dirs(manifest_path)? {
+        let member = match Manifest::open(&Some(member_dir.join(MANIFEST_FILENAME))) {
+            Ok(member) => member,
+            Err(_) => continue,
+        };
+        if member.data["package"]["name"].as_str() == Some(name) {
+            return Ok(Some(member_dir));
+        }
+    }
+    Ok(None)
+}
+
+/// Find the version requirements the other members of the workspace containing `manifest_path`
+/// use for the package `name`.
+///
+/// Returns each requirement with the number of members using it, the most common one first.
+/// Requirements used by as many members are ordered like the members in `workspace.members`.
+pub fn workspace_dependency_versions(
+    manifest_path: &Path,
+    name: &str,
+) -> Result<Vec<(String, usize)>> {
+    let own_path = manifest_path.canonicalize().ok();
+    let mut versions: Vec<(String, usize)> = Vec::new();
+    for member_dir in workspace_member_dirs(manifest_path)? {
+        let member_path = member_dir.join(MANIFEST_FILENAME);
+        if member_path.canonicalize().ok() == own_path {
+            continue;
+        }
+        let dependencies = match Manifest::open(&Some(member_path)) {
+            Ok(member) => member.get_dependencies()?,
+            Err(_) => continue,
+        };
+        let mut used = dependencies
+            .iter()
+            .filter(|(_, dep)| dep.name == name)
+            .filter_map(|(_, dep)| dep.version())
+            .collect::<Vec<_>>();
+        used.sort();
+        used.dedup();
+        for version in used {
+            match versions.iter_mut().find(|(known, _)| known == version) {
+                Some((_, count)) => *count += 1,
+                None => versions.push((version.to_owned(), 1)),
+            }
+        }
+    }
+    // The sort is stable, so ties keep the order of the members.
+    versions.sort_by(|(_, a), (_, b)| b.cmp(a));
+    Ok(versions)
+}
+
+/// Find the path of the manifest of the workspace root containing `manifest_path`, which may be
+/// the manifest itself.
+pub fn workspace_root_path(manifest_path: &Path) -> Option<PathBuf> {
+    find_workspace_root(manifest_path).map(|(path, _)| path)
+}
+
+/// Find the manifest of the workspace root containing `manifest_path`, which may be the manifest
+/// itself.
+fn find_workspace_root(manifest_path: &Path) -> Option<(PathBuf, Manifest)> {
+    let manifest_dir = manifest_path
+        .parent()
+        .expect("there must be a parent directory");
+    manifest_dir
+        .ancestors()
+        .map(|dir| dir.join(MANIFEST_FILENAME))
+        .filter(|path| path.is_file())
+        .filter_map(|path| Manifest::open(&Some(path.clone())).ok().map(|m| (path, m)))
+        .find(|(_, manifest)| manifest.data["workspace"].is_table_like())
+}
+
+/// The directories of the members of the workspace containing `manifest_path`.
+///
+/// This is empty if there is no workspace.
+fn workspace_member_dirs(manifest_path: &Path) -> Result<Vec<PathBuf>> {
+    let (root_path, root) = match find_workspace_root(manifest_path) {
+        Some(root) => root,
+        None => return Ok(Vec::new()),
+    };
+    let root_dir = root_path
+        .parent()
+        .expect("there must be a parent directory");
+
+    let patterns = root.data["workspace"]["members"]
+        .as_array()
+        .map(|members| {
+            members
+                .iter()
+                .filter_map(|m| m.as_str().map(String::from))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let mut member_dirs = Vec::new();
+    for pattern in patterns {
+        let (parent, last) = match pattern.rfind('/') {
+            Some(index) => (&pattern[..index], &pattern[index + 1..]),
+            None => ("", pattern.as_str()),
+        };
+        if last.contains('*') || last.contains('?') {
+            let entries = match fs::read_dir(root_dir.join(parent)) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries {
+                let entry = entry?;
+                if glob_match(last, &entry.file_name().to_string_lossy()) {
+                    member_dirs.push(entry.path());
+                }
+            }
+        } else {
+            member_dirs.push(root_dir.join(&pattern));
+        }
+    }
+    Ok(member_dirs)
+}
+
+/// Compute the path of `target` relative to the directory `base`, using `/` as separator.
+pub fn relative_path(base: &Path, target: &Path) -> Result<String> {
+    Ok(relative_components(
+        &base.canonicalize()?,
+        &target.canonicalize()?,
+    ))
+}
+
+/// Express `path`, which is relative to the current directory, relative to the directory `base`
+/// instead, using `/` as separator.
+///
+/// Absolute paths are kept as they are. Paths that don't exist (yet) are resolved lexically.
+pub fn rebase_path(base: &Path, path: &Path) -> Result<String> {
+    if path.is_absolute() {
+        return Ok(path.to_string_lossy().into_owned());
+    }
+    let current_dir = env::current_dir()?;
+    let base = current_dir.join(base);
+    let target = current_dir.join(path);
+    match (base.canonicalize(), target.canonicalize()) {
+        (Ok(base), Ok(target)) => Ok(relative_components(&base, &target)),
+        _ => Ok(relative_components(
+            &normalize_path(&base),
+            &normalize_path(&target),
+        )),
+    }
+}
+
+/// Resolve `.` and `..` components of an absolute path without accessing the file system.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+fn relative_components(base: &Path, target: &Path) -> String {
+    let common = base
+        .components()
+        .zip(target.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut components: Vec<String> = base
+        .components()
+        .skip(common)
+        .map(|_| "..".to_owned())
+        .collect();
+    components.extend(
+        target
+            .components()
+            .skip(common)
+            .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+    );
+
+    if components.is_empty() {
+        ".".to_owned()
+    } else {
+        components.join("/")
     }
 }
 
@@ -63,9 +557,301 @@ fn merge_inline_table(old_dep: &mut toml_edit::Item, new: &toml_edit::Item) {
     }
 }
 
+/// Whether `line` is the header of the table at `table_path`, e.g. `[dev-dependencies]`.
+fn is_table_header(line: &str, table_path: &[String]) -> bool {
+    let line = line.trim();
+    if !line.starts_with('[') || line.starts_with("[[") {
+        return false;
+    }
+    let header = match line.parse::<toml_edit::Document>() {
+        Ok(header) => header,
+        Err(_) => return false,
+    };
+    let mut item = &header.root;
+    for segment in table_path {
+        item = &item[segment.as_str()];
+    }
+    // Headers of subtables, like `[dependencies.foo]`, leave their parents non-empty.
+    item.as_table()
+        .is_some_and(|table| table.iter().next().is_none())
+}
+
+/// How a dependency is declared, see `dependency_entries`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    /// `foo = ...`
+    Key,
+    /// `foo.version = ...`
+    DottedKey,
+    /// `[dependencies.foo]`
+    Table,
+}
+
+/// A declaration of a dependency, found by `dependency_entries`.
+#[derive(Debug)]
+struct DependencyEntry {
+    table: String,
+    name: String,
+    line: usize,
+    kind: EntryKind,
+}
+
+/// The position of the first `needle` in `line` that is not quoted.
+fn find_unquoted(line: &str, needle: char) -> Option<usize> {
+    let mut quote = None;
+    for (index, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == needle => return Some(index),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Split the dotted key `key` into its unquoted segments.
+fn key_segments(key: &str) -> Vec<String> {
+    let mut segments = vec![String::new()];
+    let mut quote = None;
+    for c in key.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '.' => segments.push(String::new()),
+            None if c.is_whitespace() => {}
+            _ => segments.last_mut().expect("there is a segment").push(c),
+        }
+    }
+    segments
+}
+
+/// Whether the table at `path` lists dependencies, e.g. `target.'cfg(unix)'.dependencies`.
+fn is_dependency_table(path: &[String]) -> bool {
+    let lists_dependencies =
+        |name: &str| ["dependencies", "dev-dependencies", "build-dependencies"].contains(&name);
+    match path {
+        [name] => lists_dependencies(name),
+        [workspace, name] => workspace == "workspace" && name == "dependencies",
+        [target, _, name] => target == "target" && lists_dependencies(name),
+        _ => false,
+    }
+}
+
+/// Find the declarations of dependencies in `input`, in the order they appear.
+///
+/// This looks at the lines of the manifest rather than at the parsed document, so that it also
+/// sees the declarations a TOML parser rejects or silently drops.
+fn dependency_entries(input: &str) -> Vec<DependencyEntry> {
+    let mut entries = Vec::new();
+    let mut table: Option<Vec<String>> = None;
+    for (line_number, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            table = None;
+            if line.starts_with("[[") {
+                continue;
+            }
+            let path = match find_unquoted(line, ']') {
+                Some(end) => key_segments(&line[1..end]),
+                None => continue,
+            };
+            if is_dependency_table(&path) {
+                table = Some(path);
+            } else if let Some((name, parent)) = path.split_last() {
+                if is_dependency_table(parent) {
+                    entries.push(DependencyEntry {
+                        table: parent.join("."),
+                        name: name.clone(),
+                        line: line_number,
+                        kind: EntryKind::Table,
+                    });
+                }
+            }
+            continue;
+        }
+
+        let table = match &table {
+            Some(table) if !line.starts_with('#') => table,
+            _ => continue,
+        };
+        if let Some(end) = find_unquoted(line, '=') {
+            let key = key_segments(&line[..end]);
+            entries.push(DependencyEntry {
+                table: table.join("."),
+                name: key[0].clone(),
+                line: line_number,
+                kind: if key.len() > 1 {
+                    EntryKind::DottedKey
+                } else {
+                    EntryKind::Key
+                },
+            });
+        }
+    }
+    entries
+}
+
+/// Find the first key of `input` that is written as a dotted key, like `version.workspace`, and
+/// the same entry written as an inline table.
+///
+/// `toml_edit` can't parse those yet, so they are pointed out when a manifest fails to parse.
+fn find_dotted_key(input: &str) -> Option<(String, String)> {
+    input
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('[') && !line.starts_with('#'))
+        .filter_map(|line| {
+            let end = find_unquoted(line, '=')?;
+            let key = key_segments(&line[..end]);
+            if key.len() < 2 {
+                return None;
+            }
+            let value = &line[end + 1..];
+            let value = find_unquoted(value, '#').map_or(value, |comment| &value[..comment]);
+            let inline = format!(
+                "{} = {{ {} = {} }}",
+                key[0],
+                key[1..].join("."),
+                value.trim()
+            );
+            Some((key.join("."), inline))
+        })
+        .next()
+}
+
+/// Find the dependencies that are declared more than once in the same table of `input`.
+///
+/// Returns the table and the name of each. Setting several fields of a dependency with dotted
+/// keys, like `foo.version` and `foo.features`, doesn't count as declaring it twice.
+pub fn find_duplicate_dependencies(input: &str) -> Vec<(String, String)> {
+    let mut seen = HashMap::new();
+    let mut duplicates = Vec::new();
+    for entry in dependency_entries(input) {
+        let key = (entry.table, entry.name);
+        match seen.get(&key) {
+            None => {
+                seen.insert(key, entry.kind);
+            }
+            Some(EntryKind::DottedKey) if entry.kind == EntryKind::DottedKey => {}
+            Some(_) => {
+                if !duplicates.contains(&key) {
+                    duplicates.push(key);
+                }
+            }
+        }
+    }
+    duplicates
+}
+
+/// Remove all but the last declaration of the dependencies that are declared more than once in
+/// the same table of `input`, like TOML parsers that let the last key win.
+///
+/// Returns the new contents and the table and name of each merged dependency. Only declarations
+/// on a line of their own, like `foo = "1.0"`, are removed; duplicates declared as tables or with
+/// dotted keys are left for the parser to reject.
+pub fn merge_duplicate_dependencies(input: &str) -> (String, Vec<(String, String)>) {
+    let entries = dependency_entries(input);
+    let mut last = HashMap::new();
+    for entry in entries.iter().filter(|entry| entry.kind == EntryKind::Key) {
+        last.insert((&entry.table, &entry.name), entry.line);
+    }
+
+    let mut removed = Vec::new();
+    let mut merged = Vec::new();
+    for entry in entries.iter().filter(|entry| entry.kind == EntryKind::Key) {
+        if last[&(&entry.table, &entry.name)] != entry.line {
+            removed.push(entry.line);
+            let key = (entry.table.clone(), entry.name.clone());
+            if !merged.contains(&key) {
+                merged.push(key);
+            }
+        }
+    }
+    if removed.is_empty() {
+        return (input.to_owned(), merged);
+    }
+
+    let mut output = input
+        .lines()
+        .enumerate()
+        .filter(|(line_number, _)| !removed.contains(line_number))
+        .map(|(_, line)| line)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if input.ends_with('\n') {
+        output.push('\n');
+    }
+    (output, merged)
+}
+
+/// Give `new` the whitespace and comments around `old`, if both are values.
+fn keep_decor(old: &toml_edit::Item, new: toml_edit::Item) -> toml_edit::Item {
+    match (old.as_value(), new) {
+        (Some(old), toml_edit::Item::Value(new)) => toml_edit::Item::Value(toml_edit::decorated(
+            new,
+            old.decor().prefix(),
+            old.decor().suffix(),
+        )),
+        (_, new) => new,
+    }
+}
+
+/// The keys of a dependency entry that `tidy_dependencies` removes because they restate the
+/// default.
+fn redundant_keys(item: &toml_edit::Item) -> Vec<&'static str> {
+    let mut keys = Vec::new();
+    if item["default-features"].as_bool() == Some(true) {
+        keys.push("default-features");
+    }
+    if item["features"]
+        .as_array()
+        .is_some_and(|features| features.iter().next().is_none())
+    {
+        keys.push("features");
+    }
+    keys
+}
+
 fn str_or_1_len_table(item: &toml_edit::Item) -> bool {
     item.is_str() || item.as_table_like().map(|t| t.len() == 1).unwrap_or(false)
 }
+/// Merge a new dependency into an entry that inherits it from `[workspace.dependencies]`
+/// (`workspace = true`).
+///
+/// Only what a member may override is changed, so no version or source is written. Features are
+/// added to the ones already listed, and the dependency is made optional or loses its default
+/// features if `new` asks for it.
+fn merge_workspace_dependency(old_dep: &mut toml_edit::Item, new: &Dependency) {
+    if let Some(features) = new.features() {
+        let mut merged = old_dep["features"]
+            .as_array()
+            .map(|features| {
+                features
+                    .iter()
+                    .filter_map(|feature| feature.as_str().map(String::from))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        for feature in features {
+            if !merged.contains(feature) {
+                merged.push(feature.clone());
+            }
+        }
+        let merged: toml_edit::Value = merged.iter().map(String::as_str).collect();
+        old_dep["features"] = toml_edit::value(merged);
+    }
+    if new.is_optional() {
+        old_dep["optional"] = toml_edit::value(true);
+    }
+    if !new.default_features() {
+        old_dep["default-features"] = toml_edit::value(false);
+    } else if new.to_toml().1["default-features"].as_bool() == Some(true) {
+        old_dep["default-features"] = toml_edit::value(true);
+    }
+}
+
 /// Merge a new dependency into an old entry. See `Dependency::to_toml` for what the format of the
 /// new dependency will be.
 fn merge_dependencies(old_dep: &mut toml_edit::Item, new: &Dependency) {
@@ -73,11 +859,15 @@ fn merge_dependencies(old_dep: &mut toml_edit::Item, new: &Dependency) {
 
     let new_toml = new.to_toml().1;
 
-    if str_or_1_len_table(old_dep) {
-        // The old dependency is just a version/git/path. We are safe to overwrite.
-        *old_dep = new_toml;
+    if old_dep["workspace"].as_bool() == Some(true) {
+        merge_workspace_dependency(old_dep, new);
+    } else if str_or_1_len_table(old_dep) {
+        // The old dependency is just a version/git/path. We are safe to overwrite, but keep
+        // comments after it on the same line.
+        *old_dep = keep_decor(old_dep, new_toml);
     } else if old_dep.is_table_like() {
-        for key in &["version", "path", "git"] {
+        let old = old_dep.clone();
+        for key in &["version", "path", "git", "branch", "tag", "rev"] {
             // remove this key/value pairs
             old_dep[key] = toml_edit::Item::None;
         }
@@ -86,6 +876,15 @@ fn merge_dependencies(old_dep: &mut toml_edit::Item, new: &Dependency) {
         } else {
             merge_inline_table(old_dep, &new_toml);
         }
+        // Keys that are set again stay where they were, with their comments.
+        if let Some(table) = old.as_table() {
+            for (key, _) in table.iter() {
+                if old_dep[key].is_value() {
+                    let value = old_dep[key].clone();
+                    old_dep[key] = keep_decor(&old[key], value);
+                }
+            }
+        }
     } else {
         unreachable!("Invalid old dependency type");
     }
@@ -110,7 +909,7 @@ fn get_version(old_dep: &toml_edit::Item) -> Result<toml_edit::Item> {
     }
 }
 
-fn old_version_compatible(dependency: &Dependency, old_version: &str) -> Result<bool> {
+pub(crate) fn old_version_compatible(dependency: &Dependency, old_version: &str) -> Result<bool> {
     let old_version = VersionReq::parse(old_version).chain_err(|| {
         ErrorKind::ParseVersion(dependency.name.to_string(), old_version.to_string())
     })?;
@@ -120,16 +919,58 @@ fn old_version_compatible(dependency: &Dependency, old_version: &str) -> Result<
         None => return Ok(false),
     };
 
-    let current_version = Version::parse(&current_version).chain_err(|| {
+    let current_version = Version::parse(current_version).chain_err(|| {
         ErrorKind::ParseVersion(dependency.name.to_string(), current_version.into())
     })?;
 
     Ok(old_version.matches(&current_version))
 }
 
+/// Shorten `version` to as many components as the old requirement `old_req` has, e.g. `1.2.9`
+/// becomes `1.2` if the old requirement is `1.1`, and stays `1.2.9` if it is `1.2.3`.
+///
+/// Pre-releases, versions that aren't semver and requirements made of several comparators or
+/// wildcards keep `version` as it is.
+pub(crate) fn with_precision_of(old_req: &str, version: &str) -> String {
+    let old_req = old_req.trim().trim_start_matches(['^', '~', '=']).trim();
+    if old_req.contains([',', '<', '>', '*']) {
+        return version.to_owned();
+    }
+    let new_version = match Version::parse(version) {
+        Ok(new_version) if new_version.pre.is_empty() => new_version,
+        _ => return version.to_owned(),
+    };
+    let precision = old_req
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .count();
+    match precision {
+        1 => new_version.major.to_string(),
+        2 => format!("{}.{}", new_version.major, new_version.minor),
+        _ => version.to_owned(),
+    }
+}
+
+/// Describe the table a dependency is declared in, unless it is the plain `[dependencies]` table.
+fn table_context(table_path: &[String]) -> Option<String> {
+    match table_path {
+        [table] if table == "dependencies" => None,
+        [target, name, table] if target == "target" => Some(format!("target.'{}'.{}", name, table)),
+        _ => Some(table_path.join(".")),
+    }
+}
+
+/// Describe the table at `table_path` for messages, e.g. `target.'cfg(unix)'.dependencies`.
+pub fn table_display(table_path: &[String]) -> String {
+    table_context(table_path).unwrap_or_else(|| table_path.join("."))
+}
+
 /// Print a message if the new dependency version is different from the old one.
 fn print_upgrade_if_necessary(
     crate_name: &str,
+    table_path: &[String],
     old_dep: &toml_edit::Item,
     new_version: &toml_edit::Item,
 ) -> Result<()> {
@@ -139,28 +980,40 @@ fn print_upgrade_if_necessary(
         if old_version == new_version {
             return Ok(());
         }
-        let bufwtr = BufferWriter::stdout(ColorChoice::Always);
-        let mut buffer = bufwtr.buffer();
-        buffer
-            .set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))
-            .chain_err(|| "Failed to set output colour")?;
-        write!(&mut buffer, "    Upgrading ").chain_err(|| "Failed to write upgrade message")?;
-        buffer
-            .set_color(&ColorSpec::new())
-            .chain_err(|| "Failed to clear output colour")?;
-        writeln!(
-            &mut buffer,
-            "{} v{} -> v{}",
-            crate_name, old_version, new_version,
-        )
-        .chain_err(|| "Failed to write upgrade versions")?;
-        bufwtr
-            .print(&buffer)
-            .chain_err(|| "Failed to print upgrade message")?;
+        print_status(
+            "Upgrading",
+            &format!("{} v{} -> v{}", crate_name, old_version, new_version),
+            table_path,
+        )?;
     }
     Ok(())
 }
 
+/// Print a message about a dependency, prefixed with a highlighted `status`.
+fn print_status(status: &str, message: &str, table_path: &[String]) -> Result<()> {
+    if status_messages_hidden() {
+        return Ok(());
+    }
+    let bufwtr = BufferWriter::stdout(ColorChoice::Always);
+    let mut buffer = bufwtr.buffer();
+    buffer
+        .set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))
+        .chain_err(|| "Failed to set output colour")?;
+    write!(&mut buffer, "    {} ", status).chain_err(|| "Failed to write upgrade message")?;
+    buffer
+        .set_color(&ColorSpec::new())
+        .chain_err(|| "Failed to clear output colour")?;
+    write!(&mut buffer, "{}", message).chain_err(|| "Failed to write upgrade versions")?;
+    match table_context(table_path) {
+        Some(context) => writeln!(&mut buffer, " ({})", context),
+        None => writeln!(&mut buffer),
+    }
+    .chain_err(|| "Failed to write upgrade versions")?;
+    bufwtr
+        .print(&buffer)
+        .chain_err(|| "Failed to print upgrade message")
+}
+
 impl Manifest {
     /// Look for a `Cargo.toml` file
     ///
@@ -178,12 +1031,29 @@ impl Manifest {
 
     /// Open the `Cargo.toml` for a path (or the process' `cwd`)
     pub fn open(path: &Option<PathBuf>) -> Result<Manifest> {
+        Manifest::read(path)?
+            .parse()
+            .chain_err(|| "Unable to parse Cargo.toml")
+    }
+
+    /// Open the `Cargo.toml` like `open`, but keep only the last declaration of dependencies
+    /// that are declared more than once in a table, see `merge_duplicate_dependencies`.
+    ///
+    /// Returns the table and name of each merged dependency alongside the manifest.
+    pub fn open_merging_duplicates(
+        path: &Option<PathBuf>,
+    ) -> Result<(Manifest, Vec<(String, String)>)> {
+        let (data, merged) = merge_duplicate_dependencies(&Manifest::read(path)?);
+        let manifest = data.parse().chain_err(|| "Unable to parse Cargo.toml")?;
+        Ok((manifest, merged))
+    }
+
+    fn read(path: &Option<PathBuf>) -> Result<String> {
         let mut file = Manifest::find_file(path)?;
         let mut data = String::new();
         file.read_to_string(&mut data)
             .chain_err(|| "Failed to read manifest contents")?;
-
-        data.parse().chain_err(|| "Unable to parse Cargo.toml")
+        Ok(data)
     }
 
     /// Get the specified table from the manifest.
@@ -193,7 +1063,7 @@ impl Manifest {
             input: &'a mut toml_edit::Item,
             path: &[String],
         ) -> Result<&'a mut toml_edit::Item> {
-            if let Some(segment) = path.get(0) {
+            if let Some(segment) = path.first() {
                 let value = input[&segment].or_insert(toml_edit::table());
 
                 if value.is_table_like() {
@@ -214,7 +1084,14 @@ impl Manifest {
     pub fn get_sections(&self) -> Vec<(Vec<String>, toml_edit::Item)> {
         let mut sections = Vec::new();
 
-        for dependency_type in &["dev-dependencies", "build-dependencies", "dependencies"] {
+        // `dev_dependencies` and `build_dependencies` are old spellings Cargo still accepts.
+        for dependency_type in &[
+            "dev-dependencies",
+            "dev_dependencies",
+            "build-dependencies",
+            "build_dependencies",
+            "dependencies",
+        ] {
             // Dependencies can be in the three standard sections...
             if self.data[dependency_type].is_table_like() {
                 sections.push((
@@ -251,39 +1128,535 @@ impl Manifest {
         sections
     }
 
-    /// Overwrite a file with TOML data.
-    pub fn write_to_file(&self, file: &mut File) -> Result<()> {
-        if self.data["package"].is_none() && self.data["project"].is_none() {
-            if !self.data["workspace"].is_none() {
-                return Err(ErrorKind::UnexpectedRootManifest.into());
-            } else {
-                return Err(ErrorKind::InvalidManifest.into());
+    /// Get all dependencies declared in the manifest, together with the path of the table they
+    /// are declared in.
+    ///
+    /// Dependencies from `target.<target>` tables have their target set.
+    pub fn get_dependencies(&self) -> Result<Vec<(Vec<String>, Dependency)>> {
+        let mut dependencies = Vec::new();
+        for (table_path, table) in self.get_sections() {
+            let table = match table.as_table_like() {
+                Some(table) => table,
+                None => continue,
+            };
+            for (name, item) in table.iter() {
+                let mut dependency = Dependency::from_toml(name, item)?;
+                if table_path[0] == "target" {
+                    dependency = dependency.set_target(&table_path[1]);
+                }
+                dependencies.push((table_path.clone(), dependency));
             }
         }
+        Ok(dependencies)
+    }
 
-        let s = self.data.to_string_in_original_order();
-        let new_contents_bytes = s.as_bytes();
+    /// Get the defaults for editing this manifest, which is read from `manifest_path`, from
+    /// `[package.metadata.cargo-edit]` and from `[workspace.metadata.cargo-edit]` of the
+    /// workspace root.
+    pub fn edit_settings(&self, manifest_path: &Path) -> EditSettings {
+        let workspace = if self.is_workspace_root() {
+            self.data["workspace"]["metadata"]["cargo-edit"].clone()
+        } else {
+            find_workspace_root(manifest_path)
+                .map(|(_, root)| root.data["workspace"]["metadata"]["cargo-edit"].clone())
+                .unwrap_or(toml_edit::Item::None)
+        };
+        EditSettings {
+            package: self.data["package"]["metadata"]["cargo-edit"].clone(),
+            workspace,
+        }
+    }
 
-        // We need to truncate the file, otherwise the new contents
-        // will be mixed up with the old ones.
-        file.set_len(new_contents_bytes.len() as u64)
-            .chain_err(|| "Failed to truncate Cargo.toml")?;
-        file.write_all(new_contents_bytes)
-            .chain_err(|| "Failed to write updated Cargo.toml")
+    /// Whether the table at `table_path` exists.
+    pub fn has_table(&self, table_path: &[String]) -> bool {
+        let mut item = &self.data.root;
+        for segment in table_path {
+            item = &item[segment.as_str()];
+        }
+        item.is_table_like()
     }
 
-    /// Add entry to a Cargo.toml.
-    pub fn insert_into_table(&mut self, table_path: &[String], dep: &Dependency) -> Result<()> {
-        let table = self.get_table(table_path)?;
+    /// Write `comment` as `#` lines right above the header of the table at `table_path`.
+    ///
+    /// The manifest is re-parsed afterwards, as comments in front of table headers can't be edited
+    /// directly. Nothing changes if the table has no header of its own.
+    pub fn add_table_comment(&mut self, table_path: &[String], comment: &str) -> Result<()> {
+        let comment = comment
+            .lines()
+            .map(|line| {
+                if line.starts_with('#') {
+                    line.to_owned()
+                } else {
+                    format!("# {}", line)
+                }
+            })
+            .collect::<Vec<_>>();
+        let contents = self.data.to_string_in_original_order();
+        let mut lines = contents.split('\n').collect::<Vec<_>>();
+        let header = match lines
+            .iter()
+            .position(|line| is_table_header(line, table_path))
+        {
+            Some(header) => header,
+            None => return Ok(()),
+        };
+        for (i, line) in comment.iter().enumerate() {
+            lines.insert(header + i, line.as_str());
+        }
+        self.data = lines
+            .join("\n")
+            .parse()
+            .chain_err(|| "The table comment is not valid TOML")?;
+        Ok(())
+    }
 
-        let existing_dep = Self::find_dep(table, &dep.name);
-        if existing_dep.is_none() {
-            // insert a new entry
-            let (ref name, ref mut new_dependency) = dep.to_toml();
-            table[name] = new_dependency.clone();
+    /// Add `dep:<dep_name>` to the list of `feature` in the `[features]` table, or just
+    /// `<dep_name>` without `dep_syntax`, creating either if needed. Nothing changes if the
+    /// feature already enables the dependency.
+    pub fn enable_in_feature(
+        &mut self,
+        feature: &str,
+        dep_name: &str,
+        dep_syntax: bool,
+    ) -> Result<()> {
+        let entry = if dep_syntax {
+            FeatureValue::Dep(dep_name.into())
+        } else {
+            FeatureValue::Feature(dep_name.into())
+        }
+        .to_string();
+        let features = self.get_table(&["features".to_owned()])?;
+        let enabled = match features[feature].as_array() {
+            Some(enabled) => enabled
+                .iter()
+                .filter_map(|value| value.as_str().map(String::from))
+                .collect::<Vec<_>>(),
+            None if features[feature].is_none() => Vec::new(),
+            None => return Err(ErrorKind::InvalidFeature(feature.into()).into()),
+        };
+        if enabled
+            .iter()
+            .any(|value| FeatureValue::new(value).enables(dep_name))
+        {
+            return Ok(());
+        }
+
+        let enabled: toml_edit::Value = enabled
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(entry.as_str()))
+            .collect();
+        features[feature] = toml_edit::value(enabled);
+        Ok(())
+    }
+
+    /// Drop every entry referring to the dependency `dep_name` from the lists in the `[features]`
+    /// table: `dep_name`, `dep:dep_name`, `dep_name/feature` and `dep_name?/feature`.
+    ///
+    /// Returns the names of the features that changed.
+    pub fn remove_from_features(&mut self, dep_name: &str) -> Vec<String> {
+        let features = match self.data["features"].as_table_mut() {
+            Some(features) => features,
+            None => return Vec::new(),
+        };
+        let names = features
+            .iter()
+            .map(|(name, _)| name.to_owned())
+            .collect::<Vec<_>>();
+
+        let mut changed = Vec::new();
+        for name in names {
+            let enabled = match features[name.as_str()].as_array() {
+                Some(enabled) => enabled
+                    .iter()
+                    .filter_map(|value| value.as_str())
+                    .collect::<Vec<_>>(),
+                None => continue,
+            };
+            let kept = enabled
+                .iter()
+                .cloned()
+                .filter(|value| !FeatureValue::new(value).refers_to(dep_name))
+                .collect::<Vec<_>>();
+            if kept.len() == enabled.len() {
+                continue;
+            }
+
+            let kept: toml_edit::Value = kept.into_iter().collect();
+            features[name.as_str()] = toml_edit::value(kept);
+            changed.push(name);
+        }
+        changed
+    }
+
+    /// Find the dependencies that enable a feature of `dep_name` in their `features` list, like
+    /// `b = { version = "1.0", features = ["a/x"] }` for `a`.
+    ///
+    /// Returns the path of the table, the name of the dependency and the entry, for each entry.
+    pub fn dependency_feature_references(&self, dep_name: &str) -> Vec<(String, String, String)> {
+        let mut references = Vec::new();
+        for (table_path, table) in self.get_sections() {
+            let table = match table.as_table_like() {
+                Some(table) => table,
+                None => continue,
+            };
+            for (name, item) in table.iter() {
+                let features = item
+                    .as_table_like()
+                    .and_then(|dep| dep.get("features"))
+                    .and_then(toml_edit::Item::as_array);
+                for value in features.into_iter().flat_map(|f| f.iter()) {
+                    let value = match value.as_str() {
+                        Some(value) => value,
+                        None => continue,
+                    };
+                    if let FeatureValue::DepFeature { ref dep, .. } = FeatureValue::new(value) {
+                        if dep == dep_name {
+                            references.push((table_path.join("."), name.to_owned(), value.into()));
+                        }
+                    }
+                }
+            }
+        }
+        references
+    }
+
+    /// Remove the keys of dependency entries that only restate the default, which are
+    /// `default-features = true` and an empty `features` list. Inline tables that are left with
+    /// just a version are turned into a plain version requirement.
+    ///
+    /// Returns the path of the table and the name of each tidied entry.
+    pub fn tidy_dependencies(&mut self) -> Result<Vec<(String, String)>> {
+        let mut tidied = Vec::new();
+        for (table_path, table) in self.get_sections() {
+            let table_like = table.as_table_like().expect("Unexpected non-table");
+            let names = table_like
+                .iter()
+                .filter(|(_, item)| !redundant_keys(item).is_empty())
+                .map(|(name, _)| name.to_owned())
+                .collect::<Vec<_>>();
+            for name in names {
+                let item = &mut self.get_table(&table_path)?[&name];
+                for key in redundant_keys(item) {
+                    item[key] = toml_edit::Item::None;
+                }
+                let version = item
+                    .as_inline_table()
+                    .filter(|entry| entry.len() == 1)
+                    .and_then(|entry| entry.get("version"))
+                    .and_then(toml_edit::Value::as_str)
+                    .map(String::from);
+                if let Some(version) = version {
+                    *item = keep_decor(item, toml_edit::value(version));
+                } else if let Some(entry) = item.as_inline_table_mut() {
+                    entry.fmt();
+                }
+                tidied.push((table_path.join("."), name));
+            }
+        }
+        Ok(tidied)
+    }
+
+    /// Turn the dependency entries that are inline tables with nothing but a version into a plain
+    /// version requirement, e.g. `serde = "1.0"` for `serde = { version = "1.0" }`.
+    ///
+    /// Returns the path of the table and the name of each shortened entry.
+    pub fn shorten_dependencies(&mut self) -> Result<Vec<(String, String)>> {
+        let only_version = |item: &toml_edit::Item| {
+            item.as_inline_table()
+                .filter(|entry| entry.len() == 1)
+                .and_then(|entry| entry.get("version"))
+                .and_then(toml_edit::Value::as_str)
+                .map(String::from)
+        };
+        let mut shortened = Vec::new();
+        for (table_path, table) in self.get_sections() {
+            let table_like = table.as_table_like().expect("Unexpected non-table");
+            let names = table_like
+                .iter()
+                .filter(|(_, item)| only_version(item).is_some())
+                .map(|(name, _)| name.to_owned())
+                .collect::<Vec<_>>();
+            for name in names {
+                let item = &mut self.get_table(&table_path)?[&name];
+                if let Some(version) = only_version(item) {
+                    *item = keep_decor(item, toml_edit::value(version));
+                }
+                shortened.push((table_path.join("."), name));
+            }
+        }
+        Ok(shortened)
+    }
+
+    /// Sort the entries of every dependency table like `sort_dependencies`. Comments stay with
+    /// the entry they are attached to.
+    ///
+    /// Returns the path of each table whose order changed.
+    pub fn sort_dependency_tables(&mut self, group_order: &[String]) -> Result<Vec<String>> {
+        let mut sorted = Vec::new();
+        for (table_path, _) in self.get_sections() {
+            let table = match self.get_table(&table_path)?.as_table_mut() {
+                Some(table) => table,
+                None => continue,
+            };
+            let before = table
+                .iter()
+                .map(|(name, _)| name.to_owned())
+                .collect::<Vec<_>>();
+            sort_dependencies(table, group_order);
+            if table
+                .iter()
+                .map(|(name, _)| name)
+                .ne(before.iter().map(String::as_str))
+            {
+                sorted.push(table_path.join("."));
+            }
+        }
+        Ok(sorted)
+    }
+
+    /// Whether this is a virtual manifest, i.e. a workspace root without a `[package]` section.
+    pub fn is_virtual(&self) -> bool {
+        self.data["package"].is_none()
+            && self.data["project"].is_none()
+            && !self.data["workspace"].is_none()
+    }
+
+    /// Whether this is the root manifest of a workspace, i.e. has a `[workspace]` table.
+    pub fn is_workspace_root(&self) -> bool {
+        !self.data["workspace"].is_none()
+    }
+
+    /// Get `package.rust-version`, the minimum supported Rust version, if it is set.
+    pub fn rust_version(&self) -> Option<&str> {
+        self.data["package"]["rust-version"].as_str()
+    }
+
+    /// Get `package.<key>` of the manifest at `manifest_path`, following `<key>.workspace = true`
+    /// to `workspace.package.<key>` of the workspace root if the field is inherited.
+    pub fn package_field(&self, manifest_path: &Path, key: &str) -> toml_edit::Item {
+        let field = &self.data["package"][key];
+        if field["workspace"].as_bool() != Some(true) {
+            field.clone()
+        } else if self.is_workspace_root() {
+            self.data["workspace"]["package"][key].clone()
+        } else {
+            find_workspace_root(manifest_path)
+                .map(|(_, root)| root.data["workspace"]["package"][key].clone())
+                .unwrap_or(toml_edit::Item::None)
+        }
+    }
+
+    /// Get the `package.rust-version` that applies to the manifest at `manifest_path`, which may be
+    /// inherited from the workspace.
+    pub fn resolved_rust_version(&self, manifest_path: &Path) -> Option<String> {
+        self.package_field(manifest_path, "rust-version")
+            .as_str()
+            .map(ToOwned::to_owned)
+    }
+
+    /// Whether features of the manifest at `manifest_path` should enable optional dependencies
+    /// with `dep:<name>`, which is the case from edition 2021 on. Older editions (including the
+    /// default, 2015) use the bare name, i.e. the implicit feature of the dependency.
+    pub fn uses_dep_syntax(&self, manifest_path: &Path) -> bool {
+        self.package_field(manifest_path, "edition")
+            .as_str()
+            .and_then(|edition| edition.parse::<u32>().ok())
+            .is_some_and(|edition| edition >= 2021)
+    }
+
+    /// Whether `package.version` is inherited from the workspace, with
+    /// `version = { workspace = true }`.
+    ///
+    /// The dotted form `version.workspace = true` can't be parsed yet.
+    pub fn is_version_inherited(&self) -> bool {
+        self.data["package"]["version"]["workspace"].as_bool() == Some(true)
+    }
+
+    /// Whether `package.rust-version` is inherited from the workspace
+    /// (`rust-version.workspace = true`).
+    pub fn is_rust_version_inherited(&self) -> bool {
+        self.data["package"]["rust-version"]["workspace"].as_bool() == Some(true)
+    }
+
+    /// Whether the package has a build script, either set with `package.build` or found as
+    /// `build.rs` next to the manifest at `manifest_path`.
+    pub fn has_build_script(&self, manifest_path: &Path) -> bool {
+        let build = &self.data["package"]["build"];
+        if build.as_str().is_some() {
+            return true;
+        }
+        // `build = false` turns off looking for `build.rs`.
+        build.as_bool() != Some(false)
+            && manifest_path
+                .parent()
+                .is_some_and(|dir| dir.join("build.rs").is_file())
+    }
+
+    /// Overwrite a file with TOML data.
+    pub fn write_to_file(&self, file: &mut File) -> Result<()> {
+        self.check_package()?;
+        self.write_contents(file)
+    }
+
+    /// Fail unless this manifest has a `[package]` section.
+    fn check_package(&self) -> Result<()> {
+        if self.data["package"].is_none() && self.data["project"].is_none() {
+            if self.is_virtual() {
+                return Err(ErrorKind::UnexpectedRootManifest.into());
+            } else {
+                return Err(ErrorKind::InvalidManifest.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the edited manifest is still valid TOML by re-parsing its serialized contents,
+    /// which is meant to run once before writing.
+    pub fn verify(&self) -> Result<()> {
+        self.data
+            .to_string_in_original_order()
+            .parse::<toml_edit::Document>()
+            .chain_err(|| "The edited manifest is not valid TOML")?;
+        Ok(())
+    }
+
+    /// Replace the file at `path` with TOML data.
+    ///
+    /// The data is written to a temporary file next to `path` first, which then replaces the
+    /// original, so the manifest is never left partially written.
+    pub fn write_atomically(&self, path: &Path) -> Result<()> {
+        self.check_package()?;
+        self.replace_file(path)
+    }
+
+    /// Replace the file at `path` with TOML data like `write_atomically`, but for workspace roots,
+    /// which may be virtual manifests.
+    pub fn write_workspace_atomically(&self, path: &Path) -> Result<()> {
+        if !self.is_workspace_root() {
+            return Err(ErrorKind::InvalidManifest.into());
+        }
+        self.replace_file(path)
+    }
+
+    /// Write to a temporary file next to `path`, then move it over `path`. Nothing is written if
+    /// the contents would stay the same.
+    fn replace_file(&self, path: &Path) -> Result<()> {
+        let original = fs::read(path).unwrap_or_default();
+        if self.to_string_like(&original).as_bytes() == original.as_slice() {
+            return Ok(());
+        }
+        let tmp_path = tmp_path(path)?;
+        fs::write(&tmp_path, self.to_string_like(&original))
+            .chain_err(|| "Failed to write updated Cargo.toml")?;
+        fs::rename(&tmp_path, path).chain_err(|| "Failed to replace Cargo.toml")
+    }
+
+    /// Serialize the manifest with the line endings and indentation that dominate in `original`,
+    /// the contents of the file it is written to, so that CRLF and tab-indented files keep them.
+    fn to_string_like(&self, original: &[u8]) -> String {
+        let s = follow_tab_indentation(
+            &self.data.to_string_in_original_order(),
+            &String::from_utf8_lossy(original).replace("\r\n", "\n"),
+        );
+        let lf = original.iter().filter(|&&b| b == b'\n').count();
+        let crlf = original.windows(2).filter(|w| *w == b"\r\n").count();
+        if crlf * 2 > lf {
+            s.replace("\r\n", "\n").replace('\n', "\r\n")
         } else {
+            s
+        }
+    }
+
+    /// Whether writing this manifest to `path` would leave the file as it is.
+    pub fn is_unchanged(&self, path: &Path) -> bool {
+        fs::read(path)
+            .map(|original| self.to_string_like(&original).as_bytes() == original.as_slice())
+            .unwrap_or(false)
+    }
+
+    /// Render the changes between the contents of the file at `path` and this manifest as a
+    /// unified diff, which is empty if there are none.
+    pub fn diff(&self, path: &Path) -> Result<String> {
+        let original = fs::read_to_string(path)
+            .chain_err(|| format!("Failed to read `{}`", path.display()))?
+            .replace("\r\n", "\n");
+        Ok(unified_diff(
+            &original,
+            &follow_tab_indentation(&self.data.to_string_in_original_order(), &original),
+            &path.display().to_string(),
+        ))
+    }
+
+    /// Overwrite a file with TOML data, without checking what kind of manifest this is.
+    fn write_contents(&self, file: &mut File) -> Result<()> {
+        let mut original = Vec::new();
+        file.read_to_end(&mut original)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .chain_err(|| "Failed to read Cargo.toml")?;
+        let s = self.to_string_like(&original);
+        let new_contents_bytes = s.as_bytes();
+        if new_contents_bytes == original.as_slice() {
+            return Ok(());
+        }
+
+        // We need to truncate the file, otherwise the new contents
+        // will be mixed up with the old ones.
+        file.set_len(new_contents_bytes.len() as u64)
+            .chain_err(|| "Failed to truncate Cargo.toml")?;
+        file.write_all(new_contents_bytes)
+            .chain_err(|| "Failed to write updated Cargo.toml")
+    }
+
+    /// Add `dep` to the table at `table_path` as `{ workspace = true }`, so that it inherits its
+    /// source and version from `[workspace.dependencies]`.
+    ///
+    /// Only the features of `dep` and whether it is optional are written. An existing entry loses
+    /// its own source and version, but keeps its other keys.
+    pub fn insert_workspace_reference(
+        &mut self,
+        table_path: &[String],
+        dep: &Dependency,
+    ) -> Result<()> {
+        let table = self.get_table(table_path)?;
+        let entry = &mut table[dep.name_in_manifest()];
+        if entry["workspace"].as_bool() != Some(true) {
+            let mut reference = toml_edit::InlineTable::default();
+            reference.get_or_insert("workspace", true);
+            if let Some(old) = entry.as_table_like() {
+                for (key, value) in old.iter() {
+                    let inherited = ["version", "path", "git", "branch", "tag", "rev", "registry"];
+                    if inherited.contains(&key) {
+                        continue;
+                    }
+                    if let Some(value) = value.as_value() {
+                        reference.get_or_insert(key, value.clone());
+                    }
+                }
+            }
+            *entry = keep_decor(entry, toml_edit::value(reference));
+        }
+        merge_workspace_dependency(entry, dep);
+        if let Some(t) = entry.as_inline_table_mut() {
+            t.fmt()
+        }
+        Ok(())
+    }
+
+    /// Whether an entry of the dependency tables with the key `name` inherits from
+    /// `[workspace.dependencies]`.
+    pub fn inherits_dependency(&self, name: &str) -> bool {
+        self.get_sections()
+            .iter()
+            .any(|(_, table)| table[name]["workspace"].as_bool() == Some(true))
+    }
+
+    /// Add entry to a Cargo.toml.
+    pub fn insert_into_table(&mut self, table_path: &[String], dep: &Dependency) -> Result<()> {
+        let table = self.get_table(table_path)?;
+
+        if let Some((mut dep_name, dep_item)) = Self::find_dep(table, &dep.name) {
             // update an existing entry
-            let (mut dep_name, dep_item) = existing_dep.unwrap();
 
             // if the `dep` is renamed in the `add` command,
             // but was present before, then we need to remove
@@ -313,10 +1686,79 @@ impl Manifest {
             if let Some(t) = table.as_inline_table_mut() {
                 t.fmt()
             }
+        } else {
+            // insert a new entry
+            let (ref name, ref mut new_dependency) = dep.to_toml();
+            table[name] = new_dependency.clone();
         }
         Ok(())
     }
 
+    /// Explain why the dependency with the key `name` in the table at `table_path` is pinned, with
+    /// a trailing `# pinned: <reason>` comment on its line. Without a `reason`, such a comment is
+    /// removed. Other comments on the line are kept.
+    ///
+    /// Dependencies with a table of their own get the comment after their `version`.
+    pub fn set_pin_reason(
+        &mut self,
+        table_path: &[String],
+        name: &str,
+        reason: Option<&str>,
+    ) -> Result<()> {
+        let table = self.get_table(table_path)?;
+        let entry = &mut table[name];
+        let entry = if entry.is_table() {
+            &mut entry["version"]
+        } else {
+            entry
+        };
+        let value = entry
+            .as_value()
+            .cloned()
+            .chain_err(|| ErrorKind::NonExistentDependency(name.into(), table_path.join(".")))?;
+
+        let suffix = value.decor().suffix();
+        let kept = suffix[..suffix.find(PIN_COMMENT).unwrap_or(suffix.len())].trim_end();
+        let suffix = match reason {
+            Some(reason) => format!("{} {} {}", kept, PIN_COMMENT, reason),
+            None => kept.to_owned(),
+        };
+        let prefix = value.decor().prefix().to_owned();
+        *entry = toml_edit::Item::Value(toml_edit::decorated(value, &prefix, &suffix));
+        Ok(())
+    }
+
+    /// Move the pin of the git dependency with the key `name` in the table at `table_path` to
+    /// `reference`, keeping the comments around it. Unless quiet, the old and new pin are printed.
+    pub fn set_git_reference(
+        &mut self,
+        table_path: &[String],
+        name: &str,
+        reference: &GitReference,
+    ) -> Result<()> {
+        let table = self.get_table(table_path)?;
+        let old = table[name][reference.key()]
+            .as_str()
+            .map(ToOwned::to_owned)
+            .chain_err(|| ErrorKind::NonExistentDependency(name.into(), table_path.join(".")))?;
+        if old == reference.value() {
+            return Ok(());
+        }
+        let entry = &mut table[name][reference.key()];
+        *entry = keep_decor(entry, toml_edit::value(reference.value()));
+        print_status(
+            "Upgrading",
+            &format!(
+                "{} {} {} -> {}",
+                name,
+                reference.key(),
+                old,
+                reference.value()
+            ),
+            table_path,
+        )
+    }
+
     /// Update an entry in Cargo.toml.
     pub fn update_table_entry(
         &mut self,
@@ -340,7 +1782,9 @@ impl Manifest {
 
         // If (and only if) there is an old entry, merge the new one in.
         if !table[item_name].is_none() {
-            if let Err(e) = print_upgrade_if_necessary(&dep.name, &table[item_name], &new_dep) {
+            if let Err(e) =
+                print_upgrade_if_necessary(&dep.name, table_path, &table[item_name], &new_dep)
+            {
                 eprintln!("Error while displaying upgrade message, {}", e);
             }
             if !dry_run {
@@ -356,6 +1800,13 @@ impl Manifest {
 
     /// Remove entry from a Cargo.toml.
     ///
+    /// If there is no entry called `name`, a renamed dependency whose `package` is `name` is
+    /// removed instead. Returns the key of the removed entry.
+    ///
+    /// The comment lines above the entry are stashed with its key, so adding the dependency back
+    /// to this manifest, e.g. to change its source, puts them back above the new entry. They are
+    /// dropped when the manifest is written without it, or when the table is left empty.
+    ///
     /// # Examples
     ///
     /// ```
@@ -365,29 +1816,119 @@ impl Manifest {
     ///   let mut manifest = Manifest { data: toml_edit::Document::new() };
     ///   let dep = Dependency::new("cargo-edit").set_version("0.1.0");
     ///   let _ = manifest.insert_into_table(&vec!["dependencies".to_owned()], &dep);
-    ///   assert!(manifest.remove_from_table("dependencies", &dep.name).is_ok());
-    ///   assert!(manifest.remove_from_table("dependencies", &dep.name).is_err());
+    ///   let table = vec!["dependencies".to_owned()];
+    ///   assert!(manifest.remove_from_table(&table, &dep.name).is_ok());
+    ///   assert!(manifest.remove_from_table(&table, &dep.name).is_err());
     ///   assert!(manifest.data["dependencies"].is_none());
     /// ```
-    pub fn remove_from_table(&mut self, table: &str, name: &str) -> Result<()> {
-        if !self.data[table].is_table_like() {
-            return Err(ErrorKind::NonExistentTable(table.into()).into());
-        } else {
-            {
-                let dep = &mut self.data[table][name];
-                if dep.is_none() {
-                    return Err(ErrorKind::NonExistentDependency(name.into(), table.into()).into());
+    pub fn remove_from_table(&mut self, table_path: &[String], name: &str) -> Result<String> {
+        let table_name = table_display(table_path);
+        let table = self.table_at(table_path);
+        if !table.is_table_like() {
+            return Err(ErrorKind::NonExistentTable(table_name).into());
+        }
+
+        let key = if table[name].is_none() {
+            let renamed = table
+                .as_table_like()
+                .unwrap()
+                .iter()
+                .filter(|&(_, item)| package_name(item) == Some(name))
+                .map(|(key, _)| key.to_owned())
+                .collect::<Vec<_>>();
+            match renamed.len() {
+                0 => return Err(ErrorKind::NonExistentDependency(name.into(), table_name).into()),
+                1 => renamed.into_iter().next().unwrap(),
+                _ => {
+                    return Err(ErrorKind::AmbiguousDependency(
+                        name.into(),
+                        table_name,
+                        renamed.join("`, `"),
+                    )
+                    .into())
                 }
-                // remove the dependency
-                *dep = toml_edit::Item::None;
             }
+        } else {
+            name.to_owned()
+        };
 
-            // remove table if empty
-            if self.data[table].as_table_like().unwrap().is_empty() {
-                self.data[table] = toml_edit::Item::None;
+        // remove the dependency
+        self.table_at_mut(table_path)[key.as_str()] = toml_edit::Item::None;
+
+        // remove the tables left empty, e.g. `[target.'cfg(unix)']` along with its only table
+        for len in (1..=table_path.len()).rev() {
+            let is_empty = self
+                .table_at(&table_path[..len])
+                .as_table_like()
+                .is_some_and(toml_edit::TableLike::is_empty);
+            if !is_empty {
+                break;
             }
+            *self.table_at_mut(&table_path[..len]) = toml_edit::Item::None;
         }
-        Ok(())
+        Ok(key)
+    }
+
+    /// Get the item at `table_path`, which is `Item::None` if there is no such table.
+    pub fn table_at(&self, table_path: &[String]) -> &toml_edit::Item {
+        table_path
+            .iter()
+            .fold(&self.data.root, |item, segment| &item[segment.as_str()])
+    }
+
+    /// Get the item at `table_path` to modify it. The tables along the way must exist.
+    fn table_at_mut(&mut self, table_path: &[String]) -> &mut toml_edit::Item {
+        table_path
+            .iter()
+            .fold(&mut self.data.root, |item, segment| {
+                &mut item[segment.as_str()]
+            })
+    }
+
+    /// Get the keys of the dependencies in the table at `table_path` that match the glob
+    /// `pattern`, in the order they appear.
+    pub fn matching_dependencies(&self, table_path: &[String], pattern: &str) -> Vec<String> {
+        self.table_at(table_path)
+            .as_table_like()
+            .map(|table| {
+                table
+                    .iter()
+                    .map(|(key, _)| key)
+                    .filter(|key| glob_match(pattern, key))
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Get the keys of the optional dependencies in the table at `table_path` that no feature
+    /// refers to, in the order they appear. Cargo still gives them an implicit feature, but
+    /// nothing in the manifest enables them.
+    pub fn unreferenced_optional_dependencies(&self, table_path: &[String]) -> Vec<String> {
+        let values = self.data["features"]
+            .as_table_like()
+            .map(|features| {
+                features
+                    .iter()
+                    .filter_map(|(_, item)| item.as_array())
+                    .flat_map(|list| list.iter())
+                    .filter_map(|value| value.as_str())
+                    .map(FeatureValue::new)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        self.table_at(table_path)
+            .as_table_like()
+            .map(|table| {
+                table
+                    .iter()
+                    .filter(|(_, item)| item["optional"].as_bool() == Some(true))
+                    .map(|(key, _)| key)
+                    .filter(|key| !values.iter().any(|value| value.refers_to(key)))
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
     }
 
     /// Add multiple dependencies to manifest
@@ -409,6 +1950,8 @@ impl Manifest {
             .unwrap()
             .iter()
             .find(|&item| match item {
+                // Entries removed before are kept around, see `remove_from_table`.
+                (_, toml_edit::Item::None) => false,
                 (name, _) if name == dep_name => true,
                 (_alias, toml_edit::Item::Table(table_dep))
                     if table_dep.contains_key("package") =>
@@ -422,7 +1965,18 @@ impl Manifest {
                 }
                 _ => false,
             })
-            .and_then(|dep| Some((dep.0.into(), dep.1)))
+            .map(|dep| (dep.0.into(), dep.1))
+    }
+}
+
+/// The `package` of a renamed dependency entry.
+fn package_name(item: &toml_edit::Item) -> Option<&str> {
+    match item {
+        toml_edit::Item::Table(table_dep) => table_dep.get("package").and_then(|p| p.as_str()),
+        toml_edit::Item::Value(toml_edit::Value::InlineTable(inline_dep)) => {
+            inline_dep.get("package").and_then(|p| p.as_str())
+        }
+        _ => None,
     }
 }
 
@@ -431,7 +1985,19 @@ impl str::FromStr for Manifest {
 
     /// Read manifest data from string
     fn from_str(input: &str) -> ::std::result::Result<Self, Self::Err> {
-        let d: toml_edit::Document = input.parse().chain_err(|| "Manifest not valid TOML")?;
+        // Edits are made with LF line endings, CRLF ones are restored when writing.
+        let input = input.replace("\r\n", "\n");
+        if let Some((table, name)) = find_duplicate_dependencies(&input).into_iter().next() {
+            return Err(ErrorKind::DuplicateDependency(name, table).into());
+        }
+        let d: toml_edit::Document = input.parse().chain_err(|| match find_dotted_key(&input) {
+            Some((key, inline)) => format!(
+                "The manifest sets `{}` with a dotted key, which cargo-edit can't parse yet; \
+                 write it as an inline table instead, e.g. `{}`",
+                key, inline
+            ),
+            None => "Manifest not valid TOML".to_string(),
+        })?;
 
         Ok(Manifest { data: d })
     }
@@ -454,6 +2020,140 @@ impl Deref for LocalManifest {
     }
 }
 
+/// Indent the lines of `rendered` that are not in `original` with tabs if `original` is mostly
+/// indented with tabs.
+///
+/// Leading spaces of such lines become tabs, and entries without indentation are indented like the
+/// surrounding entries, so that edits don't mix indentation styles.
+fn follow_tab_indentation(rendered: &str, original: &str) -> String {
+    let tabs = original.lines().filter(|l| l.starts_with('\t')).count();
+    let spaces = original.lines().filter(|l| l.starts_with(' ')).count();
+    if tabs <= spaces {
+        return rendered.to_owned();
+    }
+
+    let existing: std::collections::HashSet<&str> = original.lines().collect();
+    let mut lines: Vec<String> = rendered.split('\n').map(str::to_owned).collect();
+    for i in 0..lines.len() {
+        let line = &lines[i];
+        if existing.contains(line.as_str()) || line.trim().is_empty() {
+            continue;
+        }
+        let content = line.trim_start_matches(' ');
+        let leading_spaces = line.len() - content.len();
+        let depth = if leading_spaces > 0 {
+            leading_spaces.div_ceil(4)
+        } else {
+            let next_to_indented =
+                |other: Option<&String>| other.is_some_and(|other| other.starts_with('\t'));
+            let is_entry = !content.starts_with(['[', ']', '\t']);
+            let neighbours_indented =
+                (i > 0 && next_to_indented(lines.get(i - 1))) || next_to_indented(lines.get(i + 1));
+            if is_entry && neighbours_indented {
+                1
+            } else {
+                0
+            }
+        };
+        let indented = format!("{}{}", "\t".repeat(depth), content);
+        lines[i] = indented;
+    }
+    lines.join("\n")
+}
+
+/// The temporary file next to `path` that new contents are written to before replacing it.
+pub(crate) fn tmp_path(path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .chain_err(|| format!("`{}` has no file name", path.display()))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(".cargo-edit-tmp");
+    Ok(path.with_file_name(tmp_name))
+}
+
+/// Drop the entries in `kept`, given by their table and key, from copies of dependency tables.
+fn without_entries(
+    mut sections: Vec<(Vec<String>, toml_edit::Item)>,
+    kept: &[(Vec<String>, String)],
+) -> Vec<(Vec<String>, toml_edit::Item)> {
+    for (table_path, table) in &mut sections {
+        for (_, key) in kept.iter().filter(|(path, _)| path == table_path) {
+            match table {
+                toml_edit::Item::Table(table) => {
+                    table.remove(key);
+                }
+                toml_edit::Item::Value(toml_edit::Value::InlineTable(table)) => {
+                    table.remove(key);
+                }
+                _ => {}
+            }
+        }
+    }
+    sections
+}
+
+/// Write several manifests with all-or-nothing semantics.
+///
+/// All new contents are written to temporary files first, and only once that succeeded for every
+/// manifest are the originals replaced. If replacing one of them fails, the ones replaced before
+/// are restored, so either all manifests are updated or none is. Manifests whose contents would
+/// stay the same are not written.
+pub fn write_manifests_atomically(manifests: &[LocalManifest]) -> Result<()> {
+    let manifests = manifests
+        .iter()
+        .filter(|manifest| !manifest.is_unchanged(&manifest.path))
+        .collect::<Vec<_>>();
+
+    let remove_staged = |staged: &[(PathBuf, Vec<u8>)]| {
+        for (tmp_path, _) in staged {
+            let _ = fs::remove_file(tmp_path);
+        }
+    };
+
+    let mut staged = Vec::new();
+    for manifest in &manifests {
+        // Workspace roots may be virtual manifests, which have no `[package]` to check.
+        let checked = if manifest.is_workspace_root() {
+            Ok(())
+        } else {
+            manifest.check_package()
+        };
+        let staging = checked.and_then(|_| {
+            let original = fs::read(&manifest.path)
+                .chain_err(|| format!("Failed to read `{}`", manifest.path.display()))?;
+            let tmp_path = tmp_path(&manifest.path)?;
+            fs::write(&tmp_path, manifest.to_string_like(&original))
+                .chain_err(|| format!("Failed to write `{}`", tmp_path.display()))?;
+            Ok((tmp_path, original))
+        });
+        match staging {
+            Ok(entry) => staged.push(entry),
+            Err(e) => {
+                remove_staged(&staged);
+                return Err(e);
+            }
+        }
+    }
+
+    for (index, (manifest, (tmp_path, _))) in manifests.iter().zip(&staged).enumerate() {
+        if let Err(e) = fs::rename(tmp_path, &manifest.path) {
+            for (manifest, (_, original)) in manifests.iter().zip(&staged).take(index) {
+                let _ = fs::write(&manifest.path, original);
+            }
+            remove_staged(&staged[index..]);
+            return Err(Error::with_chain(
+                e,
+                format!(
+                    "Failed to replace `{}`, no manifest was changed",
+                    manifest.path.display()
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
 impl LocalManifest {
     /// Construct a `LocalManifest`. If no path is provided, make an educated guess as to which one
     /// the user means.
@@ -476,22 +2176,328 @@ impl LocalManifest {
         Manifest::find_file(&Some(self.path.clone()))
     }
 
-    /// Instruct this manifest to upgrade a single dependency. If this manifest does not have that
-    /// dependency, it does nothing.
-    pub fn upgrade(
+    /// Write the manifest back to its file. Unlike `write_to_file`, this accepts virtual
+    /// manifests, e.g. to update `[workspace.package]`.
+    pub fn write(&self) -> Result<()> {
+        let mut file = self.get_file()?;
+        self.manifest
+            .write_contents(&mut file)
+            .chain_err(|| "Failed to write new manifest contents")
+    }
+
+    /// Set `package.version`, replacing any previous value.
+    pub fn set_package_version(&mut self, version: &str) {
+        self.manifest.data["package"]["version"] = toml_edit::value(version);
+    }
+
+    /// Set `workspace.package.version`, the version shared by members that inherit it.
+    pub fn set_workspace_package_version(&mut self, version: &str) -> Result<()> {
+        let table = self
+            .manifest
+            .get_table(&["workspace".to_owned(), "package".to_owned()])?;
+        table["version"] = toml_edit::value(version);
+        Ok(())
+    }
+
+    /// Set `package.rust-version`, replacing any previous value.
+    pub fn set_package_rust_version(&mut self, version: &str) {
+        self.manifest.data["package"]["rust-version"] = toml_edit::value(version);
+    }
+
+    /// Set `workspace.package.rust-version`, the minimum supported Rust version shared by members
+    /// that inherit it.
+    pub fn set_workspace_package_rust_version(&mut self, version: &str) -> Result<()> {
+        let table = self
+            .manifest
+            .get_table(&["workspace".to_owned(), "package".to_owned()])?;
+        table["rust-version"] = toml_edit::value(version);
+        Ok(())
+    }
+
+    /// Set the version requirement of every path dependency on `dependency.name` to the version of
+    /// `dependency`, keeping the path for local development. Returns whether any path dependency
+    /// was found.
+    pub fn set_path_dependency_version(
         &mut self,
         dependency: &Dependency,
         dry_run: bool,
-        skip_compatible: bool,
-    ) -> Result<()> {
+    ) -> Result<bool> {
+        let version = match dependency.version() {
+            Some(version) => version,
+            None => return Ok(false),
+        };
+        let entries = self.path_dependency_entries(&dependency.name);
+        let found = !entries.is_empty();
+        let changes = entries
+            .into_iter()
+            .map(|(table_path, key, old_version)| {
+                (table_path, key, old_version, version.to_owned())
+            })
+            .collect();
+        self.set_dependency_versions(&dependency.name, changes, dry_run)?;
+        Ok(found)
+    }
+
+    /// Update the version requirement of every path dependency on `name` that has one to require
+    /// `version`, keeping its operator and precision, e.g. `=0.1.0` becomes `=0.2.0` and `0.1`
+    /// becomes `0.2`. Returns whether any requirement was changed.
+    pub fn sync_path_dependency_requirement(
+        &mut self,
+        name: &str,
+        version: &str,
+        dry_run: bool,
+    ) -> Result<bool> {
+        let changes = self
+            .path_dependency_entries(name)
+            .into_iter()
+            .filter_map(|(table_path, key, old_req)| {
+                let old_req = old_req?;
+                let operator = old_req
+                    .trim()
+                    .chars()
+                    .take_while(|c| *c == '^' || *c == '~' || *c == '=')
+                    .collect::<String>();
+                let new_req = format!("{}{}", operator, with_precision_of(&old_req, version));
+                Some((table_path, key, Some(old_req), new_req))
+            })
+            .collect::<Vec<_>>();
+        self.set_dependency_versions(name, changes, dry_run)
+    }
+
+    /// The table path, key and version requirement of every path dependency on the crate `name`
+    fn path_dependency_entries(&self, name: &str) -> Vec<(Vec<String>, String, Option<String>)> {
+        let mut entries = Vec::new();
         for (table_path, table) in self.get_sections() {
+            let table_like = table.as_table_like().expect("Unexpected non-table");
+            for (key, toml_item) in table_like.iter() {
+                let entry = match toml_item.as_table_like() {
+                    Some(entry) if entry.get("path").is_some() => entry,
+                    _ => continue,
+                };
+                let dep_name = entry.get("package").and_then(|p| p.as_str()).unwrap_or(key);
+                if dep_name != name {
+                    continue;
+                }
+                let version = entry.get("version").and_then(|v| v.as_str());
+                entries.push((
+                    table_path.clone(),
+                    key.to_owned(),
+                    version.map(str::to_owned),
+                ));
+            }
+        }
+        entries
+    }
+
+    /// Apply `changes` of the form (table path, key, old requirement, new requirement) to the
+    /// dependencies on `name`, printing each of them, and write the manifest unless `dry_run`.
+    /// Returns whether anything changed.
+    fn set_dependency_versions(
+        &mut self,
+        name: &str,
+        changes: Vec<(Vec<String>, String, Option<String>, String)>,
+        dry_run: bool,
+    ) -> Result<bool> {
+        let mut changed = false;
+        for (table_path, key, old_version, version) in changes {
+            let message = match old_version {
+                Some(old_version) if old_version == version => continue,
+                Some(old_version) => format!("{} v{} -> v{}", name, old_version, version),
+                None => format!("{} v{}", name, version),
+            };
+            print_status("Versioning", &message, &table_path)?;
+            changed = true;
+
+            if !dry_run {
+                let item = &mut self.manifest.get_table(&table_path)?[key.as_str()];
+                item["version"] = toml_edit::value(version);
+                if let Some(t) = item.as_inline_table_mut() {
+                    t.fmt()
+                }
+            }
+        }
+
+        if changed && !dry_run {
+            let mut file = self.get_file()?;
+            self.write_to_file(&mut file)
+                .chain_err(|| "Failed to write new manifest contents")?;
+        }
+        Ok(changed)
+    }
+
+    /// Instruct this manifest to upgrade a single dependency. If this manifest does not have that
+    /// dependency, it does nothing.
+    pub fn upgrade(
+        &mut self,
+        dependency: &Dependency,
+        dry_run: bool,
+        skip_compatible: bool,
+    ) -> Result<()> {
+        self.apply_upgrade(dependency, dry_run, skip_compatible, false, None)?;
+
+        let mut file = self.get_file()?;
+        self.write_to_file(&mut file)
+            .chain_err(|| "Failed to write new manifest contents")
+    }
+
+    /// Remove redundant keys from dependency entries in memory, see
+    /// `Manifest::tidy_dependencies`.
+    pub fn tidy_dependencies(&mut self) -> Result<Vec<(String, String)>> {
+        self.manifest.tidy_dependencies()
+    }
+
+    /// Turn inline tables with just a version into plain requirements in memory, see
+    /// `Manifest::shorten_dependencies`.
+    pub fn shorten_dependencies(&mut self) -> Result<Vec<(String, String)>> {
+        self.manifest.shorten_dependencies()
+    }
+
+    /// Sort the dependency tables in memory, see `Manifest::sort_dependency_tables`.
+    pub fn sort_dependency_tables(&mut self, group_order: &[String]) -> Result<Vec<String>> {
+        self.manifest.sort_dependency_tables(group_order)
+    }
+
+    /// Move the pin of a git dependency in memory, see `Manifest::set_git_reference`.
+    pub fn set_git_reference(
+        &mut self,
+        table_path: &[String],
+        name: &str,
+        reference: &GitReference,
+    ) -> Result<()> {
+        self.manifest.set_git_reference(table_path, name, reference)
+    }
+
+    /// Add `dep` to the table at `table_path` in memory, see `Manifest::insert_into_table`.
+    pub fn insert_into_table(&mut self, table_path: &[String], dep: &Dependency) -> Result<()> {
+        self.manifest.insert_into_table(table_path, dep)
+    }
+
+    /// Make `dep` in the table at `table_path` inherit from `[workspace.dependencies]` in memory,
+    /// see `Manifest::insert_workspace_reference`.
+    pub fn insert_workspace_reference(
+        &mut self,
+        table_path: &[String],
+        dep: &Dependency,
+    ) -> Result<()> {
+        self.manifest.insert_workspace_reference(table_path, dep)
+    }
+
+    /// Upgrade the entry `item_name` in the table at `table_path` to `dependency` in memory,
+    /// unlike `apply_upgrade`, which upgrades the crate in every table.
+    pub fn apply_upgrade_to_entry(
+        &mut self,
+        table_path: &[String],
+        item_name: &str,
+        dependency: &Dependency,
+    ) -> Result<()> {
+        self.manifest
+            .update_table_named_entry(table_path, item_name, dependency, false)
+    }
+
+    /// Make the changes of `upgrade` in memory only, without writing the manifest.
+    ///
+    /// With `preserve_precision`, the new requirement has as many components as the old one of
+    /// each entry, e.g. `1.2` is upgraded to `1.3` instead of `1.3.0`.
+    /// With `req_style`, the new requirement is then written in that style, e.g. `~1.3`.
+    ///
+    /// Entries with `workspace = true` are left alone, as their requirement is upgraded in the
+    /// `[workspace.dependencies]` of the workspace root, which this includes.
+    pub fn apply_upgrade(
+        &mut self,
+        dependency: &Dependency,
+        dry_run: bool,
+        skip_compatible: bool,
+        preserve_precision: bool,
+        req_style: Option<RequirementStyle>,
+    ) -> Result<()> {
+        let mut sections = self.get_sections();
+        sections.extend(self.workspace_dependencies_section());
+        self.apply_upgrade_in(
+            sections,
+            dependency,
+            dry_run,
+            skip_compatible,
+            preserve_precision,
+            req_style,
+        )
+    }
+
+    /// Make the changes of `apply_upgrade`, except to the entries in `kept`, given by their table
+    /// and key, e.g. because their requirement is pinned.
+    pub fn apply_upgrade_except(
+        &mut self,
+        dependency: &Dependency,
+        kept: &[(Vec<String>, String)],
+        skip_compatible: bool,
+        preserve_precision: bool,
+        req_style: Option<RequirementStyle>,
+    ) -> Result<()> {
+        let mut sections = self.get_sections();
+        sections.extend(self.workspace_dependencies_section());
+        self.apply_upgrade_in(
+            without_entries(sections, kept),
+            dependency,
+            false,
+            skip_compatible,
+            preserve_precision,
+            req_style,
+        )
+    }
+
+    /// Make the changes of `apply_upgrade_except` to `[workspace.dependencies]` only, e.g. for a
+    /// root whose own dependencies are not to be upgraded.
+    pub fn apply_workspace_upgrade(
+        &mut self,
+        dependency: &Dependency,
+        kept: &[(Vec<String>, String)],
+        skip_compatible: bool,
+        preserve_precision: bool,
+        req_style: Option<RequirementStyle>,
+    ) -> Result<()> {
+        let sections = self.workspace_dependencies_section().into_iter().collect();
+        self.apply_upgrade_in(
+            without_entries(sections, kept),
+            dependency,
+            false,
+            skip_compatible,
+            preserve_precision,
+            req_style,
+        )
+    }
+
+    /// The path and contents of `[workspace.dependencies]`, if there is such a table
+    pub fn workspace_dependencies_section(&self) -> Option<(Vec<String>, toml_edit::Item)> {
+        let table = &self.data["workspace"]["dependencies"];
+        if table.is_table_like() {
+            let table_path = vec!["workspace".to_owned(), "dependencies".to_owned()];
+            Some((table_path, table.clone()))
+        } else {
+            None
+        }
+    }
+
+    fn apply_upgrade_in(
+        &mut self,
+        sections: Vec<(Vec<String>, toml_edit::Item)>,
+        dependency: &Dependency,
+        dry_run: bool,
+        skip_compatible: bool,
+        preserve_precision: bool,
+        req_style: Option<RequirementStyle>,
+    ) -> Result<()> {
+        for (table_path, table) in sections {
             let table_like = table.as_table_like().expect("Unexpected non-table");
             for (name, toml_item) in table_like.iter() {
                 let dep_name = toml_item
                     .as_table_like()
                     .and_then(|t| t.get("package").and_then(|p| p.as_str()))
                     .unwrap_or(name);
-                if dep_name == dependency.name {
+                // A renamed dependency only upgrades the entry of its alias, as other aliases of
+                // the same package may have other requirements.
+                let is_alias = dependency.rename().is_none_or(|alias| alias == name);
+                // Inherited entries are upgraded in `[workspace.dependencies]` instead.
+                let inherited = toml_item["workspace"].as_bool() == Some(true);
+                if dep_name == dependency.name && is_alias && !inherited {
                     if skip_compatible {
                         if let Some(old_version) = get_version(toml_item)?.as_str() {
                             if old_version_compatible(dependency, old_version)? {
@@ -499,19 +2505,29 @@ impl LocalManifest {
                             }
                         }
                     }
+                    let mut dependency = dependency.clone();
+                    if preserve_precision {
+                        let old_version = get_version(toml_item).ok();
+                        let old_version = old_version.as_ref().and_then(|v| v.as_str());
+                        if let (Some(old), Some(new)) = (old_version, dependency.version()) {
+                            let new = with_precision_of(old, new);
+                            dependency = dependency.set_version(&new);
+                        }
+                    }
+                    if let (Some(style), Some(new)) = (req_style, dependency.version()) {
+                        let new = style.requirement(new);
+                        dependency = dependency.set_version(&new);
+                    }
                     self.manifest.update_table_named_entry(
                         &table_path,
-                        &name,
-                        dependency,
+                        name,
+                        &dependency,
                         dry_run,
                     )?;
                 }
             }
         }
-
-        let mut file = self.get_file()?;
-        self.write_to_file(&mut file)
-            .chain_err(|| "Failed to write new manifest contents")
+        Ok(())
     }
 }
 
@@ -519,7 +2535,747 @@ impl LocalManifest {
 mod tests {
     use super::*;
     use crate::dependency::Dependency;
-    use toml_edit;
+
+    #[test]
+    fn resolve_dependency_table_paths() {
+        let kind = |s: &str| s.parse::<DependencyKind>().unwrap();
+        assert_eq!(
+            dependency_table_path(kind("normal"), None),
+            vec!["dependencies"]
+        );
+        assert_eq!(
+            dependency_table_path(kind("dev"), None),
+            vec!["dev-dependencies"]
+        );
+        assert_eq!(
+            dependency_table_path(kind("build-dependencies"), None),
+            vec!["build-dependencies"]
+        );
+        assert_eq!(
+            dependency_table_path(DependencyKind::Normal, Some("cfg(unix)")),
+            vec!["target", "cfg(unix)", "dependencies"]
+        );
+        assert_eq!(
+            dependency_table_path(DependencyKind::Development, Some("x86_64-pc-windows-gnu")),
+            vec!["target", "x86_64-pc-windows-gnu", "dev-dependencies"]
+        );
+        assert!("features".parse::<DependencyKind>().is_err());
+    }
+
+    #[test]
+    fn write_manifests_atomically_rolls_back() {
+        let tmpdir = tempdir::TempDir::new("cargo-edit-manifests").unwrap();
+        let paths = ["one", "two", "three"]
+            .iter()
+            .map(|member| {
+                let path = tmpdir.path().join(member).join("Cargo.toml");
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(
+                    &path,
+                    format!(
+                        "[package]\nname = \"{}\"\n\n[dependencies]\nfoo = \"0.1\"\n",
+                        member
+                    ),
+                )
+                .unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+        let originals = paths
+            .iter()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut manifests = paths
+            .iter()
+            .map(|path| LocalManifest::try_new(path).unwrap())
+            .collect::<Vec<_>>();
+        for manifest in &mut manifests {
+            manifest
+                .apply_upgrade(
+                    &Dependency::new("foo").set_version("0.2"),
+                    false,
+                    false,
+                    false,
+                    None,
+                )
+                .unwrap();
+        }
+
+        // Replacing the last manifest fails, as a directory is in its way.
+        fs::remove_file(&paths[2]).unwrap();
+        fs::create_dir_all(paths[2].join("blocker")).unwrap();
+        assert!(write_manifests_atomically(&manifests).is_err());
+        for (path, original) in paths.iter().zip(&originals).take(2) {
+            assert_eq!(&fs::read_to_string(path).unwrap(), original);
+        }
+        assert!(!tmp_path(&paths[0]).unwrap().exists());
+        assert!(!tmp_path(&paths[2]).unwrap().exists());
+
+        fs::remove_dir_all(&paths[2]).unwrap();
+        fs::write(&paths[2], &originals[2]).unwrap();
+        write_manifests_atomically(&manifests).unwrap();
+        for path in &paths {
+            assert!(fs::read_to_string(path).unwrap().contains("foo = \"0.2\""));
+        }
+    }
+
+    #[test]
+    fn upgrade_preserving_precision() {
+        let tmpdir = tempdir::TempDir::new("cargo-edit-precision").unwrap();
+        let path = tmpdir.path().join("Cargo.toml");
+        fs::write(
+            &path,
+            "[package]\nname = \"foo\"\n\n[dependencies]\nfoo = \"1.2.3\"\n\n\
+             [dev-dependencies]\nfoo = { version = \"~1.1\", features = [\"x\"] }\n\n\
+             [build-dependencies]\nfoo = \">=1.0, <2\"\n",
+        )
+        .unwrap();
+        let mut manifest = LocalManifest::try_new(&path).unwrap();
+        let dependency = Dependency::new("foo").set_version("1.2.9");
+        manifest
+            .apply_upgrade(&dependency, false, false, true, None)
+            .unwrap();
+
+        assert_eq!(manifest.data["dependencies"]["foo"].as_str(), Some("1.2.9"));
+        assert_eq!(
+            manifest.data["dev-dependencies"]["foo"]["version"].as_str(),
+            Some("1.2")
+        );
+        assert_eq!(
+            manifest.data["build-dependencies"]["foo"].as_str(),
+            Some("1.2.9")
+        );
+
+        assert_eq!(with_precision_of("1", "2.0.1"), "2");
+        assert_eq!(with_precision_of("^0.3", "0.4.2"), "0.4");
+        assert_eq!(with_precision_of("1.2", "1.3.0-rc.1"), "1.3.0-rc.1");
+        assert_eq!(with_precision_of("1.2.*", "1.3.0"), "1.3.0");
+    }
+
+    #[test]
+    fn upgrade_in_requirement_style() {
+        let tmpdir = tempdir::TempDir::new("cargo-edit-req-style").unwrap();
+        let path = tmpdir.path().join("Cargo.toml");
+        fs::write(
+            &path,
+            "[package]\nname = \"foo\"\n\n[dependencies]\nfoo = \"1.2.3\"\n\n\
+             [dev-dependencies]\nfoo = \"1.1\"\n",
+        )
+        .unwrap();
+        let mut manifest = LocalManifest::try_new(&path).unwrap();
+        let dependency = Dependency::new("foo").set_version("1.3.1");
+        manifest
+            .apply_upgrade(
+                &dependency,
+                false,
+                false,
+                true,
+                Some(RequirementStyle::Tilde),
+            )
+            .unwrap();
+
+        assert_eq!(
+            manifest.data["dependencies"]["foo"].as_str(),
+            Some("~1.3.1")
+        );
+        assert_eq!(
+            manifest.data["dev-dependencies"]["foo"].as_str(),
+            Some("~1.3")
+        );
+    }
+
+    #[test]
+    fn keep_comments_in_dependency_tables() {
+        let tmpdir = tempdir::TempDir::new("cargo-edit-comments").unwrap();
+        let path = tmpdir.path().join("Cargo.toml");
+        let original = "[package]\nname = \"foo\"\n\n\
+                        [dependencies.bar]\n\
+                        # the last version supporting our MSRV\n\
+                        version = \"1.0\" # pinned by hand\n\
+                        features = [\"a\"] # needed for x\n";
+        fs::write(&path, original).unwrap();
+        let mut manifest = LocalManifest::try_new(&path).unwrap();
+        let dependency = Dependency::new("bar").set_version("1.4");
+        manifest
+            .apply_upgrade(&dependency, false, false, false, None)
+            .unwrap();
+
+        assert_eq!(
+            manifest.data.to_string_in_original_order(),
+            original.replace("\"1.0\"", "\"1.4\"")
+        );
+    }
+
+    #[test]
+    fn sync_path_dependency_requirements() {
+        let tmpdir = tempdir::TempDir::new("cargo-edit-sync").unwrap();
+        let path = tmpdir.path().join("Cargo.toml");
+        fs::write(
+            &path,
+            "[package]\nname = \"foo\"\n\n[dependencies]\n\
+             bar = { path = \"../bar\", version = \"=0.1.0\" }\n\
+             renamed = { path = \"../bar\", package = \"bar\", version = \"0.1\" }\n\n\
+             [dev-dependencies]\nbar = { path = \"../bar\" }\n\n\
+             [build-dependencies]\nbar = \"0.1.0\"\n",
+        )
+        .unwrap();
+        let mut manifest = LocalManifest::try_new(&path).unwrap();
+        assert!(manifest
+            .sync_path_dependency_requirement("bar", "0.2.0", false)
+            .unwrap());
+
+        let manifest = LocalManifest::try_new(&path).unwrap();
+        let dependencies = &manifest.data["dependencies"];
+        assert_eq!(dependencies["bar"]["version"].as_str(), Some("=0.2.0"));
+        assert_eq!(dependencies["renamed"]["version"].as_str(), Some("0.2"));
+        // Path dependencies without a requirement and registry dependencies are left alone.
+        assert!(manifest.data["dev-dependencies"]["bar"]["version"].is_none());
+        assert_eq!(
+            manifest.data["build-dependencies"]["bar"].as_str(),
+            Some("0.1.0")
+        );
+    }
+
+    #[test]
+    fn upgrade_inherited_dependencies_in_workspace_table() {
+        let tmpdir = tempdir::TempDir::new("cargo-edit-inherit").unwrap();
+        let path = tmpdir.path().join("Cargo.toml");
+        fs::write(
+            &path,
+            "[package]\nname = \"foo\"\n\n[dependencies]\n\
+             bar = { workspace = true, features = [\"a\"] }\n\n\
+             [workspace]\n\n[workspace.dependencies]\nbar = \"1.0\"\n",
+        )
+        .unwrap();
+        let mut manifest = LocalManifest::try_new(&path).unwrap();
+        let dependency = Dependency::new("bar").set_version("1.4");
+        manifest
+            .apply_upgrade(&dependency, false, false, false, None)
+            .unwrap();
+
+        assert_eq!(
+            manifest.data["workspace"]["dependencies"]["bar"].as_str(),
+            Some("1.4")
+        );
+        assert!(manifest.data["dependencies"]["bar"]["version"].is_none());
+        assert!(manifest.inherits_dependency("bar"));
+    }
+
+    #[test]
+    fn upgrade_dependencies_in_target_and_old_style_tables() {
+        let tmpdir = tempdir::TempDir::new("cargo-edit-target").unwrap();
+        let path = tmpdir.path().join("Cargo.toml");
+        fs::write(
+            &path,
+            "[package]\nname = \"foo\"\n\n[dev_dependencies]\nbar = \"1.0\"\n\n\
+             [target.'cfg(unix)'.build-dependencies]\nbar = \"1.0\"\n\n\
+             [target.'cfg(windows)'.dev_dependencies.bar]\nversion = \"1.0\"\noptional = true\n",
+        )
+        .unwrap();
+        let mut manifest = LocalManifest::try_new(&path).unwrap();
+        let dependency = Dependency::new("bar").set_version("1.4");
+        manifest
+            .apply_upgrade(&dependency, false, false, false, None)
+            .unwrap();
+
+        assert_eq!(
+            manifest.data["dev_dependencies"]["bar"].as_str(),
+            Some("1.4")
+        );
+        let targets = &manifest.data["target"];
+        assert_eq!(
+            targets["cfg(unix)"]["build-dependencies"]["bar"].as_str(),
+            Some("1.4")
+        );
+        assert_eq!(
+            targets["cfg(windows)"]["dev_dependencies"]["bar"]["version"].as_str(),
+            Some("1.4")
+        );
+    }
+
+    #[test]
+    fn upgrade_renamed_dependency_only_for_its_alias() {
+        let tmpdir = tempdir::TempDir::new("cargo-edit-alias").unwrap();
+        let path = tmpdir.path().join("Cargo.toml");
+        fs::write(
+            &path,
+            "[package]\nname = \"foo\"\n\n[dependencies]\n\
+             old_rand = { package = \"rand\", version = \"0.7\" }\n\
+             new_rand = { package = \"rand\", version = \"0.8\" }\n",
+        )
+        .unwrap();
+        let mut manifest = LocalManifest::try_new(&path).unwrap();
+        let dependency = Dependency::new("rand")
+            .set_version("0.7.3")
+            .set_rename("old_rand");
+        manifest
+            .apply_upgrade(&dependency, false, false, false, None)
+            .unwrap();
+
+        let dependencies = &manifest.data["dependencies"];
+        assert_eq!(dependencies["old_rand"]["version"].as_str(), Some("0.7.3"));
+        assert_eq!(dependencies["new_rand"]["version"].as_str(), Some("0.8"));
+    }
+
+    #[test]
+    fn remove_from_target_table_removes_empty_target() {
+        let mut manifest: Manifest = "[package]\nname = \"foo\"\n\n\
+                                      [target.'cfg(unix)'.dependencies]\nlibc = \"0.2\"\n"
+            .parse()
+            .unwrap();
+        let table = dependency_table_path(DependencyKind::Normal, Some("cfg(unix)"));
+        assert_eq!(manifest.remove_from_table(&table, "libc").unwrap(), "libc");
+        assert!(manifest.data["target"].is_none());
+        assert!(manifest.remove_from_table(&table, "libc").is_err());
+    }
+
+    #[test]
+    fn readding_removed_dependency_keeps_comments() {
+        let mut manifest: Manifest = "[package]\nname = \"foo\"\n\n[dependencies]\n\
+                                      # explains why we need bar\n\
+                                      bar = \"0.1\"\n\
+                                      baz = \"0.2\"\n"
+            .parse()
+            .unwrap();
+        let table = vec!["dependencies".to_owned()];
+        manifest.remove_from_table(&table, "bar").unwrap();
+        assert!(!manifest.data.to_string().contains("explains"));
+
+        let dep = Dependency::new("bar").set_git("https://example.com/bar");
+        manifest.insert_into_table(&table, &dep).unwrap();
+        assert_eq!(
+            manifest.data.to_string(),
+            "[package]\nname = \"foo\"\n\n[dependencies]\n\
+             # explains why we need bar\n\
+             bar = { git = \"https://example.com/bar\" }\n\
+             baz = \"0.2\"\n"
+        );
+    }
+
+    #[test]
+    fn set_git_reference_keeps_comments() {
+        let mut manifest: Manifest = "[package]\nname = \"foo\"\n\n[dependencies]\n\
+                                      bar = { git = \"https://example.com/bar\", \
+                                      tag = \"v1.0.0\" } # bar\n"
+            .parse()
+            .unwrap();
+        let section = vec!["dependencies".to_owned()];
+        manifest
+            .set_git_reference(&section, "bar", &GitReference::Tag("v1.2.0".to_owned()))
+            .unwrap();
+        assert_eq!(
+            manifest.data.to_string(),
+            "[package]\nname = \"foo\"\n\n[dependencies]\n\
+             bar = { git = \"https://example.com/bar\", tag = \"v1.2.0\" } # bar\n"
+        );
+        assert!(manifest
+            .set_git_reference(&section, "bar", &GitReference::Rev("abc1234".to_owned()))
+            .is_err());
+    }
+
+    #[test]
+    fn insert_workspace_reference_keeps_member_keys() {
+        let mut manifest: Manifest = "[package]\nname = \"foo\"\n\n[dependencies]\n\
+                                      bar = { version = \"1.0\", optional = true }\n"
+            .parse()
+            .unwrap();
+        let section = vec!["dependencies".to_owned()];
+        manifest
+            .insert_workspace_reference(
+                &section,
+                &Dependency::new("bar")
+                    .set_version("1.4")
+                    .set_features(Some(vec!["a".to_owned()])),
+            )
+            .unwrap();
+
+        let entry = &manifest.data["dependencies"]["bar"];
+        assert_eq!(entry["workspace"].as_bool(), Some(true));
+        assert_eq!(entry["optional"].as_bool(), Some(true));
+        assert!(entry["version"].is_none());
+        assert_eq!(entry["features"][0].as_str(), Some("a"));
+    }
+
+    #[test]
+    fn iterate_over_all_dependency_tables() {
+        let manifest: Manifest = r#"[package]
+name = "foo"
+
+[dependencies]
+a = "1.0"
+b = { path = "../b" }
+
+[dev-dependencies]
+c = "0.1"
+
+[build-dependencies]
+d = { git = "https://example.com/d.git" }
+
+[target.'cfg(unix)'.dependencies]
+e = "2.0"
+
+[target.x86_64-pc-windows-gnu.dev-dependencies]
+f = "3.0"
+
+[workspace.dependencies]
+g = "4.0"
+"#
+        .parse()
+        .unwrap();
+
+        let found = iter_dependencies(&manifest)
+            .map(|(context, dep)| {
+                let target = dep.target().map(String::from);
+                (context.table_path().join("."), dep.name, target)
+            })
+            .collect::<Vec<_>>();
+        let expected = [
+            ("dependencies", "a", None),
+            ("dependencies", "b", None),
+            ("dev-dependencies", "c", None),
+            ("build-dependencies", "d", None),
+            ("target.cfg(unix).dependencies", "e", Some("cfg(unix)")),
+            (
+                "target.x86_64-pc-windows-gnu.dev-dependencies",
+                "f",
+                Some("x86_64-pc-windows-gnu"),
+            ),
+            ("workspace.dependencies", "g", None),
+        ]
+        .iter()
+        .map(|&(table, name, target)| (table.to_owned(), name.to_owned(), target.map(String::from)))
+        .collect::<Vec<_>>();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn package_settings_take_precedence_over_workspace() {
+        let tmpdir = tempdir::TempDir::new("cargo-edit-settings").unwrap();
+        fs::write(
+            tmpdir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"member\"]\n\n\
+             [workspace.metadata.cargo-edit]\n\
+             table-always = true\ndefault-features-style = \"explicit\"\n",
+        )
+        .unwrap();
+        let member_path = tmpdir.path().join("member").join("Cargo.toml");
+        fs::create_dir_all(member_path.parent().unwrap()).unwrap();
+        fs::write(
+            &member_path,
+            "[package]\nname = \"member\"\nversion = \"0.1.0\"\n\n\
+             [package.metadata.cargo-edit]\ndefault-features-style = \"auto\"\n",
+        )
+        .unwrap();
+
+        let member = Manifest::open(&Some(member_path.clone())).unwrap();
+        let settings = member.edit_settings(&member_path);
+        assert!(settings.table_always());
+        assert_eq!(settings.explicit_default_features(), Some(false));
+        assert!(!settings.sort_features());
+        assert_eq!(settings.table_header(), None);
+    }
+
+    #[test]
+    fn resolve_package_fields_inherited_from_workspace() {
+        let tmpdir = tempdir::TempDir::new("cargo-edit-inherited").unwrap();
+        fs::write(
+            tmpdir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"member\"]\n\n\
+             [workspace.package]\nrust-version = \"1.56\"\nedition = \"2018\"\n",
+        )
+        .unwrap();
+        let member_path = tmpdir.path().join("member").join("Cargo.toml");
+        fs::create_dir_all(member_path.parent().unwrap()).unwrap();
+        fs::write(
+            &member_path,
+            "[package]\nname = \"member\"\nversion = \"0.1.0\"\n\
+             rust-version = { workspace = true }\nedition = \"2021\"\n\
+             license = { workspace = true }\n",
+        )
+        .unwrap();
+
+        let member = Manifest::open(&Some(member_path.clone())).unwrap();
+        assert_eq!(
+            member.resolved_rust_version(&member_path).as_deref(),
+            Some("1.56")
+        );
+        // Fields set in the member itself are not inherited.
+        assert_eq!(
+            member.package_field(&member_path, "edition").as_str(),
+            Some("2021")
+        );
+        // Inheriting a field the workspace doesn't set yields nothing.
+        assert!(member.package_field(&member_path, "license").is_none());
+    }
+
+    #[test]
+    fn enable_optional_dependency_by_edition() {
+        let tmpdir = tempdir::TempDir::new("cargo-edit-edition").unwrap();
+        let manifest_path = tmpdir.path().join("Cargo.toml");
+        let manifest = |edition: &str| -> Manifest {
+            format!("[package]\nname = \"foo\"\n{}", edition)
+                .parse()
+                .unwrap()
+        };
+        assert!(manifest("edition = \"2021\"\n").uses_dep_syntax(&manifest_path));
+        assert!(!manifest("edition = \"2018\"\n").uses_dep_syntax(&manifest_path));
+        assert!(!manifest("").uses_dep_syntax(&manifest_path));
+
+        let mut manifest = manifest("");
+        manifest.enable_in_feature("gate", "bar", false).unwrap();
+        manifest.enable_in_feature("gate", "bar", true).unwrap();
+        manifest.enable_in_feature("modern", "bar", true).unwrap();
+        let features = |name: &str| {
+            manifest.data["features"][name]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|value| value.as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(features("gate"), vec!["bar"]);
+        assert_eq!(features("modern"), vec!["dep:bar"]);
+    }
+
+    #[test]
+    fn find_unreferenced_optional_dependencies() {
+        let manifest: Manifest = r#"[package]
+name = "foo"
+
+[dependencies]
+plain = "1.0"
+gated = { version = "1.0", optional = true }
+weak = { version = "1.0", optional = true }
+legacy = { version = "1.0", optional = true }
+forgotten = { version = "1.0", optional = true }
+
+[dependencies.also-forgotten]
+version = "1.0"
+optional = true
+
+[features]
+default = ["dep:gated", "weak?/std"]
+old = ["legacy"]
+"#
+        .parse()
+        .unwrap();
+
+        assert_eq!(
+            manifest.unreferenced_optional_dependencies(&["dependencies".to_owned()]),
+            vec!["forgotten", "also-forgotten"]
+        );
+        assert!(manifest
+            .unreferenced_optional_dependencies(&["build-dependencies".to_owned()])
+            .is_empty());
+    }
+
+    #[test]
+    fn set_and_remove_pin_reason() {
+        let mut manifest: Manifest = r#"[package]
+name = "foo"
+
+[dependencies]
+bar = "=1.2.3"
+baz = { version = "=0.4.1", features = ["std"] } # keep this comment
+
+[dependencies.qux]
+version = "=2.0.0"
+"#
+        .parse()
+        .unwrap();
+        let deps = vec!["dependencies".to_owned()];
+
+        manifest
+            .set_pin_reason(&deps, "bar", Some("CVE workaround"))
+            .unwrap();
+        manifest.set_pin_reason(&deps, "baz", Some("MSRV")).unwrap();
+        manifest.set_pin_reason(&deps, "qux", Some("API")).unwrap();
+        let pinned = manifest.data.to_string_in_original_order();
+        assert!(pinned.contains("bar = \"=1.2.3\" # pinned: CVE workaround\n"));
+        assert!(pinned.contains("} # keep this comment # pinned: MSRV\n"));
+        assert!(pinned.contains("version = \"=2.0.0\" # pinned: API\n"));
+
+        // Setting the reason again replaces it, and it can be removed.
+        manifest
+            .set_pin_reason(&deps, "bar", Some("updated"))
+            .unwrap();
+        manifest.set_pin_reason(&deps, "baz", None).unwrap();
+        let updated = manifest.data.to_string_in_original_order();
+        assert!(updated.contains("bar = \"=1.2.3\" # pinned: updated\n"));
+        assert!(updated.contains("} # keep this comment\n"));
+        assert!(manifest.set_pin_reason(&deps, "missing", None).is_err());
+    }
+
+    #[test]
+    fn tidy_redundant_dependency_keys() {
+        let mut manifest: Manifest = r#"[package]
+name = "foo"
+
+[dependencies]
+bar = { version = "1.0", default-features = true } # keep this comment
+baz = { version = "0.4", default-features = false, features = [] }
+qux = { version = "2.0", features = ["std"] }
+
+[target.'cfg(unix)'.dependencies.quux]
+version = "0.1"
+default-features = true
+"#
+        .parse()
+        .unwrap();
+
+        let tidied = manifest.tidy_dependencies().unwrap();
+        assert_eq!(
+            tidied,
+            vec![
+                ("dependencies".to_owned(), "bar".to_owned()),
+                ("dependencies".to_owned(), "baz".to_owned()),
+                (
+                    "target.cfg(unix).dependencies".to_owned(),
+                    "quux".to_owned()
+                ),
+            ]
+        );
+        let contents = manifest.data.to_string_in_original_order();
+        assert!(contents.contains("bar = \"1.0\" # keep this comment\n"));
+        assert!(contents.contains("baz = { version = \"0.4\", default-features = false }\n"));
+        assert!(contents.contains("qux = { version = \"2.0\", features = [\"std\"] }\n"));
+        assert!(!contents.contains("default-features = true"));
+        assert!(manifest.tidy_dependencies().unwrap().is_empty());
+    }
+
+    #[test]
+    fn normalize_dependency_tables() {
+        let mut manifest: Manifest = r#"[package]
+name = "foo"
+
+[dependencies]
+# Serialization
+serde = { version = "1.0" } # keep this comment
+bar = "0.2"
+qux = { version = "2.0", features = ["std"] }
+
+[dev-dependencies]
+aaa = "1"
+bbb = "1"
+"#
+        .parse()
+        .unwrap();
+
+        let shortened = manifest.shorten_dependencies().unwrap();
+        assert_eq!(
+            shortened,
+            vec![("dependencies".to_owned(), "serde".to_owned())]
+        );
+        let sorted = manifest.sort_dependency_tables(&[]).unwrap();
+        assert_eq!(sorted, vec!["dependencies".to_owned()]);
+
+        let contents = manifest.data.to_string();
+        assert!(contents.contains(
+            "bar = \"0.2\"\nqux = { version = \"2.0\", features = [\"std\"] }\n\
+             # Serialization\nserde = \"1.0\" # keep this comment\n"
+        ));
+        assert!(manifest.shorten_dependencies().unwrap().is_empty());
+        assert!(manifest.sort_dependency_tables(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn add_comment_above_table_header() {
+        let mut manifest: Manifest = r#"[package]
+name = "foo"
+
+[dependencies.bar]
+version = "1.0"
+
+[dev-dependencies] # tests
+baz = "1.0"
+"#
+        .parse()
+        .unwrap();
+
+        manifest
+            .add_table_comment(&["dependencies".to_owned()], "no header")
+            .unwrap();
+        manifest
+            .add_table_comment(&["dev-dependencies".to_owned()], "managed by cargo-edit")
+            .unwrap();
+        assert_eq!(
+            manifest.data.to_string_in_original_order(),
+            r#"[package]
+name = "foo"
+
+[dependencies.bar]
+version = "1.0"
+
+# managed by cargo-edit
+[dev-dependencies] # tests
+baz = "1.0"
+"#
+        );
+        assert!(manifest.has_table(&["dev-dependencies".to_owned()]));
+        assert!(!manifest.has_table(&["build-dependencies".to_owned()]));
+    }
+
+    #[test]
+    fn detect_and_merge_duplicate_dependencies() {
+        let input = r#"[package]
+name = "dupes"
+
+[dependencies]
+foo = "0.1"
+bar = { version = "1.0", features = ["std"] }
+foo = "0.2"
+
+[dependencies.baz]
+version = "1.0"
+
+[target.'cfg(unix)'.dependencies]
+foo = "0.3"
+baz.version = "1.0"
+baz.features = ["std"]
+
+[dependencies.baz]
+version = "2.0"
+"#;
+        assert_eq!(
+            find_duplicate_dependencies(input),
+            vec![
+                ("dependencies".to_owned(), "foo".to_owned()),
+                ("dependencies".to_owned(), "baz".to_owned()),
+            ]
+        );
+        match input.parse::<Manifest>().unwrap_err().kind() {
+            ErrorKind::DuplicateDependency(name, table) => {
+                assert_eq!((name.as_str(), table.as_str()), ("foo", "dependencies"))
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+
+        // `baz` is declared as a table twice, which is left alone.
+        let (merged, names) = merge_duplicate_dependencies(input);
+        assert_eq!(names, vec![("dependencies".to_owned(), "foo".to_owned())]);
+        assert!(!merged.contains("foo = \"0.1\""));
+        assert!(merged.contains("foo = \"0.2\""));
+        assert!(merged.contains("foo = \"0.3\""));
+        assert_eq!(
+            find_duplicate_dependencies(&merged),
+            vec![("dependencies".to_owned(), "baz".to_owned())]
+        );
+    }
+
+    #[test]
+    fn verify_detects_broken_edits() {
+        let mut manifest: Manifest =
+            "[package]\nname = \"verify\"\n\n[dependencies]\nfoo = \"0.1\"\n"
+                .parse()
+                .unwrap();
+        assert!(manifest.verify().is_ok());
+
+        // A stray decoration makes the serialized manifest invalid.
+        manifest.data["dependencies"]["foo"] =
+            toml_edit::Item::Value(toml_edit::decorated("0.2".into(), " ", " garbage"));
+        assert!(manifest.verify().is_err());
+    }
 
     #[test]
     fn add_remove_dependency() {
@@ -530,11 +3286,74 @@ mod tests {
         let dep = Dependency::new("cargo-edit").set_version("0.1.0");
         let _ = manifest.insert_into_table(&["dependencies".to_owned()], &dep);
         assert!(manifest
-            .remove_from_table("dependencies", &dep.name)
+            .remove_from_table(&["dependencies".to_owned()], &dep.name)
             .is_ok());
         assert_eq!(manifest.data.to_string(), clone.data.to_string());
     }
 
+    #[test]
+    fn get_dependencies_with_target() {
+        let manifest: Manifest = r#"[dependencies]
+serde = "1.0"
+
+[target.'cfg(unix)'.dev-dependencies]
+nix = { version = "0.17", optional = true }
+"#
+        .parse()
+        .unwrap();
+
+        let dependencies = manifest.get_dependencies().unwrap();
+        assert_eq!(dependencies.len(), 2);
+
+        let (nix_table, nix) = &dependencies[0];
+        assert_eq!(nix_table, &["target", "cfg(unix)", "dev-dependencies"]);
+        assert_eq!(nix.name, "nix");
+        assert_eq!(nix.target(), Some("cfg(unix)"));
+        assert!(nix.is_optional());
+
+        let (serde_table, serde) = &dependencies[1];
+        assert_eq!(serde_table, &["dependencies"]);
+        assert_eq!(serde.version(), Some("1.0"));
+        assert_eq!(serde.target(), None);
+    }
+
+    #[test]
+    fn describe_table_context() {
+        let path = |path: &[&str]| path.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(table_context(&path(&["dependencies"])), None);
+        assert_eq!(
+            table_context(&path(&["dev-dependencies"])),
+            Some("dev-dependencies".to_owned())
+        );
+        assert_eq!(
+            table_context(&path(&["target", "cfg(windows)", "build-dependencies"])),
+            Some("target.'cfg(windows)'.build-dependencies".to_owned())
+        );
+    }
+
+    #[test]
+    fn rebase_nonexistent_paths() {
+        let normalized = |path: &str| normalize_path(Path::new(path));
+        assert_eq!(normalized("/a/./b/../c"), PathBuf::from("/a/c"));
+
+        assert_eq!(
+            relative_components(
+                &normalized("/ws/crates/app"),
+                &normalized("/ws/crates/app/../lib")
+            ),
+            "../lib"
+        );
+        assert_eq!(
+            relative_components(&normalized("/ws/app"), &normalized("/ws/app/vendor/lib")),
+            "vendor/lib"
+        );
+        assert_eq!(
+            rebase_path(Path::new("app"), Path::new("/abs/lib")).unwrap(),
+            "/abs/lib"
+        );
+    }
+
     #[test]
     fn update_dependency() {
         let mut manifest = Manifest {
@@ -577,7 +3396,7 @@ mod tests {
         };
         let dep = Dependency::new("cargo-edit").set_version("0.1.0");
         assert!(manifest
-            .remove_from_table("dependencies", &dep.name)
+            .remove_from_table(&["dependencies".to_owned()], &dep.name)
             .is_err());
     }
 
@@ -590,10 +3409,52 @@ mod tests {
         let other_dep = Dependency::new("other-dep").set_version("0.1.0");
         let _ = manifest.insert_into_table(&["dependencies".to_owned()], &other_dep);
         assert!(manifest
-            .remove_from_table("dependencies", &dep.name)
+            .remove_from_table(&["dependencies".to_owned()], &dep.name)
             .is_err());
     }
 
+    #[test]
+    fn glob_matches() {
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("internal-*", "internal-utils"));
+        assert!(!glob_match("internal-*", "utils"));
+        assert!(glob_match("internal/*", "internal/utils"));
+        assert!(glob_match("?erde", "serde"));
+        assert!(!glob_match("serde", "serde_json"));
+    }
+
+    #[test]
+    fn sort_dependencies_by_group() {
+        let mut manifest: Manifest = r#"[dependencies]
+toml_edit = "0.1.5"
+internal-utils = { path = "../utils" }
+atty = "0.2.13"
+local = { path = "internal/local" }
+"#
+        .parse()
+        .unwrap();
+        let table = manifest.data["dependencies"].as_table_mut().unwrap();
+        sort_dependencies(table, &["internal*".to_owned()]);
+
+        let names: Vec<_> = table.iter().map(|(name, _)| name.to_owned()).collect();
+        assert_eq!(names, vec!["internal-utils", "local", "atty", "toml_edit"]);
+    }
+
+    #[test]
+    fn follow_tab_indentation_of_original() {
+        let original = "[features]\ndefault = [\n\t\"std\",\n]\n\n[dependencies]\n\tfoo = \"1\"\n";
+        let rendered = "[features]\ndefault = [\n\t\"std\",\n    \"alloc\",\n]\n\n\
+                        [dependencies]\n\tfoo = \"1\"\nbar = \"2\"\n\n[build-dependencies]\ncc = \"1\"\n";
+        assert_eq!(
+            follow_tab_indentation(rendered, original),
+            "[features]\ndefault = [\n\t\"std\",\n\t\"alloc\",\n]\n\n\
+             [dependencies]\n\tfoo = \"1\"\n\tbar = \"2\"\n\n[build-dependencies]\ncc = \"1\"\n"
+        );
+
+        let spaces = original.replace('\t', "    ");
+        assert_eq!(follow_tab_indentation(rendered, &spaces), rendered);
+    }
+
     #[test]
     fn old_version_is_compatible() -> Result<()> {
         let with_version = Dependency::new("foo").set_version("2.3.4");
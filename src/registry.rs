@@ -1,33 +1,205 @@
-use self::code_from_cargo::Kind;
+use self::code_from_cargo::{Kind, StableHasher};
 use crate::errors::*;
+use crate::warning::warn;
+use crate::GitReference;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::fmt;
+#[allow(deprecated)]
+use std::hash::{Hash, Hasher, SipHasher};
 use std::path::{Path, PathBuf};
 use url::Url;
 
 const CRATES_IO_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+const CRATES_IO_SPARSE_INDEX: &str = "sparse+https://index.crates.io/";
 const CRATES_IO_REGISTRY: &str = "crates-io";
+/// Selects the protocol crates.io is accessed with, like `registries.crates-io.protocol`
+const CRATES_IO_PROTOCOL_ENV_VAR: &str = "CARGO_REGISTRIES_CRATES_IO_PROTOCOL";
+/// Opts into expanding `${VAR}` in the index URLs of cargo config files
+const EXPAND_ENV_VAR: &str = "CARGO_EDIT_EXPAND_REGISTRY_ENV";
+/// Marks the URL of a directory source, i.e. a directory of unpacked crates like `cargo vendor`
+/// writes
+pub(crate) const DIRECTORY_PREFIX: &str = "directory+";
+/// Marks the URL of a git source, i.e. a repository containing the crates themselves
+pub(crate) const GIT_PREFIX: &str = "git+";
 
-pub fn registry_path(manifest_path: &Path, registry: Option<&str>) -> Result<PathBuf> {
-    registry_path_from_url(&registry_url(manifest_path, registry)?)
+/// The directory the index of `registry` is read from: the directory a `file://` URL points to,
+/// or else the copy of the index in `CARGO_HOME`.
+///
+/// For directory and git sources, this is where their crates are read from instead.
+pub fn registry_path_from_url(registry: &Url) -> Result<PathBuf> {
+    let location = source_location(registry);
+    if location.scheme() == "file" {
+        return location
+            .to_file_path()
+            .map_err(|()| ErrorKind::InvalidCargoConfig.into());
+    }
+    Ok(cached_index_path(
+        &cargo_home()?.join("registry").join("index"),
+        registry,
+    ))
 }
 
-pub fn registry_path_from_url(registry: &Url) -> Result<PathBuf> {
-    Ok(cargo_home()?
-        .join("registry")
-        .join("index")
-        .join(short_name(registry)))
+/// The copy of the index of `registry` in a Cargo cache of indexes.
+///
+/// Cargo 1.85 changed the hash in the names of these directories, so the legacy one is only read
+/// if an older Cargo filled it in and a newer one hasn't.
+fn cached_index_path(cache: &Path, registry: &Url) -> PathBuf {
+    let current = cache.join(short_name(registry, StableHasher::default()));
+    #[allow(deprecated)]
+    let legacy = cache.join(short_name(registry, SipHasher::new_with_keys(0, 0)));
+    if !current.exists() && legacy.exists() {
+        legacy
+    } else {
+        current
+    }
 }
 
-#[derive(Debug, Deserialize)]
+/// The URL of a directory or git source without its `directory+` or `git+` prefix and without
+/// the git reference, i.e. where its crates are. Other URLs are returned as they are.
+pub(crate) fn source_location(registry: &Url) -> Url {
+    let url = registry.as_str();
+    let stripped = [DIRECTORY_PREFIX, GIT_PREFIX]
+        .iter()
+        .find(|prefix| url.starts_with(*prefix))
+        .and_then(|prefix| Url::parse(&url[prefix.len()..]).ok());
+    match stripped {
+        Some(mut location) => {
+            location.set_query(None);
+            location
+        }
+        None => registry.clone(),
+    }
+}
+
+/// The web API of crates.io, which its index is not needed to find
+pub(crate) const CRATES_IO_API: &str = "https://crates.io";
+
+/// Whether `registry` is the git or sparse index of crates.io
+pub(crate) fn is_crates_io(registry: &Url) -> bool {
+    let url = registry.as_str().trim_end_matches('/');
+    url == CRATES_IO_INDEX || url == CRATES_IO_SPARSE_INDEX.trim_end_matches('/')
+}
+
+/// The branch, tag or revision a git source is read at, if it isn't the default branch
+pub(crate) fn git_source_reference(registry: &Url) -> Option<GitReference> {
+    registry.query_pairs().find_map(|(key, value)| match &*key {
+        "branch" => Some(GitReference::Branch(value.into_owned())),
+        "tag" => Some(GitReference::Tag(value.into_owned())),
+        "rev" => Some(GitReference::Rev(value.into_owned())),
+        _ => None,
+    })
+}
+
+#[derive(Debug, Default, Deserialize)]
 struct Source {
     #[serde(rename = "replace-with")]
     replace_with: Option<String>,
     registry: Option<String>,
+    /// A directory with the `.crate` files and an `index` next to them, like
+    /// `cargo local-registry` creates
+    #[serde(rename = "local-registry")]
+    local_registry: Option<PathBuf>,
+    /// A directory of unpacked crates, like `cargo vendor` creates
+    directory: Option<PathBuf>,
+    /// A git repository containing the crates, at `branch`, `tag` or `rev`
+    git: Option<String>,
+    branch: Option<String>,
+    tag: Option<String>,
+    rev: Option<String>,
+}
+
+impl Source {
+    /// The URL crates are looked up at, with paths resolved against `base`, the directory
+    /// containing the `.cargo` directory of the config file the source is set in
+    ///
+    /// Local registries are read through their index, directory and git sources get a
+    /// `directory+` or `git+` prefix and the git reference as query, like `?branch=main`.
+    fn resolve_url(&self, base: &Path) -> Result<Option<String>> {
+        if let Some(registry) = &self.registry {
+            return resolve_relative_file_url(expand_env(registry)?, base).map(Some);
+        }
+        if let Some(path) = &self.local_registry {
+            return directory_url(&base.join(path).join("index")).map(Some);
+        }
+        if let Some(path) = &self.directory {
+            let url = directory_url(&base.join(path))?;
+            return Ok(Some(format!("{}{}", DIRECTORY_PREFIX, url)));
+        }
+        if let Some(git) = &self.git {
+            let mut url = Url::parse(git).chain_err(|| ErrorKind::InvalidCargoConfig)?;
+            let reference = [
+                ("branch", &self.branch),
+                ("tag", &self.tag),
+                ("rev", &self.rev),
+            ];
+            for (key, value) in reference.iter() {
+                if let Some(value) = value {
+                    url.query_pairs_mut().append_pair(key, value);
+                }
+            }
+            return Ok(Some(format!("{}{}", GIT_PREFIX, url)));
+        }
+        Ok(None)
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct Registry {
     index: Option<String>,
+    /// Either `git` or `sparse`
+    protocol: Option<String>,
+}
+
+impl Registry {
+    /// The index URL, with a `sparse+` prefix if the registry uses the sparse protocol.
+    fn index_url(self, name: &str) -> Result<Option<String>> {
+        let index = self.index.map(|index| expand_env(&index)).transpose()?;
+        match self.protocol.as_deref() {
+            None | Some("git") => Ok(index),
+            Some("sparse") => match index {
+                Some(index) if index.starts_with("sparse+") => Ok(Some(index)),
+                Some(index) => Ok(Some(format!("sparse+{}", index))),
+                None if name == CRATES_IO_REGISTRY => Ok(Some(CRATES_IO_SPARSE_INDEX.to_owned())),
+                None => Ok(None),
+            },
+            Some(_) => Err(ErrorKind::InvalidCargoConfig.into()),
+        }
+    }
+}
+
+/// Proxy and TLS settings for network access, from the `[http]` table of cargo config files
+///
+/// `CARGO_HTTP_PROXY`, `CARGO_HTTP_CAINFO`, `CARGO_HTTP_SSL_CERT` and
+/// `CARGO_HTTP_SSL_CERT_PASSWORD` take precedence over the config files. Without a proxy, the
+/// standard `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` variables are honored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpConfig {
+    /// The proxy for all requests, as `[protocol://]host[:port]` (`http.proxy`)
+    pub proxy: Option<String>,
+    /// A PEM file with additional certificate authorities to trust (`http.cainfo`)
+    pub cainfo: Option<PathBuf>,
+    /// A PKCS#12 bundle with the client certificate and its key, for registries that require
+    /// mutual TLS (`http.ssl-cert`)
+    pub ssl_cert: Option<PathBuf>,
+    /// The password of `ssl_cert` (`http.ssl-cert-password`)
+    pub ssl_cert_password: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct HttpSection {
+    proxy: Option<String>,
+    cainfo: Option<PathBuf>,
+    #[serde(rename = "ssl-cert")]
+    ssl_cert: Option<PathBuf>,
+    #[serde(rename = "ssl-cert-password")]
+    ssl_cert_password: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HttpOnlyConfig {
+    #[serde(default)]
+    http: HttpSection,
 }
 
 #[derive(Debug, Deserialize)]
@@ -36,9 +208,70 @@ struct CargoConfig {
     registries: HashMap<String, Registry>,
     #[serde(default)]
     source: HashMap<String, Source>,
+    #[serde(default)]
+    registry: RegistryTable,
+}
+
+/// The `[registry]` table of a cargo config
+#[derive(Debug, Default, Deserialize)]
+struct RegistryTable {
+    /// The name of the registry used when none is given
+    default: Option<String>,
+}
+
+/// Replace `${VAR}` in the index URL `url` with the value of the environment variable `VAR`, if
+/// `CARGO_EDIT_EXPAND_REGISTRY_ENV` is set.
+///
+/// Cargo itself doesn't do this, but some setups template their config files like that.
+fn expand_env(url: &str) -> Result<String> {
+    if std::env::var_os(EXPAND_ENV_VAR).is_none() {
+        return Ok(url.to_owned());
+    }
+    expand_vars(url, |var| std::env::var(var).ok())
+}
+
+fn expand_vars(url: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = url;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let var = &rest[start + 2..end];
+        let value = lookup(var)
+            .chain_err(|| ErrorKind::UnsetRegistryEnvVar(var.to_owned(), url.to_owned()))?;
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
 }
 
-fn cargo_home() -> Result<PathBuf> {
+/// Resolve a relative `file:` URL such as `file:index` against `base`, the directory containing
+/// the `.cargo` directory of the config file it is set in. Other URLs are returned as they are.
+fn resolve_relative_file_url(url: String, base: &Path) -> Result<String> {
+    match url.get(..5) {
+        Some("file:") if !url[5..].starts_with('/') => directory_url(&base.join(&url[5..])),
+        _ => Ok(url),
+    }
+}
+
+/// The `file://` URL of the directory `path`, relative to the current directory if it isn't
+/// absolute
+fn directory_url(path: &Path) -> Result<String> {
+    let path = if path.is_absolute() {
+        path.to_owned()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+    Url::from_directory_path(&path)
+        .map(|url| url.to_string())
+        .map_err(|()| ErrorKind::InvalidCargoConfig.into())
+}
+
+pub(crate) fn cargo_home() -> Result<PathBuf> {
     let default_cargo_home = dirs::home_dir()
         .map(|x| x.join(".cargo"))
         .chain_err(|| ErrorKind::ReadHomeDirFailure)?;
@@ -48,22 +281,340 @@ fn cargo_home() -> Result<PathBuf> {
     Ok(cargo_home)
 }
 
+/// The cargo config files that apply to `manifest_path`, the most specific one first.
+fn config_paths(manifest_path: &Path) -> Result<Vec<PathBuf>> {
+    // ref: https://doc.rust-lang.org/cargo/reference/config.html#hierarchical-structure
+    let mut paths = manifest_path
+        .parent()
+        .expect("there must be a parent directory")
+        .ancestors()
+        .map(|work_dir| work_dir.join(".cargo"))
+        .collect::<Vec<_>>();
+    paths.push(cargo_home()?);
+    Ok(paths.into_iter().filter_map(config_file).collect())
+}
+
+/// The config file in the directory `dir`: `config`, or else `config.toml`, which is what cargo
+/// reads if both exist.
+fn config_file(dir: PathBuf) -> Option<PathBuf> {
+    let legacy = dir.join("config");
+    if legacy.is_file() {
+        return Some(legacy);
+    }
+    Some(dir.join("config.toml")).filter(|path| path.is_file())
+}
+
+/// Find the proxy and TLS settings for network access on behalf of `manifest_path`
+///
+/// Relative paths are resolved against the directory containing the `.cargo` directory of the
+/// config file they are set in. Config files that cannot be parsed are skipped, as finding the
+/// registry already reports them.
+pub fn http_config(manifest_path: &Path) -> Result<HttpConfig> {
+    let mut config = HttpConfig::default();
+    for path in config_paths(manifest_path)? {
+        let content = std::fs::read(&path)?;
+        let section = match toml::from_slice::<HttpOnlyConfig>(&content) {
+            Ok(parsed) => parsed.http,
+            Err(_) => continue,
+        };
+        let base = path
+            .parent()
+            .and_then(Path::parent)
+            .expect("config files are in a `.cargo` directory");
+        let HttpSection {
+            proxy,
+            cainfo,
+            ssl_cert,
+            ssl_cert_password,
+        } = section;
+        config.proxy = config.proxy.or(proxy);
+        config.cainfo = config.cainfo.or_else(|| cainfo.map(|p| base.join(p)));
+        config.ssl_cert = config.ssl_cert.or_else(|| ssl_cert.map(|p| base.join(p)));
+        config.ssl_cert_password = config.ssl_cert_password.or(ssl_cert_password);
+    }
+
+    if let Ok(proxy) = std::env::var("CARGO_HTTP_PROXY") {
+        config.proxy = Some(proxy);
+    }
+    if let Some(cainfo) = std::env::var_os("CARGO_HTTP_CAINFO") {
+        config.cainfo = Some(cainfo.into());
+    }
+    if let Some(ssl_cert) = std::env::var_os("CARGO_HTTP_SSL_CERT") {
+        config.ssl_cert = Some(ssl_cert.into());
+    }
+    if let Ok(password) = std::env::var("CARGO_HTTP_SSL_CERT_PASSWORD") {
+        config.ssl_cert_password = Some(password);
+    }
+    Ok(config)
+}
+
+/// Find the proxy and TLS settings that apply in the current directory, like cargo does, for
+/// network access that isn't on behalf of a particular manifest, e.g. fetching a git index.
+pub(crate) fn ambient_http_config() -> Result<HttpConfig> {
+    http_config(&std::env::current_dir()?.join("Cargo.toml"))
+}
+
+/// An API token for a registry
+///
+/// The token is masked when formatted, so it cannot end up in logs or error messages by accident.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RegistryToken(String);
+
+impl RegistryToken {
+    /// The token itself, to be sent to the registry
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for RegistryToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RegistryToken(****)")
+    }
+}
+
+impl fmt::Display for RegistryToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "****")
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct TokenEntry {
+    token: Option<String>,
+}
+
+/// The contents of `CARGO_HOME/credentials.toml`, or the tokens set in a cargo config
+#[derive(Debug, Clone, Default, Deserialize)]
+struct Credentials {
+    #[serde(default)]
+    registry: TokenEntry,
+    #[serde(default)]
+    registries: HashMap<String, TokenEntry>,
+}
+
+impl Credentials {
+    /// Take the tokens that are missing here from `fallback`.
+    fn or(mut self, fallback: Credentials) -> Credentials {
+        if self.registry.token.is_none() {
+            self.registry = fallback.registry;
+        }
+        for (name, entry) in fallback.registries {
+            let own = self.registries.entry(name).or_default();
+            if own.token.is_none() {
+                *own = entry;
+            }
+        }
+        self
+    }
+}
+
+/// Read `credentials.toml` (or the older `credentials`) from `CARGO_HOME`. A file that cannot be
+/// parsed is treated as empty.
+fn read_credentials() -> Result<Credentials> {
+    let cargo_home = cargo_home()?;
+    let path = ["credentials.toml", "credentials"]
+        .iter()
+        .map(|name| cargo_home.join(name))
+        .find(|path| path.is_file());
+    let content = match path {
+        Some(path) => std::fs::read(path)?,
+        None => return Ok(Credentials::default()),
+    };
+    Ok(toml::from_slice(&content).unwrap_or_default())
+}
+
+/// Read the tokens set as `registry.token` and `registries.<name>.token` in the cargo configs of
+/// `manifest_path`, where the closest config wins. Config files that cannot be parsed are skipped.
+fn read_config_credentials(manifest_path: &Path) -> Result<Credentials> {
+    let mut credentials = Credentials::default();
+    for path in config_paths(manifest_path)? {
+        let content = std::fs::read(&path)?;
+        if let Ok(config) = toml::from_slice::<Credentials>(&content) {
+            credentials = credentials.or(config);
+        }
+    }
+    Ok(credentials)
+}
+
+/// The environment variable overriding the index of the registry `name` in `[registries]`.
+fn index_env_var(name: &str) -> String {
+    format!(
+        "CARGO_REGISTRIES_{}_INDEX",
+        name.to_uppercase().replace('-', "_")
+    )
+}
+
+/// The environment variable holding the token of `registry`, or of the default registry.
+fn token_env_var(registry: Option<&str>) -> String {
+    match registry {
+        Some(name) => format!(
+            "CARGO_REGISTRIES_{}_TOKEN",
+            name.to_uppercase().replace('-', "_")
+        ),
+        None => "CARGO_REGISTRY_TOKEN".to_owned(),
+    }
+}
+
+/// Pick the token of `registry` (`None` for the default registry), preferring the environment
+/// over the credentials file.
+fn select_token(
+    registry: Option<&str>,
+    env: impl Fn(&str) -> Option<String>,
+    credentials: Credentials,
+) -> Option<RegistryToken> {
+    let Credentials {
+        registry: default,
+        mut registries,
+    } = credentials;
+    env(&token_env_var(registry))
+        .or_else(|| match registry {
+            Some(name) => registries.remove(name).and_then(|entry| entry.token),
+            None => default.token,
+        })
+        .map(RegistryToken)
+}
+
+/// The name under which `registry` is configured in `[registries]`, if any.
+fn configured_registry_name(manifest_path: &Path, registry: &Url) -> Result<Option<String>> {
+    for path in config_paths(manifest_path)? {
+        let content = std::fs::read(&path)?;
+        let config = match toml::from_slice::<CargoConfig>(&content) {
+            Ok(config) => config,
+            Err(_) => continue,
+        };
+        for (name, value) in config.registries {
+            let index = value.index_url(&name).ok().flatten();
+            if index.and_then(|index| Url::parse(&index).ok()).as_ref() == Some(registry) {
+                return Ok(Some(name));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Find the name of the registry configured as `registry.default`, either with the
+/// `CARGO_REGISTRY_DEFAULT` environment variable or in the closest cargo config setting it.
+///
+/// `None` means crates.io. Config files that cannot be parsed are skipped, as finding the
+/// registry already reports them.
+pub fn default_registry_name(manifest_path: &Path) -> Result<Option<String>> {
+    let name = match std::env::var("CARGO_REGISTRY_DEFAULT") {
+        Ok(name) => Some(name),
+        Err(_) => {
+            let mut name = None;
+            for path in config_paths(manifest_path)? {
+                let content = std::fs::read(&path)?;
+                if let Ok(config) = toml::from_slice::<CargoConfig>(&content) {
+                    if config.registry.default.is_some() {
+                        name = config.registry.default;
+                        break;
+                    }
+                }
+            }
+            name
+        }
+    };
+    Ok(name.filter(|name| name != CRATES_IO_REGISTRY))
+}
+
+/// Find the API token to send to `registry`
+///
+/// The default registry uses `CARGO_REGISTRY_TOKEN`, a registry configured as `<name>` in
+/// `[registries]` uses `CARGO_REGISTRIES_<NAME>_TOKEN`. Both take precedence over the tokens in
+/// `CARGO_HOME/credentials.toml`, which take precedence over the ones in the cargo configs. Other
+/// registries get no token.
+pub fn registry_token(manifest_path: &Path, registry: &Url) -> Result<Option<RegistryToken>> {
+    let name = if registry_url(manifest_path, None).ok().as_ref() == Some(registry) {
+        None
+    } else {
+        match configured_registry_name(manifest_path, registry)? {
+            Some(name) => Some(name),
+            None => return Ok(None),
+        }
+    };
+    Ok(select_token(
+        name.as_deref(),
+        |var| std::env::var(var).ok(),
+        read_credentials()?.or(read_config_credentials(manifest_path)?),
+    ))
+}
+
 /// Find the URL of a registry
 pub fn registry_url(manifest_path: &Path, registry: Option<&str>) -> Result<Url> {
-    // TODO support local registry sources, directory sources, git sources: https://doc.rust-lang.org/cargo/reference/source-replacement.html?highlight=replace-with#source-replacement
-    fn read_config(registries: &mut HashMap<String, Source>, path: impl AsRef<Path>) -> Result<()> {
-        // TODO unit test for source replacement
+    resolve_registry_url(manifest_path, registry, false, true)
+}
+
+/// Find the URL of a registry like `registry_url`, but skip cargo config files that cannot be
+/// parsed (with a warning) instead of failing.
+pub fn registry_url_lenient(manifest_path: &Path, registry: Option<&str>) -> Result<Url> {
+    resolve_registry_url(manifest_path, registry, true, true)
+}
+
+/// Find the URL of a registry like `registry_url`, but without following `replace-with`, i.e. the
+/// URL of the original registry even if a source replacement such as a mirror is configured.
+pub fn original_registry_url(
+    manifest_path: &Path,
+    registry: Option<&str>,
+    lenient: bool,
+) -> Result<Url> {
+    resolve_registry_url(manifest_path, registry, lenient, false)
+}
+
+fn resolve_registry_url(
+    manifest_path: &Path,
+    registry: Option<&str>,
+    lenient: bool,
+    follow_replacements: bool,
+) -> Result<Url> {
+    // ref: https://doc.rust-lang.org/cargo/reference/source-replacement.html
+    fn read_config(
+        registries: &mut HashMap<String, Source>,
+        path: impl AsRef<Path>,
+        lenient: bool,
+    ) -> Result<()> {
+        let path = path.as_ref();
         let content = std::fs::read(path)?;
-        let config =
-            toml::from_slice::<CargoConfig>(&content).map_err(|_| ErrorKind::InvalidCargoConfig)?;
+        let config = match toml::from_slice::<CargoConfig>(&content) {
+            Ok(config) => config,
+            Err(_) if lenient => {
+                warn(format!(
+                    "Skipping invalid cargo config `{}`",
+                    path.display()
+                ))?;
+                return Ok(());
+            }
+            Err(_) => return Err(ErrorKind::InvalidCargoConfig.into()),
+        };
+        let base = path
+            .parent()
+            .and_then(Path::parent)
+            .expect("config files are in a `.cargo` directory");
         for (key, value) in config.registries {
+            let index = value
+                .index_url(&key)?
+                .map(|url| resolve_relative_file_url(url, base))
+                .transpose()?;
             registries.entry(key).or_insert(Source {
-                registry: value.index,
-                replace_with: None,
+                registry: index,
+                ..Source::default()
             });
         }
-        for (key, value) in config.source {
-            registries.entry(key).or_insert(value);
+        for (key, mut value) in config.source {
+            value.registry = value.resolve_url(base)?;
+            match registries.entry(key) {
+                Entry::Vacant(entry) => {
+                    entry.insert(value);
+                }
+                // A registry from `[registries]` only has its index, it can still be replaced by
+                // the `[source]` of the same name.
+                Entry::Occupied(mut entry) => {
+                    let source = entry.get_mut();
+                    if source.replace_with.is_none() {
+                        source.replace_with = value.replace_with;
+                    }
+                }
+            }
         }
         Ok(())
     }
@@ -71,40 +622,34 @@ pub fn registry_url(manifest_path: &Path, registry: Option<&str>) -> Result<Url>
     // it's looks like a singly linked list
     // put relations in this map.
     let mut registries: HashMap<String, Source> = HashMap::new();
-    // ref: https://doc.rust-lang.org/cargo/reference/config.html#hierarchical-structure
-    for work_dir in manifest_path
-        .parent()
-        .expect("there must be a parent directory")
-        .ancestors()
-    {
-        let config_path = work_dir.join(".cargo").join("config");
-        if config_path.is_file() {
-            read_config(&mut registries, config_path)?;
-        }
-    }
-
-    let default_config_path = cargo_home()?.join("config");
-    if default_config_path.is_file() {
-        read_config(&mut registries, default_config_path)?;
+    for config_path in config_paths(manifest_path)? {
+        read_config(&mut registries, config_path, lenient)?;
     }
 
     // find head of the relevant linked list
-    let mut source = match registry {
-        Some(CRATES_IO_INDEX) | None => {
-            registries
-                .remove(CRATES_IO_REGISTRY)
-                .unwrap_or_else(|| Source {
-                    replace_with: None,
-                    registry: Some(CRATES_IO_INDEX.to_string()),
-                })
-        }
-        Some(r) => registries
-            .remove(r)
-            .chain_err(|| ErrorKind::NoSuchRegistryFound(r.to_string()))?,
+    let name = match registry {
+        Some(CRATES_IO_INDEX) | None => CRATES_IO_REGISTRY,
+        Some(r) => r,
     };
+    // Like cargo, the environment takes precedence over the config files.
+    if name != CRATES_IO_REGISTRY {
+        if let Ok(index) = std::env::var(index_env_var(name)) {
+            registries.entry(name.to_owned()).or_default().registry = Some(index);
+        }
+    }
+    let mut source = match registries.remove(name) {
+        Some(source) => source,
+        None if name == CRATES_IO_REGISTRY => Source::default(),
+        None => return Err(ErrorKind::NoSuchRegistryFound(name.to_string()).into()),
+    };
+    // `[source.crates-io]` usually only says what replaces it.
+    if name == CRATES_IO_REGISTRY {
+        let protocol = std::env::var(CRATES_IO_PROTOCOL_ENV_VAR).ok();
+        source.registry = Some(crates_io_index(source.registry, protocol.as_deref())?);
+    }
 
     // search this linked list and find the tail
-    while let Some(replace_with) = &source.replace_with {
+    while let Some(replace_with) = source.replace_with.as_ref().filter(|_| follow_replacements) {
         source = registries
             .remove(replace_with)
             .chain_err(|| ErrorKind::NoSuchSourceFound(replace_with.to_string()))?;
@@ -118,13 +663,28 @@ pub fn registry_url(manifest_path: &Path, registry: Option<&str>) -> Result<Url>
     Ok(registry_url)
 }
 
-fn short_name(registry: &Url) -> String {
-    // ref: https://github.com/rust-lang/cargo/blob/4c1fa54d10f58d69ac9ff55be68e1b1c25ecb816/src/cargo/sources/registry/mod.rs#L386-L390
-    #![allow(deprecated)]
-    use std::hash::{Hash, Hasher, SipHasher};
+/// The index of crates.io: `configured` in the cargo config, if any, unless `protocol` from
+/// `CARGO_REGISTRIES_CRATES_IO_PROTOCOL` switches between the git and the sparse index.
+fn crates_io_index(configured: Option<String>, protocol: Option<&str>) -> Result<String> {
+    let is_default = |url: &str| url == CRATES_IO_INDEX || url == CRATES_IO_SPARSE_INDEX;
+    match (configured, protocol) {
+        (Some(url), Some(_)) if !is_default(&url) => Ok(url),
+        (Some(url), None) => Ok(url),
+        (_, None) | (_, Some("git")) => Ok(CRATES_IO_INDEX.to_owned()),
+        (_, Some("sparse")) => Ok(CRATES_IO_SPARSE_INDEX.to_owned()),
+        (_, Some(_)) => Err(ErrorKind::InvalidCargoConfig.into()),
+    }
+}
 
-    let mut hasher = SipHasher::new_with_keys(0, 0);
-    Kind::Registry.hash(&mut hasher);
+fn short_name(registry: &Url, mut hasher: impl Hasher) -> String {
+    // ref: https://github.com/rust-lang/cargo/blob/4c1fa54d10f58d69ac9ff55be68e1b1c25ecb816/src/cargo/sources/registry/mod.rs#L386-L390
+    // Cargo hashes sparse registries with their own kind, and with the `sparse+` prefix in the URL.
+    let kind = if registry.as_str().starts_with("sparse+") {
+        Kind::SparseRegistry
+    } else {
+        Kind::Registry
+    };
+    kind.hash(&mut hasher);
     registry.as_str().hash(&mut hasher);
     let hash = hex::encode(hasher.finish().to_le_bytes());
 
@@ -135,24 +695,506 @@ fn short_name(registry: &Url) -> String {
 
 #[test]
 fn test_short_name() {
-    fn test_helper(url: &str, name: &str) {
+    #[allow(deprecated)]
+    fn test_helper(url: &str, name: &str, legacy_name: &str) {
         let url = Url::parse(url).unwrap();
-        assert_eq!(short_name(&url), name);
+        assert_eq!(short_name(&url, StableHasher::default()), name);
+        assert_eq!(
+            short_name(&url, SipHasher::new_with_keys(0, 0)),
+            legacy_name
+        );
     }
     test_helper(
         "https://github.com/rust-lang/crates.io-index",
+        "github.com-25cdd57fae9f0462",
         "github.com-1ecc6299db9ec823",
     );
+    test_helper(
+        CRATES_IO_SPARSE_INDEX,
+        "index.crates.io-1949cf8c6b5b557f",
+        "index.crates.io-6f17d22bba15001f",
+    );
+    // Alternative registries are hashed like crates.io, with the kind of their index.
+    test_helper(
+        "sparse+https://example.com/index/",
+        "example.com-6fe91b6a5796005e",
+        "example.com-baca101d629c921a",
+    );
+    test_helper(
+        "https://example.com/index",
+        "example.com-3bab6764c353b068",
+        "example.com-4b9bc5264e97aa3e",
+    );
+}
+
+#[test]
+fn test_cached_index_path() {
+    let cache = tempdir::TempDir::new("cached-index").unwrap();
+    let registry = Url::parse(CRATES_IO_SPARSE_INDEX).unwrap();
+    let current = cache.path().join("index.crates.io-1949cf8c6b5b557f");
+    let legacy = cache.path().join("index.crates.io-6f17d22bba15001f");
+
+    assert_eq!(cached_index_path(cache.path(), &registry), current);
+    std::fs::create_dir(&legacy).unwrap();
+    assert_eq!(cached_index_path(cache.path(), &registry), legacy);
+    std::fs::create_dir(&current).unwrap();
+    assert_eq!(cached_index_path(cache.path(), &registry), current);
+}
+
+#[test]
+fn test_lenient_config() {
+    let tmpdir = tempdir::TempDir::new("cargo-edit-config").unwrap();
+    let valid = tmpdir.path().join(".cargo");
+    std::fs::create_dir_all(&valid).unwrap();
+    std::fs::write(
+        valid.join("config"),
+        "[registries.alternative]\nindex = \"https://example.com/index\"\n",
+    )
+    .unwrap();
+    let broken = tmpdir.path().join("member").join(".cargo");
+    std::fs::create_dir_all(&broken).unwrap();
+    std::fs::write(broken.join("config"), "[registries\n").unwrap();
+    let manifest_path = tmpdir.path().join("member").join("Cargo.toml");
+
+    assert!(registry_url(&manifest_path, Some("alternative")).is_err());
+    assert_eq!(
+        registry_url_lenient(&manifest_path, Some("alternative"))
+            .unwrap()
+            .as_str(),
+        "https://example.com/index"
+    );
+}
+
+#[test]
+fn test_source_replacement() {
+    let tmpdir = tempdir::TempDir::new("cargo-edit-config").unwrap();
+    let config_dir = tmpdir.path().join(".cargo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config"),
+        "[source.crates-io]\n\
+         replace-with = \"vendored\"\n\
+         [source.vendored]\n\
+         directory = \"vendor\"\n\
+         [source.alternative]\n\
+         replace-with = \"local\"\n\
+         [source.local]\n\
+         local-registry = \"/srv/registry\"\n\
+         [source.other]\n\
+         replace-with = \"upstream\"\n\
+         [source.upstream]\n\
+         git = \"https://example.com/crates.git\"\n\
+         branch = \"stable\"\n\
+         [registries.alternative]\n\
+         index = \"https://example.com/index\"\n\
+         [registries.other]\n\
+         index = \"https://example.com/other-index\"\n",
+    )
+    .unwrap();
+    let manifest_path = tmpdir.path().join("Cargo.toml");
+
+    let vendored = registry_url(&manifest_path, None).unwrap();
+    assert_eq!(
+        vendored.as_str(),
+        format!(
+            "directory+{}",
+            Url::from_directory_path(tmpdir.path().join("vendor")).unwrap()
+        )
+    );
+    assert_eq!(
+        registry_path_from_url(&vendored).unwrap(),
+        tmpdir.path().join("vendor")
+    );
+    assert_eq!(
+        registry_url(&manifest_path, Some("alternative"))
+            .unwrap()
+            .as_str(),
+        "file:///srv/registry/index/"
+    );
+    let git = registry_url(&manifest_path, Some("other")).unwrap();
+    assert_eq!(
+        git.as_str(),
+        "git+https://example.com/crates.git?branch=stable"
+    );
+    assert_eq!(
+        source_location(&git).as_str(),
+        "https://example.com/crates.git"
+    );
+    assert_eq!(
+        git_source_reference(&git),
+        Some(GitReference::Branch("stable".to_owned()))
+    );
+    assert_eq!(
+        original_registry_url(&manifest_path, None, false)
+            .unwrap()
+            .as_str(),
+        CRATES_IO_INDEX
+    );
+}
+
+#[test]
+fn test_expand_env_in_index_url() {
+    let tmpdir = tempdir::TempDir::new("cargo-edit-config").unwrap();
+    let config_dir = tmpdir.path().join(".cargo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config"),
+        "[registries.templated]\n\
+         index = \"https://${MY_REG_HOST}/index\"\n",
+    )
+    .unwrap();
+    let manifest_path = tmpdir.path().join("Cargo.toml");
+
+    std::env::set_var("MY_REG_HOST", "registry.example.com");
+    std::env::set_var(EXPAND_ENV_VAR, "1");
+    assert_eq!(
+        registry_url(&manifest_path, Some("templated"))
+            .unwrap()
+            .as_str(),
+        "https://registry.example.com/index"
+    );
+
+    let err = expand_vars("https://${MY_UNSET_REG_HOST}/index", |_| None).unwrap_err();
+    assert!(err.to_string().contains("MY_UNSET_REG_HOST"));
+    assert_eq!(
+        expand_vars("https://${A}.${B}/x", |var| Some(var.to_lowercase())).unwrap(),
+        "https://a.b/x"
+    );
+}
+
+#[test]
+fn test_default_registry_name() {
+    let tmpdir = tempdir::TempDir::new("cargo-edit-config").unwrap();
+    let workspace = tmpdir.path().join(".cargo");
+    std::fs::create_dir_all(&workspace).unwrap();
+    std::fs::write(
+        workspace.join("config"),
+        "[registry]\ndefault = \"private\"\n",
+    )
+    .unwrap();
+    let member = tmpdir.path().join("member").join(".cargo");
+    std::fs::create_dir_all(&member).unwrap();
+    std::fs::write(
+        member.join("config"),
+        "[registries.private]\nindex = \"https://example.com/private-index\"\n",
+    )
+    .unwrap();
+
+    let name = default_registry_name(&tmpdir.path().join("member").join("Cargo.toml")).unwrap();
+    assert_eq!(name.as_deref(), Some("private"));
+
+    std::fs::write(
+        member.join("config"),
+        "[registry]\ndefault = \"crates-io\"\n",
+    )
+    .unwrap();
+    let name = default_registry_name(&tmpdir.path().join("member").join("Cargo.toml")).unwrap();
+    assert_eq!(name, None);
+}
+
+#[test]
+fn test_config_toml() {
+    let tmpdir = tempdir::TempDir::new("cargo-edit-config").unwrap();
+    let config_dir = tmpdir.path().join(".cargo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config.toml"),
+        "[registries.toml-only]\nindex = \"https://example.com/toml-index\"\n",
+    )
+    .unwrap();
+    let manifest_path = tmpdir.path().join("Cargo.toml");
+
+    let url = registry_url(&manifest_path, Some("toml-only")).unwrap();
+    assert_eq!(url.as_str(), "https://example.com/toml-index");
+
+    // `config` wins over `config.toml` in the same directory.
+    std::fs::write(
+        config_dir.join("config"),
+        "[registries.toml-only]\nindex = \"https://example.com/legacy-index\"\n",
+    )
+    .unwrap();
+    let url = registry_url(&manifest_path, Some("toml-only")).unwrap();
+    assert_eq!(url.as_str(), "https://example.com/legacy-index");
+}
+
+#[test]
+fn test_index_from_env() {
+    let tmpdir = tempdir::TempDir::new("cargo-edit-config").unwrap();
+    let config_dir = tmpdir.path().join(".cargo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config.toml"),
+        "[registries.env-override]\nindex = \"https://example.com/config-index\"\n",
+    )
+    .unwrap();
+    let manifest_path = tmpdir.path().join("Cargo.toml");
+
+    std::env::set_var(
+        "CARGO_REGISTRIES_ENV_OVERRIDE_INDEX",
+        "https://example.com/env-index",
+    );
+    std::env::set_var(
+        "CARGO_REGISTRIES_ENV_ONLY_INDEX",
+        "https://example.com/env-only-index",
+    );
+    let overridden = registry_url(&manifest_path, Some("env-override")).unwrap();
+    let env_only = registry_url(&manifest_path, Some("env-only")).unwrap();
+    std::env::remove_var("CARGO_REGISTRIES_ENV_OVERRIDE_INDEX");
+    std::env::remove_var("CARGO_REGISTRIES_ENV_ONLY_INDEX");
+
+    assert_eq!(overridden.as_str(), "https://example.com/env-index");
+    assert_eq!(env_only.as_str(), "https://example.com/env-only-index");
+}
+
+#[test]
+fn test_sparse_protocol_config() {
+    let tmpdir = tempdir::TempDir::new("cargo-edit-config").unwrap();
+    let config_dir = tmpdir.path().join(".cargo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config"),
+        "[registries.alternative]\n\
+         index = \"https://example.com/index/\"\n\
+         protocol = \"sparse\"\n\
+         [registries.legacy]\n\
+         index = \"https://example.com/git-index\"\n\
+         protocol = \"git\"\n",
+    )
+    .unwrap();
+    let manifest_path = tmpdir.path().join("Cargo.toml");
+
+    let sparse = registry_url(&manifest_path, Some("alternative")).unwrap();
+    assert_eq!(sparse.as_str(), "sparse+https://example.com/index/");
+    let git = registry_url(&manifest_path, Some("legacy")).unwrap();
+    assert_eq!(git.as_str(), "https://example.com/git-index");
+
+    // The sparse index is cached separately from a git index at the same URL.
+    let git_at_same_url = Url::parse("https://example.com/index/").unwrap();
+    assert_ne!(
+        registry_path_from_url(&sparse).unwrap(),
+        registry_path_from_url(&git_at_same_url).unwrap()
+    );
+}
+
+#[test]
+fn test_crates_io_protocol() {
+    let sparse = Some(CRATES_IO_SPARSE_INDEX.to_owned());
+    assert_eq!(crates_io_index(None, None).unwrap(), CRATES_IO_INDEX);
+    assert_eq!(
+        crates_io_index(sparse.clone(), None).unwrap(),
+        CRATES_IO_SPARSE_INDEX
+    );
+    assert_eq!(
+        crates_io_index(None, Some("sparse")).unwrap(),
+        CRATES_IO_SPARSE_INDEX
+    );
+    // The environment takes precedence over `registries.crates-io.protocol`.
+    assert_eq!(
+        crates_io_index(sparse, Some("git")).unwrap(),
+        CRATES_IO_INDEX
+    );
+    // A mirror set as the index of crates.io is kept.
+    assert_eq!(
+        crates_io_index(
+            Some("https://mirror.example.com/".to_owned()),
+            Some("sparse")
+        )
+        .unwrap(),
+        "https://mirror.example.com/"
+    );
+    assert!(crates_io_index(None, Some("ftp")).is_err());
+}
+
+#[test]
+fn test_original_registry_url_skips_source_replacement() {
+    let tmpdir = tempdir::TempDir::new("cargo-edit-config").unwrap();
+    let config_dir = tmpdir.path().join(".cargo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config"),
+        "[source.crates-io]\n\
+         replace-with = \"mirror\"\n\
+         [source.mirror]\n\
+         registry = \"https://mirror.example.com/index\"\n\
+         [registries.alternative]\n\
+         index = \"https://example.com/index\"\n",
+    )
+    .unwrap();
+    let manifest_path = tmpdir.path().join("Cargo.toml");
+
+    assert_eq!(
+        registry_url(&manifest_path, None).unwrap().as_str(),
+        "https://mirror.example.com/index"
+    );
+    assert_eq!(
+        original_registry_url(&manifest_path, None, false)
+            .unwrap()
+            .as_str(),
+        CRATES_IO_INDEX
+    );
+    assert_eq!(
+        original_registry_url(&manifest_path, Some("alternative"), false)
+            .unwrap()
+            .as_str(),
+        "https://example.com/index"
+    );
+}
+
+#[test]
+fn test_relative_file_index_url() {
+    let tmpdir = tempdir::TempDir::new("cargo-edit-config").unwrap();
+    let config_dir = tmpdir.path().join(".cargo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config"),
+        "[registries.relative]\n\
+         index = \"file:vendor/index\"\n\
+         [registries.absolute]\n\
+         index = \"file:///srv/index\"\n",
+    )
+    .unwrap();
+    let manifest_path = tmpdir.path().join("member").join("Cargo.toml");
+
+    let relative = registry_url(&manifest_path, Some("relative")).unwrap();
+    assert_eq!(
+        registry_path_from_url(&relative).unwrap(),
+        tmpdir.path().join("vendor").join("index")
+    );
+    let absolute = registry_url(&manifest_path, Some("absolute")).unwrap();
+    assert_eq!(absolute.as_str(), "file:///srv/index");
+    assert_eq!(
+        registry_path_from_url(&absolute).unwrap(),
+        Path::new("/srv/index")
+    );
+}
+
+#[test]
+fn test_http_config() {
+    let tmpdir = tempdir::TempDir::new("cargo-edit-config").unwrap();
+    let workspace = tmpdir.path().join(".cargo");
+    std::fs::create_dir_all(&workspace).unwrap();
+    std::fs::write(
+        workspace.join("config"),
+        "[http]
+         proxy = \"proxy.example.com:3128\"
+         cainfo = \"certs/ca.pem\"
+         ssl-cert = \"/etc/ssl/workspace.p12\"
+",
+    )
+    .unwrap();
+    let member = tmpdir.path().join("member").join(".cargo");
+    std::fs::create_dir_all(&member).unwrap();
+    std::fs::write(
+        member.join("config"),
+        "[http]
+         ssl-cert = \"client.p12\"
+         ssl-cert-password = \"secret\"
+",
+    )
+    .unwrap();
+    let manifest_path = tmpdir.path().join("member").join("Cargo.toml");
+
+    let config = http_config(&manifest_path).unwrap();
+    assert_eq!(config.proxy.as_deref(), Some("proxy.example.com:3128"));
+    assert_eq!(config.cainfo, Some(tmpdir.path().join("certs/ca.pem")));
+    assert_eq!(
+        config.ssl_cert,
+        Some(tmpdir.path().join("member").join("client.p12"))
+    );
+    assert_eq!(config.ssl_cert_password.as_deref(), Some("secret"));
+}
+
+#[test]
+fn test_select_registry_token() {
+    let env = |var: &str| match var {
+        "CARGO_REGISTRY_TOKEN" => Some("default-env".to_owned()),
+        "CARGO_REGISTRIES_INTERNAL_TOKEN" => Some("internal-env".to_owned()),
+        "CARGO_REGISTRIES_MY_MIRROR_TOKEN" => Some("mirror-env".to_owned()),
+        _ => None,
+    };
+    let credentials = || -> Credentials {
+        toml::from_str(
+            "[registry]
+             token = \"default-file\"
+             [registries.internal]
+             token = \"internal-file\"
+             [registries.other]
+             token = \"other-file\"
+",
+        )
+        .unwrap()
+    };
+    let token = |registry, env: &dyn Fn(&str) -> Option<String>| {
+        select_token(registry, env, credentials()).map(|token| token.expose().to_owned())
+    };
+
+    assert_eq!(token(None, &env).as_deref(), Some("default-env"));
+    assert_eq!(
+        token(Some("internal"), &env).as_deref(),
+        Some("internal-env")
+    );
+    assert_eq!(
+        token(Some("my-mirror"), &env).as_deref(),
+        Some("mirror-env")
+    );
+    assert_eq!(token(Some("other"), &env).as_deref(), Some("other-file"));
+    assert_eq!(token(Some("unknown"), &env), None);
+
+    let no_env = |_: &str| None;
+    assert_eq!(token(None, &no_env).as_deref(), Some("default-file"));
+    assert_eq!(
+        token(Some("internal"), &no_env).as_deref(),
+        Some("internal-file")
+    );
+}
+
+#[test]
+fn test_config_tokens_fill_in_missing_credentials() {
+    let parse = |content: &str| toml::from_str::<Credentials>(content).unwrap();
+    let credentials = parse(
+        "[registries.internal]
+         token = \"internal-file\"
+",
+    )
+    .or(parse(
+        "[registry]
+         token = \"default-config\"
+         default = \"internal\"
+         [registries.internal]
+         index = \"https://example.com/index\"
+         token = \"internal-config\"
+         [registries.other]
+         token = \"other-config\"
+",
+    ));
+    let token = |registry| {
+        select_token(registry, |_| None, credentials.clone()).map(|token| token.expose().to_owned())
+    };
+
+    assert_eq!(token(None).as_deref(), Some("default-config"));
+    assert_eq!(token(Some("internal")).as_deref(), Some("internal-file"));
+    assert_eq!(token(Some("other")).as_deref(), Some("other-config"));
+}
+
+#[test]
+fn test_registry_token_is_masked() {
+    let token = RegistryToken("secret".to_owned());
+    assert_eq!(format!("{}", token), "****");
+    assert!(!format!("{:?}", token).contains("secret"));
 }
 
 mod code_from_cargo {
     #![allow(dead_code)]
+    use std::hash::Hasher;
 
+    // The variants are in the order of Cargo's `SourceKind`, whose derived hash of the variant
+    // goes into the names of registry cache directories.
     #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub enum Kind {
         Git(GitReference),
         Path,
         Registry,
+        SparseRegistry,
         LocalRegistry,
         Directory,
     }
@@ -163,4 +1205,99 @@ mod code_from_cargo {
         Branch(String),
         Rev(String),
     }
+
+    // ref: https://github.com/rust-lang/rustc-stable-hash/blob/main/src/stable_hasher.rs
+    /// The `StableSipHasher128` Cargo 1.85 and later hash the names of cache directories with:
+    /// SipHash-1-3 with 128 bits of output, folded into 64 bits.
+    #[derive(Debug, Default)]
+    pub struct StableHasher {
+        bytes: Vec<u8>,
+    }
+
+    impl Hasher for StableHasher {
+        fn write(&mut self, bytes: &[u8]) {
+            self.bytes.extend_from_slice(bytes);
+        }
+
+        fn write_u16(&mut self, i: u16) {
+            self.write(&i.to_le_bytes());
+        }
+
+        fn write_u32(&mut self, i: u32) {
+            self.write(&i.to_le_bytes());
+        }
+
+        fn write_u64(&mut self, i: u64) {
+            self.write(&i.to_le_bytes());
+        }
+
+        fn write_usize(&mut self, i: usize) {
+            self.write_u64(i as u64);
+        }
+
+        // Enum discriminants are hashed as `isize`, in a single byte if they fit in one.
+        fn write_isize(&mut self, i: isize) {
+            let value = i as u64;
+            if value < 0xFF {
+                self.write_u8(value as u8);
+            } else {
+                self.write_u8(0xFF);
+                self.write_u64(value);
+            }
+        }
+
+        fn finish(&self) -> u64 {
+            let (first, second) = sip13_128(&self.bytes);
+            first.wrapping_mul(3).wrapping_add(second)
+        }
+    }
+
+    fn sip13_128(bytes: &[u8]) -> (u64, u64) {
+        fn round(v: &mut [u64; 4]) {
+            v[0] = v[0].wrapping_add(v[1]);
+            v[1] = v[1].rotate_left(13) ^ v[0];
+            v[0] = v[0].rotate_left(32);
+            v[2] = v[2].wrapping_add(v[3]);
+            v[3] = v[3].rotate_left(16) ^ v[2];
+            v[0] = v[0].wrapping_add(v[3]);
+            v[3] = v[3].rotate_left(21) ^ v[0];
+            v[2] = v[2].wrapping_add(v[1]);
+            v[1] = v[1].rotate_left(17) ^ v[2];
+            v[2] = v[2].rotate_left(32);
+        }
+
+        // The keys are zero, and the 128-bit variant tweaks `v1` before it starts.
+        let mut v = [
+            0x736f_6d65_7073_6575,
+            0x646f_7261_6e64_6f6d ^ 0xee,
+            0x6c79_6765_6e65_7261,
+            0x7465_6462_7974_6573,
+        ];
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0; 8];
+            word.copy_from_slice(chunk);
+            let m = u64::from_le_bytes(word);
+            v[3] ^= m;
+            round(&mut v);
+            v[0] ^= m;
+        }
+        let mut tail = [0; 8];
+        tail[..chunks.remainder().len()].copy_from_slice(chunks.remainder());
+        let b = ((bytes.len() as u64 & 0xff) << 56) | u64::from_le_bytes(tail);
+        v[3] ^= b;
+        round(&mut v);
+        v[0] ^= b;
+
+        v[2] ^= 0xee;
+        for _ in 0..3 {
+            round(&mut v);
+        }
+        let first = v[0] ^ v[1] ^ v[2] ^ v[3];
+        v[1] ^= 0xdd;
+        for _ in 0..3 {
+            round(&mut v);
+        }
+        (first, v[0] ^ v[1] ^ v[2] ^ v[3])
+    }
 }
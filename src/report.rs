@@ -0,0 +1,243 @@
+//! Machine-readable reports of manifest changes, written with `--report-file`
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::dependency::ResolvedSource;
+use crate::errors::*;
+use crate::manifest::{iter_dependencies, iter_dependency_sources, tmp_path, Manifest};
+
+/// The version of the report layout, bumped when existing fields change
+pub const REPORT_FORMAT_VERSION: u32 = 1;
+
+/// Write `records` to the report at `path`, as
+///
+/// ```json
+/// {"format_version":1,"records":[...]}
+/// ```
+///
+/// The report is written to a temporary file next to `path` first, which then replaces it, so a
+/// partially written report is never collected.
+pub fn write_report(path: &Path, records: &[Value]) -> Result<()> {
+    let report = json!({
+        "format_version": REPORT_FORMAT_VERSION,
+        "records": records,
+    });
+    let tmp_path = tmp_path(path)?;
+    fs::write(&tmp_path, format!("{:#}\n", report))
+        .chain_err(|| format!("Failed to write `{}`", tmp_path.display()))?;
+    fs::rename(&tmp_path, path).chain_err(|| format!("Failed to replace `{}`", path.display()))
+}
+
+/// Get a record for each dependency requirement that differs between `before` and `after`, the
+/// old and new contents of the manifest at `manifest_path`, e.g.
+///
+/// ```json
+/// {"manifest":"Cargo.toml","crate":"serde","name":"serde","table":["dependencies"],
+///  "action":"upgrade","old_requirement":"1.0","requirement":"1.0.104"}
+/// ```
+pub fn requirement_changes(
+    manifest_path: &Path,
+    before: &Manifest,
+    after: &Manifest,
+) -> Vec<Value> {
+    let old_requirements = iter_dependencies(before)
+        .map(|(context, dep)| {
+            let key = (context.table_path(), dep.name_in_manifest().to_owned());
+            (key, dep.version().map(ToOwned::to_owned))
+        })
+        .collect::<HashMap<_, _>>();
+
+    iter_dependencies(after)
+        .filter_map(|(context, dep)| {
+            let table = context.table_path();
+            let old = old_requirements.get(&(table.clone(), dep.name_in_manifest().to_owned()))?;
+            if old.as_deref() == dep.version() {
+                return None;
+            }
+            Some(json!({
+                "manifest": manifest_path,
+                "crate": dep.name,
+                "name": dep.name_in_manifest(),
+                "table": table,
+                "action": "upgrade",
+                "old_requirement": old,
+                "requirement": dep.version(),
+            }))
+        })
+        .collect()
+}
+
+/// Get a record of the source of each dependency of the manifest at `manifest_path`, in the order
+/// of `iter_dependencies`, e.g.
+///
+/// ```json
+/// {"manifest":"Cargo.toml","name":"serde","table":["dependencies"],"source":"git",
+///  "git":"https://github.com/serde-rs/serde","reference":{"tag":"v1.0.104"}}
+/// ```
+///
+/// Registry dependencies have a `registry` and path dependencies a `path` instead.
+pub fn source_records(manifest_path: &Path, manifest: &Manifest) -> Vec<Value> {
+    iter_dependency_sources(manifest)
+        .map(|(context, name, source)| {
+            let mut record = json!({
+                "manifest": manifest_path,
+                "name": name,
+                "table": context.table_path(),
+                "source": source.kind(),
+            });
+            match &source {
+                ResolvedSource::Registry(registry) => record["registry"] = json!(registry),
+                ResolvedSource::Git { repo, reference } => {
+                    record["git"] = json!(repo);
+                    if let Some(reference) = reference {
+                        record["reference"] = json!({ reference.key(): reference.value() });
+                    }
+                }
+                ResolvedSource::Path(path) => record["path"] = json!(path),
+                ResolvedSource::CratesIo | ResolvedSource::Workspace => {}
+            }
+            record
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_changed_requirements_only() {
+        let before = r#"
+[package]
+name = "foo"
+
+[dependencies]
+serde = "1.0"
+log = "0.4"
+
+[target.'cfg(unix)'.dev-dependencies]
+libc = "0.2.50"
+"#
+        .parse::<Manifest>()
+        .unwrap();
+        let after = r#"
+[package]
+name = "foo"
+
+[dependencies]
+serde = "1.0.104"
+log = "0.4"
+
+[target.'cfg(unix)'.dev-dependencies]
+libc = "0.2.66"
+"#
+        .parse::<Manifest>()
+        .unwrap();
+
+        let records = requirement_changes(Path::new("Cargo.toml"), &before, &after);
+        assert_eq!(
+            records,
+            vec![
+                json!({
+                    "manifest": "Cargo.toml",
+                    "crate": "serde",
+                    "name": "serde",
+                    "table": ["dependencies"],
+                    "action": "upgrade",
+                    "old_requirement": "1.0",
+                    "requirement": "1.0.104",
+                }),
+                json!({
+                    "manifest": "Cargo.toml",
+                    "crate": "libc",
+                    "name": "libc",
+                    "table": ["target", "cfg(unix)", "dev-dependencies"],
+                    "action": "upgrade",
+                    "old_requirement": "0.2.50",
+                    "requirement": "0.2.66",
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn classify_dependency_sources() {
+        let manifest = r#"
+[package]
+name = "foo"
+
+[workspace.dependencies]
+log = "0.4"
+
+[dependencies]
+serde = "1.0"
+log = { workspace = true }
+internal = { version = "0.3", registry = "company" }
+local = { path = "../local", version = "0.1" }
+patched = { git = "https://github.com/example/patched", tag = "v2.0.0" }
+
+[target.'cfg(unix)'.build-dependencies]
+cc = { git = "https://github.com/alexcrichton/cc-rs" }
+"#
+        .parse::<Manifest>()
+        .unwrap();
+
+        let records = source_records(Path::new("Cargo.toml"), &manifest);
+        let record = |name: &str, table: Value, source: &str| json!({"manifest": "Cargo.toml", "name": name, "table": table, "source": source});
+        let mut internal = record("internal", json!(["dependencies"]), "registry");
+        internal["registry"] = json!("company");
+        let mut local = record("local", json!(["dependencies"]), "path");
+        local["path"] = json!("../local");
+        let mut patched = record("patched", json!(["dependencies"]), "git");
+        patched["git"] = json!("https://github.com/example/patched");
+        patched["reference"] = json!({"tag": "v2.0.0"});
+        let mut cc = record(
+            "cc",
+            json!(["target", "cfg(unix)", "build-dependencies"]),
+            "git",
+        );
+        cc["git"] = json!("https://github.com/alexcrichton/cc-rs");
+        assert_eq!(
+            records,
+            vec![
+                record("serde", json!(["dependencies"]), "crates-io"),
+                record("log", json!(["dependencies"]), "workspace"),
+                internal,
+                local,
+                patched,
+                cc,
+                record("log", json!(["workspace", "dependencies"]), "crates-io"),
+            ]
+        );
+
+        let sources = iter_dependency_sources(&manifest)
+            .map(|(_, name, source)| format!("{}: {}", name, source))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sources[2..5],
+            [
+                "internal: registry company",
+                "local: path ../local",
+                "patched: git https://github.com/example/patched (tag v2.0.0)",
+            ]
+        );
+    }
+
+    #[test]
+    fn write_report_with_format_version() {
+        let tmpdir = tempdir::TempDir::new("cargo-edit-report").unwrap();
+        let path = tmpdir.path().join("report.json");
+        write_report(&path, &[json!({"crate": "serde"})]).unwrap();
+
+        let report: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            report,
+            json!({"format_version": 1, "records": [{"crate": "serde"}]})
+        );
+        assert_eq!(fs::read_dir(tmpdir.path()).unwrap().count(), 1);
+    }
+}
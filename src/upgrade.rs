@@ -0,0 +1,361 @@
+//! Upgrading the dependencies of a manifest, the core of `cargo upgrade` for use by other tools
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::dependency::Dependency;
+use crate::errors::*;
+use crate::fetch::{get_latest_dependency_from_source, VersionSource};
+use crate::manifest::{
+    iter_dependencies, old_version_compatible, write_manifests_atomically, LocalManifest,
+};
+
+/// What `upgrade` should do, mirroring the flags of `cargo upgrade`
+#[derive(Debug, Clone, Default)]
+pub struct UpgradeOptions {
+    only: Vec<String>,
+    exclude: Vec<String>,
+    skip_compatible: bool,
+    allow_prerelease: bool,
+    force: bool,
+    dry_run: bool,
+    rust_version: Option<String>,
+}
+
+impl UpgradeOptions {
+    /// Only upgrade the dependencies with these names, by package name or the name in the
+    /// manifest. By default, every dependency is upgraded.
+    pub fn only(mut self, names: Vec<String>) -> Self {
+        self.only = names;
+        self
+    }
+
+    /// Don't upgrade the dependencies with these names, by package name or the name in the
+    /// manifest.
+    pub fn exclude(mut self, names: Vec<String>) -> Self {
+        self.exclude = names;
+        self
+    }
+
+    /// Only upgrade a dependency if its latest version doesn't match its current requirement, like
+    /// `--skip-compatible`.
+    pub fn skip_compatible(mut self, skip_compatible: bool) -> Self {
+        self.skip_compatible = skip_compatible;
+        self
+    }
+
+    /// Include prerelease versions, like `--allow-prerelease`.
+    pub fn allow_prerelease(mut self, allow_prerelease: bool) -> Self {
+        self.allow_prerelease = allow_prerelease;
+        self
+    }
+
+    /// Also upgrade exact (`=`) and pre-release requirements, like `--force`.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Plan the upgrades without writing the manifest, like `--dry-run`.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Only upgrade to versions that support this Rust version, like `--rust-version`.
+    pub fn rust_version(mut self, rust_version: &str) -> Self {
+        self.rust_version = Some(rust_version.into());
+        self
+    }
+
+    fn selects(&self, dep: &Dependency) -> bool {
+        let matches = |names: &[String]| {
+            names
+                .iter()
+                .any(|name| *name == dep.name || name == dep.name_in_manifest())
+        };
+        (self.only.is_empty() || matches(&self.only)) && !matches(&self.exclude)
+    }
+}
+
+/// What happened to a dependency in `upgrade`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpgradeOutcome {
+    /// The requirement was changed
+    Applied,
+    /// The requirement already is the latest version, or matches it with `skip_compatible`
+    Unchanged,
+    /// The dependency was not selected by `only`, or was listed in `exclude`
+    Excluded,
+    /// The dependency was not considered for the given reason, e.g. because it is a path
+    /// dependency
+    Skipped(String),
+}
+
+/// The outcome of `upgrade` for one entry in a dependency table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyUpgrade {
+    /// The name of the crate
+    pub name: String,
+    /// The key of the entry, which differs from `name` for renamed dependencies
+    pub name_in_manifest: String,
+    /// The path of the table, e.g. `["target", "cfg(unix)", "dependencies"]`
+    pub table: Vec<String>,
+    /// The requirement before the upgrade
+    pub old_requirement: Option<String>,
+    /// The requirement after the upgrade, which is the same as `old_requirement` unless the
+    /// upgrade was applied
+    pub new_requirement: Option<String>,
+    /// What happened to the dependency
+    pub outcome: UpgradeOutcome,
+}
+
+/// The result of `upgrade`, with an entry for every dependency declared in the manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpgradeReport {
+    /// The upgraded manifest
+    pub manifest_path: PathBuf,
+    /// The dependencies, table by table
+    pub dependencies: Vec<DependencyUpgrade>,
+}
+
+impl UpgradeReport {
+    /// Get the dependencies whose requirement was changed.
+    pub fn applied(&self) -> impl Iterator<Item = &DependencyUpgrade> {
+        self.dependencies
+            .iter()
+            .filter(|dep| dep.outcome == UpgradeOutcome::Applied)
+    }
+}
+
+/// Why a requirement is not upgraded without `force`, if it is pinned.
+pub fn pinned_reason(req: &str) -> Option<&'static str> {
+    if req
+        .split(',')
+        .any(|predicate| predicate.trim().starts_with('='))
+    {
+        Some("an exact version requirement")
+    } else if req.contains('-') {
+        Some("a pre-release version requirement")
+    } else {
+        None
+    }
+}
+
+/// Upgrade the registry dependencies of the manifest at `manifest_path` to their latest versions,
+/// like `cargo upgrade` does for a single manifest.
+///
+/// The versions of each dependency are looked up in the source `source_for` returns for the name
+/// of its registry, which is `None` for dependencies from the default registry.
+///
+/// Unless `options` is a dry run, the manifest is written if any requirement changed. Like
+/// `cargo upgrade`, the upgrades are printed. The manifest is only written once every lookup
+/// succeeded, so it is left alone if this fails.
+pub fn upgrade<S: VersionSource>(
+    manifest_path: &Path,
+    options: &UpgradeOptions,
+    source_for: impl Fn(Option<&str>) -> S,
+) -> Result<UpgradeReport> {
+    let mut manifest = LocalManifest::try_new(manifest_path)?;
+    let declared = iter_dependencies(&manifest)
+        .map(|(context, dep)| (context.table_path(), dep))
+        .collect::<Vec<_>>();
+
+    // Crates are declared in several tables at times, so look each one up only once.
+    let mut latest = HashMap::new();
+    let mut dependencies = Vec::new();
+    for (table, dep) in declared {
+        let old_requirement = dep.version().map(String::from);
+        let outcome = if !options.selects(&dep) {
+            UpgradeOutcome::Excluded
+        } else if dep.path().is_some() || dep.git().is_some() {
+            UpgradeOutcome::Skipped("it is not a registry dependency".into())
+        } else if let Some(old_req) = &old_requirement {
+            match pinned_reason(old_req).filter(|_| !options.force) {
+                Some(reason) => UpgradeOutcome::Skipped(format!("it has {}", reason)),
+                None => {
+                    let key = (dep.name.clone(), dep.registry().map(String::from));
+                    let version = match latest.get(&key) {
+                        Some(version) => String::clone(version),
+                        None => {
+                            let new_dep = get_latest_dependency_from_source(
+                                &dep.name,
+                                options.allow_prerelease || old_req.contains('-'),
+                                options.rust_version.as_deref(),
+                                &source_for(dep.registry()),
+                            )
+                            .chain_err(|| "Failed to get new version")?;
+                            let version = new_dep.version().expect("Invalid dependency type");
+                            latest.insert(key, version.to_owned());
+                            version.to_owned()
+                        }
+                    };
+
+                    let mut new_dep = Dependency::new(&dep.name).set_version(&version);
+                    if let Some(rename) = dep.rename() {
+                        new_dep = new_dep.set_rename(rename);
+                    }
+                    if options.skip_compatible && old_version_compatible(&new_dep, old_req)? {
+                        UpgradeOutcome::Unchanged
+                    } else {
+                        manifest.apply_upgrade_to_entry(
+                            &table,
+                            dep.name_in_manifest(),
+                            &new_dep,
+                        )?;
+                        UpgradeOutcome::Applied
+                    }
+                }
+            }
+        } else {
+            UpgradeOutcome::Skipped("it has no version requirement".into())
+        };
+        dependencies.push(DependencyUpgrade {
+            name: dep.name.clone(),
+            name_in_manifest: dep.name_in_manifest().to_owned(),
+            table,
+            new_requirement: old_requirement.clone(),
+            old_requirement,
+            outcome,
+        });
+    }
+
+    // Entries that already had the latest version were rewritten to the same requirement.
+    let new_requirements = iter_dependencies(&manifest)
+        .map(|(context, dep)| {
+            let key = (context.table_path(), dep.name_in_manifest().to_owned());
+            (key, dep.version().map(String::from))
+        })
+        .collect::<HashMap<_, _>>();
+    for dep in &mut dependencies {
+        if dep.outcome != UpgradeOutcome::Applied {
+            continue;
+        }
+        let key = (dep.table.clone(), dep.name_in_manifest.clone());
+        if let Some(new_requirement) = new_requirements.get(&key) {
+            dep.new_requirement = new_requirement.clone();
+        }
+        if dep.new_requirement == dep.old_requirement {
+            dep.outcome = UpgradeOutcome::Unchanged;
+        }
+    }
+
+    if !options.dry_run {
+        write_manifests_atomically(&[manifest])?;
+    }
+    Ok(UpgradeReport {
+        manifest_path: manifest_path.to_path_buf(),
+        dependencies,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::CrateVersion;
+    use std::fs;
+
+    /// Knows `foo` and `bar`, instead of reading a registry index.
+    struct MockSource;
+
+    impl VersionSource for MockSource {
+        fn versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>> {
+            let versions: &[&str] = match crate_name {
+                "foo" => &["0.1.0", "0.2.0", "0.3.0-beta"],
+                "bar" => &["1.0.0", "1.2.0"],
+                _ => return Err(ErrorKind::NoCrate(crate_name.into()).into()),
+            };
+            Ok(versions
+                .iter()
+                .map(|v| CrateVersion::new(crate_name, semver::Version::parse(v).unwrap()))
+                .collect())
+        }
+    }
+
+    const MANIFEST: &str = r#"[package]
+name = "mock"
+version = "0.1.0"
+
+[dependencies]
+foo = "0.1"
+bar = "1.0"
+pinned = "=0.4.0"
+local = { path = "../local" }
+
+[dev-dependencies]
+renamed-foo = { version = "0.2.0", package = "foo" }
+"#;
+
+    fn outcomes(report: &UpgradeReport) -> Vec<(&str, Option<&str>, &UpgradeOutcome)> {
+        report
+            .dependencies
+            .iter()
+            .map(|dep| {
+                (
+                    dep.name_in_manifest.as_str(),
+                    dep.new_requirement.as_deref(),
+                    &dep.outcome,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn upgrade_through_api_reports_outcomes() {
+        let tmpdir = tempdir::TempDir::new("upgrade-api").unwrap();
+        let manifest_path = tmpdir.path().join("Cargo.toml");
+        fs::write(&manifest_path, MANIFEST).unwrap();
+
+        let options = UpgradeOptions::default().exclude(vec!["bar".to_owned()]);
+        let report = upgrade(&manifest_path, &options, |_| MockSource).unwrap();
+        let skipped = |reason: &str| UpgradeOutcome::Skipped(reason.to_owned());
+        assert_eq!(
+            outcomes(&report),
+            vec![
+                ("foo", Some("0.2.0"), &UpgradeOutcome::Applied),
+                ("bar", Some("1.0"), &UpgradeOutcome::Excluded),
+                (
+                    "pinned",
+                    Some("=0.4.0"),
+                    &skipped("it has an exact version requirement")
+                ),
+                ("local", None, &skipped("it is not a registry dependency")),
+                ("renamed-foo", Some("0.2.0"), &UpgradeOutcome::Unchanged),
+            ]
+        );
+        assert_eq!(report.applied().count(), 1);
+        assert_eq!(
+            report.dependencies[0].old_requirement.as_deref(),
+            Some("0.1")
+        );
+
+        let upgraded = fs::read_to_string(&manifest_path).unwrap();
+        assert!(upgraded.contains("foo = \"0.2.0\""));
+        assert!(upgraded.contains("bar = \"1.0\""));
+    }
+
+    #[test]
+    fn dry_run_through_api_leaves_manifest_alone() {
+        let tmpdir = tempdir::TempDir::new("upgrade-api").unwrap();
+        let manifest_path = tmpdir.path().join("Cargo.toml");
+        fs::write(&manifest_path, MANIFEST).unwrap();
+
+        let options = UpgradeOptions::default()
+            .only(vec!["bar".to_owned()])
+            .skip_compatible(true)
+            .dry_run(true);
+        let report = upgrade(&manifest_path, &options, |_| MockSource).unwrap();
+        assert_eq!(
+            outcomes(&report)[..2],
+            [
+                ("foo", Some("0.1"), &UpgradeOutcome::Excluded),
+                ("bar", Some("1.0"), &UpgradeOutcome::Unchanged),
+            ]
+        );
+
+        let options = UpgradeOptions::default().dry_run(true);
+        let report = upgrade(&manifest_path, &options, |_| MockSource).unwrap();
+        assert_eq!(report.applied().count(), 2);
+        assert_eq!(fs::read_to_string(&manifest_path).unwrap(), MANIFEST);
+    }
+}
@@ -0,0 +1,121 @@
+//! Projects that vendor their dependencies with `cargo vendor`, and replace the registry with the
+//! directory of vendored crates in their cargo config
+use crate::errors::*;
+use crate::fetch::is_offline;
+use crate::registry::{
+    original_registry_url, registry_url, registry_url_lenient, source_location, DIRECTORY_PREFIX,
+};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use url::Url;
+
+/// Find the directory of vendored crates that `registry` (crates.io if `None`) is replaced with
+/// in the cargo configs applying to `manifest_path`, if it is replaced with a directory source.
+///
+/// With `lenient`, cargo configs that cannot be parsed are skipped.
+pub fn vendor_directory(
+    manifest_path: &Path,
+    registry: Option<&str>,
+    lenient: bool,
+) -> Result<Option<PathBuf>> {
+    let url = if lenient {
+        registry_url_lenient(manifest_path, registry)?
+    } else {
+        registry_url(manifest_path, registry)?
+    };
+    if !url.as_str().starts_with(DIRECTORY_PREFIX) {
+        return Ok(None);
+    }
+    source_location(&url)
+        .to_file_path()
+        .map(Some)
+        .map_err(|()| ErrorKind::InvalidCargoConfig.into())
+}
+
+/// Find the URL of a registry to look up the latest versions in, like `registry_url`, unless the
+/// registry is replaced with a directory of vendored crates. That only has the versions vendored
+/// before, so the original registry is used instead, except offline.
+pub fn upstream_registry_url(
+    manifest_path: &Path,
+    registry: Option<&str>,
+    lenient: bool,
+) -> Result<Url> {
+    let url = if lenient {
+        registry_url_lenient(manifest_path, registry)?
+    } else {
+        registry_url(manifest_path, registry)?
+    };
+    if url.as_str().starts_with(DIRECTORY_PREFIX) && !is_offline() {
+        original_registry_url(manifest_path, registry, lenient)
+    } else {
+        Ok(url)
+    }
+}
+
+/// Refresh the vendored crates in `directory` for the package or workspace at `manifest_path`
+/// by running `cargo vendor`, e.g. after its dependencies were edited.
+///
+/// The cargo binary is taken from `CARGO`, as set for cargo subcommands, or else found in `PATH`.
+pub fn revendor(manifest_path: &Path, directory: &Path) -> Result<()> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let status = Command::new(cargo)
+        .arg("vendor")
+        .arg("--manifest-path")
+        .arg(manifest_path)
+        .arg(directory)
+        // `cargo vendor` prints the config that uses the directory, which is already in place.
+        .stdout(Stdio::null())
+        .status()
+        .chain_err(|| "Failed to run `cargo vendor`")?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(ErrorKind::VendorFailed(directory.display().to_string()).into())
+    }
+}
+
+#[test]
+fn find_vendor_directory() {
+    let tmpdir = tempdir::TempDir::new("cargo-edit-config").unwrap();
+    let config_dir = tmpdir.path().join(".cargo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config"),
+        "[source.crates-io]\n\
+         replace-with = \"vendored-sources\"\n\
+         [source.vendored-sources]\n\
+         directory = \"vendor\"\n\
+         [source.alternative]\n\
+         replace-with = \"mirror\"\n\
+         [source.mirror]\n\
+         registry = \"https://mirror.example.com/index\"\n\
+         [registries.alternative]\n\
+         index = \"https://example.com/index\"\n",
+    )
+    .unwrap();
+    let manifest_path = tmpdir.path().join("Cargo.toml");
+
+    assert_eq!(
+        vendor_directory(&manifest_path, None, false).unwrap(),
+        Some(tmpdir.path().join("vendor"))
+    );
+    assert_eq!(
+        vendor_directory(&manifest_path, Some("alternative"), false).unwrap(),
+        None
+    );
+    assert_eq!(
+        upstream_registry_url(&manifest_path, None, false)
+            .unwrap()
+            .as_str(),
+        original_registry_url(&manifest_path, None, false)
+            .unwrap()
+            .as_str()
+    );
+    // Other source replacements, like mirrors, are still followed.
+    assert_eq!(
+        upstream_registry_url(&manifest_path, Some("alternative"), false)
+            .unwrap()
+            .as_str(),
+        "https://mirror.example.com/index"
+    );
+}
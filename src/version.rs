@@ -0,0 +1,5 @@
+//! Working with versions and version requirements
+
+pub mod bump;
+pub mod ranges;
+pub mod style;
@@ -0,0 +1,119 @@
+//! Bumping versions to the next release or release candidate
+//!
+//! The pre-release of a version is released by bumping the level it was made for, e.g. a `major`
+//! bump of `2.0.0-rc.1` gives `2.0.0`, while `1.2.0-rc.1` becomes `2.0.0`. Build metadata is
+//! always dropped.
+
+use crate::errors::*;
+use semver::{Identifier, Version};
+use std::str::FromStr;
+
+/// Which part of a version to bump
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BumpLevel {
+    /// `1.2.3` becomes `2.0.0`
+    Major,
+    /// `1.2.3` becomes `1.3.0`
+    Minor,
+    /// `1.2.3` becomes `1.2.4`
+    Patch,
+    /// `1.2.3` becomes `1.2.4-rc.1`, `1.2.4-rc.1` becomes `1.2.4-rc.2`, and other pre-releases
+    /// such as `1.2.4-beta.2` become `1.2.4-rc.1`
+    Rc,
+}
+
+impl FromStr for BumpLevel {
+    type Err = Error;
+
+    fn from_str(level: &str) -> Result<BumpLevel> {
+        match level {
+            "major" => Ok(BumpLevel::Major),
+            "minor" => Ok(BumpLevel::Minor),
+            "patch" => Ok(BumpLevel::Patch),
+            "rc" => Ok(BumpLevel::Rc),
+            _ => Err(ErrorKind::InvalidBumpLevel(level.to_owned()).into()),
+        }
+    }
+}
+
+/// The version following `version` at `level`
+pub fn bump_version(version: &Version, level: BumpLevel) -> Version {
+    let mut bumped = version.clone();
+    bumped.pre.clear();
+    bumped.build.clear();
+    let is_pre = !version.pre.is_empty();
+    match level {
+        BumpLevel::Major => {
+            if !is_pre || version.minor != 0 || version.patch != 0 {
+                bumped.major += 1;
+                bumped.minor = 0;
+                bumped.patch = 0;
+            }
+        }
+        BumpLevel::Minor => {
+            if !is_pre || version.patch != 0 {
+                bumped.minor += 1;
+                bumped.patch = 0;
+            }
+        }
+        BumpLevel::Patch => {
+            if !is_pre {
+                bumped.patch += 1;
+            }
+        }
+        BumpLevel::Rc => {
+            let candidate = match version.pre.as_slice() {
+                [] => {
+                    bumped.patch += 1;
+                    1
+                }
+                [Identifier::AlphaNumeric(rc), Identifier::Numeric(n)] if rc == "rc" => n + 1,
+                _ => 1,
+            };
+            bumped.pre = vec![
+                Identifier::AlphaNumeric("rc".to_owned()),
+                Identifier::Numeric(candidate),
+            ];
+        }
+    }
+    bumped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bump(version: &str, level: &str) -> String {
+        let version = Version::parse(version).unwrap();
+        bump_version(&version, level.parse().unwrap()).to_string()
+    }
+
+    #[test]
+    fn bump_releases() {
+        assert_eq!(bump("1.2.3", "major"), "2.0.0");
+        assert_eq!(bump("1.2.3", "minor"), "1.3.0");
+        assert_eq!(bump("1.2.3", "patch"), "1.2.4");
+        assert_eq!(bump("0.1.9+build.5", "patch"), "0.1.10");
+    }
+
+    #[test]
+    fn bump_pre_releases() {
+        assert_eq!(bump("2.0.0-rc.1", "major"), "2.0.0");
+        assert_eq!(bump("1.2.0-rc.1", "major"), "2.0.0");
+        assert_eq!(bump("1.3.0-beta.2", "minor"), "1.3.0");
+        assert_eq!(bump("1.2.4-alpha", "patch"), "1.2.4");
+    }
+
+    #[test]
+    fn bump_release_candidates() {
+        assert_eq!(bump("1.2.3", "rc"), "1.2.4-rc.1");
+        assert_eq!(bump("1.2.4-rc.1", "rc"), "1.2.4-rc.2");
+        assert_eq!(bump("2.0.0-beta.3", "rc"), "2.0.0-rc.1");
+    }
+
+    #[test]
+    fn parse_bump_levels() {
+        assert_eq!("rc".parse::<BumpLevel>().unwrap(), BumpLevel::Rc);
+        assert!("prerelease".parse::<BumpLevel>().is_err());
+    }
+}
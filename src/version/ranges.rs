@@ -0,0 +1,488 @@
+//! Set arithmetic on version requirements
+//!
+//! A requirement is turned into the set of versions it matches, kept as sorted, disjoint,
+//! half-open ranges. Comma-separated comparators intersect and `||` unites, so requirements can
+//! be compared regardless of how they are spelled: `^1.2`, `~1` with `>=1.2` and
+//! `>=1.2.0, <2.0.0` all match the same versions.
+//!
+//! Versions are ordered by semver precedence, ignoring build metadata. Like Cargo, a bound that
+//! is a release (`<2.0.0`, `>1.2`, or the ones implied by `^` and `~`) doesn't let in the
+//! pre-releases of that version. Cargo's rule that other pre-releases only match requirements
+//! naming the same `major.minor.patch` is not modelled, so ranges can hold more pre-releases than
+//! Cargo would match.
+
+use crate::errors::*;
+use semver::{Identifier, Version, VersionReq};
+
+/// A half-open range of versions, `lower <= version < upper`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Range {
+    lower: Version,
+    /// `None` if the range has no upper bound
+    upper: Option<Version>,
+}
+
+impl Range {
+    fn is_empty(&self) -> bool {
+        self.upper
+            .as_ref()
+            .is_some_and(|upper| self.lower >= *upper)
+    }
+
+    fn contains(&self, version: &Version) -> bool {
+        self.lower <= *version && self.upper.as_ref().is_none_or(|upper| version < upper)
+    }
+
+    fn intersection(&self, other: &Range) -> Range {
+        let lower = std::cmp::max(&self.lower, &other.lower).clone();
+        let upper = match (&self.upper, &other.upper) {
+            (Some(a), Some(b)) => Some(std::cmp::min(a, b).clone()),
+            (Some(a), None) | (None, Some(a)) => Some(a.clone()),
+            (None, None) => None,
+        };
+        Range { lower, upper }
+    }
+}
+
+/// The set of versions matched by a version requirement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionRanges {
+    /// Sorted, non-empty ranges that neither overlap nor touch
+    ranges: Vec<Range>,
+}
+
+impl VersionRanges {
+    /// The set of all versions, as matched by `*`
+    pub fn any() -> VersionRanges {
+        VersionRanges {
+            ranges: vec![Range {
+                lower: min_version(),
+                upper: None,
+            }],
+        }
+    }
+
+    /// Parse a requirement like `^1.2`, `>=1.0, <1.5` or `1.* || 2.*`
+    pub fn parse(req: &str) -> Result<VersionRanges> {
+        let invalid = || ErrorKind::ParseVersionReq(req.into());
+        let mut union = VersionRanges::from_ranges(Vec::new());
+        for comparators in req.split("||") {
+            let mut intersection = VersionRanges::any();
+            let mut found = false;
+            for comparator in comparators.split(',').map(str::trim) {
+                if comparator.is_empty() {
+                    return Err(invalid().into());
+                }
+                let range = parse_comparator(comparator).ok_or_else(invalid)?;
+                intersection = intersection.intersection(&VersionRanges::from_ranges(vec![range]));
+                found = true;
+            }
+            if !found {
+                return Err(invalid().into());
+            }
+            union = union.union(&intersection);
+        }
+        Ok(union)
+    }
+
+    /// Get the set of versions matched by a parsed requirement
+    pub fn from_req(req: &VersionReq) -> Result<VersionRanges> {
+        VersionRanges::parse(&req.to_string())
+    }
+
+    fn from_ranges(mut ranges: Vec<Range>) -> VersionRanges {
+        ranges.retain(|range| !range.is_empty());
+        ranges.sort_by(|a, b| a.lower.cmp(&b.lower));
+        let mut merged: Vec<Range> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            if let Some(last) = merged.last_mut() {
+                let joined = last
+                    .upper
+                    .as_ref()
+                    .is_none_or(|upper| touches(upper, &range.lower));
+                if joined {
+                    last.upper = match (&last.upper, &range.upper) {
+                        (Some(a), Some(b)) => Some(std::cmp::max(a, b).clone()),
+                        _ => None,
+                    };
+                    continue;
+                }
+            }
+            merged.push(range);
+        }
+        VersionRanges { ranges: merged }
+    }
+
+    /// Whether no version is in the set, e.g. for `>=2, <1`
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The lowest version in the set, or `None` if it is empty or unbounded below, e.g. for `<2`
+    pub fn lowest(&self) -> Option<&Version> {
+        self.ranges
+            .first()
+            .map(|range| &range.lower)
+            .filter(|lower| **lower != min_version())
+    }
+
+    /// Whether `version` is in the set
+    pub fn contains(&self, version: &Version) -> bool {
+        let mut version = version.clone();
+        version.build.clear();
+        self.ranges.iter().any(|range| range.contains(&version))
+    }
+
+    /// The versions in both sets
+    pub fn intersection(&self, other: &VersionRanges) -> VersionRanges {
+        VersionRanges::from_ranges(
+            self.ranges
+                .iter()
+                .flat_map(|a| other.ranges.iter().map(move |b| a.intersection(b)))
+                .collect(),
+        )
+    }
+
+    /// The versions in either set
+    pub fn union(&self, other: &VersionRanges) -> VersionRanges {
+        VersionRanges::from_ranges(self.ranges.iter().chain(&other.ranges).cloned().collect())
+    }
+
+    /// Whether every version in this set is in `other` as well
+    pub fn is_subset(&self, other: &VersionRanges) -> bool {
+        self.intersection(other) == *self
+    }
+
+    /// Whether some version is in both sets
+    pub fn overlaps(&self, other: &VersionRanges) -> bool {
+        !self.intersection(other).is_empty()
+    }
+}
+
+/// Whether the requirements `a` and `b` match the same versions
+pub fn equivalent(a: &str, b: &str) -> Result<bool> {
+    Ok(VersionRanges::parse(a)? == VersionRanges::parse(b)?)
+}
+
+/// Whether every version matching the requirement `a` matches `b` as well
+pub fn is_subset(a: &str, b: &str) -> Result<bool> {
+    Ok(VersionRanges::parse(a)?.is_subset(&VersionRanges::parse(b)?))
+}
+
+/// Whether some version matches both requirements `a` and `b`
+pub fn overlaps(a: &str, b: &str) -> Result<bool> {
+    Ok(VersionRanges::parse(a)?.overlaps(&VersionRanges::parse(b)?))
+}
+
+/// Whether a range ending at `upper` can be joined with one starting at `lower`. Ranges only
+/// separated by the pre-releases of a release bound are joined too, as in `<1.5.0 || >=1.5.0`.
+fn touches(upper: &Version, lower: &Version) -> bool {
+    lower <= upper
+        || (lower.pre.is_empty() && *upper == release_bound(lower.major, lower.minor, lower.patch))
+}
+
+/// The smallest version there is, `0.0.0-0`
+fn min_version() -> Version {
+    version(0, 0, 0, vec![Identifier::Numeric(0)])
+}
+
+fn version(major: u64, minor: u64, patch: u64, pre: Vec<Identifier>) -> Version {
+    Version {
+        major,
+        minor,
+        patch,
+        pre,
+        build: Vec::new(),
+    }
+}
+
+/// A version as written in a comparator, where minor and patch may be missing or wildcards
+struct Partial {
+    major: u64,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre: Vec<Identifier>,
+    /// Whether the version ended in a wildcard, as in `1.*`
+    wildcard: bool,
+}
+
+impl Partial {
+    fn parse(s: &str) -> Option<Partial> {
+        // Build metadata doesn't take part in matching.
+        let s = s.split('+').next()?;
+        let mut parts = s.splitn(2, '-');
+        let core = parts.next()?;
+        let pre = match parts.next() {
+            Some(pre) => pre
+                .split('.')
+                .map(|id| {
+                    if id.is_empty() {
+                        None
+                    } else if id.bytes().all(|b| b.is_ascii_digit()) {
+                        id.parse().ok().map(Identifier::Numeric)
+                    } else if id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+                        Some(Identifier::AlphaNumeric(id.to_owned()))
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Option<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+
+        let mut numbers = Vec::new();
+        let mut wildcard = false;
+        for part in core.split('.') {
+            match part {
+                "*" | "x" | "X" => wildcard = true,
+                _ if wildcard => return None,
+                _ if !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()) => {
+                    numbers.push(part.parse().ok()?)
+                }
+                _ => return None,
+            }
+        }
+        if numbers.is_empty() || numbers.len() > 3 || (numbers.len() < 3 && !pre.is_empty()) {
+            return None;
+        }
+        Some(Partial {
+            major: numbers[0],
+            minor: numbers.get(1).cloned(),
+            patch: numbers.get(2).cloned(),
+            pre,
+            wildcard,
+        })
+    }
+
+    /// The smallest version matching this partial version
+    fn lower(&self) -> Version {
+        version(
+            self.major,
+            self.minor.unwrap_or(0),
+            self.patch.unwrap_or(0),
+            self.pre.clone(),
+        )
+    }
+
+    /// The exclusive upper bound of the versions matching this partial version
+    fn upper(&self) -> Version {
+        match (self.minor, self.patch) {
+            (Some(_), Some(_)) => next_version(&self.lower()),
+            (Some(minor), None) => release_bound(self.major, minor + 1, 0),
+            (None, _) => release_bound(self.major + 1, 0, 0),
+        }
+    }
+}
+
+/// The exclusive upper bound for versions below the release `major.minor.patch`, which leaves out
+/// its pre-releases
+fn release_bound(major: u64, minor: u64, patch: u64) -> Version {
+    version(major, minor, patch, vec![Identifier::Numeric(0)])
+}
+
+/// The version right after `v` by precedence
+fn next_version(v: &Version) -> Version {
+    if v.pre.is_empty() {
+        release_bound(v.major, v.minor, v.patch + 1)
+    } else {
+        let mut pre = v.pre.clone();
+        pre.push(Identifier::Numeric(0));
+        version(v.major, v.minor, v.patch, pre)
+    }
+}
+
+fn parse_comparator(comparator: &str) -> Option<Range> {
+    if ["*", "x", "X"].contains(&comparator) {
+        return Some(Range {
+            lower: min_version(),
+            upper: None,
+        });
+    }
+    let (op, rest) = [">=", "<=", ">", "<", "=", "~", "^"]
+        .iter()
+        .find(|op| comparator.starts_with(*op))
+        .map(|op| (*op, &comparator[op.len()..]))
+        .unwrap_or(("", comparator));
+    let partial = Partial::parse(rest.trim())?;
+
+    let (lower, upper) = match op {
+        "=" => (partial.lower(), Some(partial.upper())),
+        "" if partial.wildcard => (partial.lower(), Some(partial.upper())),
+        ">=" => (partial.lower(), None),
+        ">" => {
+            let lower = match (partial.minor, partial.patch) {
+                (Some(minor), Some(patch)) if partial.pre.is_empty() => {
+                    version(partial.major, minor, patch + 1, Vec::new())
+                }
+                (Some(_), Some(_)) => next_version(&partial.lower()),
+                (Some(minor), None) => version(partial.major, minor + 1, 0, Vec::new()),
+                (None, _) => version(partial.major + 1, 0, 0, Vec::new()),
+            };
+            (lower, None)
+        }
+        "<" => {
+            let upper = if partial.pre.is_empty() {
+                release_bound(
+                    partial.major,
+                    partial.minor.unwrap_or(0),
+                    partial.patch.unwrap_or(0),
+                )
+            } else {
+                partial.lower()
+            };
+            (min_version(), Some(upper))
+        }
+        "<=" => (min_version(), Some(partial.upper())),
+        "~" => {
+            let upper = match partial.minor {
+                Some(minor) => release_bound(partial.major, minor + 1, 0),
+                None => release_bound(partial.major + 1, 0, 0),
+            };
+            (partial.lower(), Some(upper))
+        }
+        _ => {
+            // `^` and bare versions
+            let upper = match (partial.major, partial.minor, partial.patch) {
+                (0, Some(0), Some(patch)) => release_bound(0, 0, patch + 1),
+                (0, Some(minor), _) => release_bound(0, minor + 1, 0),
+                (major, _, _) => release_bound(major + 1, 0, 0),
+            };
+            (partial.lower(), Some(upper))
+        }
+    };
+    Some(Range { lower, upper })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_equivalent(a: &str, b: &str) {
+        assert!(equivalent(a, b).unwrap(), "`{}` should match `{}`", a, b);
+    }
+
+    fn assert_not_equivalent(a: &str, b: &str) {
+        assert!(
+            !equivalent(a, b).unwrap(),
+            "`{}` shouldn't match `{}`",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn caret_equivalences() {
+        assert_equivalent("1.2.3", "^1.2.3");
+        assert_equivalent("^1.2.3", ">=1.2.3, <2.0.0");
+        assert_equivalent("^1.2", ">=1.2.0, <2");
+        assert_equivalent("^1", "1.*");
+        assert_equivalent("^0.2.3", ">=0.2.3, <0.3.0");
+        assert_equivalent("^0.0.3", "=0.0.3");
+        assert_equivalent("^0.0", "0.0.*");
+        assert_equivalent("^0", "0.x");
+        assert_not_equivalent("^1.2.3", "^1.2");
+        assert_not_equivalent("^0.2.3", "^0.2.4");
+        assert_not_equivalent("^0.2", "^0");
+    }
+
+    #[test]
+    fn tilde_equivalences() {
+        assert_equivalent("~1.2.3", ">=1.2.3, <1.3.0");
+        assert_equivalent("~1.2", "1.2.*");
+        assert_equivalent("~1.2", "=1.2");
+        assert_equivalent("~1", "^1");
+        assert_equivalent("~0.2.3", "^0.2.3");
+        assert_not_equivalent("~1.2.3", "^1.2.3");
+        assert_not_equivalent("~0.0.3", "^0.0.3");
+    }
+
+    #[test]
+    fn comparator_equivalences() {
+        assert_equivalent(">1.2.3", ">=1.2.4");
+        assert_equivalent(">1.2", ">=1.3.0");
+        assert_equivalent("<=1.2.3", "<1.2.4");
+        assert_equivalent("<=1.2", "<1.3");
+        assert_equivalent(">= 1.0.0, < 2.0.0", ">=1.0.0,<2.0.0");
+        assert_equivalent("=1.2.3", ">=1.2.3, <=1.2.3");
+        assert_equivalent("=1.2.3+build.5", "=1.2.3");
+        assert_equivalent("*", ">=0.0.0-0");
+        assert_equivalent(">=1, <1.5 || >=1.5, <2", "^1");
+        assert_equivalent(">=2, <1", ">3, <3");
+        assert_not_equivalent(">1.2.3", ">=1.2.3");
+        assert_not_equivalent("<2.0.0", "<2.0.0-beta");
+        assert_not_equivalent("=1.2.3-alpha", "=1.2.3");
+        assert_not_equivalent("*", ">=0.0.0");
+    }
+
+    #[test]
+    fn subsets_and_overlaps() {
+        assert!(is_subset("~1.2.3", "^1.2").unwrap());
+        assert!(is_subset("=1.5.0", ">=1.0, <2").unwrap());
+        assert!(!is_subset("^1.2", "~1.2.3").unwrap());
+        assert!(is_subset(">=2, <1", "=0.1.0").unwrap());
+
+        assert!(overlaps("^1.2", ">=1.9").unwrap());
+        assert!(overlaps("<1.2.4", "~1.2.3").unwrap());
+        assert!(!overlaps("^1", "^2").unwrap());
+        assert!(!overlaps("<1.2.3", ">=1.2.3").unwrap());
+        assert!(!overlaps("^0.1", "^0.2").unwrap());
+    }
+
+    #[test]
+    fn contains_versions() {
+        let ranges = VersionRanges::parse("^1.2 || =3.0.0-beta.1").unwrap();
+        let contains = |v: &str| ranges.contains(&Version::parse(v).unwrap());
+        assert!(contains("1.2.0"));
+        assert!(contains("1.9.9+build"));
+        assert!(contains("3.0.0-beta.1"));
+        assert!(!contains("1.1.9"));
+        assert!(!contains("2.0.0-alpha"));
+        assert!(!contains("3.0.0"));
+    }
+
+    #[test]
+    fn from_parsed_requirement() {
+        for req in &["^1.2.3", "~0.3", ">= 1.0, < 1.4", "=0.0.1", "*"] {
+            let parsed = VersionReq::parse(req).unwrap();
+            assert_eq!(
+                VersionRanges::from_req(&parsed).unwrap(),
+                VersionRanges::parse(req).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_requirements() {
+        for req in &[
+            "",
+            "1.2.3,",
+            "abc",
+            "1.*.3",
+            ">=1.2-beta",
+            "1.2.3.4",
+            "^1 || ",
+        ] {
+            assert!(
+                VersionRanges::parse(req).is_err(),
+                "`{}` should be invalid",
+                req
+            );
+        }
+    }
+
+    #[test]
+    fn lowest_matching_version() {
+        let lowest = |req: &str| {
+            VersionRanges::parse(req)
+                .unwrap()
+                .lowest()
+                .map(ToString::to_string)
+        };
+        assert_eq!(lowest("1.2").as_deref(), Some("1.2.0"));
+        assert_eq!(lowest("~0.3.1").as_deref(), Some("0.3.1"));
+        assert_eq!(lowest(">= 1.4, < 2 || 1.0").as_deref(), Some("1.0.0"));
+        assert_eq!(lowest("<2"), None);
+        assert_eq!(lowest("*"), None);
+        assert_eq!(lowest(">=2, <1"), None);
+    }
+}
@@ -0,0 +1,93 @@
+//! Writing versions as requirements of a fixed form
+//!
+//! Only bare versions are rewritten, e.g. `1.2.3` or `1.2`. Anything else, like a requirement
+//! that is already spelled out, is kept as it is.
+
+use crate::errors::*;
+use semver::Version;
+use std::str::FromStr;
+
+/// The form of the requirements to write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequirementStyle {
+    /// `1.2.3` becomes `^1.2.3`
+    Caret,
+    /// `1.2.3` becomes `~1.2.3`
+    Tilde,
+    /// `1.2.3` becomes `=1.2.3`
+    Exact,
+    /// `1.2.3` becomes `~1.2`, which allows any patch release of the minor version
+    Minor,
+}
+
+impl FromStr for RequirementStyle {
+    type Err = Error;
+
+    fn from_str(style: &str) -> Result<RequirementStyle> {
+        match style {
+            "caret" => Ok(RequirementStyle::Caret),
+            "tilde" => Ok(RequirementStyle::Tilde),
+            "exact" => Ok(RequirementStyle::Exact),
+            "minor" => Ok(RequirementStyle::Minor),
+            _ => Err(ErrorKind::InvalidRequirementStyle(style.to_owned()).into()),
+        }
+    }
+}
+
+impl RequirementStyle {
+    /// Write `version` as a requirement of this style. Pre-releases keep all of their components,
+    /// as `~1.2` would not match them.
+    pub fn requirement(self, version: &str) -> String {
+        let version = version.trim();
+        if !is_plain_version(version) {
+            return version.to_owned();
+        }
+        match self {
+            RequirementStyle::Caret => format!("^{}", version),
+            RequirementStyle::Tilde => format!("~{}", version),
+            RequirementStyle::Exact => format!("={}", version),
+            RequirementStyle::Minor => match Version::parse(version) {
+                Ok(version) if version.pre.is_empty() => {
+                    format!("~{}.{}", version.major, version.minor)
+                }
+                _ => format!("~{}", version),
+            },
+        }
+    }
+}
+
+/// Whether `version` is a bare version, possibly with fewer than three components, rather than a
+/// requirement with operators, wildcards or several comparators
+fn is_plain_version(version: &str) -> bool {
+    !version.is_empty() && !version.contains(|c: char| "^~=<>*,".contains(c) || c.is_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(style: &str, version: &str) -> String {
+        style
+            .parse::<RequirementStyle>()
+            .unwrap()
+            .requirement(version)
+    }
+
+    #[test]
+    fn write_requirement_styles() {
+        assert_eq!(style("caret", "1.2.3"), "^1.2.3");
+        assert_eq!(style("tilde", "1.2.3"), "~1.2.3");
+        assert_eq!(style("exact", "1.2.3"), "=1.2.3");
+        assert_eq!(style("minor", "1.2.3"), "~1.2");
+        assert_eq!(style("tilde", "1.2"), "~1.2");
+    }
+
+    #[test]
+    fn keep_pre_releases_and_requirements() {
+        assert_eq!(style("minor", "2.0.0-rc.1"), "~2.0.0-rc.1");
+        assert_eq!(style("exact", "~1.2"), "~1.2");
+        assert_eq!(style("caret", ">=1.2, <2"), ">=1.2, <2");
+        assert_eq!(style("minor", "1.*"), "1.*");
+        assert!("loose".parse::<RequirementStyle>().is_err());
+    }
+}
@@ -0,0 +1,69 @@
+//! Warnings, which `--strict` turns into errors
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::errors::*;
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+static STATUS_HIDDEN: AtomicBool = AtomicBool::new(false);
+
+/// Set how `warn` reports warnings: with `strict`, they fail instead of being printed, and with
+/// `quiet`, they are not printed. `quiet` also hides the messages about upgraded dependencies.
+pub fn configure_warnings(strict: bool, quiet: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Whether `configure_warnings` was asked to be quiet, e.g. because stdout is kept for JSON
+/// records.
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Hide the messages about upgraded dependencies, but not the warnings, e.g. because a summary
+/// of the upgrades is printed instead.
+pub fn hide_status_messages(hidden: bool) {
+    STATUS_HIDDEN.store(hidden, Ordering::Relaxed);
+}
+
+/// Whether the messages about upgraded dependencies are hidden, by `configure_warnings` or
+/// `hide_status_messages`.
+pub fn status_messages_hidden() -> bool {
+    is_quiet() || STATUS_HIDDEN.load(Ordering::Relaxed)
+}
+
+/// Report a warning, printed as `WARN: <message>`.
+///
+/// With `--strict`, this fails with `ErrorKind::Warning` instead, so that callers propagating it
+/// stop right away.
+pub fn warn(message: impl Into<String>) -> Result<()> {
+    report(
+        message.into(),
+        STRICT.load(Ordering::Relaxed),
+        QUIET.load(Ordering::Relaxed),
+    )
+}
+
+fn report(message: String, strict: bool, quiet: bool) -> Result<()> {
+    if strict {
+        return Err(ErrorKind::Warning(message).into());
+    }
+    if !quiet {
+        println!("WARN: {}", message);
+    }
+    Ok(())
+}
+
+#[test]
+fn strict_warnings_fail() {
+    assert!(report("printed".to_owned(), false, false).is_ok());
+    assert!(report("not printed".to_owned(), false, true).is_ok());
+    // Quiet doesn't hide a warning that fails.
+    match report("`foo` has no feature `bar`".to_owned(), true, true) {
+        Err(Error(ErrorKind::Warning(message), _)) => {
+            assert_eq!(message, "`foo` has no feature `bar`")
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
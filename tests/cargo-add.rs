@@ -4,8 +4,9 @@ extern crate pretty_assertions;
 use std::process;
 mod utils;
 use crate::utils::{
-    clone_out_test, execute_bad_command, execute_command, get_command_path, get_toml,
-    setup_alt_registry_config,
+    clone_out_test, copy_workspace_test, execute_bad_command, execute_command,
+    execute_command_in_dir, get_command_path, get_toml, setup_alt_registry_config,
+    WORKSPACE_MEMBERS,
 };
 
 /// Some of the tests need to have a crate name that does not exist on crates.io. Hence this rather
@@ -52,6 +53,117 @@ fn adds_prerelease_dependency() {
     assert_eq!(val.as_str().unwrap(), "my-package--PRERELEASE_VERSION_TEST");
 }
 
+#[test]
+fn checks_rust_version_of_added_crates() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "my-package",
+        "--rust-version",
+        "latest",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("`latest` is not a valid Rust version")
+    .unwrap();
+
+    execute_command(&["add", "my-package", "--rust-version", "1.56"], &manifest);
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["my-package"];
+    assert_eq!(val.as_str().unwrap(), "my-package--CURRENT_VERSION_TEST");
+}
+
+#[test]
+fn explains_pinned_requirement_in_comment() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(
+        &["add", "my-package@=1.2.3", "--reason", "CVE workaround"],
+        &manifest,
+    );
+    let pinned = "my-package = \"=1.2.3\" # pinned: CVE workaround\n";
+    assert!(std::fs::read_to_string(&manifest).unwrap().contains(pinned));
+
+    // Re-adding the same requirement keeps the comment.
+    execute_command(&["add", "my-package@=1.2.3"], &manifest);
+    assert!(std::fs::read_to_string(&manifest).unwrap().contains(pinned));
+
+    execute_command(&["add", "my-package@=1.2.3", "--clear-reason"], &manifest);
+    let contents = std::fs::read_to_string(&manifest).unwrap();
+    assert!(contents.contains("my-package = \"=1.2.3\"\n"));
+    assert!(!contents.contains("pinned"));
+
+    // Only exact requirements are pins.
+    execute_bad_command(&["add", "other@1.2", "--reason", "MSRV"], &manifest);
+}
+
+#[test]
+fn strict_turns_warnings_into_errors() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_bad_command(
+        &[
+            "add",
+            "my-package",
+            "--features",
+            "unknown-feature",
+            "--strict",
+        ],
+        &manifest,
+    );
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"]["my-package"].is_none());
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "my-package",
+        "--features",
+        "unknown-feature",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("WARN: `my-package` has no feature `unknown-feature`")
+    .unwrap();
+    let toml = get_toml(&manifest);
+    assert!(!toml["dependencies"]["my-package"].is_none());
+}
+
+#[test]
+fn readding_identical_dependency_leaves_manifest_untouched() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    execute_command(&["add", "my-package", "--vers", "0.1"], &manifest);
+    let modified = || std::fs::metadata(&manifest).unwrap().modified().unwrap();
+    let before = modified();
+
+    // Make sure that a write would show up in the mtime, even with a coarse resolution.
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "my-package",
+        "--vers",
+        "0.1",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("No changes, the manifest was not written.")
+    .unwrap();
+
+    assert_eq!(modified(), before);
+}
+
 fn upgrade_test_helper(upgrade_method: &str, expected_prefix: &str) {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
 
@@ -88,6 +200,97 @@ fn adds_dependency_with_upgrade_all() {
     upgrade_test_helper("all", ">=");
 }
 
+fn requirement_style_test_helper(style: &str, expected_prefix: &str) {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "my-package", style], &manifest);
+
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["my-package"];
+    let expected_result = format!("{0}my-package--CURRENT_VERSION_TEST", expected_prefix);
+    assert_eq!(val.as_str().unwrap(), expected_result);
+}
+
+#[test]
+fn adds_dependency_with_caret_requirement() {
+    requirement_style_test_helper("--caret", "^");
+}
+#[test]
+fn adds_dependency_with_tilde_requirement() {
+    requirement_style_test_helper("--tilde", "~");
+}
+#[test]
+fn adds_dependency_with_exact_requirement() {
+    requirement_style_test_helper("--exact", "=");
+}
+
+#[test]
+fn adds_dependency_with_req_style() {
+    requirement_style_test_helper("--req-style=exact", "=");
+    requirement_style_test_helper("--req-style=minor", "~");
+}
+
+#[test]
+fn adds_dependency_with_req_style_from_manifest_default() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let mut contents = std::fs::read_to_string(&manifest).unwrap();
+    contents.push_str("\n[package.metadata.cargo-edit]\nreq-style = \"tilde\"\n");
+    std::fs::write(&manifest, contents).unwrap();
+
+    execute_command(&["add", "my-package1"], &manifest);
+    execute_command(&["add", "my-package2", "--exact"], &manifest);
+
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package1"].as_str(),
+        Some("~my-package1--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(
+        toml["dependencies"]["my-package2"].as_str(),
+        Some("=my-package2--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn refreshing_dependency_keeps_its_operator() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.operators");
+
+    execute_command(
+        &["add", "my-package1", "my-package2", "my-package3"],
+        &manifest,
+    );
+
+    let toml = get_toml(&manifest);
+    let deps = &toml["dependencies"];
+    assert_eq!(
+        deps["my-package1"].as_str(),
+        Some("~my-package1--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(
+        deps["my-package2"]["version"].as_str(),
+        Some("=my-package2--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(
+        deps["my-package3"].as_str(),
+        Some("my-package3--CURRENT_VERSION_TEST")
+    );
+
+    // An explicit style wins over the existing operator.
+    execute_command(&["add", "my-package1", "--caret"], &manifest);
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package1"].as_str(),
+        Some("^my-package1--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn requirement_styles_are_mutually_exclusive() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    execute_bad_command(&["add", "my-package", "--tilde", "--exact"], &manifest);
+    assert!(get_toml(&manifest)["dependencies"].is_none());
+}
+
 #[test]
 fn adds_dependency_with_upgrade_bad() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -96,7 +299,7 @@ fn adds_dependency_with_upgrade_bad() {
     let toml = get_toml(&manifest);
     assert!(toml["dependencies"].is_none());
 
-    let upgrade_arg = format!("--upgrade=an_invalid_string",);
+    let upgrade_arg = "--upgrade=an_invalid_string".to_string();
     execute_bad_command(&["add", "my-package", upgrade_arg.as_str()], &manifest);
 }
 
@@ -207,7 +410,7 @@ fn adds_dev_build_dependency() {
 
     // cannot run with both --dev and --build at the same time
     let call = process::Command::new(get_command_path("add").as_str())
-        .args(&["add", BOGUS_CRATE_NAME, "--dev", "--build"])
+        .args(["add", BOGUS_CRATE_NAME, "--dev", "--build"])
         .arg(format!("--manifest-path={}", &manifest))
         .output()
         .unwrap();
@@ -217,145 +420,543 @@ fn adds_dev_build_dependency() {
 }
 
 #[test]
-fn adds_multiple_dev_build_dependencies() {
+fn prints_json_records_for_batch() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    execute_command(&["add", "existing", "--vers", "0.1"], &manifest);
+    let before = std::fs::read_to_string(&manifest).unwrap();
 
-    // dependencies not present beforehand
-    let toml = get_toml(&manifest);
-    assert!(toml["dev-dependencies"].is_none());
-    assert!(toml["dev-dependencies"].is_none());
-    assert!(toml["build-dependencies"].is_none());
-    assert!(toml["build-dependencies"].is_none());
+    let output = process::Command::new(get_command_path("add"))
+        .args([
+            "add",
+            "existing",
+            "new-crate",
+            "--features",
+            "a b",
+            "--message-format",
+            "json",
+            "--dry-run",
+        ])
+        .arg(format!("--manifest-path={}", manifest))
+        .env("CARGO_IS_TEST", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let records = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(records.len(), 2);
+    for record in &records {
+        assert_eq!(record["schema"], 1);
+        assert_eq!(record["table"], serde_json::json!(["dependencies"]));
+        assert_eq!(record["features"], serde_json::json!(["a", "b"]));
+        assert_eq!(record["optional"], false);
+    }
+    assert_eq!(records[0]["crate"], "existing");
+    assert_eq!(records[0]["action"], "update");
+    assert_eq!(records[0]["requirement"], "existing--CURRENT_VERSION_TEST");
+    assert_eq!(records[1]["crate"], "new-crate");
+    assert_eq!(records[1]["action"], "add");
+
+    // Nothing is written with `--dry-run`.
+    assert_eq!(std::fs::read_to_string(&manifest).unwrap(), before);
+}
+
+#[test]
+fn edits_custom_named_manifest() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let template = tmpdir.path().join("Cargo.toml.in");
+    std::fs::rename(&manifest, &template).unwrap();
+    let dir = tmpdir.path().to_str().unwrap();
 
     execute_command(
-        &["add", "my-dev-package1", "my-dev-package2", "--dev"],
-        &manifest,
+        &["add", "my-package", "--manifest-file-name", "Cargo.toml.in"],
+        dir,
+    );
+    let toml = get_toml(template.to_str().unwrap());
+    assert_eq!(
+        toml["dependencies"]["my-package"].as_str(),
+        Some("my-package--CURRENT_VERSION_TEST")
     );
+    assert!(!tmpdir.path().join("Cargo.toml").exists());
+
     execute_command(
-        &["add", "my-build-package1", "--build", "my-build-package2"],
-        &manifest,
+        &["rm", "my-package", "--manifest-file-name", "Cargo.toml.in"],
+        dir,
     );
+    let toml = get_toml(template.to_str().unwrap());
+    assert!(toml["dependencies"]["my-package"].is_none());
 
-    // dependencies present afterwards
+    // Without the override, there is no manifest in the directory.
+    execute_bad_command(&["add", "my-package"], dir);
+}
+
+#[test]
+fn accepts_manifest_directory() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "my-package"], tmpdir.path().to_str().unwrap());
     let toml = get_toml(&manifest);
-    let val = &toml["dev-dependencies"]["my-dev-package1"];
-    assert_eq!(
-        val.as_str().unwrap(),
-        "my-dev-package1--CURRENT_VERSION_TEST"
-    );
-    let val = &toml["dev-dependencies"]["my-dev-package2"];
-    assert_eq!(
-        val.as_str().unwrap(),
-        "my-dev-package2--CURRENT_VERSION_TEST"
-    );
-    let val = &toml["build-dependencies"]["my-build-package1"];
-    assert_eq!(
-        val.as_str().unwrap(),
-        "my-build-package1--CURRENT_VERSION_TEST"
-    );
-    let val = &toml["build-dependencies"]["my-build-package2"];
     assert_eq!(
-        val.as_str().unwrap(),
-        "my-build-package2--CURRENT_VERSION_TEST"
+        toml["dependencies"]["my-package"].as_str(),
+        Some("my-package--CURRENT_VERSION_TEST")
     );
+
+    let missing = tmpdir.path().join("missing");
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "my-package",
+        &format!("--manifest-path={}", missing.display()),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails()
+    .and()
+    .stderr()
+    .contains("is neither a manifest nor a directory containing `Cargo.toml`")
+    .unwrap();
 }
 
 #[test]
-fn adds_specified_version() {
+fn adds_dependency_to_named_section() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
 
-    // dependency not present beforehand
-    let toml = get_toml(&manifest);
-    assert!(toml["dependencies"].is_none());
-
+    execute_command(&["add", "my-dev-package", "--section", "dev"], &manifest);
     execute_command(
-        &["add", "versioned-package", "--vers", ">=0.1.1"],
+        &["add", "my-build-package", "--section", "build-dependencies"],
+        &manifest,
+    );
+    execute_command(
+        &[
+            "add",
+            "my-unix-package",
+            "--section",
+            "dev",
+            "--target",
+            "cfg(unix)",
+        ],
         &manifest,
     );
 
-    // dependency present afterwards
     let toml = get_toml(&manifest);
-    let val = &toml["dependencies"]["versioned-package"];
-    assert_eq!(val.as_str().expect("not string"), ">=0.1.1");
-
-    // cannot run with both --dev and --build at the same time
-    let call = process::Command::new(get_command_path("add").as_str())
-        .args(&["add", BOGUS_CRATE_NAME, "--vers", "invalid version string"])
-        .arg(format!("--manifest-path={}", &manifest))
-        .output()
-        .unwrap();
+    assert!(toml["dependencies"].is_none());
+    assert!(!toml["dev-dependencies"]["my-dev-package"].is_none());
+    assert!(!toml["build-dependencies"]["my-build-package"].is_none());
+    assert!(!toml["target"]["cfg(unix)"]["dev-dependencies"]["my-unix-package"].is_none());
 
-    assert!(!call.status.success());
-    assert!(no_manifest_failures(&get_toml(&manifest).root));
+    execute_bad_command(&["add", "my-package", "--section", "features"], &manifest);
+    execute_bad_command(
+        &["add", "my-package", "--section", "dev", "--build"],
+        &manifest,
+    );
 }
 
 #[test]
-fn adds_specified_version_with_inline_notation() {
-    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
-
-    // dependency not present beforehand
-    let toml = get_toml(&manifest);
-    assert!(toml["dependencies"].is_none());
-
-    execute_command(&["add", "versioned-package@>=0.1.1"], &manifest);
+fn notes_missing_build_script() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let add_build_dependency = |name: &str| {
+        assert_cli::Assert::command(&[
+            get_command_path("add").as_str(),
+            "add",
+            name,
+            "--build",
+            &format!("--manifest-path={}", manifest),
+        ])
+        .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+        .succeeds()
+    };
+
+    add_build_dependency("cc")
+        .and()
+        .stdout()
+        .contains("NOTE: Build dependencies are only available to a build script.")
+        .unwrap();
 
-    // dependency present afterwards
-    let toml = get_toml(&manifest);
-    let val = &toml["dependencies"]["versioned-package"];
-    assert_eq!(val.as_str().expect("not string"), ">=0.1.1");
+    std::fs::write(tmpdir.path().join("build.rs"), "fn main() {}\n").unwrap();
+    add_build_dependency("bindgen")
+        .and()
+        .stdout()
+        .doesnt_contain("NOTE")
+        .unwrap();
 }
 
 #[test]
-fn adds_multiple_dependencies_with_versions() {
+fn adds_normal_and_dev_dependency() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
 
-    // dependencies not present beforehand
-    let toml = get_toml(&manifest);
-    assert!(toml["dependencies"].is_none());
-    assert!(toml["dependencies"].is_none());
-
     execute_command(
-        &["add", "my-package1@>=0.1.1", "my-package2@0.2.3"],
+        &[
+            "add",
+            "my-package",
+            "--dev",
+            "--normal",
+            "--features",
+            "foo",
+        ],
         &manifest,
     );
 
-    // dependencies present afterwards
     let toml = get_toml(&manifest);
-    let val = &toml["dependencies"]["my-package1"];
-    assert_eq!(val.as_str().expect("not string"), ">=0.1.1");
-    let val = &toml["dependencies"]["my-package2"];
-    assert_eq!(val.as_str().expect("not string"), "0.2.3");
+    for table in &["dependencies", "dev-dependencies"] {
+        let val = &toml[table]["my-package"];
+        assert_eq!(
+            val["version"].as_str(),
+            Some("my-package--CURRENT_VERSION_TEST")
+        );
+        assert_eq!(val["features"].as_array().map(|a| a.len()), Some(1));
+    }
+    assert!(toml["build-dependencies"].is_none());
 }
 
 #[test]
-fn adds_multiple_dependencies_with_some_versions() {
+fn adds_dependency_as_table_always() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
 
-    // dependencies not present beforehand
-    let toml = get_toml(&manifest);
-    assert!(toml["dependencies"].is_none());
-    assert!(toml["dependencies"].is_none());
-
-    execute_command(&["add", "my-package1", "my-package2@0.2.3"], &manifest);
+    execute_command(&["add", "my-package", "--table-always"], &manifest);
+    // Adding it once more leaves the entry as it is.
+    execute_command(&["add", "my-package", "--table-always"], &manifest);
 
-    // dependencies present afterwards
     let toml = get_toml(&manifest);
-    let val = &toml["dependencies"]["my-package1"];
+    let val = &toml["dependencies"]["my-package"];
+    assert!(val.is_inline_table());
     assert_eq!(
-        val.as_str().expect("not string"),
-        "my-package1--CURRENT_VERSION_TEST"
+        val["version"].as_str(),
+        Some("my-package--CURRENT_VERSION_TEST")
     );
-    let val = &toml["dependencies"]["my-package2"];
-    assert_eq!(val.as_str().expect("not string"), "0.2.3");
 }
 
 #[test]
-fn adds_git_source_using_flag() {
-    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+fn adds_dependency_as_table_from_manifest_default() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.table_always");
 
-    // dependency not present beforehand
-    let toml = get_toml(&manifest);
-    assert!(toml["dependencies"].is_none());
+    execute_command(&["add", "my-package"], &manifest);
+
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"]["my-package"].is_inline_table());
+    // Unrelated entries keep their form.
+    assert_eq!(toml["dependencies"]["existing"].as_str(), Some("1.0"));
+}
+
+#[test]
+fn writes_table_header_once_when_creating_table() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.table_header");
+
+    execute_command(&["add", "--dev", "my-package1"], &manifest);
+    execute_command(&["add", "--dev", "my-package2"], &manifest);
+
+    let contents = std::fs::read_to_string(&manifest).unwrap();
+    assert!(contents.contains("# managed by cargo-edit\n[dev-dependencies]\n"));
+    assert_eq!(contents.matches("# managed by cargo-edit").count(), 1);
+    let toml = get_toml(&manifest);
+    assert!(toml["dev-dependencies"]["my-package1"].is_str());
+    assert!(toml["dev-dependencies"]["my-package2"].is_str());
+}
+
+#[test]
+fn adds_optional_dependency_to_feature() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.features");
+
+    execute_command(
+        &[
+            "add",
+            "my-package",
+            "--optional",
+            "--enable-feature",
+            "mygate",
+        ],
+        &manifest,
+    );
+    // Enabling it again doesn't duplicate the entry.
+    execute_command(
+        &[
+            "add",
+            "my-package",
+            "--optional",
+            "--enable-feature",
+            "mygate",
+        ],
+        &manifest,
+    );
+    execute_command(
+        &[
+            "add",
+            "other-package",
+            "--optional",
+            "--feature-name",
+            "newgate",
+        ],
+        &manifest,
+    );
+
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package"]["optional"].as_bool(),
+        Some(true)
+    );
+    let features = |name: &str| {
+        toml["features"][name]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|value| value.as_str().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(features("mygate"), vec!["std", "dep:my-package"]);
+    assert_eq!(features("newgate"), vec!["dep:other-package"]);
+}
+
+#[test]
+fn adds_optional_dependency_to_feature_before_edition_2021() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(
+        &[
+            "add",
+            "my-package",
+            "--optional",
+            "--enable-feature",
+            "gate",
+        ],
+        &manifest,
+    );
+
+    // Without `dep:`, the feature refers to the implicit feature of the dependency.
+    let toml = get_toml(&manifest);
+    let features = toml["features"]["gate"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|value| value.as_str().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(features, vec!["my-package"]);
+}
+
+#[test]
+fn adds_features_in_given_order() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(
+        &["add", "my-package", "--features", "zeta alpha"],
+        &manifest,
+    );
+
+    let toml = get_toml(&manifest);
+    let features = toml["dependencies"]["my-package"]["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f.as_str().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(features, vec!["zeta", "alpha"]);
+}
+
+#[test]
+fn adds_sorted_features() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(
+        &[
+            "add",
+            "my-package",
+            "--features",
+            "zeta alpha zeta",
+            "--sort-features",
+        ],
+        &manifest,
+    );
+
+    let toml = get_toml(&manifest);
+    let features = toml["dependencies"]["my-package"]["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f.as_str().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(features, vec!["alpha", "zeta"]);
+}
+
+#[test]
+fn adds_multiple_dev_build_dependencies() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    // dependencies not present beforehand
+    let toml = get_toml(&manifest);
+    assert!(toml["dev-dependencies"].is_none());
+    assert!(toml["dev-dependencies"].is_none());
+    assert!(toml["build-dependencies"].is_none());
+    assert!(toml["build-dependencies"].is_none());
+
+    execute_command(
+        &["add", "my-dev-package1", "my-dev-package2", "--dev"],
+        &manifest,
+    );
+    execute_command(
+        &["add", "my-build-package1", "--build", "my-build-package2"],
+        &manifest,
+    );
+
+    // dependencies present afterwards
+    let toml = get_toml(&manifest);
+    let val = &toml["dev-dependencies"]["my-dev-package1"];
+    assert_eq!(
+        val.as_str().unwrap(),
+        "my-dev-package1--CURRENT_VERSION_TEST"
+    );
+    let val = &toml["dev-dependencies"]["my-dev-package2"];
+    assert_eq!(
+        val.as_str().unwrap(),
+        "my-dev-package2--CURRENT_VERSION_TEST"
+    );
+    let val = &toml["build-dependencies"]["my-build-package1"];
+    assert_eq!(
+        val.as_str().unwrap(),
+        "my-build-package1--CURRENT_VERSION_TEST"
+    );
+    let val = &toml["build-dependencies"]["my-build-package2"];
+    assert_eq!(
+        val.as_str().unwrap(),
+        "my-build-package2--CURRENT_VERSION_TEST"
+    );
+}
+
+#[test]
+fn adds_specified_version() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    // dependency not present beforehand
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
+
+    execute_command(
+        &["add", "versioned-package", "--vers", ">=0.1.1"],
+        &manifest,
+    );
+
+    // dependency present afterwards
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["versioned-package"];
+    assert_eq!(val.as_str().expect("not string"), ">=0.1.1");
+
+    // cannot run with both --dev and --build at the same time
+    let call = process::Command::new(get_command_path("add").as_str())
+        .args(["add", BOGUS_CRATE_NAME, "--vers", "invalid version string"])
+        .arg(format!("--manifest-path={}", &manifest))
+        .output()
+        .unwrap();
+
+    assert!(!call.status.success());
+    assert!(no_manifest_failures(&get_toml(&manifest).root));
+}
+
+#[test]
+fn adds_specified_version_with_inline_notation() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    // dependency not present beforehand
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
+
+    execute_command(&["add", "versioned-package@>=0.1.1"], &manifest);
+
+    // dependency present afterwards
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["versioned-package"];
+    assert_eq!(val.as_str().expect("not string"), ">=0.1.1");
+}
+
+#[test]
+fn adds_multiple_dependencies_with_versions() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    // dependencies not present beforehand
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
+    assert!(toml["dependencies"].is_none());
+
+    execute_command(
+        &["add", "my-package1@>=0.1.1", "my-package2@0.2.3"],
+        &manifest,
+    );
+
+    // dependencies present afterwards
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["my-package1"];
+    assert_eq!(val.as_str().expect("not string"), ">=0.1.1");
+    let val = &toml["dependencies"]["my-package2"];
+    assert_eq!(val.as_str().expect("not string"), "0.2.3");
+}
+
+#[test]
+fn adds_inline_features_to_the_crate_before_them() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(
+        &["add", "serde@1", "+derive,rc", "tokio@^1", "log"],
+        &manifest,
+    );
+
+    let toml = get_toml(&manifest);
+    let serde = &toml["dependencies"]["serde"];
+    assert_eq!(serde["version"].as_str(), Some("1"));
+    let features = serde["features"].as_array().expect("features not an array");
+    let features: Vec<_> = features.iter().map(|f| f.as_str().unwrap()).collect();
+    assert_eq!(features, ["derive", "rc"]);
+    assert_eq!(toml["dependencies"]["tokio"].as_str(), Some("^1"));
+    assert_eq!(
+        toml["dependencies"]["log"].as_str(),
+        Some("log--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn fails_to_add_inline_features_without_crate() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "+derive",
+        "serde",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("`+derive` enables features of the crate before it")
+    .unwrap();
+}
+
+#[test]
+fn adds_multiple_dependencies_with_some_versions() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    // dependencies not present beforehand
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
+    assert!(toml["dependencies"].is_none());
+
+    execute_command(&["add", "my-package1", "my-package2@0.2.3"], &manifest);
+
+    // dependencies present afterwards
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["my-package1"];
+    assert_eq!(
+        val.as_str().expect("not string"),
+        "my-package1--CURRENT_VERSION_TEST"
+    );
+    let val = &toml["dependencies"]["my-package2"];
+    assert_eq!(val.as_str().expect("not string"), "0.2.3");
+}
+
+#[test]
+fn adds_git_source_using_flag() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    // dependency not present beforehand
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
 
     execute_command(
         &[
@@ -619,9 +1220,9 @@ fn git_and_version_flags_are_mutually_exclusive() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
 
     let call = process::Command::new(get_command_path("add").as_str())
-        .args(&["add", BOGUS_CRATE_NAME])
-        .args(&["--vers", "0.4.3"])
-        .args(&["--git", "git://git.git"])
+        .args(["add", BOGUS_CRATE_NAME])
+        .args(["--vers", "0.4.3"])
+        .args(["--git", "git://git.git"])
         .arg(format!("--manifest-path={}", &manifest))
         .output()
         .unwrap();
@@ -635,8 +1236,8 @@ fn git_flag_and_inline_version_are_mutually_exclusive() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
 
     let call = process::Command::new(get_command_path("add").as_str())
-        .args(&["add", &format!("{}@0.4.3", BOGUS_CRATE_NAME)])
-        .args(&["--git", "git://git.git"])
+        .args(["add", &format!("{}@0.4.3", BOGUS_CRATE_NAME)])
+        .args(["--git", "git://git.git"])
         .arg(format!("--manifest-path={}", &manifest))
         .output()
         .unwrap();
@@ -650,9 +1251,9 @@ fn git_and_path_are_mutually_exclusive() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
 
     let call = process::Command::new(get_command_path("add").as_str())
-        .args(&["add", BOGUS_CRATE_NAME])
-        .args(&["--git", "git://git.git"])
-        .args(&["--path", "/path/here"])
+        .args(["add", BOGUS_CRATE_NAME])
+        .args(["--git", "git://git.git"])
+        .args(["--path", "/path/here"])
         .arg(format!("--manifest-path={}", &manifest))
         .output()
         .unwrap();
@@ -666,9 +1267,9 @@ fn git_and_registry_are_mutually_exclusive() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
 
     let call = process::Command::new(get_command_path("add").as_str())
-        .args(&["add", BOGUS_CRATE_NAME])
-        .args(&["--git", "git://git.git"])
-        .args(&["--registry", "alternative"])
+        .args(["add", BOGUS_CRATE_NAME])
+        .args(["--git", "git://git.git"])
+        .args(["--registry", "alternative"])
         .arg(format!("--manifest-path={}", &manifest))
         .output()
         .unwrap();
@@ -682,9 +1283,9 @@ fn registry_and_path_are_mutually_exclusive() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
 
     let call = process::Command::new(get_command_path("add").as_str())
-        .args(&["add", BOGUS_CRATE_NAME])
-        .args(&["--registry", "alternative"])
-        .args(&["--path", "/path/here"])
+        .args(["add", BOGUS_CRATE_NAME])
+        .args(["--registry", "alternative"])
+        .args(["--path", "/path/here"])
         .arg(format!("--manifest-path={}", &manifest))
         .output()
         .unwrap();
@@ -693,6 +1294,17 @@ fn registry_and_path_are_mutually_exclusive() {
     assert!(no_manifest_failures(&get_toml(&manifest).root));
 }
 
+#[test]
+fn default_registry_fallback_requires_registry() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_bad_command(
+        &["add", "my-package", "--default-registry-fallback"],
+        &manifest,
+    );
+    assert!(get_toml(&manifest)["dependencies"].is_none());
+}
+
 #[test]
 fn adds_optional_dependency() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -742,16 +1354,188 @@ fn adds_multiple_optional_dependencies() {
 }
 
 #[test]
-fn adds_no_default_features_dependency() {
+fn keeps_crlf_line_endings() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let contents = std::fs::read_to_string(&manifest).unwrap();
+    std::fs::write(&manifest, contents.replace('\n', "\r\n")).unwrap();
 
-    // dependency not present beforehand
-    let toml = get_toml(&manifest);
-    assert!(toml["dependencies"].is_none());
+    execute_command(&["add", "my-package", "--features", "std"], &manifest);
 
-    execute_command(
-        &[
-            "add",
+    let contents = std::fs::read_to_string(&manifest).unwrap();
+    assert!(contents.contains("[dependencies]\r\nmy-package = "));
+    assert_eq!(
+        contents.matches('\n').count(),
+        contents.matches("\r\n").count(),
+        "every line should end in CRLF:\n{:?}",
+        contents
+    );
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package"]["version"].as_str(),
+        Some("my-package--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn adds_normal_and_dev_dependencies_in_one_edit() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    // The whole edit shows up as a single diff of the manifest.
+    let output = std::process::Command::new(get_command_path("add"))
+        .args([
+            "add",
+            "my-package1",
+            "my-package2",
+            "--dev-crate",
+            "my-dev-package",
+        ])
+        .args(["--dry-run", "--show-diff"])
+        .arg(format!("--manifest-path={}", manifest))
+        .env("CARGO_IS_TEST", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.matches("+++ ").count(), 1, "{}", stdout);
+    assert!(stdout.contains("+[dev-dependencies]\n+my-dev-package = "));
+    assert!(get_toml(&manifest)["dependencies"].is_none());
+
+    execute_command(
+        &[
+            "add",
+            "my-package1",
+            "my-package2",
+            "--dev-crate",
+            "my-dev-package",
+        ],
+        &manifest,
+    );
+
+    let toml = get_toml(&manifest);
+    for name in &["my-package1", "my-package2"] {
+        assert_eq!(
+            toml["dependencies"][name].as_str(),
+            Some(format!("{}--CURRENT_VERSION_TEST", name).as_str())
+        );
+        assert!(toml["dev-dependencies"][name].is_none());
+    }
+    assert_eq!(
+        toml["dev-dependencies"]["my-dev-package"].as_str(),
+        Some("my-dev-package--CURRENT_VERSION_TEST")
+    );
+    assert!(toml["dependencies"]["my-dev-package"].is_none());
+}
+
+#[test]
+fn fails_to_add_invalid_crate_spec() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    for (spec, reason) in &[
+        (
+            "1password",
+            "the crate name must start with a letter or `_`, not `1`",
+        ),
+        ("my-package@", "the version requirement after `@` is empty"),
+        ("my-package@one", "`one` is not a valid version requirement"),
+    ] {
+        assert_cli::Assert::command(&[
+            get_command_path("add").as_str(),
+            "add",
+            spec,
+            &format!("--manifest-path={}", manifest),
+        ])
+        .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+        .fails()
+        .and()
+        .stderr()
+        .contains(format!("`{}` is not a valid crate specifier: {}", spec, reason).as_str())
+        .unwrap();
+    }
+
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
+}
+
+#[test]
+fn keeps_tab_indentation() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    std::fs::write(
+        &manifest,
+        "[package]\n\tname = \"cargo-list-test-fixture\"\n\tversion = \"0.0.0\"\n\n\
+         [dependencies]\n\tdocopt = \"0.6\"\n",
+    )
+    .unwrap();
+
+    execute_command(&["add", "my-package"], &manifest);
+
+    let contents = std::fs::read_to_string(&manifest).unwrap();
+    assert!(
+        contents.contains("\n\tmy-package = \"my-package--CURRENT_VERSION_TEST\"\n"),
+        "the new dependency should be indented with a tab:\n{:?}",
+        contents
+    );
+    assert!(contents.contains("\n\tdocopt = \"0.6\"\n"));
+}
+
+#[test]
+fn refuses_duplicate_dependencies_unless_merging() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.duplicates");
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "new-package",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("The dependency `my-package` is declared more than once in `dependencies`")
+    .unwrap();
+
+    execute_command(&["add", "new-package", "--merge-duplicates"], &manifest);
+    let contents = std::fs::read_to_string(&manifest).unwrap();
+    assert_eq!(contents.matches("my-package =").count(), 1);
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["my-package"].as_str(), Some("0.2"));
+    assert_eq!(toml["dependencies"]["other-package"].as_str(), Some("1.0"));
+    assert!(!toml["dependencies"]["new-package"].is_none());
+}
+
+#[test]
+fn add_shows_diff() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "my-package",
+        "--show-diff",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("+[dependencies]\n+my-package = \"my-package--CURRENT_VERSION_TEST\"\n")
+    .unwrap();
+
+    // The manifest is still written.
+    let toml = get_toml(&manifest);
+    assert!(!toml["dependencies"]["my-package"].is_none());
+}
+
+#[test]
+fn adds_no_default_features_dependency() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    // dependency not present beforehand
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
+
+    execute_command(
+        &[
+            "add",
             "versioned-package",
             "--vers",
             ">=0.1.1",
@@ -766,6 +1550,102 @@ fn adds_no_default_features_dependency() {
     assert_eq!(val.as_bool().expect("default-features not a bool"), false);
 }
 
+#[test]
+fn adds_explicit_default_features_dependency() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "my-package1", "--default-features"], &manifest);
+    execute_command(
+        &["add", "my-package2", "--default-features-style", "explicit"],
+        &manifest,
+    );
+    execute_command(&["add", "my-package3"], &manifest);
+
+    let toml = get_toml(&manifest);
+    for name in &["my-package1", "my-package2"] {
+        let val = &toml["dependencies"][name];
+        assert_eq!(val["default-features"].as_bool(), Some(true));
+        assert_eq!(
+            val["version"].as_str(),
+            Some(format!("{}--CURRENT_VERSION_TEST", name).as_str())
+        );
+    }
+    // Without the flag, enabled default features are left implicit.
+    assert_eq!(
+        toml["dependencies"]["my-package3"].as_str(),
+        Some("my-package3--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn adds_explicit_default_features_from_manifest_default() {
+    let (_tmpdir, manifest) =
+        clone_out_test("tests/fixtures/add/Cargo.toml.explicit_default_features");
+
+    execute_command(&["add", "my-package1"], &manifest);
+    execute_command(&["add", "my-package2", "--no-default-features"], &manifest);
+    execute_command(
+        &["add", "my-package3", "--default-features-style", "auto"],
+        &manifest,
+    );
+
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package1"]["default-features"].as_bool(),
+        Some(true)
+    );
+    assert_eq!(
+        toml["dependencies"]["my-package2"]["default-features"].as_bool(),
+        Some(false)
+    );
+    assert_eq!(
+        toml["dependencies"]["my-package3"].as_str(),
+        Some("my-package3--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn applies_workspace_metadata_defaults() {
+    let (_tmpdir, root_manifest, workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+    let mut root = std::fs::read_to_string(&root_manifest).unwrap();
+    root.push_str("\n\n[workspace.metadata.cargo-edit]\ndefault-features-style = \"explicit\"\n");
+    std::fs::write(&root_manifest, root).unwrap();
+    let manifest = &workspace_manifests[0];
+
+    execute_command(&["add", "my-package1"], manifest);
+    execute_command(
+        &["add", "my-package2", "--default-features-style", "auto"],
+        manifest,
+    );
+
+    let toml = get_toml(manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package1"]["default-features"].as_bool(),
+        Some(true)
+    );
+    // The flag takes precedence over the workspace default.
+    assert_eq!(
+        toml["dependencies"]["my-package2"].as_str(),
+        Some("my-package2--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn default_features_conflicts_with_no_default_features() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_bad_command(
+        &[
+            "add",
+            "my-package",
+            "--default-features",
+            "--no-default-features",
+        ],
+        &manifest,
+    );
+}
+
 #[test]
 fn adds_multiple_no_default_features_dependencies() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -816,6 +1696,71 @@ fn adds_alternative_registry_dependency() {
     assert_eq!(val.as_str().expect("registry not a string"), "alternative");
 }
 
+#[test]
+fn bypasses_source_replacement() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    std::fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    std::fs::write(
+        tmpdir.path().join(".cargo").join("config"),
+        "[source.crates-io]\nreplace-with = \"broken-mirror\"\n",
+    )
+    .unwrap();
+
+    // The mirror isn't configured, so following the replacement fails.
+    execute_bad_command(&["add", "my-package", "--registry", "crates-io"], &manifest);
+    assert!(get_toml(&manifest)["dependencies"].is_none());
+
+    execute_command(
+        &[
+            "add",
+            "my-package",
+            "--registry",
+            "crates-io",
+            "--no-source-replacement",
+        ],
+        &manifest,
+    );
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package"]["version"].as_str(),
+        Some("my-package--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn reminds_to_refresh_vendored_crates() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    std::fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    std::fs::write(
+        tmpdir.path().join(".cargo").join("config"),
+        "[source.crates-io]\n\
+         replace-with = \"vendored-sources\"\n\
+         [source.vendored-sources]\n\
+         directory = \"vendor\"\n",
+    )
+    .unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "my-package",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("which may be out of date now. Run `cargo vendor` or add `--revendor`")
+    .unwrap();
+
+    // The latest version is still looked up, instead of the vendored one.
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package"].as_str(),
+        Some("my-package--CURRENT_VERSION_TEST")
+    );
+}
+
 #[test]
 fn adds_multiple_alternative_registry_dependencies() {
     let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -889,6 +1834,52 @@ fn adds_dependency_with_target_cfg() {
     assert_eq!(val.as_str().unwrap(), "my-package1--CURRENT_VERSION_TEST");
 }
 
+#[test]
+fn adds_featured_dependency_with_target_cfg() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(
+        &[
+            "add",
+            "tokio",
+            "--target",
+            "cfg(unix)",
+            "--features",
+            "signal",
+            "--optional",
+            "--no-default-features",
+        ],
+        &manifest,
+    );
+
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
+    let val = &toml["target"]["cfg(unix)"]["dependencies"]["tokio"];
+    assert_eq!(val["optional"].as_bool(), Some(true));
+    assert_eq!(val["default-features"].as_bool(), Some(false));
+    let contents = std::fs::read_to_string(&manifest).unwrap();
+    assert!(contents.contains(
+        "tokio = { version = \"tokio--CURRENT_VERSION_TEST\", optional = true, \
+         default-features = false, features = [\"signal\"] }\n"
+    ));
+
+    // Adding more features updates the entry in the target table.
+    execute_command(
+        &["add", "tokio", "--target", "cfg(unix)", "--features", "net"],
+        &manifest,
+    );
+    let toml = get_toml(&manifest);
+    let val = &toml["target"]["cfg(unix)"]["dependencies"]["tokio"];
+    let features = val["features"]
+        .as_array()
+        .expect("features not an array")
+        .iter()
+        .map(|feature| feature.as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert!(features.contains(&"net"));
+    assert!(toml["dependencies"].is_none());
+}
+
 #[test]
 fn adds_dependency_with_custom_target() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -1002,13 +1993,57 @@ fn fails_to_add_inexistent_local_source_without_flag() {
     execute_bad_command(&["add", "./tests/fixtures/local"], &manifest);
 }
 
+// Both ways of writing a dependency as a table are overwritten in place, without reshaping the
+// others.
+#[test]
+fn overwrite_dependency_syntaxes_in_place() {
+    for name in &["inline", "table"] {
+        let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.syntaxes");
+
+        execute_command(&["add", name, "--vers", "0.2"], &manifest);
+
+        let target = format!("tests/fixtures/add/Cargo.toml.syntaxes_{}_target", name);
+        assert_eq!(
+            std::fs::read_to_string(&manifest).unwrap(),
+            std::fs::read_to_string(&target).unwrap()
+        );
+    }
+}
+
+#[test]
+fn add_rejects_dotted_keys() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.dotted");
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "dotted",
+        "--vers",
+        "0.2",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("The manifest sets `dotted.version` with a dotted key")
+    .unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(&manifest).unwrap(),
+        std::fs::read_to_string("tests/fixtures/upgrade/Cargo.toml.dotted").unwrap()
+    );
+}
+
 fn overwrite_dependency_test(first_command: &[&str], second_command: &[&str], expected: &str) {
     // First, add a dependency.
-    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
-    execute_command(first_command, &manifest);
+    // Run from the directory of the manifest, which paths given on the command line are relative
+    // to.
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    execute_command_in_dir(first_command, tmpdir.path());
 
     // Then, overwite with the latest version
-    execute_command(second_command, &manifest);
+    execute_command_in_dir(second_command, tmpdir.path());
 
     // Verify that the dependency is as expected.
     let toml = get_toml(&manifest);
@@ -1149,7 +2184,7 @@ fn no_argument() {
     <crate>...
 
 USAGE:
-    cargo add <crate>... --upgrade <method>
+    cargo add <crate>... --message-format <fmt> --upgrade <method>
 
 For more information try --help")
         .unwrap();
@@ -1183,7 +2218,7 @@ fn add_prints_message() {
         "--vers=0.6.0",
         &format!("--manifest-path={}", manifest),
     ])
-    .with_env(&[("CARGO_IS_TEST", "1")])
+    .with_env([("CARGO_IS_TEST", "1")])
     .succeeds()
     .and()
     .stdout()
@@ -1204,7 +2239,7 @@ fn add_prints_message_with_section() {
         "--vers=0.1.0",
         &format!("--manifest-path={}", manifest),
     ])
-    .with_env(&[("CARGO_IS_TEST", "1")])
+    .with_env([("CARGO_IS_TEST", "1")])
     .succeeds()
     .and()
     .stdout()
@@ -1225,7 +2260,7 @@ fn add_prints_message_for_dev_deps() {
         "0.8.0",
         &format!("--manifest-path={}", manifest),
     ])
-    .with_env(&[("CARGO_IS_TEST", "1")])
+    .with_env([("CARGO_IS_TEST", "1")])
     .succeeds()
     .and()
     .stdout()
@@ -1246,7 +2281,7 @@ fn add_prints_message_for_build_deps() {
         "0.1.0",
         &format!("--manifest-path={}", manifest),
     ])
-    .with_env(&[("CARGO_IS_TEST", "1")])
+    .with_env([("CARGO_IS_TEST", "1")])
     .succeeds()
     .and()
     .stdout()
@@ -1296,3 +2331,930 @@ toml_edit = "0.1.5"
 "#
     );
 }
+
+#[test]
+fn fails_to_add_to_virtual_manifest() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.virtual");
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "docopt",
+        "--vers=0.6.0",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env([("CARGO_IS_TEST", "1")])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains(
+        "is a virtual manifest; choose the workspace member to add the dependency to with \
+         `-p <member>`",
+    )
+    .unwrap();
+
+    // the manifest is left untouched
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
+}
+
+#[test]
+fn adds_to_workspace_member_chosen_with_package() {
+    let (_tmpdir, root_manifest, workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+
+    execute_command(
+        &["add", "docopt", "--vers=0.6.0", "-p", "one"],
+        &root_manifest,
+    );
+    let toml = get_toml(&workspace_manifests[0]);
+    assert_eq!(toml["dependencies"]["docopt"].as_str(), Some("0.6.0"));
+    assert!(get_toml(&root_manifest)["dependencies"].is_none());
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "docopt",
+        "--vers=0.6.0",
+        "-p",
+        "five",
+        &format!("--manifest-path={}", root_manifest),
+    ])
+    .with_env([("CARGO_IS_TEST", "1")])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("`five` is not a member of the workspace")
+    .unwrap();
+}
+
+#[test]
+fn adds_to_workspace_dependencies_of_virtual_manifest() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.virtual");
+
+    execute_command(&["add", "--workspace", "my-package1"], &manifest);
+    execute_command(
+        &["add", "--workspace", "my-package2", "--features", "derive"],
+        &manifest,
+    );
+
+    let toml = get_toml(&manifest);
+    let deps = &toml["workspace"]["dependencies"];
+    assert_eq!(
+        deps["my-package1"].as_str(),
+        Some("my-package1--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(
+        deps["my-package2"]["version"].as_str(),
+        Some("my-package2--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(
+        deps["my-package2"]["features"].as_array().map(|a| a.len()),
+        Some(1)
+    );
+    // Nothing is added for a package.
+    assert!(toml["dependencies"].is_none());
+    assert_eq!(
+        toml["workspace"]["members"].as_array().map(|a| a.len()),
+        Some(2)
+    );
+}
+
+#[test]
+fn fails_to_add_to_workspace_dependencies_outside_workspace_root() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "--workspace",
+        "my-package",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("has no `[workspace]` table")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert!(toml["workspace"].is_none());
+}
+
+#[test]
+fn adds_sorted_dependencies_with_group_order() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.grouped");
+
+    execute_command_in_dir(
+        &["add", "--sort", "internal-core", "--path", "../core"],
+        tmpdir.path(),
+    );
+
+    // `internal-*` crates come first, everything else is sorted after them
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml.to_string(),
+        r#"[package]
+name = "cargo-list-test-fixture"
+version = "0.0.0"
+
+[package.metadata.cargo-edit]
+group-order = ["internal-*", "*"]
+
+[dependencies]
+internal-core = { path = "../core" }
+internal-utils = { path = "../utils" }
+atty = "0.2.13"
+toml_edit = "0.1.5"
+"#
+    );
+}
+
+#[test]
+fn adds_dependency_with_explicit_default_feature() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    // dependency not present beforehand
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
+
+    execute_command(
+        &[
+            "add",
+            "versioned-package",
+            "--vers",
+            "0.1.1",
+            "--features",
+            "default,extra",
+        ],
+        &manifest,
+    );
+
+    // `default` is kept as an explicit feature
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["versioned-package"];
+    assert_eq!(val["version"].as_str(), Some("0.1.1"));
+    let features: Vec<_> = val["features"]
+        .as_array()
+        .expect("features not an array")
+        .iter()
+        .map(|f| f.as_str().unwrap())
+        .collect();
+    assert_eq!(features, vec!["default", "extra"]);
+}
+
+#[test]
+fn fails_to_add_default_feature_without_default_features() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_bad_command(
+        &[
+            "add",
+            "versioned-package",
+            "--features",
+            "default",
+            "--no-default-features",
+        ],
+        &manifest,
+    );
+
+    // the manifest is left untouched
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
+}
+
+#[test]
+fn suggests_path_for_workspace_member() {
+    let (_tmpdir, _root_manifest, workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+    let manifest = &workspace_manifests[0];
+    let manifest_dir = std::path::Path::new(manifest).parent().unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "four",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .current_dir(manifest_dir)
+    .with_env([("CARGO_IS_TEST", "1")])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("`four` is a member of the workspace, add it with `--path ../explicit/four`")
+    .unwrap();
+
+    // neither the registry nor the member is added
+    let toml = get_toml(manifest);
+    assert!(toml["dependencies"]["four"].is_none());
+
+    // following the suggestion adds the member
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "four",
+        "--path",
+        "../explicit/four",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .current_dir(manifest_dir)
+    .with_env([("CARGO_IS_TEST", "1")])
+    .succeeds()
+    .unwrap();
+    let toml = get_toml(manifest);
+    let val = &toml["dependencies"]["four"];
+    assert_eq!(val["path"].as_str(), Some("../explicit/four"));
+}
+
+/// Add a member without dependencies to the workspace from `copy_workspace_test`.
+fn add_empty_workspace_member(workspace: &std::path::Path) -> String {
+    let member_dir = workspace.join("explicit").join("five");
+    std::fs::create_dir_all(&member_dir).unwrap();
+    let manifest = member_dir.join("Cargo.toml");
+    std::fs::write(
+        &manifest,
+        "[package]\nname = \"five\"\nversion = \"0.1.0\"\n\n[lib]\npath = \"../../dummy.rs\"\n",
+    )
+    .unwrap();
+    manifest.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn adds_dependency_matching_workspace_version() {
+    let (tmpdir, _root_manifest, _workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+    let manifest = add_empty_workspace_member(tmpdir.path());
+
+    execute_command(&["add", "libc", "--match-workspace"], &manifest);
+
+    // The other members use `libc = "0.2.28"`.
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["libc"].as_str(), Some("0.2.28"));
+}
+
+#[test]
+fn match_workspace_picks_most_common_version_unless_strict() {
+    let (tmpdir, _root_manifest, _workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+    let manifest = add_empty_workspace_member(tmpdir.path());
+
+    // `one` uses `rand = "0.3"`, `two` uses `rand = "0.2"`.
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "rand",
+        "--match-workspace",
+        "--strict-match-workspace",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("Members of the workspace use different versions of `rand`: `0.3` (1), `0.2` (1)")
+    .unwrap();
+    assert!(get_toml(&manifest)["dependencies"].is_none());
+
+    execute_command(&["add", "rand", "--match-workspace"], &manifest);
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["rand"].as_str(), Some("0.3"));
+}
+
+#[test]
+fn adds_path_dependency_relative_to_manifest() {
+    let (tmpdir, _root_manifest, workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+    let manifest = &workspace_manifests[0];
+
+    // Run from the workspace root, not from the directory of the manifest.
+    execute_command_in_dir(
+        &[
+            "add",
+            "two",
+            "--path",
+            "two",
+            "--manifest-path",
+            "one/Cargo.toml",
+        ],
+        tmpdir.path(),
+    );
+    execute_command_in_dir(
+        &[
+            "add",
+            "./explicit/four",
+            "--manifest-path",
+            "one/Cargo.toml",
+        ],
+        tmpdir.path(),
+    );
+    execute_command_in_dir(
+        &[
+            "add",
+            "missing",
+            "--path",
+            "./vendor/../vendor/missing",
+            "--manifest-path",
+            "one/Cargo.toml",
+        ],
+        tmpdir.path(),
+    );
+
+    let toml = get_toml(manifest);
+    assert_eq!(toml["dependencies"]["two"]["path"].as_str(), Some("../two"));
+    assert_eq!(
+        toml["dependencies"]["four"]["path"].as_str(),
+        Some("../explicit/four")
+    );
+    assert_eq!(
+        toml["dependencies"]["missing"]["path"].as_str(),
+        Some("../vendor/missing")
+    );
+}
+
+#[test]
+fn adds_remaining_crates_with_keep_going() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "my-package1",
+        "./does-not-exist",
+        "my-package2",
+        "--keep-going",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("Added 2 of 3 crates, these failed:")
+    .and()
+    .stderr()
+    .contains("Failed to add `./does-not-exist`")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package1"].as_str(),
+        Some("my-package1--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(
+        toml["dependencies"]["my-package2"].as_str(),
+        Some("my-package2--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(toml["dependencies"].as_table().unwrap().len(), 2);
+}
+
+#[test]
+fn keeps_comments_when_readding_dependency() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.commented");
+
+    execute_command(
+        &["add", "foo", "--git", "https://github.com/example/foo.git"],
+        &manifest,
+    );
+
+    let contents = std::fs::read_to_string(&manifest).unwrap();
+    assert!(contents.contains(
+        "# explains why we need foo\nfoo = { git = \"https://github.com/example/foo.git\" } \
+         # keep in sync with bar\n"
+    ));
+    assert!(contents.contains("bar = \"0.1\"\n"));
+}
+
+#[test]
+fn prints_existing_dependency() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.print");
+    let before = std::fs::read_to_string(&manifest).unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "serde_json",
+        "nix",
+        "--print",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .is(r#"json
+    table: dependencies
+    package: serde_json
+    source: registry crates-io
+    version: 1.0
+    default-features: false
+    features: [std, alloc]
+    optional: true
+    target: none
+nix
+    table: dependencies
+    package: nix
+    source: git https://github.com/nix-rust/nix
+    version: none
+    default-features: true
+    features: []
+    optional: false
+    target: cfg(unix)"#)
+    .unwrap();
+
+    assert_eq!(std::fs::read_to_string(&manifest).unwrap(), before);
+}
+
+#[test]
+fn adds_package_at_path_by_its_name() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let crate_dir = "tests/fixtures/git-multi-crate/crate-a";
+
+    // The name and version are read from the package, and the path is relative to the manifest.
+    execute_command(&["add", "--path", crate_dir, "--path-version"], &manifest);
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["crate-a"];
+    assert_eq!(
+        tmpdir
+            .path()
+            .join(val["path"].as_str().unwrap())
+            .canonicalize()
+            .unwrap(),
+        std::path::Path::new(crate_dir).canonicalize().unwrap()
+    );
+    assert_eq!(val["version"].as_str(), Some("0.1.0"));
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "crate-b",
+        "--path",
+        crate_dir,
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("is `crate-a`, not `crate-b`")
+    .unwrap();
+    assert!(get_toml(&manifest)["dependencies"]["crate-b"].is_none());
+}
+
+#[test]
+fn adds_patch_from_path() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let crate_dir = "tests/fixtures/git-multi-crate/crate-a";
+
+    execute_command(
+        &[
+            "add",
+            "crate-a",
+            "--patch",
+            "crates-io",
+            "--path",
+            crate_dir,
+        ],
+        &manifest,
+    );
+    let toml = get_toml(&manifest);
+    assert!(toml["patch"]["crates-io"]["crate-a"]["path"]
+        .as_str()
+        .is_some());
+    assert!(toml["dependencies"].is_none());
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "serde",
+        "--patch",
+        "crates-io",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("but neither was given")
+    .unwrap();
+}
+
+#[test]
+fn adds_patch_only_to_workspace_root() {
+    let (_tmpdir, root_manifest, workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+    let crate_dir = "tests/fixtures/git-multi-crate/crate-a";
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "crate-a",
+        "--patch",
+        "crates-io",
+        "--path",
+        crate_dir,
+        &format!("--manifest-path={}", workspace_manifests[0]),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("only reads `[patch]` from the workspace root")
+    .unwrap();
+
+    execute_command(
+        &[
+            "add",
+            "crate-a",
+            "--patch",
+            "crates-io",
+            "--path",
+            crate_dir,
+        ],
+        &root_manifest,
+    );
+    let toml = get_toml(&root_manifest);
+    assert!(!toml["patch"]["crates-io"]["crate-a"].is_none());
+}
+
+/// Turn `tests/fixtures/git-multi-crate` into a git repository with a single commit.
+fn init_multi_crate_git_repo() -> (tempdir::TempDir, String) {
+    let tmpdir = tempdir::TempDir::new("cargo-add-git-repo").unwrap();
+    let repo = git2::Repository::init(tmpdir.path()).unwrap();
+    for path in &[
+        "Cargo.toml",
+        "crate-a/Cargo.toml",
+        "crates/crate-b/Cargo.toml",
+    ] {
+        let target = tmpdir.path().join(path);
+        std::fs::create_dir_all(target.parent().unwrap()).unwrap();
+        std::fs::copy(format!("tests/fixtures/git-multi-crate/{}", path), &target).unwrap();
+    }
+
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = git2::Signature::now("cargo-edit", "cargo-edit@example.com").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+        .unwrap();
+
+    let url = url::Url::from_directory_path(tmpdir.path()).unwrap();
+    (tmpdir, url.to_string())
+}
+
+#[test]
+fn adds_crate_from_multi_crate_git_repo() {
+    let (_repo_dir, repo_url) = init_multi_crate_git_repo();
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    // The crate is found by its package name, wherever it lives in the repository.
+    execute_command(&["add", "crate-b", "--git", &repo_url], &manifest);
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["crate-b"];
+    assert_eq!(val["git"].as_str(), Some(repo_url.as_str()));
+    assert!(val["package"].is_none());
+
+    execute_command(
+        &["add", "crate-a", "--git", &repo_url, "--rename", "a"],
+        &manifest,
+    );
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["a"]["package"].as_str(),
+        Some("crate-a")
+    );
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "crate-c",
+        "--git",
+        &repo_url,
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("contains no package `crate-c`, only `crate-a`, `crate-b`")
+    .unwrap();
+    assert!(get_toml(&manifest)["dependencies"]["crate-c"].is_none());
+}
+
+#[test]
+fn adds_git_dependency_at_latest_tag() {
+    let (repo_dir, repo_url) = init_multi_crate_git_repo();
+    let repo = git2::Repository::open(repo_dir.path()).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    for tag in &["v1.0.0", "v1.2.0", "v2.0.0-beta.1", "nightly"] {
+        repo.tag_lightweight(tag, head.as_object(), false).unwrap();
+    }
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(
+        &["add", "crate-a", "--git", &repo_url, "--at-latest-tag"],
+        &manifest,
+    );
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["crate-a"]["tag"].as_str(),
+        Some("v1.2.0")
+    );
+}
+
+#[test]
+fn verifies_git_reference() {
+    let (repo_dir, repo_url) = init_multi_crate_git_repo();
+    let repo = git2::Repository::open(repo_dir.path()).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    repo.tag_lightweight("v1.0.0", head.as_object(), false)
+        .unwrap();
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(
+        &[
+            "add",
+            "crate-a",
+            "--git",
+            &repo_url,
+            "--tag",
+            "v1.0.0",
+            "--verify-git",
+        ],
+        &manifest,
+    );
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["crate-a"]["tag"].as_str(),
+        Some("v1.0.0")
+    );
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "crate-b",
+        "--git",
+        &repo_url,
+        "--branch",
+        "no-such-branch",
+        "--verify-git",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("has no branch `no-such-branch`")
+    .unwrap();
+    assert!(get_toml(&manifest)["dependencies"]["crate-b"].is_none());
+
+    // Without the flag, the branch is written as given.
+    execute_command(
+        &[
+            "add",
+            "crate-b",
+            "--git",
+            &repo_url,
+            "--branch",
+            "no-such-branch",
+        ],
+        &manifest,
+    );
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["crate-b"]["branch"].as_str(),
+        Some("no-such-branch")
+    );
+}
+
+#[test]
+fn moves_dependency_from_dev_dependencies() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.move_from");
+
+    execute_command(&["add", "my-package", "--move-from", "dev"], &manifest);
+
+    let toml = get_toml(&manifest);
+    assert!(toml["dev-dependencies"].is_none());
+    let val = &toml["dependencies"]["my-package"];
+    assert_eq!(
+        val["version"].as_str(),
+        Some("my-package--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(val["default-features"].as_bool(), Some(false));
+    let features = val["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|feature| feature.as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(features, vec!["derive", "std"]);
+
+    // Moving it again fails, as it is no longer a development dependency.
+    execute_bad_command(&["add", "my-package", "--move-from", "dev"], &manifest);
+}
+
+#[test]
+fn adds_features_to_workspace_inherited_dependency() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.workspace_inherited");
+
+    execute_command(&["add", "my-package", "--features", "y x"], &manifest);
+
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["my-package"];
+    assert_eq!(val["workspace"].as_bool(), Some(true));
+    assert!(val["version"].is_none());
+    assert_eq!(val["optional"].as_bool(), Some(true));
+    let features = val["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|feature| feature.as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(features, vec!["x", "y"]);
+}
+
+#[test]
+fn adds_dependency_without_verifying() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let before = std::fs::read_to_string(&manifest).unwrap();
+
+    // The test hook makes verifying the manifest fail, so it's only written without verifying.
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "my-package",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(
+        assert_cli::Environment::inherit()
+            .insert("CARGO_IS_TEST", "1")
+            .insert("CARGO_EDIT_TEST_FAIL_VERIFY", &manifest),
+    )
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("The edited manifest is not valid TOML")
+    .unwrap();
+    assert_eq!(std::fs::read_to_string(&manifest).unwrap(), before);
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "my-package",
+        "--no-verify",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(
+        assert_cli::Environment::inherit()
+            .insert("CARGO_IS_TEST", "1")
+            .insert("CARGO_EDIT_TEST_FAIL_VERIFY", &manifest),
+    )
+    .succeeds()
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["my-package"];
+    assert_eq!(val.as_str().unwrap(), "my-package--CURRENT_VERSION_TEST");
+}
+
+#[test]
+fn adds_workspace_dependency_without_verifying() {
+    let (_tmpdir, root_manifest, workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+    let manifest = &workspace_manifests[0];
+    let root_before = std::fs::read_to_string(&root_manifest).unwrap();
+    let before = std::fs::read_to_string(manifest).unwrap();
+
+    // The workspace root is verified as well, and nothing is written if that fails.
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "my-package",
+        "--workspace-dep",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(
+        assert_cli::Environment::inherit()
+            .insert("CARGO_IS_TEST", "1")
+            .insert("CARGO_EDIT_TEST_FAIL_VERIFY", &root_manifest),
+    )
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("The edited manifest is not valid TOML")
+    .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&root_manifest).unwrap(),
+        root_before
+    );
+    assert_eq!(std::fs::read_to_string(manifest).unwrap(), before);
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "my-package",
+        "--workspace-dep",
+        "--no-verify",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(
+        assert_cli::Environment::inherit()
+            .insert("CARGO_IS_TEST", "1")
+            .insert("CARGO_EDIT_TEST_FAIL_VERIFY", &root_manifest),
+    )
+    .succeeds()
+    .unwrap();
+
+    let root = get_toml(&root_manifest);
+    assert_eq!(
+        root["workspace"]["dependencies"]["my-package"].as_str(),
+        Some("my-package--CURRENT_VERSION_TEST")
+    );
+    let toml = get_toml(manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package"]["workspace"].as_bool(),
+        Some(true)
+    );
+}
+
+#[test]
+fn adds_inherited_dependency_to_workspace_root() {
+    let (_tmpdir, root_manifest, workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+    let manifest = &workspace_manifests[0];
+
+    execute_command(
+        &[
+            "add",
+            "my-package",
+            "--workspace-dep",
+            "--features",
+            "derive",
+        ],
+        manifest,
+    );
+
+    let root = get_toml(&root_manifest);
+    assert_eq!(
+        root["workspace"]["dependencies"]["my-package"].as_str(),
+        Some("my-package--CURRENT_VERSION_TEST")
+    );
+    let toml = get_toml(manifest);
+    let entry = &toml["dependencies"]["my-package"];
+    assert_eq!(entry["workspace"].as_bool(), Some(true));
+    assert!(entry["version"].is_none());
+    assert_eq!(entry["features"][0].as_str(), Some("derive"));
+}
+
+#[test]
+fn fails_to_inherit_dependency_outside_workspace() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "--workspace-dep",
+        "my-package",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("is not part of a workspace")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"]["my-package"].is_none());
+}
+
+#[test]
+fn dry_run_prints_diff() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    execute_command(&["add", "my-package"], &manifest);
+    let before = std::fs::read_to_string(&manifest).unwrap();
+
+    // Feature edits of an existing entry show up like new sections.
+    let output = std::process::Command::new(get_command_path("add"))
+        .args(["add", "my-package", "--features", "a", "--dry-run"])
+        .args(["--dev-crate", "my-dev-package"])
+        .arg(format!("--manifest-path={}", manifest))
+        .env("CARGO_IS_TEST", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("-my-package = "), "{}", stdout);
+    assert!(stdout.contains("features = [\"a\"]"), "{}", stdout);
+    assert!(stdout.contains("+[dev-dependencies]\n+my-dev-package = "));
+    assert!(stdout.contains("Dry run, the manifest was not changed."));
+    assert_eq!(std::fs::read_to_string(&manifest).unwrap(), before);
+
+    // Only the messages are printed when asked to be quiet.
+    let output = std::process::Command::new(get_command_path("add"))
+        .args(["add", "my-package", "--dry-run", "--quiet"])
+        .arg(format!("--manifest-path={}", manifest))
+        .env("CARGO_IS_TEST", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("+++ "));
+}
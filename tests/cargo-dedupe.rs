@@ -0,0 +1,108 @@
+#[macro_use]
+extern crate pretty_assertions;
+
+use std::fs;
+
+mod utils;
+use crate::utils::{copy_workspace_test, execute_command, get_command_path, get_toml};
+
+#[test]
+fn report_divergent_requirements() {
+    let (_tmpdir, root_manifest, member_manifests) = copy_workspace_test("dedupe", &["one", "two"]);
+    let before = fs::read_to_string(&member_manifests[1]).unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("dedupe").as_str(),
+        "dedupe",
+        &format!("--manifest-path={}", root_manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("rand: 2 requirements")
+    .and()
+    .stdout()
+    .contains("serde: 3 requirements")
+    .and()
+    .stdout()
+    .contains("1.0.100  one (dependencies)")
+    .and()
+    .stdout()
+    .contains("2 of 3 dependencies have divergent requirements")
+    .unwrap();
+
+    // Nothing is changed without `--fix`.
+    assert_eq!(fs::read_to_string(&member_manifests[1]).unwrap(), before);
+}
+
+#[test]
+fn fix_divergent_requirements() {
+    let (_tmpdir, root_manifest, member_manifests) = copy_workspace_test("dedupe", &["one", "two"]);
+
+    execute_command(&["dedupe", "--fix"], &root_manifest);
+
+    let two = get_toml(&member_manifests[1]);
+    assert_eq!(
+        two["dependencies"]["serde"]["version"].as_str(),
+        Some("1.0.100")
+    );
+    assert_eq!(
+        two["dependencies"]["serde"]["features"]
+            .as_array()
+            .map(|features| features.len()),
+        Some(1)
+    );
+    assert_eq!(two["dev-dependencies"]["serde"].as_str(), Some("1.0.100"));
+    // `0.2` and `0.3` aren't compatible, so they are left alone.
+    assert_eq!(two["dependencies"]["rand"].as_str(), Some("0.2"));
+    let one = get_toml(&member_manifests[0]);
+    assert_eq!(one["dependencies"]["rand"].as_str(), Some("0.3"));
+}
+
+#[test]
+fn hoist_agreed_requirements_into_workspace() {
+    let (_tmpdir, root_manifest, member_manifests) = copy_workspace_test("dedupe", &["one", "two"]);
+
+    execute_command(&["dedupe", "--fix", "--hoist"], &root_manifest);
+
+    let root = get_toml(&root_manifest);
+    assert_eq!(
+        root["workspace"]["dependencies"]["serde"].as_str(),
+        Some("1.0.100")
+    );
+    assert!(root["workspace"]["dependencies"]["rand"].is_none());
+
+    let one = get_toml(&member_manifests[0]);
+    assert_eq!(
+        one["dependencies"]["serde"]["workspace"].as_bool(),
+        Some(true)
+    );
+    let two = get_toml(&member_manifests[1]);
+    assert_eq!(
+        two["dependencies"]["serde"]["workspace"].as_bool(),
+        Some(true)
+    );
+    assert!(two["dependencies"]["serde"]["version"].is_none());
+    assert_eq!(
+        two["dependencies"]["serde"]["features"]
+            .as_array()
+            .map(|features| features.len()),
+        Some(1)
+    );
+    assert_eq!(
+        two["dev-dependencies"]["serde"]["workspace"].as_bool(),
+        Some(true)
+    );
+}
+
+#[test]
+fn dry_run_leaves_manifests_untouched() {
+    let (_tmpdir, root_manifest, member_manifests) = copy_workspace_test("dedupe", &["one", "two"]);
+    let before = fs::read_to_string(&member_manifests[1]).unwrap();
+
+    execute_command(&["dedupe", "--fix", "--hoist", "--dry-run"], &root_manifest);
+
+    assert_eq!(fs::read_to_string(&member_manifests[1]).unwrap(), before);
+    assert!(get_toml(&root_manifest)["workspace"]["dependencies"].is_none());
+}
@@ -1,5 +1,7 @@
 mod utils;
-use crate::utils::{clone_out_test, execute_command, get_command_path, get_toml};
+use crate::utils::{
+    clone_out_test, execute_bad_command, execute_command, get_command_path, get_toml,
+};
 
 #[test]
 fn remove_existing_dependency() {
@@ -12,6 +14,72 @@ fn remove_existing_dependency() {
     assert!(toml["dependencies"]["docopt"].is_none());
 }
 
+// Both ways of writing a dependency as a table are removed without reshaping the others.
+#[test]
+fn remove_dependency_syntaxes_in_place() {
+    for name in &["inline", "table"] {
+        let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.syntaxes");
+
+        execute_command(&["rm", name], &manifest);
+
+        let target = format!("tests/fixtures/rm/Cargo.toml.syntaxes_{}_target", name);
+        assert_eq!(
+            std::fs::read_to_string(&manifest).unwrap(),
+            std::fs::read_to_string(&target).unwrap()
+        );
+    }
+}
+
+#[test]
+fn remove_rejects_dotted_keys() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.dotted");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "dotted",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("The manifest sets `dotted.version` with a dotted key")
+    .unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(&manifest).unwrap(),
+        std::fs::read_to_string("tests/fixtures/upgrade/Cargo.toml.dotted").unwrap()
+    );
+}
+
+#[test]
+fn remove_prints_json_records() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
+
+    let output = std::process::Command::new(get_command_path("rm"))
+        .args(["rm", "docopt", "--message-format", "json"])
+        .arg(format!("--manifest-path={}", manifest))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let records = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["schema"], 1);
+    assert_eq!(records[0]["crate"], "docopt");
+    assert_eq!(records[0]["table"], serde_json::json!(["dependencies"]));
+    assert_eq!(records[0]["action"], "remove");
+    assert_eq!(records[0]["old_requirement"], "0.6");
+    assert!(records[0]["requirement"].is_null());
+
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"]["docopt"].is_none());
+}
+
 #[test]
 fn remove_multiple_existing_dependencies() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
@@ -94,6 +162,103 @@ fn issue_32() {
     assert!(toml["dependencies"]["bar"].is_none());
 }
 
+#[test]
+fn remove_dependency_from_target_table() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.target");
+
+    execute_command(&["rm", "libc", "--target", "cfg(unix)"], &manifest);
+    let toml = get_toml(&manifest);
+    assert!(toml["target"]["cfg(unix)"]["dependencies"]["libc"].is_none());
+    assert!(!toml["target"]["cfg(unix)"]["dependencies"]["nix"].is_none());
+    assert!(!toml["dependencies"]["libc"].is_none());
+
+    // The table of the target goes away along with its last dependency.
+    execute_command(&["rm", "winapi", "--target", "cfg(windows)"], &manifest);
+    let toml = get_toml(&manifest);
+    assert!(toml["target"]["cfg(windows)"].is_none());
+    assert!(!toml["target"]["cfg(unix)"].is_none());
+}
+
+#[test]
+fn remove_patch() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.patch");
+
+    // Only the override goes away, not the dependency of the same name.
+    execute_command(&["rm", "serde", "--patch", "crates-io"], &manifest);
+    let toml = get_toml(&manifest);
+    assert!(toml["patch"]["crates-io"]["serde"].is_none());
+    assert!(!toml["patch"]["crates-io"]["libc"].is_none());
+    assert!(!toml["dependencies"]["serde"].is_none());
+
+    execute_command(
+        &[
+            "rm",
+            "cargo",
+            "--patch",
+            "https://github.com/rust-lang/cargo",
+        ],
+        &manifest,
+    );
+    let toml = get_toml(&manifest);
+    assert!(toml["patch"]["https://github.com/rust-lang/cargo"].is_none());
+    assert!(!toml["patch"]["crates-io"].is_none());
+}
+
+#[test]
+fn remove_dependency_dry_run_reports_other_tables() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.target");
+    let before = std::fs::read_to_string(&manifest).unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "libc",
+        "winapi",
+        "--target",
+        "cfg(windows)",
+        "--dry-run",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "libc",
+        "--dry-run",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("`libc` is still declared in `target.'cfg(unix)'.dependencies`")
+    .and()
+    .stdout()
+    .contains("Dry run, the manifest was not changed.")
+    .unwrap();
+    assert_eq!(std::fs::read_to_string(&manifest).unwrap(), before);
+}
+
+#[test]
+fn remove_dependency_missing_from_target_table() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.target");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "winapi",
+        "--target",
+        "cfg(unix)",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("The dependency `winapi` could not be found in `target.'cfg(unix)'.dependencies`.")
+    .unwrap();
+}
+
 #[test]
 fn invalid_dependency() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
@@ -204,7 +369,7 @@ fn rm_prints_message() {
     .succeeds()
     .and()
     .stdout()
-    .is("Removing semver from dependencies")
+    .is("Removing semver from dependencies\n    `semver` is still declared in `build-dependencies`")
     .unwrap();
 }
 
@@ -222,6 +387,263 @@ fn rm_prints_messages_for_multiple() {
     .succeeds()
     .and()
     .stdout()
-    .is("Removing semver from dependencies\n    Removing docopt from dependencies")
+    .is("Removing semver from dependencies\n    \
+         `semver` is still declared in `build-dependencies`\n    \
+         Removing docopt from dependencies")
+    .unwrap();
+}
+
+#[test]
+fn remove_renamed_dependency_by_package_name() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.renamed");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "serde_json",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("Matched `json` by its package name")
     .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"]["json"].is_none());
+    assert!(!toml["dependencies"]["toml"].is_none());
+}
+
+#[test]
+fn rm_ambiguous_package_name_requires_key() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.renamed");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "rand",
+        "--dev",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("Several dependencies in `dev-dependencies` refer to the package `rand`")
+    .unwrap();
+
+    execute_command(&["rm", "old_rand", "--dev"], &manifest);
+    let toml = get_toml(&manifest);
+    assert!(toml["dev-dependencies"]["old_rand"].is_none());
+    assert!(!toml["dev-dependencies"]["new_rand"].is_none());
+}
+
+#[test]
+fn rm_unused_optional_dependencies() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.unused");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "--unused",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("Removing forgotten from dependencies")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"]["forgotten"].is_none());
+    assert!(!toml["dependencies"]["gated"].is_none());
+    assert!(!toml["dependencies"]["required"].is_none());
+    // Only the given table is looked at.
+    assert!(!toml["build-dependencies"]["cc"].is_none());
+
+    execute_command(&["rm", "--unused", "--build"], &manifest);
+    let toml = get_toml(&manifest);
+    assert!(toml["build-dependencies"]["cc"].is_none());
+
+    execute_bad_command(&["rm", "--unused", "gated"], &manifest);
+}
+
+#[test]
+fn rm_dependency_from_features() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.features");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "foo",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("Removed `foo` from feature `std`")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"]["foo"].is_none());
+    let feature = |name: &str| -> Vec<String> {
+        toml["features"][name]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|value| value.as_str().unwrap().to_owned())
+            .collect()
+    };
+    assert_eq!(feature("default"), vec!["std"]);
+    // weak form
+    assert_eq!(feature("std"), vec!["serde?/std"]);
+    // strong form
+    assert_eq!(feature("derive"), vec!["serde/derive"]);
+    assert_eq!(feature("with-foo"), vec!["foo_bar"]);
+    assert!(feature("legacy").is_empty());
+}
+
+#[test]
+fn remove_dev_dependencies_matching_pattern() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.matching");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "--dev",
+        "--matching",
+        "test-*",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("Removing test-utils from dev-dependencies")
+    .and()
+    .stdout()
+    .contains("Removing test-macros from dev-dependencies")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert!(toml["dev-dependencies"]["test-utils"].is_none());
+    assert!(toml["dev-dependencies"]["test-macros"].is_none());
+    assert!(!toml["dev-dependencies"]["regex"].is_none());
+    // Normal dependencies are left alone.
+    assert!(!toml["dependencies"]["test-runtime"].is_none());
+}
+
+#[test]
+fn remove_matching_fails_if_nothing_matches() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.matching");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "--build",
+        "--matching",
+        "test-*",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("No dependency in `build-dependencies` matches `test-*`")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert!(!toml["dependencies"]["test-runtime"].is_none());
+}
+
+#[test]
+fn warns_about_dependency_features_of_removed_dependency() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.dependency_features");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "a",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .contains(
+        "WARN: `b` in `dependencies` enables `a/x`, which refers to the removed dependency `a`",
+    )
+    .and()
+    .stdout()
+    .contains(
+        "WARN: `c` in `dev-dependencies` enables `a?/y`, which refers to the removed dependency \
+         `a`",
+    )
+    .unwrap();
+
+    // The references are only reported, not removed.
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"]["a"].is_none());
+    assert_eq!(
+        toml["dependencies"]["b"]["features"]
+            .as_array()
+            .map(|features| features.len()),
+        Some(2)
+    );
+}
+
+#[test]
+fn dry_run_prints_diff() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
+    let before = std::fs::read_to_string(&manifest).unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "docopt",
+        "--dry-run",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("-docopt = \"0.6\"")
+    .and()
+    .stdout()
+    .contains("Dry run, the manifest was not changed.")
+    .unwrap();
+    assert_eq!(std::fs::read_to_string(&manifest).unwrap(), before);
+}
+
+#[test]
+fn check_usage_before_removing() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
+    let src = tmpdir.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(
+        src.join("main.rs"),
+        "use docopt::Docopt;\n\nfn main() {\n    // pad::PadStr is no longer needed\n}\n",
+    )
+    .unwrap();
+    let before = std::fs::read_to_string(&manifest).unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "docopt",
+        "--check-usage",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("`docopt` is still used by the code of the package:")
+    .and()
+    .stderr()
+    .contains("main.rs:1: use docopt::Docopt;")
+    .unwrap();
+    assert_eq!(std::fs::read_to_string(&manifest).unwrap(), before);
+
+    // Commented out references don't count.
+    execute_command(&["rm", "pad", "--check-usage"], &manifest);
+    assert!(get_toml(&manifest)["dependencies"]["pad"].is_none());
+
+    execute_command(&["rm", "docopt", "--check-usage", "--force"], &manifest);
+    assert!(get_toml(&manifest)["dependencies"]["docopt"].is_none());
 }
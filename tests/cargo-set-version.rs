@@ -0,0 +1,284 @@
+#[macro_use]
+extern crate pretty_assertions;
+
+use std::fs;
+
+mod utils;
+use crate::utils::{copy_workspace_test, execute_command, get_command_path, get_toml};
+
+#[test]
+fn set_inherited_workspace_version() {
+    let (_tmpdir, root_manifest, member_manifests) =
+        copy_workspace_test("set-version-workspace", &["one", "two"]);
+
+    execute_command(&["set-version", "0.2.0", "--workspace"], &root_manifest);
+
+    // Only the shared value in the root manifest changes.
+    let root = get_toml(&root_manifest);
+    assert_eq!(
+        root["workspace"]["package"]["version"].as_str(),
+        Some("0.2.0")
+    );
+    for member in member_manifests {
+        let member = get_toml(&member);
+        assert_eq!(
+            member["package"]["version"]["workspace"].as_bool(),
+            Some(true)
+        );
+    }
+}
+
+#[test]
+fn set_inherited_version_from_member() {
+    let (_tmpdir, root_manifest, member_manifests) =
+        copy_workspace_test("set-version-workspace", &["one", "two"]);
+
+    execute_command(&["set-version", "0.3.0"], &member_manifests[0]);
+
+    let root = get_toml(&root_manifest);
+    assert_eq!(
+        root["workspace"]["package"]["version"].as_str(),
+        Some("0.3.0")
+    );
+    let member = get_toml(&member_manifests[0]);
+    assert_eq!(
+        member["package"]["version"]["workspace"].as_bool(),
+        Some(true)
+    );
+}
+
+#[test]
+fn rejects_dotted_inherited_version() {
+    let (_tmpdir, _root_manifest, member_manifests) =
+        copy_workspace_test("set-version-workspace", &["one", "two"]);
+    let manifest = fs::read_to_string(&member_manifests[0]).unwrap();
+    let dotted = manifest.replace("version = { workspace = true }", "version.workspace = true");
+    assert_ne!(manifest, dotted);
+    fs::write(&member_manifests[0], &dotted).unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("set-version").as_str(),
+        "set-version",
+        "0.3.0",
+        &format!("--manifest-path={}", member_manifests[0]),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("The manifest sets `version.workspace` with a dotted key")
+    .unwrap();
+
+    assert_eq!(fs::read_to_string(&member_manifests[0]).unwrap(), dotted);
+}
+
+#[test]
+fn set_version_dry_run() {
+    let (_tmpdir, root_manifest, _member_manifests) =
+        copy_workspace_test("set-version-workspace", &["one", "two"]);
+
+    execute_command(
+        &["set-version", "0.2.0", "--workspace", "--dry-run"],
+        &root_manifest,
+    );
+
+    let root = get_toml(&root_manifest);
+    assert_eq!(
+        root["workspace"]["package"]["version"].as_str(),
+        Some("0.1.0")
+    );
+}
+
+#[test]
+fn set_package_rust_version() {
+    let (_tmpdir, root_manifest, member_manifests) =
+        copy_workspace_test("set-version-workspace", &["one", "two"]);
+
+    execute_command(
+        &["set-version", "--rust-version", "1.70"],
+        &member_manifests[1],
+    );
+
+    let member = get_toml(&member_manifests[1]);
+    assert_eq!(member["package"]["rust-version"].as_str(), Some("1.70"));
+    // The package version and the shared rust-version are left alone.
+    assert_eq!(
+        member["package"]["version"]["workspace"].as_bool(),
+        Some(true)
+    );
+    let root = get_toml(&root_manifest);
+    assert_eq!(
+        root["workspace"]["package"]["rust-version"].as_str(),
+        Some("1.56")
+    );
+}
+
+#[test]
+fn set_workspace_package_rust_version() {
+    let (_tmpdir, root_manifest, member_manifests) =
+        copy_workspace_test("set-version-workspace", &["one", "two"]);
+
+    execute_command(
+        &["set-version", "--rust-version", "1.70.1", "--workspace"],
+        &root_manifest,
+    );
+
+    let root = get_toml(&root_manifest);
+    assert_eq!(
+        root["workspace"]["package"]["rust-version"].as_str(),
+        Some("1.70.1")
+    );
+    assert_eq!(
+        root["workspace"]["package"]["version"].as_str(),
+        Some("0.1.0")
+    );
+    let one = get_toml(&member_manifests[0]);
+    assert_eq!(
+        one["package"]["rust-version"]["workspace"].as_bool(),
+        Some(true)
+    );
+    let two = get_toml(&member_manifests[1]);
+    assert_eq!(two["package"]["rust-version"].as_str(), Some("1.70.1"));
+}
+
+#[test]
+fn set_invalid_rust_version() {
+    let (_tmpdir, _root_manifest, member_manifests) =
+        copy_workspace_test("set-version-workspace", &["one", "two"]);
+
+    assert_cli::Assert::command(&[
+        get_command_path("set-version").as_str(),
+        "set-version",
+        "--rust-version",
+        "^1.70",
+        &format!("--manifest-path={}", member_manifests[1]),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("`^1.70` is not a valid Rust version")
+    .unwrap();
+
+    let member = get_toml(&member_manifests[1]);
+    assert_eq!(member["package"]["rust-version"].as_str(), Some("1.56"));
+}
+
+#[test]
+fn set_prerelease_version_with_build_metadata() {
+    let (_tmpdir, root_manifest, _member_manifests) =
+        copy_workspace_test("set-version-workspace", &["one", "two"]);
+
+    execute_command(
+        &["set-version", "1.2.3-beta.1+build.05", "--workspace"],
+        &root_manifest,
+    );
+
+    // Prerelease and build metadata are written exactly as given.
+    let root = get_toml(&root_manifest);
+    assert_eq!(
+        root["workspace"]["package"]["version"].as_str(),
+        Some("1.2.3-beta.1+build.05")
+    );
+}
+
+#[test]
+fn set_invalid_version() {
+    let (_tmpdir, root_manifest, _member_manifests) =
+        copy_workspace_test("set-version-workspace", &["one", "two"]);
+
+    for version in &["1.2", "1.2.3.4", "one.two.three"] {
+        assert_cli::Assert::command(&[
+            get_command_path("set-version").as_str(),
+            "set-version",
+            version,
+            "--workspace",
+            &format!("--manifest-path={}", root_manifest),
+        ])
+        .fails_with(1)
+        .and()
+        .stderr()
+        .contains(format!("`{}` is not a valid semver version", version).as_str())
+        .unwrap();
+    }
+
+    let root = get_toml(&root_manifest);
+    assert_eq!(
+        root["workspace"]["package"]["version"].as_str(),
+        Some("0.1.0")
+    );
+}
+
+/// Copy the workspace whose members depend on each other into a temporary directory.
+fn copy_bump_test() -> (tempdir::TempDir, String, String) {
+    let tmpdir =
+        tempdir::TempDir::new("set_version_bump").expect("failed to construct temporary directory");
+    for file in &["Cargo.toml", "dummy.rs", "app/Cargo.toml", "lib/Cargo.toml"] {
+        let path = tmpdir.path().join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::copy(format!("tests/fixtures/set-version-bump/{}", file), &path)
+            .unwrap_or_else(|err| panic!("could not copy test file: {}", err));
+    }
+    let path = |file: &str| tmpdir.path().join(file).to_str().unwrap().to_string();
+    let (app, lib) = (path("app/Cargo.toml"), path("lib/Cargo.toml"));
+    (tmpdir, app, lib)
+}
+
+#[test]
+fn bump_member_and_sync_dependents() {
+    let (tmpdir, app_manifest, lib_manifest) = copy_bump_test();
+    let root_manifest = tmpdir.path().join("Cargo.toml");
+
+    execute_command(
+        &["set-version", "--bump", "minor", "-p", "lib"],
+        root_manifest.to_str().unwrap(),
+    );
+
+    let lib = get_toml(&lib_manifest);
+    assert_eq!(lib["package"]["version"].as_str(), Some("0.2.0"));
+    let app = get_toml(&app_manifest);
+    assert_eq!(app["package"]["version"].as_str(), Some("1.2.3"));
+    assert_eq!(
+        app["dependencies"]["lib"]["version"].as_str(),
+        Some("0.2.0")
+    );
+}
+
+#[test]
+fn bump_release_candidate_of_workspace() {
+    let (tmpdir, app_manifest, lib_manifest) = copy_bump_test();
+    let root_manifest = tmpdir.path().join("Cargo.toml");
+
+    execute_command(
+        &["set-version", "--bump", "rc", "--workspace"],
+        root_manifest.to_str().unwrap(),
+    );
+
+    let lib = get_toml(&lib_manifest);
+    assert_eq!(lib["package"]["version"].as_str(), Some("0.1.1-rc.1"));
+    let app = get_toml(&app_manifest);
+    assert_eq!(app["package"]["version"].as_str(), Some("1.2.4-rc.1"));
+    assert_eq!(
+        app["dependencies"]["lib"]["version"].as_str(),
+        Some("0.1.1-rc.1")
+    );
+}
+
+#[test]
+fn fails_to_bump_unknown_member() {
+    let (tmpdir, _app_manifest, _lib_manifest) = copy_bump_test();
+    let root_manifest = tmpdir.path().join("Cargo.toml");
+
+    assert_cli::Assert::command(&[
+        get_command_path("set-version").as_str(),
+        "set-version",
+        "--bump",
+        "patch",
+        "-p",
+        "missing",
+        &format!("--manifest-path={}", root_manifest.display()),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("`missing` is not a member of the workspace")
+    .unwrap();
+}
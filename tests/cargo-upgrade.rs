@@ -5,59 +5,10 @@ use std::fs;
 
 mod utils;
 use crate::utils::{
-    clone_out_test, execute_command, execute_command_in_dir, get_command_path, get_toml,
-    setup_alt_registry_config,
+    clone_out_test, copy_workspace_test, execute_command, execute_command_in_dir, get_command_path,
+    get_toml, setup_alt_registry_config, WORKSPACE_MEMBERS,
 };
 
-/// Helper function that copies the workspace test into a temporary directory.
-pub fn copy_workspace_test() -> (tempdir::TempDir, String, Vec<String>) {
-    // Create a temporary directory and copy in the root manifest, the dummy rust file, and
-    // workspace member manifests.
-    let tmpdir = tempdir::TempDir::new("upgrade_workspace")
-        .expect("failed to construct temporary directory");
-
-    let (root_manifest_path, workspace_manifest_paths) = {
-        // Helper to copy in files to the temporary workspace. The standard library doesn't have a
-        // good equivalent of `cp -r`, hence this oddity.
-        let copy_in = |dir, file| {
-            let file_path = tmpdir
-                .path()
-                .join(dir)
-                .join(file)
-                .to_str()
-                .unwrap()
-                .to_string();
-
-            fs::create_dir_all(tmpdir.path().join(dir)).unwrap();
-
-            fs::copy(
-                format!("tests/fixtures/workspace/{}/{}", dir, file),
-                &file_path,
-            )
-            .unwrap_or_else(|err| panic!("could not copy test file: {}", err));
-
-            file_path
-        };
-
-        let root_manifest_path = copy_in(".", "Cargo.toml");
-        copy_in(".", "dummy.rs");
-        copy_in(".", "Cargo.lock");
-
-        let workspace_manifest_paths = ["one", "two", "implicit/three", "explicit/four"]
-            .iter()
-            .map(|member| copy_in(member, "Cargo.toml"))
-            .collect::<Vec<_>>();
-
-        (root_manifest_path, workspace_manifest_paths)
-    };
-
-    (
-        tmpdir,
-        root_manifest_path,
-        workspace_manifest_paths.to_owned(),
-    )
-}
-
 // Verify that an upgraded Cargo.toml matches what we expect.
 #[test]
 fn upgrade_as_expected() {
@@ -71,6 +22,49 @@ fn upgrade_as_expected() {
     assert_eq!(target.to_string(), upgraded.to_string());
 }
 
+// Each way of writing a dependency is edited in place, without reshaping the others.
+#[test]
+fn upgrade_dependency_syntaxes_in_place() {
+    for name in &["plain", "inline", "table"] {
+        let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.syntaxes");
+
+        execute_command(&["upgrade", name], &manifest);
+
+        let target = format!("tests/fixtures/upgrade/Cargo.toml.syntaxes_{}_target", name);
+        assert_eq!(
+            fs::read_to_string(&manifest).unwrap(),
+            fs::read_to_string(&target).unwrap()
+        );
+    }
+}
+
+// Dotted keys can't be parsed by the TOML editor yet, so such manifests are rejected with a hint
+// instead of being rewritten in another shape.
+#[test]
+fn upgrade_rejects_dotted_keys() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.dotted");
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains(
+        "The manifest sets `dotted.version` with a dotted key, which cargo-edit can't parse yet; \
+         write it as an inline table instead, e.g. `dotted = { version = \"0.1\" }`",
+    )
+    .unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&manifest).unwrap(),
+        fs::read_to_string("tests/fixtures/upgrade/Cargo.toml.dotted").unwrap()
+    );
+}
+
 #[test]
 fn upgrade_all() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -113,7 +107,7 @@ fn upgrade_prereleased_without_the_flag() {
     execute_command(&["add", "b", "--vers", "0.8-alpha"], &manifest);
 
     // Now, upgrade `b` to its latest version
-    execute_command(&["upgrade", "b"], &manifest);
+    execute_command(&["upgrade", "b", "--force"], &manifest);
 
     // Verify that `b` has been updated successfully to a prerelease version.
     assert_eq!(
@@ -131,7 +125,7 @@ fn upgrade_prerelease_already_prereleased() {
     execute_command(&["add", "b", "--vers", "0.8-alpha"], &manifest);
 
     // Now, upgrade all dependencies to their latest versions
-    execute_command(&["upgrade"], &manifest);
+    execute_command(&["upgrade", "--force"], &manifest);
 
     // Verify that `a` has been updated successfully to a stable version.
     assert_eq!(
@@ -145,6 +139,402 @@ fn upgrade_prerelease_already_prereleased() {
     );
 }
 
+#[test]
+fn upgrade_skips_exact_requirement() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "a", "--vers", "=1.0.0"], &manifest);
+    execute_command(&["add", "b", "--vers", "1.0"], &manifest);
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("Skipping a: it has an exact version requirement, use --force to upgrade it")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["a"].as_str(), Some("=1.0.0"));
+    assert_eq!(
+        toml["dependencies"]["b"].as_str(),
+        Some("b--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn upgrade_skips_exact_requirement_in_one_member_only() {
+    let (_tmpdir, root_manifest, workspace_manifests) =
+        copy_workspace_test("pinned-workspace", &["one", "two"]);
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--all",
+        &format!("--manifest-path={}", root_manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("Skipping docopt: it has an exact version requirement, use --force to upgrade it")
+    .unwrap();
+
+    // Only the member that pins `docopt` keeps its requirement.
+    assert_eq!(
+        get_toml(&workspace_manifests[0])["dependencies"]["docopt"].as_str(),
+        Some("=0.6.0")
+    );
+    assert_eq!(
+        get_toml(&workspace_manifests[1])["dependencies"]["docopt"].as_str(),
+        Some("docopt--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn upgrade_skip_pinned_spells_out_the_default() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "a", "--vers", "=1.0.0"], &manifest);
+    execute_command(&["upgrade", "--skip-pinned"], &manifest);
+    assert_eq!(
+        get_toml(&manifest)["dependencies"]["a"].as_str(),
+        Some("=1.0.0")
+    );
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--skip-pinned",
+        "--force",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("cannot be used with")
+    .unwrap();
+}
+
+#[test]
+fn upgrade_reports_wildcard_requirement() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "a", "--vers", "*"], &manifest);
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains(
+        "a: `*` matches the latest version a--CURRENT_VERSION_TEST, use --pin-wildcards to pin it",
+    )
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["a"].as_str(), Some("*"));
+}
+
+#[test]
+fn upgrade_pins_wildcard_requirement() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "a", "--vers", "*"], &manifest);
+    execute_command(&["upgrade", "--pin-wildcards"], &manifest);
+
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["a"].as_str(),
+        Some("a--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn upgrade_writes_report_file() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.syntaxes");
+    let report_file = tmpdir.path().join("report.json");
+
+    execute_command(
+        &[
+            "upgrade",
+            "plain",
+            "--report-file",
+            report_file.to_str().unwrap(),
+        ],
+        &manifest,
+    );
+
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report_file).unwrap()).unwrap();
+    assert_eq!(report["format_version"], 1);
+    let records = report["records"].as_array().unwrap();
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    assert!(record["manifest"].as_str().unwrap().ends_with("Cargo.toml"));
+    assert_eq!(record["crate"], "plain");
+    assert_eq!(record["table"], serde_json::json!(["dependencies"]));
+    assert_eq!(record["action"], "upgrade");
+    assert_eq!(record["old_requirement"], "0.1");
+    assert_eq!(record["requirement"], "plain--CURRENT_VERSION_TEST");
+}
+
+#[test]
+fn upgrade_prints_json_records() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.syntaxes");
+    let before = fs::read_to_string(&manifest).unwrap();
+
+    let output = std::process::Command::new(get_command_path("upgrade"))
+        .args(["upgrade", "plain", "--output-format", "json", "--dry-run"])
+        .arg(format!("--manifest-path={}", manifest))
+        .env("CARGO_IS_TEST", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // Only the records are printed, without the upgrade messages.
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let records = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["crate"], "plain");
+    assert_eq!(records[0]["table"], serde_json::json!(["dependencies"]));
+    assert_eq!(records[0]["action"], "upgrade");
+    assert_eq!(records[0]["old_requirement"], "0.1");
+    assert_eq!(records[0]["requirement"], "plain--CURRENT_VERSION_TEST");
+
+    assert_eq!(fs::read_to_string(&manifest).unwrap(), before);
+}
+
+#[test]
+fn upgrade_to_precise_version() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.syntaxes");
+    execute_command(&["upgrade", "plain", "--precise", "0.1.5"], &manifest);
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["plain"].as_str(), Some("0.1.5"));
+
+    // Leaving the compatible range has to be asked for.
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "inline",
+        "--precise",
+        "0.2.0",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("`inline` 0.2.0 is not semver compatible with the requirement `^0.1`")
+    .unwrap();
+    execute_command(
+        &["upgrade", "inline", "--precise", "0.2.0", "--incompatible"],
+        &manifest,
+    );
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["inline"]["version"].as_str(),
+        Some("0.2.0")
+    );
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "plain",
+        "inline",
+        "--precise",
+        "0.3.0",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("`--precise` upgrades exactly one dependency")
+    .unwrap();
+}
+
+#[test]
+fn upgrade_links_changelogs() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.syntaxes");
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "plain",
+        "--changelog",
+        "--dry-run",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("Changes of plain: https://github.com/plain/plain")
+    .unwrap();
+
+    let output = std::process::Command::new(get_command_path("upgrade"))
+        .args(["upgrade", "plain", "--changelog", "--output-format", "json"])
+        .arg("--dry-run")
+        .arg(format!("--manifest-path={}", manifest))
+        .env("CARGO_IS_TEST", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let record: serde_json::Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    assert_eq!(record["repository"], "https://github.com/plain/plain");
+    // The fake version of the tests isn't semver, so there are no release tags to compare.
+    assert_eq!(record["changelog"], serde_json::Value::Null);
+}
+
+#[test]
+fn upgrade_tidies_redundant_keys() {
+    let setup = || {
+        let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+        execute_command(
+            &["add", "implicit", "--vers", "1.0", "--default-features"],
+            &manifest,
+        );
+        execute_command(
+            &["add", "explicit", "--vers", "1.0", "--no-default-features"],
+            &manifest,
+        );
+        (tmpdir, manifest)
+    };
+
+    // Without `--tidy`, the redundant key is kept.
+    let (_tmpdir, manifest) = setup();
+    execute_command(&["upgrade"], &manifest);
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["implicit"]["default-features"].as_bool(),
+        Some(true)
+    );
+
+    let (_tmpdir, manifest) = setup();
+    execute_command(&["upgrade", "--tidy"], &manifest);
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["implicit"].as_str(),
+        Some("implicit--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(
+        toml["dependencies"]["explicit"]["default-features"].as_bool(),
+        Some(false)
+    );
+}
+
+#[test]
+fn upgrade_normalizes_dependency_tables() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    fs::write(
+        &manifest,
+        r#"[package]
+name = "cargo-list-test-fixture"
+version = "0.0.0"
+
+[lib]
+path = "dummy.rs"
+
+[dependencies]
+# The serializer
+serde = { version = "1.0", default-features = true }
+docopt = { version = "0.8" }
+"#,
+    )
+    .unwrap();
+
+    execute_command(&["upgrade", "--normalize"], &manifest);
+    let content = fs::read_to_string(&manifest).unwrap();
+    assert!(content.contains(
+        "docopt = \"docopt--CURRENT_VERSION_TEST\"\n# The serializer\nserde = \"serde--CURRENT_VERSION_TEST\"\n"
+    ));
+}
+
+#[test]
+fn upgrade_skips_prerelease_requirement() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "b", "--vers", "0.8.0-alpha"], &manifest);
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("Skipping b: it has a pre-release version requirement, use --force to upgrade it")
+    .unwrap();
+
+    assert_eq!(
+        get_toml(&manifest)["dependencies"]["b"].as_str(),
+        Some("0.8.0-alpha")
+    );
+}
+
+#[test]
+fn upgrade_exact_requirement_with_force() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "a", "--vers", "=1.0.0"], &manifest);
+    execute_command(&["upgrade", "--force"], &manifest);
+
+    assert_eq!(
+        get_toml(&manifest)["dependencies"]["a"].as_str(),
+        Some("a--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn upgrade_dependency_in_all_target_tables() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.target_deps");
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("winapi v0.2 -> vwinapi--CURRENT_VERSION_TEST (target.'cfg(windows)'.dependencies)")
+    .and()
+    .stdout()
+    .contains("winapi v0.2 -> vwinapi--CURRENT_VERSION_TEST (target.'cfg(unix)'.dependencies)")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["target"]["cfg(windows)"]["dependencies"]["winapi"].as_str(),
+        Some("winapi--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(
+        toml["target"]["cfg(unix)"]["dependencies"]["winapi"]["version"].as_str(),
+        Some("winapi--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(
+        toml["target"]["cfg(target_os = \"linux\")"]["build-dependencies"]["winapi"].as_str(),
+        Some("winapi--CURRENT_VERSION_TEST")
+    );
+}
+
 #[test]
 fn upgrade_all_dry_run() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -162,6 +552,32 @@ fn upgrade_all_dry_run() {
     );
 }
 
+#[test]
+fn upgrade_dry_run_shows_diff() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.syntaxes");
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "plain",
+        "--dry-run",
+        "--show-diff",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("\n-plain = \"0.1\"\n+plain = \"plain--CURRENT_VERSION_TEST\"\n")
+    .unwrap();
+
+    // The diff is only printed.
+    assert_eq!(
+        get_toml(&manifest)["dependencies"]["plain"].as_str(),
+        Some("0.1")
+    );
+}
+
 #[test]
 fn upgrade_all_allow_prerelease_dry_run() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -179,6 +595,42 @@ fn upgrade_all_allow_prerelease_dry_run() {
     );
 }
 
+#[test]
+fn upgrade_dry_run_fails_with_exit_code_if_outdated() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.syntaxes");
+    let before = fs::read_to_string(&manifest).unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "plain",
+        "--dry-run",
+        "--exit-code",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(2)
+    .and()
+    .stdout()
+    .contains("plain v0.1 -> vplain--CURRENT_VERSION_TEST")
+    .unwrap();
+    assert_eq!(fs::read_to_string(&manifest).unwrap(), before);
+
+    // Pinned requirements are kept, so there is nothing to change.
+    execute_command(&["add", "pinned", "--vers", "=0.1.0"], &manifest);
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "pinned",
+        "--dry-run",
+        "--exit-code",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .unwrap();
+}
+
 #[test]
 fn upgrade_specified_only() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -199,6 +651,170 @@ fn upgrade_specified_only() {
     assert_eq!(dependencies["env_proxy"].as_str(), Some("0.1.1"));
 }
 
+#[test]
+fn upgrade_without_updating_the_index() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    execute_command(&["add", "docopt", "--vers", "0.8"], &manifest);
+
+    execute_command(&["upgrade", "docopt", "--no-update"], &manifest);
+
+    let dependencies = &get_toml(&manifest)["dependencies"];
+    assert_eq!(
+        dependencies["docopt"].as_str(),
+        Some("docopt--CURRENT_VERSION_TEST")
+    );
+
+    // A locked index can't be used to look up git references.
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--locked-index",
+        "--git",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("cannot be used with")
+    .unwrap();
+}
+
+#[test]
+fn upgrade_confirms_breaking_changes() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "test_breaking", "--vers", "0.1"], &manifest);
+    execute_command(&["add", "test_nonbreaking", "--vers", "0.0.5"], &manifest);
+
+    // Crates are asked about in alphabetical order: accept `test_breaking`, decline
+    // `test_nonbreaking`.
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(
+        assert_cli::Environment::inherit()
+            .insert("CARGO_IS_TEST", "1")
+            .insert("CARGO_EDIT_INTERACTIVE", "1"),
+    )
+    .stdin("y\nn\n")
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("Upgrade test_breaking from ^0.1 to 0.2.0? This is a breaking change [y/N]")
+    .and()
+    .stdout()
+    .contains("Upgrade test_nonbreaking from ^0.0.5 to 0.1.1? This is a breaking change [y/N]")
+    .unwrap();
+
+    let dependencies = &get_toml(&manifest)["dependencies"];
+    assert_eq!(dependencies["test_breaking"].as_str(), Some("0.2.0"));
+    assert_eq!(dependencies["test_nonbreaking"].as_str(), Some("0.0.5"));
+}
+
+#[test]
+fn upgrade_skips_breaking_changes_without_terminal() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "test_breaking", "--vers", "0.1"], &manifest);
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    // A pipe rather than the terminal the tests may run in.
+    .stdin("")
+    .succeeds()
+    .and()
+    .stdout()
+    .contains(
+        "WARN: Skipping test_breaking: ^0.1 -> 0.2.0 is a breaking change, use --yes to upgrade it",
+    )
+    .unwrap();
+
+    let dependencies = &get_toml(&manifest)["dependencies"];
+    assert_eq!(dependencies["test_breaking"].as_str(), Some("0.1"));
+
+    // It is a warning, so `--strict` turns skipping into an error.
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--strict",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .stdin("")
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains(
+        "Skipping test_breaking: ^0.1 -> 0.2.0 is a breaking change, use --yes to upgrade it \
+         (warnings are errors with `--strict`)",
+    )
+    .unwrap();
+
+    let dependencies = &get_toml(&manifest)["dependencies"];
+    assert_eq!(dependencies["test_breaking"].as_str(), Some("0.1"));
+}
+
+#[test]
+fn upgrade_interactively() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "test_breaking", "--vers", "0.1"], &manifest);
+    execute_command(&["add", "test_nonbreaking", "--vers", "0.0.5"], &manifest);
+
+    // Skip `test_breaking`, and pick another version of `test_nonbreaking` after a typo.
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--interactive",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(
+        assert_cli::Environment::inherit()
+            .insert("CARGO_IS_TEST", "1")
+            .insert("CARGO_EDIT_INTERACTIVE", "1"),
+    )
+    .stdin("n\nmaybe\n0.0.9\n")
+    .succeeds()
+    .and()
+    .stdout()
+    .contains(
+        "test_breaking ^0.1: latest compatible test_breaking--CURRENT_VERSION_TEST, latest 0.2.0",
+    )
+    .and()
+    .stdout()
+    .contains("Upgrade test_nonbreaking to 0.1.1? [Y/n/c/<version>]")
+    .and()
+    .stdout()
+    .contains("Invalid answer `maybe`, enter y, n, c or a version")
+    .unwrap();
+
+    let dependencies = &get_toml(&manifest)["dependencies"];
+    assert_eq!(dependencies["test_breaking"].as_str(), Some("0.1"));
+    assert_eq!(dependencies["test_nonbreaking"].as_str(), Some("0.0.9"));
+}
+
+#[test]
+fn upgrade_to_minimal_versions() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_command(&["add", "docopt", "--vers", ">=0.8, <2"], &manifest);
+
+    execute_command(&["upgrade", "--minimal"], &manifest);
+
+    let dependencies = &get_toml(&manifest)["dependencies"];
+    assert_eq!(
+        dependencies["docopt"].as_str(),
+        Some("docopt--MINIMAL_VERSION_TEST")
+    );
+}
+
 #[test]
 fn upgrade_skip_compatible() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -238,60 +854,154 @@ fn upgrade_optional_dependency() {
     // Now, update without including the `optional` flag.
     execute_command(&["upgrade"], &manifest);
 
-    // Dependency present afterwards - correct version, and still optional.
+    // Dependency present afterwards - correct version, and still optional.
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["docopt"];
+    assert_eq!(
+        val["version"].as_str(),
+        Some("docopt--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(val["optional"].as_bool(), Some(true));
+}
+
+#[test]
+fn upgrade_renamed_dependency_all() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.renamed_dep");
+
+    execute_command(&["upgrade"], &manifest);
+
+    let toml = get_toml(&manifest);
+
+    let dep1 = &toml["dependencies"]["te"];
+    assert_eq!(
+        dep1["version"].as_str(),
+        Some("toml_edit--CURRENT_VERSION_TEST")
+    );
+
+    let dep2 = &toml["dependencies"]["rx"];
+    assert_eq!(
+        dep2["version"].as_str(),
+        Some("regex--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn upgrade_renamed_dependency_inline_specified_only() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.renamed_dep");
+
+    execute_command(&["upgrade", "toml_edit"], &manifest);
+
+    let toml = get_toml(&manifest);
+    let dep = &toml["dependencies"]["te"];
+    assert_eq!(
+        dep["version"].as_str(),
+        Some("toml_edit--CURRENT_VERSION_TEST")
+    );
+}
+
+#[test]
+fn upgrade_renamed_dependency_table_specified_only() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.renamed_dep");
+
+    execute_command(&["upgrade", "regex"], &manifest);
+
+    let toml = get_toml(&manifest);
+    let dep = &toml["dependencies"]["rx"];
+    assert_eq!(dep["version"].as_str(), Some("regex--CURRENT_VERSION_TEST"));
+}
+
+#[test]
+fn upgrade_skips_excluded_dependencies() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.renamed_dep");
+
+    execute_command(&["upgrade", "--exclude", "toml_edit"], &manifest);
+
     let toml = get_toml(&manifest);
-    let val = &toml["dependencies"]["docopt"];
     assert_eq!(
-        val["version"].as_str(),
-        Some("docopt--CURRENT_VERSION_TEST")
+        toml["dependencies"]["te"]["version"].as_str(),
+        Some("0.1.5")
+    );
+    assert_eq!(
+        toml["dependencies"]["rx"]["version"].as_str(),
+        Some("regex--CURRENT_VERSION_TEST")
     );
-    assert_eq!(val["optional"].as_bool(), Some(true));
 }
 
 #[test]
-fn upgrade_renamed_dependency_all() {
+fn upgrade_skips_dependencies_held_back_in_manifest() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.renamed_dep");
+    let mut contents = fs::read_to_string(&manifest).unwrap();
+    contents.push_str("\n[package.metadata.cargo-edit.upgrade]\nskip = [\"rx\"]\n");
+    fs::write(&manifest, contents).unwrap();
 
     execute_command(&["upgrade"], &manifest);
 
     let toml = get_toml(&manifest);
-
-    let dep1 = &toml["dependencies"]["te"];
     assert_eq!(
-        dep1["version"].as_str(),
+        toml["dependencies"]["te"]["version"].as_str(),
         Some("toml_edit--CURRENT_VERSION_TEST")
     );
+    assert_eq!(toml["dependencies"]["rx"]["version"].as_str(), Some("0.2"));
+}
 
-    let dep2 = &toml["dependencies"]["rx"];
+#[test]
+fn upgrade_with_req_style() {
+    // Each upgrade starts from a fresh copy, as the placeholder versions written by the first one
+    // aren't valid requirements for the `cargo metadata` of the next.
+    let setup = || {
+        let (tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.renamed_dep");
+        let mut contents = fs::read_to_string(&manifest).unwrap();
+        contents.push_str("\n[package.metadata.cargo-edit]\nreq-style = \"exact\"\n");
+        fs::write(&manifest, contents).unwrap();
+        (tmpdir, manifest)
+    };
+
+    let (_tmpdir, manifest) = setup();
+    execute_command(&["upgrade", "te"], &manifest);
     assert_eq!(
-        dep2["version"].as_str(),
-        Some("regex--CURRENT_VERSION_TEST")
+        get_toml(&manifest)["dependencies"]["te"]["version"].as_str(),
+        Some("=toml_edit--CURRENT_VERSION_TEST")
+    );
+
+    let (_tmpdir, manifest) = setup();
+    execute_command(&["upgrade", "rx", "--req-style", "tilde"], &manifest);
+    assert_eq!(
+        get_toml(&manifest)["dependencies"]["rx"]["version"].as_str(),
+        Some("~regex--CURRENT_VERSION_TEST")
     );
 }
 
 #[test]
-fn upgrade_renamed_dependency_inline_specified_only() {
+fn upgrade_renamed_dependency_keeps_alias() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.renamed_dep");
 
-    execute_command(&["upgrade", "toml_edit"], &manifest);
+    execute_command(&["upgrade"], &manifest);
 
+    // The versions are resolved against the real package names, and written to the alias keys.
     let toml = get_toml(&manifest);
-    let dep = &toml["dependencies"]["te"];
-    assert_eq!(
-        dep["version"].as_str(),
-        Some("toml_edit--CURRENT_VERSION_TEST")
-    );
+    let dependencies = &toml["dependencies"];
+    assert_eq!(dependencies["te"]["package"].as_str(), Some("toml_edit"));
+    assert_eq!(dependencies["rx"]["package"].as_str(), Some("regex"));
+    assert!(dependencies["toml_edit"].is_none());
+    assert!(dependencies["regex"].is_none());
 }
 
 #[test]
-fn upgrade_renamed_dependency_table_specified_only() {
+fn upgrade_renamed_dependency_by_alias() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.renamed_dep");
 
-    execute_command(&["upgrade", "regex"], &manifest);
+    execute_command(&["upgrade", "te"], &manifest);
 
     let toml = get_toml(&manifest);
-    let dep = &toml["dependencies"]["rx"];
-    assert_eq!(dep["version"].as_str(), Some("regex--CURRENT_VERSION_TEST"));
+    let dep = &toml["dependencies"]["te"];
+    assert_eq!(
+        dep["version"].as_str(),
+        Some("toml_edit--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(dep["package"].as_str(), Some("toml_edit"));
+
+    // Only the specified dependency is upgraded.
+    assert_eq!(toml["dependencies"]["rx"]["version"].as_str(), Some("0.2"));
 }
 
 #[test]
@@ -370,7 +1080,8 @@ fn upgrade_at() {
 
 #[test]
 fn upgrade_workspace() {
-    let (_tmpdir, root_manifest, workspace_manifests) = copy_workspace_test();
+    let (_tmpdir, root_manifest, workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
 
     execute_command(&["upgrade", "--all"], &root_manifest);
 
@@ -383,11 +1094,219 @@ fn upgrade_workspace() {
     }
 }
 
+#[test]
+fn upgrade_virtual_workspace_dependencies() {
+    let (_tmpdir, root_manifest, workspace_manifests) =
+        copy_workspace_test("workspace-dependencies", &["one"]);
+
+    execute_command(&["upgrade", "--all"], &root_manifest);
+
+    // The requirement is rewritten in the virtual root manifest, which is not a package.
+    assert_eq!(
+        get_toml(&root_manifest)["workspace"]["dependencies"]["docopt"].as_str(),
+        Some("docopt--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(
+        get_toml(&workspace_manifests[0])["dependencies"]["docopt"]["workspace"].as_bool(),
+        Some(true)
+    );
+}
+
+#[test]
+fn upgrade_workspace_with_summary() {
+    let (_tmpdir, root_manifest, _workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--all",
+        "--summary",
+        &format!("--manifest-path={}", root_manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("Summary:\n    libc\n")
+    .and()
+    .stdout()
+    .contains("        four: 0.2.28 -> libc--CURRENT_VERSION_TEST")
+    .and()
+    .stdout()
+    .contains("        two: 0.2 -> rand--CURRENT_VERSION_TEST")
+    .and()
+    .stdout()
+    .contains("2 upgraded, 0 unchanged, 0 skipped")
+    .and()
+    .stdout()
+    .doesnt_contain("Upgrading")
+    .unwrap();
+}
+
+#[test]
+fn upgrade_selected_workspace_members() {
+    let libc_of = |workspace_manifests: &[String], name: &str| {
+        let manifest = workspace_manifests
+            .iter()
+            .find(|manifest| get_toml(manifest)["package"]["name"].as_str() == Some(name))
+            .unwrap();
+        get_toml(manifest)["dependencies"]["libc"]
+            .as_str()
+            .map(String::from)
+    };
+    let upgraded = Some("libc--CURRENT_VERSION_TEST".to_owned());
+    let untouched = Some("0.2.28".to_owned());
+
+    let (_tmpdir, root_manifest, workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+    execute_command(&["upgrade", "-p", "one", "-p", "three"], &root_manifest);
+    assert_eq!(libc_of(&workspace_manifests, "one"), upgraded);
+    assert_eq!(libc_of(&workspace_manifests, "two"), untouched);
+    assert_eq!(libc_of(&workspace_manifests, "three"), upgraded);
+    assert_eq!(libc_of(&workspace_manifests, "four"), untouched);
+
+    // Each run starts from a fresh copy, as the placeholder versions written by an upgrade aren't
+    // valid requirements for the `cargo metadata` of the next.
+    let (_tmpdir, root_manifest, workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+    execute_command(&["upgrade", "--exclude-member", "four"], &root_manifest);
+    assert_eq!(libc_of(&workspace_manifests, "two"), upgraded);
+    assert_eq!(libc_of(&workspace_manifests, "four"), untouched);
+
+    let (_tmpdir, root_manifest, _) = copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "-p",
+        "five",
+        &format!("--manifest-path={}", root_manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("`five` is not a member of the workspace")
+    .unwrap();
+}
+
+#[test]
+fn upgrade_path_dependency_to_registry() {
+    let (_tmpdir, _root_manifest, workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
+    let one = &workspace_manifests[0];
+
+    execute_command(&["upgrade", "--to-registry"], one);
+
+    let toml = get_toml(one);
+    let three = &toml["dependencies"]["three"];
+    assert_eq!(three["path"].as_str(), Some("../implicit/three"));
+    assert_eq!(
+        three["version"].as_str(),
+        Some("three--CURRENT_VERSION_TEST")
+    );
+    // Registry dependencies are left alone.
+    assert_eq!(toml["dependencies"]["libc"].as_str(), Some("0.2.28"));
+}
+
+fn clone_out_frozen_test() -> (tempdir::TempDir, String) {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.frozen");
+    fs::copy(
+        "tests/fixtures/upgrade/Cargo.lock.frozen",
+        tmpdir.path().join("Cargo.lock"),
+    )
+    .unwrap_or_else(|err| panic!("could not copy test lock file: {}", err));
+    (tmpdir, manifest)
+}
+
+#[test]
+fn frozen_rejects_upgrade_breaking_lockfile() {
+    let (_tmpdir, manifest) = clone_out_frozen_test();
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--frozen",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("Upgrading `test_breaking` to `0.2.0` is incompatible with the locked version 0.1.0")
+    .unwrap();
+
+    // Nothing was written.
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["test_breaking"].as_str(), Some("0.1"));
+    assert_eq!(
+        toml["dependencies"]["test_nonbreaking"].as_str(),
+        Some("0.1")
+    );
+}
+
+#[test]
+fn frozen_allows_upgrade_matching_lockfile() {
+    let (_tmpdir, manifest) = clone_out_frozen_test();
+
+    execute_command(&["upgrade", "test_nonbreaking", "--frozen"], &manifest);
+
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["test_nonbreaking"].as_str(),
+        Some("0.1.1")
+    );
+}
+
+#[test]
+fn check_reports_outdated_dependencies_through_exit_status() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.frozen");
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--check",
+        "--quiet",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(2)
+    .and()
+    .stdout()
+    .is("")
+    .and()
+    .stderr()
+    .is("")
+    .unwrap();
+
+    // `test_nonbreaking` 0.1.1 still matches its requirement.
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "test_nonbreaking",
+        "--check",
+        "--quiet",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .is("")
+    .unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&manifest).unwrap(),
+        fs::read_to_string("tests/fixtures/upgrade/Cargo.toml.frozen").unwrap()
+    );
+}
+
 /// Detect if attempting to run against a workspace root and give a helpful warning.
 #[test]
 #[cfg(feature = "test-external-apis")]
 fn detect_workspace() {
-    let (_tmpdir, root_manifest, _workspace_manifests) = copy_workspace_test();
+    let (_tmpdir, root_manifest, _workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
 
     assert_cli::Assert::command(&[
         get_command_path("upgrade").as_str(),
@@ -415,7 +1334,7 @@ fn invalid_manifest() {
         "--manifest-path",
         &manifest,
     ])
-    .with_env(&[("CARGO_IS_TEST", "1")])
+    .with_env([("CARGO_IS_TEST", "1")])
     .fails_with(1)
     .and()
     .stderr()
@@ -444,7 +1363,7 @@ fn invalid_root_manifest() {
         "--manifest-path",
         &manifest,
     ])
-    .with_env(&[("CARGO_IS_TEST", "1")])
+    .with_env([("CARGO_IS_TEST", "1")])
     .fails_with(1)
     .and()
     .stderr()
@@ -460,7 +1379,7 @@ fn unknown_flags() {
         "foo",
         "--flag",
     ])
-    .with_env(&[("CARGO_IS_TEST", "1")])
+    .with_env([("CARGO_IS_TEST", "1")])
     .fails_with(1)
     .and()
     .stderr()
@@ -468,13 +1387,52 @@ fn unknown_flags() {
         "error: Found argument '--flag' which wasn't expected, or isn't valid in this context
 
 USAGE:
-    cargo upgrade [FLAGS] [OPTIONS] [dependency]...
+    cargo upgrade [FLAGS] [OPTIONS] [--] [dependency]...
 
 For more information try --help ",
     )
     .unwrap();
 }
 
+#[test]
+fn rust_version_conflicts_with_ignore_rust_version() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.source");
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--rust-version",
+        "1.56",
+        "--ignore-rust-version",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("cannot be used with")
+    .unwrap();
+}
+
+#[test]
+fn fails_with_invalid_rust_version() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.source");
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--rust-version",
+        "^1.56",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("`^1.56` is not a valid Rust version")
+    .unwrap();
+}
+
 // Verify that an upgraded Cargo.toml matches what we expect.
 #[test]
 #[cfg(feature = "test-external-apis")]
@@ -496,7 +1454,8 @@ fn upgrade_to_lockfile() {
 #[test]
 #[cfg(feature = "test-external-apis")]
 fn upgrade_workspace_to_lockfile() {
-    let (tmpdir, root_manifest, _workspace_manifests) = copy_workspace_test();
+    let (tmpdir, root_manifest, _workspace_manifests) =
+        copy_workspace_test("workspace", WORKSPACE_MEMBERS);
 
     execute_command(&["upgrade", "--all", "--to-lockfile"], &root_manifest);
 
@@ -528,3 +1487,178 @@ fn upgrade_prints_messages() {
     .contains("docopt v0.8 -> v")
     .unwrap();
 }
+
+#[test]
+fn upgrade_only_dependencies_from_registry() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.mixed_registry");
+    setup_alt_registry_config(tmpdir.path());
+
+    execute_command_in_dir(&["upgrade", "--registry", "alternative"], tmpdir.path());
+
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["toml_edit"]["version"].as_str(),
+        Some("toml_edit--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(toml["dependencies"]["docopt"].as_str(), Some("0.8"));
+
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.mixed_registry");
+    setup_alt_registry_config(tmpdir.path());
+
+    execute_command_in_dir(&["upgrade", "--registry", "crates-io"], tmpdir.path());
+
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["docopt"].as_str(),
+        Some("docopt--CURRENT_VERSION_TEST")
+    );
+    assert_eq!(
+        toml["dependencies"]["toml_edit"]["version"].as_str(),
+        Some("0.1.5")
+    );
+}
+
+#[test]
+fn upgrade_git_pins() {
+    // A repository with `v1.0.0` on its first commit and `v1.1.0` on the second one.
+    let repo_dir = tempdir::TempDir::new("cargo-upgrade-git-repo").unwrap();
+    let repo = git2::Repository::init(repo_dir.path()).unwrap();
+    let signature = git2::Signature::now("cargo-edit", "cargo-edit@example.com").unwrap();
+    let tree = repo
+        .find_tree(repo.index().unwrap().write_tree().unwrap())
+        .unwrap();
+    let first = repo
+        .commit(Some("HEAD"), &signature, &signature, "first", &tree, &[])
+        .unwrap();
+    let first = repo.find_commit(first).unwrap();
+    repo.tag_lightweight("v1.0.0", first.as_object(), false)
+        .unwrap();
+    let second = repo
+        .commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "second",
+            &tree,
+            &[&first],
+        )
+        .unwrap();
+    let second = repo.find_commit(second).unwrap();
+    repo.tag_lightweight("v1.1.0", second.as_object(), false)
+        .unwrap();
+    let repo_url = url::Url::from_directory_path(repo_dir.path())
+        .unwrap()
+        .to_string();
+
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let mut contents = fs::read_to_string(&manifest).unwrap();
+    contents.push_str(&format!(
+        "\n[dependencies]\n\
+         tagged = {{ git = \"{0}\", tag = \"v1.0.0\" }}\n\
+         pinned = {{ git = \"{0}\", rev = \"{1}\" }} # keep me\n\
+         nightly = {{ git = \"{0}\", tag = \"nightly\" }}\n\
+         tracking = {{ git = \"{0}\", branch = \"master\" }}\n",
+        repo_url,
+        &first.id().to_string()[..7]
+    ));
+    fs::write(&manifest, contents).unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--git",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("tagged tag v1.0.0 -> v1.1.0")
+    .and()
+    .stdout()
+    .contains("Skipping nightly: tag `nightly` is not a semver version")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    let dependencies = &toml["dependencies"];
+    assert_eq!(dependencies["tagged"]["tag"].as_str(), Some("v1.1.0"));
+    assert_eq!(
+        dependencies["pinned"]["rev"].as_str(),
+        Some(&second.id().to_string()[..7])
+    );
+    assert_eq!(dependencies["nightly"]["tag"].as_str(), Some("nightly"));
+    assert_eq!(dependencies["tracking"]["branch"].as_str(), Some("master"));
+    assert!(fs::read_to_string(&manifest).unwrap().contains("# keep me"));
+}
+
+#[test]
+fn upgrade_git_pins_offline() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let mut contents = fs::read_to_string(&manifest).unwrap();
+    contents.push_str(
+        "\n[dependencies]\n\
+         tagged = { git = \"https://example.com/tagged.git\", tag = \"v1.0.0\" }\n",
+    );
+    fs::write(&manifest, &contents).unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--git",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(
+        assert_cli::Environment::inherit()
+            .insert("CARGO_IS_TEST", "1")
+            .insert("CARGO_NET_OFFLINE", "true"),
+    )
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("Cannot list the references of `https://example.com/tagged.git` offline")
+    .unwrap();
+
+    assert_eq!(fs::read_to_string(&manifest).unwrap(), contents);
+}
+
+#[test]
+fn fix_advisories_with_local_advisory_db() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.source");
+    let advisory_db = tmpdir.path().join("advisory-db");
+    fs::create_dir_all(advisory_db.join("crates").join("docopt")).unwrap();
+    fs::write(
+        advisory_db
+            .join("crates")
+            .join("docopt")
+            .join("RUSTSEC-2021-0001.md"),
+        "```toml\n[advisory]\nid = \"RUSTSEC-2021-0001\"\npackage = \"docopt\"\n\n\
+         [versions]\npatched = [\">= 0.9.0\"]\n```\n",
+    )
+    .unwrap();
+    let contents = fs::read_to_string(&manifest).unwrap();
+
+    // The versions are faked in tests, so none of them is affected.
+    execute_command(
+        &[
+            "upgrade",
+            "--fix-advisories",
+            &format!("--advisory-db={}", advisory_db.display()),
+        ],
+        &manifest,
+    );
+    assert_eq!(fs::read_to_string(&manifest).unwrap(), contents);
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--fix-advisories",
+        &format!("--advisory-db={}", tmpdir.path().join("missing").display()),
+        &format!("--manifest-path={}", manifest),
+    ])
+    .with_env(assert_cli::Environment::inherit().insert("CARGO_IS_TEST", "1"))
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("There is no advisory database at")
+    .unwrap();
+}
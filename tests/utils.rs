@@ -20,6 +20,64 @@ pub fn clone_out_test(source: &str) -> (tempdir::TempDir, String) {
     (tmpdir, path)
 }
 
+/// Members of the `tests/fixtures/workspace` test workspace.
+pub const WORKSPACE_MEMBERS: &[&str] = &["one", "two", "implicit/three", "explicit/four"];
+
+/// Helper function that copies the workspace in `tests/fixtures/{fixture}` into a temporary
+/// directory, and returns the paths of its root manifest and of the manifests of `members`.
+pub fn copy_workspace_test(
+    fixture: &str,
+    members: &[&str],
+) -> (tempdir::TempDir, String, Vec<String>) {
+    // Create a temporary directory and copy in the root manifest, the dummy rust file, and
+    // workspace member manifests.
+    let tmpdir = tempdir::TempDir::new(&format!("{}_workspace", fixture))
+        .expect("failed to construct temporary directory");
+
+    let (root_manifest_path, workspace_manifest_paths) = {
+        // Helper to copy in files to the temporary workspace. The standard library doesn't have a
+        // good equivalent of `cp -r`, hence this oddity.
+        let copy_in = |dir, file| {
+            let file_path = tmpdir
+                .path()
+                .join(dir)
+                .join(file)
+                .to_str()
+                .unwrap()
+                .to_string();
+
+            fs::create_dir_all(tmpdir.path().join(dir)).unwrap();
+
+            fs::copy(
+                format!("tests/fixtures/{}/{}/{}", fixture, dir, file),
+                &file_path,
+            )
+            .unwrap_or_else(|err| panic!("could not copy test file: {}", err));
+
+            file_path
+        };
+
+        let root_manifest_path = copy_in(".", "Cargo.toml");
+        copy_in(".", "dummy.rs");
+        if Path::new(&format!("tests/fixtures/{}/Cargo.lock", fixture)).exists() {
+            copy_in(".", "Cargo.lock");
+        }
+
+        let workspace_manifest_paths = members
+            .iter()
+            .map(|member| copy_in(member, "Cargo.toml"))
+            .collect::<Vec<_>>();
+
+        (root_manifest_path, workspace_manifest_paths)
+    };
+
+    (
+        tmpdir,
+        root_manifest_path,
+        workspace_manifest_paths.to_owned(),
+    )
+}
+
 /// Add directory
 pub fn setup_alt_registry_config(path: &std::path::Path) {
     fs::create_dir(path.join(".cargo")).expect("failed to create .cargo directory");
@@ -37,7 +95,7 @@ where
 {
     let subcommand_name = &command[0].as_ref();
 
-    let call = process::Command::new(&get_command_path(subcommand_name))
+    let call = process::Command::new(get_command_path(subcommand_name))
         .args(command)
         .arg(format!("--manifest-path={}", manifest))
         .env("CARGO_IS_TEST", "1")
@@ -62,7 +120,7 @@ where
 {
     let subcommand_name = &command[0].as_ref();
 
-    let call = process::Command::new(&get_command_path(subcommand_name))
+    let call = process::Command::new(get_command_path(subcommand_name))
         .args(command)
         .arg(format!("--manifest-path={}", manifest))
         .env("CARGO_IS_TEST", "1")
@@ -87,7 +145,7 @@ where
 {
     let subcommand_name = &command[0].as_ref();
 
-    let call = process::Command::new(&get_command_path(subcommand_name))
+    let call = process::Command::new(get_command_path(subcommand_name))
         .args(command)
         .env("CARGO_IS_TEST", "1")
         .current_dir(dir)